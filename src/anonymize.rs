@@ -0,0 +1,70 @@
+use crate::flame::{FlameGraph, ROOT_ID};
+use std::collections::HashMap;
+
+/// How frame names are replaced when anonymizing a profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum AnonymizeMode {
+    /// Rename frames to generic names like "frame_1" in order of appearance
+    Rename,
+    /// Replace frames with a hash of their names so that the same name always maps to the same
+    /// value across profiles
+    Hash,
+}
+
+/// Produce folded stacks data with all frame names replaced while preserving the structure of
+/// the stacks and their counts.
+pub fn anonymize(flamegraph: &FlameGraph, mode: AnonymizeMode) -> String {
+    let mut names: HashMap<String, String> = HashMap::new();
+    flamegraph.to_folded(&ROOT_ID, |name| {
+        let next_index = names.len() + 1;
+        names
+            .entry(name.to_string())
+            .or_insert_with(|| match mode {
+                AnonymizeMode::Rename => format!("frame_{}", next_index),
+                AnonymizeMode::Hash => format!("{:016x}", fnv1a(name)),
+            })
+            .clone()
+    })
+}
+
+/// FNV-1a hash, used instead of DefaultHasher as the output needs to be stable across builds.
+pub(crate) fn fnv1a(s: &str) -> u64 {
+    fnv1a_seeded(s, 0)
+}
+
+/// FNV-1a hash with the offset basis changed by `seed`, for other hashes that are just as stable.
+pub(crate) fn fnv1a_seeded(s: &str, seed: u64) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325 ^ seed, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rename() {
+        let fg =
+            FlameGraph::from_string("main;foo;bar 3\nmain;foo 2\nmain;baz;bar 1\n".into(), false);
+        assert_eq!(
+            anonymize(&fg, AnonymizeMode::Rename),
+            "frame_1;frame_2 2\nframe_1;frame_2;frame_3 3\nframe_1;frame_4;frame_3 1\n"
+        );
+    }
+
+    #[test]
+    fn test_hash_preserves_counts() {
+        let content = std::fs::read_to_string("tests/data/py-spy-simple.txt").unwrap();
+        let fg = FlameGraph::from_string(content, true);
+        let anonymized = anonymize(&fg, AnonymizeMode::Hash);
+        assert!(!anonymized.contains("long_running.py"));
+        let anonymized_fg = FlameGraph::from_string(anonymized, true);
+        assert_eq!(anonymized_fg.total_count(), fg.total_count());
+        assert_eq!(anonymized_fg.get_num_levels(), fg.get_num_levels());
+        assert_eq!(
+            anonymized_fg.function_counts().len(),
+            fg.function_counts().len()
+        );
+    }
+}
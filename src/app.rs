@@ -1,14 +1,26 @@
-use crate::flame::{FlameGraph, SearchPattern};
-#[cfg(feature = "python")]
-use crate::py_spy::{record_samples, ProfilerOutput, SamplerState, SamplerStatus};
-use crate::state::FlameGraphState;
+use crate::archive::{list_snapshots, SnapshotArchive, SnapshotBrowser};
+use crate::config::{Config, NotificationMode, Palette};
+use crate::diff::{Baseline, LiveDelta};
+use crate::editor::{editor_command, resolve_source_path, user_editor};
+use crate::event::Event;
+use crate::export::{export, ExportFormat};
+use crate::flame::{
+    fuzzy_match, source_location, without_line_number, FlameGraph, GroupBy, SearchPattern,
+    StackFilter, StackIdentifier, StackInfo,
+};
+use crate::import::to_folded;
+use crate::listen::{listen, ListenAddress, Listener};
+use crate::messages::Messages;
+use crate::notes::Notes;
+use crate::sampler::{ProfilerOutput, Sampler, SamplerState, SamplerStatus};
+use crate::session::{Session, SessionInput};
+use crate::state::{FlameGraphState, MinWidth, ProcessSelector, ViewKind};
+use crate::ui::RenderedFlameGraph;
 use crate::view::FlameGraphView;
-#[cfg(feature = "python")]
-use remoteprocess;
 use std::collections::HashMap;
 use std::error;
-use std::sync::{Arc, Mutex};
-#[cfg(feature = "python")]
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
@@ -19,25 +31,90 @@ pub type AppResult<T> = std::result::Result<T, Box<dyn error::Error>>;
 pub enum FlameGraphInput {
     File(String),
     Pid(u64, Option<String>),
+    /// Address profiles are pushed to, along with the number of profiles received so far
+    Listen(String, usize),
 }
 
-#[derive(Debug)]
+impl std::fmt::Display for FlameGraphInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlameGraphInput::File(path) => write!(f, "file {}", path),
+            FlameGraphInput::Pid(pid, _) => write!(f, "live profile of process {}", pid),
+            FlameGraphInput::Listen(address, _) => write!(f, "profiles pushed to {}", address),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ParsedFlameGraph {
     pub flamegraph: FlameGraph,
     pub elapsed: Duration,
+    /// Samples of the live Python sampler whose thread held the GIL
+    pub gil_flamegraph: Option<FlameGraph>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    Search,
+    Command,
+    Note,
+    Level,
+    TableFilter,
 }
 
 #[derive(Debug)]
 pub struct InputBuffer {
+    pub kind: InputKind,
     pub buffer: tui_input::Input,
     pub cursor: Option<(u16, u16)>,
 }
 
+impl InputBuffer {
+    pub fn new(kind: InputKind) -> Self {
+        Self::with_value(kind, "")
+    }
+
+    pub fn with_value(kind: InputKind, value: &str) -> Self {
+        Self {
+            kind,
+            buffer: tui_input::Input::new(value.to_string()),
+            cursor: None,
+        }
+    }
+}
+
+/// Which key hints are shown in the help bar at the bottom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum HintsMode {
+    /// All keys available in the current view, falling back to the essential ones if they don't
+    /// fit
+    #[default]
+    Full,
+    /// Only the essential keys such as moving, zooming, searching and switching views
+    Essential,
+    /// Hide the help bar
+    Off,
+}
+
+impl HintsMode {
+    pub fn next(self) -> Self {
+        match self {
+            HintsMode::Full => HintsMode::Essential,
+            HintsMode::Essential => HintsMode::Off,
+            HintsMode::Off => HintsMode::Full,
+        }
+    }
+}
+
 /// Application.
 #[derive(Debug)]
 pub struct App {
     /// Is the application running?
     pub running: bool,
+    /// Should the application suspend to the shell once the current events are handled?
+    pub suspend_requested: bool,
+    /// Editor to run once the current events are handled, to show the source of a frame
+    pub editor_requested: Option<std::process::Command>,
     /// Flamegraph view
     pub flamegraph_view: FlameGraphView,
     /// Flamegraph input information
@@ -46,125 +123,960 @@ pub struct App {
     pub input_buffer: Option<InputBuffer>,
     /// Timing information for debugging
     pub elapsed: HashMap<String, Duration>,
-    /// Transient message
-    pub transient_message: Option<String>,
+    /// Messages shown in the status bar, along with their log
+    pub messages: Messages,
     /// Debug mode
     pub debug: bool,
-    /// Next flamegraph to swap in
-    next_flamegraph: Arc<Mutex<Option<ParsedFlameGraph>>>,
-    #[cfg(feature = "python")]
+    /// Notes attached to frames
+    pub notes: Notes,
+    /// Sidecar file to persist the notes, if the input is a file
+    pub notes_path: Option<PathBuf>,
+    /// Whether to show the notes panel
+    pub show_notes: bool,
+    /// Whether to show the depth histogram panel
+    pub show_depth_histogram: bool,
+    /// Whether to mark the share of each frame spent in the function itself
+    pub show_own_time: bool,
+    /// Whether to show a ruler at 25, 50 and 75% of the flamegraph width
+    pub show_ruler: bool,
+    /// Whether the header, status bars and key hints are hidden for more room, except for the
+    /// prompt and messages
+    pub zen: bool,
+    /// Fraction of the stacks loaded with `--sample-rate`
+    pub sample_rate: Option<f64>,
+    /// Frames matching this become the root of the flamegraph, including the live updates
+    pub root_frame: Option<regex::Regex>,
+    /// First key of a multi-key command such as `zz`
+    pub pending_key: Option<char>,
+    /// Function whose callers are shown, along with the view to go back to
+    pub callers_of: Option<(String, FlameGraphView)>,
+    /// Which key hints to show at the bottom
+    pub hints: HintsMode,
+    /// Settings from the config file
+    pub config: Config,
+    /// Desktop notifications waiting to be sent through the terminal
+    pub notifications: Vec<String>,
+    /// Text waiting to be copied to the clipboard through the terminal
+    pub clipboard: Option<String>,
+    /// Flamegraph body as last drawn, to reuse while it is unchanged
+    pub rendered_flamegraph: Option<RenderedFlameGraph>,
+    /// Pixels of each cell of the terminal, when the flamegraph is also drawn as an image
+    pub cell_size: Option<(u16, u16)>,
+    /// Frame last shown in the "Selected" bar and since when, to scroll its text from the start
+    pub selected_shown_since: Option<(StackIdentifier, std::time::Instant)>,
+    /// Whether the text of the selected frame is longer than the "Selected" bar, which then
+    /// scrolls through it over time
+    pub selected_overflows: bool,
+    /// Latest flamegraph received while frozen, to swap in once unfrozen
+    next_flamegraph: Option<ParsedFlameGraph>,
+    /// Windows of the live profile, if showing the latest one instead of all samples
+    live_delta: Option<LiveDelta>,
+    /// Profile given with --baseline to compare with
+    pub baseline: Option<Arc<Baseline>>,
+    /// Whether frames are colored by how they changed since the baseline
+    show_baseline: bool,
+    /// Samples of the live Python sampler whose thread held the GIL, along with the latest update
+    gil_flamegraph: Option<FlameGraph>,
+    /// Whether frames are colored by how often they held the GIL
+    show_gil: bool,
+    /// Flamegraph as received, while the one shown is reshaped by the options below
+    unprocessed: Option<FlameGraph>,
+    /// Whether kernel frames are left out, their samples counting for their callers
+    hide_kernel_frames: bool,
+    /// Whether the frames common to all stacks are collapsed into one
+    collapse_common_prefix: bool,
+    /// Frame each stack is grouped under
+    group_by: Option<GroupBy>,
+    /// Whether the frames of a function at different lines are merged into one
+    aggregate_lines: bool,
+    /// Functions shown at line granularity all the same, named without the line number
+    expanded_lines: Vec<String>,
+    /// Whether the samples of active and idle threads are shown apart, under the frames the live
+    /// sampler puts them under
+    split_activity: bool,
+    /// How long the live session samples for, if limited with --duration
+    pub capture_duration: Option<Duration>,
+    /// What happened when the live sampler stopped, shown in the header
+    pub sampler_finished: Option<String>,
+    /// Directory of snapshots taken with --snapshot-dir, to load with the snapshot browser
+    pub snapshot_dir: Option<PathBuf>,
+    /// Snapshot browser popup, while shown
+    pub snapshot_browser: Option<SnapshotBrowser>,
+    /// Process selector popup, while shown
+    pub process_selector: Option<ProcessSelector>,
     sampler_state: Option<Arc<Mutex<SamplerState>>>,
+    /// Profiles being received with --listen, stopped once the app is dropped
+    listener: Option<Listener>,
 }
 
 impl App {
     /// Constructs a new instance of [`App`].
     pub fn with_flamegraph(filename: &str, flamegraph: FlameGraph) -> Self {
-        Self {
+        let notes_path = Some(Path::new(filename))
+            .filter(|p| p.is_file())
+            .map(Notes::sidecar_path);
+        let notes = notes_path
+            .as_ref()
+            .and_then(|p| Notes::load(p).ok())
+            .unwrap_or_default();
+        let mut messages = Messages::default();
+        if let Some(warning) = Self::get_skipped_lines_warning(&flamegraph) {
+            messages.push(&warning);
+        }
+        let mut app = Self {
             running: true,
+            suspend_requested: false,
+            editor_requested: None,
             flamegraph_view: FlameGraphView::new(flamegraph),
             flamegraph_input: FlameGraphInput::File(filename.to_string()),
             input_buffer: None,
             elapsed: HashMap::new(),
-            transient_message: None,
+            messages,
             debug: false,
-            next_flamegraph: Arc::new(Mutex::new(None)),
-            #[cfg(feature = "python")]
+            notes,
+            notes_path,
+            show_notes: false,
+            show_depth_histogram: false,
+            show_own_time: false,
+            show_ruler: false,
+            zen: false,
+            sample_rate: None,
+            root_frame: None,
+            pending_key: None,
+            callers_of: None,
+            hints: HintsMode::default(),
+            config: Config::default(),
+            notifications: vec![],
+            clipboard: None,
+            rendered_flamegraph: None,
+            cell_size: None,
+            selected_shown_since: None,
+            selected_overflows: false,
+            next_flamegraph: None,
+            live_delta: None,
+            baseline: None,
+            show_baseline: false,
+            gil_flamegraph: None,
+            show_gil: false,
+            unprocessed: None,
+            hide_kernel_frames: false,
+            collapse_common_prefix: false,
+            group_by: None,
+            split_activity: false,
+            aggregate_lines: false,
+            expanded_lines: vec![],
+            capture_duration: None,
+            sampler_finished: None,
+            snapshot_dir: None,
+            snapshot_browser: None,
+            process_selector: None,
             sampler_state: None,
+            listener: None,
+        };
+        // Recorded from a live session with the samples split by activity
+        if app.main_view().flamegraph.has_activity_frames() {
+            app.reshape_flamegraph();
         }
+        app
+    }
+
+    /// Constructs an [`App`] showing the latest profile pushed to the address. Received profiles
+    /// are sent as [`Event::FlameGraph`].
+    pub fn with_listener(
+        address: &ListenAddress,
+        sorted: bool,
+        stack_filter: StackFilter,
+        sender: mpsc::Sender<Event>,
+    ) -> std::io::Result<Self> {
+        let listener = listen(address, move |content| {
+            let tic = std::time::Instant::now();
+            let flamegraph =
+                FlameGraph::from_string(stack_filter.apply(to_folded(content)), sorted);
+            let parsed = ParsedFlameGraph {
+                flamegraph,
+                elapsed: tic.elapsed(),
+                gil_flamegraph: None,
+            };
+            let _ = sender.send(Event::FlameGraph(Box::new(parsed)));
+        })?;
+        let mut app = Self::with_flamegraph("", FlameGraph::from_string("".to_string(), sorted));
+        app.flamegraph_input = FlameGraphInput::Listen(listener.address.to_string(), 0);
+        app.listener = Some(listener);
+        Ok(app)
     }
 
-    #[cfg(feature = "python")]
-    pub fn with_pid(pid: u64, py_spy_args: Option<String>) -> Self {
-        let next_flamegraph: Arc<Mutex<Option<ParsedFlameGraph>>> = Arc::new(Mutex::new(None));
-        let pyspy_data: Arc<Mutex<Option<ProfilerOutput>>> = Arc::new(Mutex::new(None));
+    /// Constructs an [`App`] showing a live flamegraph of the process sampled by the sampler.
+    /// Updated flamegraphs are sent as [`Event::FlameGraph`], followed by
+    /// [`Event::SamplerFinished`] once the sampler stops, after writing the last samples to
+    /// `record` if given. The samples are also written to `archive` every so often if given.
+    pub fn with_sampler(
+        sampler: Box<dyn Sampler>,
+        stack_filter: StackFilter,
+        record: Option<PathBuf>,
+        mut archive: Option<SnapshotArchive>,
+        sender: mpsc::Sender<Event>,
+    ) -> Self {
+        let (sampler_sender, sampler_receiver) = mpsc::channel::<ProfilerOutput>();
         let sampler_state = Arc::new(Mutex::new(SamplerState::default()));
+        let pid = sampler.pid();
+        let process_info = sampler.command_line();
 
-        // Thread to poll data from pyspy and construct the next flamegraph
+        // Thread to construct the next flamegraph from the latest sampler data
         {
-            let next_flamegraph = next_flamegraph.clone();
-            let pyspy_data = pyspy_data.clone();
-            let _handle = thread::spawn(move || loop {
-                if let Some(output) = pyspy_data.lock().unwrap().take() {
+            let _handle = thread::spawn(move || {
+                let mut last_data = None;
+                while let Ok(mut output) = sampler_receiver.recv() {
+                    // Skip the outputs that are already outdated
+                    while let Ok(newer) = sampler_receiver.try_recv() {
+                        output = newer;
+                    }
                     let tic = std::time::Instant::now();
-                    let flamegraph = FlameGraph::from_string(output.data, true);
+                    let data = stack_filter.apply(output.data);
+                    if let Some(written) = archive.as_mut().map(|a| a.write_if_due(&data)) {
+                        match written {
+                            Ok(Some(path)) => tracing::debug!(?path, "Wrote snapshot"),
+                            Ok(None) => {}
+                            Err(e) => {
+                                tracing::warn!(error = ?e, "Could not write snapshot, stopping");
+                                archive = None;
+                            }
+                        }
+                    }
+                    if record.is_some() {
+                        last_data = Some(data.clone());
+                    }
+                    let flamegraph = FlameGraph::from_string(data, true);
+                    let gil_flamegraph = output.gil_data.map(|gil_data| {
+                        FlameGraph::from_string(stack_filter.apply(gil_data), true)
+                    });
                     let parsed = ParsedFlameGraph {
                         flamegraph,
                         elapsed: tic.elapsed(),
+                        gil_flamegraph,
                     };
-                    *next_flamegraph.lock().unwrap() = Some(parsed);
+                    if sender.send(Event::FlameGraph(Box::new(parsed))).is_err() {
+                        return;
+                    }
                 }
-                thread::sleep(std::time::Duration::from_millis(250));
+                // The sampler stopped, having sent all its samples
+                let recorded = record.map(|path| {
+                    std::fs::write(&path, last_data.unwrap_or_default())
+                        .map(|_| path.display().to_string())
+                        .map_err(|e| e.to_string())
+                });
+                let _ = sender.send(Event::SamplerFinished(recorded));
             });
         }
 
-        // pyspy live sampler thread
+        // Live sampler thread
         {
-            let pyspy_data = pyspy_data.clone();
             let sampler_state = sampler_state.clone();
             let _handle = thread::spawn(move || {
-                // Note: mimic a record command's invocation vs simply getting default Config as
-                // from_args does a lot of heavy lifting
-                let mut args = [
-                    "py-spy",
-                    "record",
-                    "--pid",
-                    pid.to_string().as_str(),
-                    "--format",
-                    "raw",
-                ]
-                .iter()
-                .map(|s| s.to_string())
-                .collect::<Vec<String>>();
-                if let Some(py_spy_args) = py_spy_args {
-                    args.extend(py_spy_args.split_whitespace().map(|s| s.to_string()));
-                }
-                let config = py_spy::Config::from_args(&args).unwrap();
-                let pid = pid as remoteprocess::Pid;
-                record_samples(pid, &config, pyspy_data, sampler_state);
+                sampler.record(sampler_sender, sampler_state);
             });
         }
 
         let flamegraph = FlameGraph::from_string("".to_string(), true);
-        let process_info = remoteprocess::Process::new(pid as remoteprocess::Pid)
-            .and_then(|p| p.cmdline())
-            .ok()
-            .map(|c| c.join(" "));
         Self {
             running: true,
+            suspend_requested: false,
+            editor_requested: None,
             flamegraph_view: FlameGraphView::new(flamegraph),
             flamegraph_input: FlameGraphInput::Pid(pid, process_info),
-            next_flamegraph: next_flamegraph.clone(),
+            next_flamegraph: None,
+            live_delta: None,
+            baseline: None,
+            show_baseline: false,
+            gil_flamegraph: None,
+            show_gil: false,
+            unprocessed: None,
+            hide_kernel_frames: false,
+            collapse_common_prefix: false,
+            group_by: None,
+            split_activity: false,
+            aggregate_lines: false,
+            expanded_lines: vec![],
             input_buffer: None,
             elapsed: HashMap::new(),
-            transient_message: None,
+            messages: Messages::default(),
             debug: false,
+            notes: Notes::default(),
+            notes_path: None,
+            show_notes: false,
+            show_depth_histogram: false,
+            show_own_time: false,
+            show_ruler: false,
+            zen: false,
+            sample_rate: None,
+            root_frame: None,
+            pending_key: None,
+            callers_of: None,
+            hints: HintsMode::default(),
+            config: Config::default(),
+            notifications: vec![],
+            clipboard: None,
+            rendered_flamegraph: None,
+            cell_size: None,
+            selected_shown_since: None,
+            selected_overflows: false,
+            capture_duration: None,
+            sampler_finished: None,
+            snapshot_dir: None,
+            snapshot_browser: None,
+            process_selector: None,
             sampler_state: Some(sampler_state),
+            listener: None,
         }
     }
 
-    /// Handles the tick event of the terminal.
-    pub fn tick(&mut self) {
-        // Replace flamegraph
+    /// Handles the tick event of the terminal. Returns whether anything shown on screen changed
+    /// and needs to be redrawn.
+    pub fn tick(&mut self) -> bool {
+        let mut changed = false;
+
+        // Swap in the flamegraph received while frozen
         if !self.flamegraph_view.state.freeze {
-            if let Some(parsed) = self.next_flamegraph.lock().unwrap().take() {
-                self.elapsed
-                    .insert("flamegraph".to_string(), parsed.elapsed);
-                let tic = std::time::Instant::now();
-                self.flamegraph_view.replace_flamegraph(parsed.flamegraph);
-                self.elapsed
-                    .insert("replacement".to_string(), tic.elapsed());
+            if let Some(parsed) = self.next_flamegraph.take() {
+                self.receive_flamegraph(parsed);
+                changed = true;
             }
         }
 
+        changed |= self.messages.expire(std::time::Instant::now());
+
         // Exit if fatal error in sampler
-        #[cfg(feature = "python")]
         if let Some(SamplerStatus::Error(s)) = self
             .sampler_state
             .as_ref()
             .map(|s| s.lock().unwrap().status.clone())
         {
-            panic!("py-spy sampler exited with error: {}\n\nYou likely need to rerun this program with sudo.", s);
+            panic!("{}", s);
+        }
+
+        // The sampled duration in the header keeps going up while the sampler is running
+        changed || self.is_sampling() || self.selected_overflows
+    }
+
+    /// Whether ticks have to come often, i.e. something on screen changes over time rather than
+    /// in response to an event.
+    pub fn needs_frequent_ticks(&self) -> bool {
+        self.messages.current().is_some()
+            || (!self.flamegraph_view.state.freeze && self.next_flamegraph.is_some())
+            || self.is_sampling()
+            || self.selected_overflows
+    }
+
+    fn is_sampling(&self) -> bool {
+        self.sampler_state
+            .as_ref()
+            .is_some_and(|s| matches!(s.lock().unwrap().status, SamplerStatus::Running))
+    }
+
+    /// Handles a flamegraph received from the live sampler or pushed to the listener, which is
+    /// held back while the view is frozen.
+    pub fn receive_flamegraph(&mut self, parsed: ParsedFlameGraph) {
+        if self.flamegraph_view.state.freeze {
+            self.next_flamegraph = Some(parsed);
+            return;
+        }
+        self.elapsed
+            .insert("flamegraph".to_string(), parsed.elapsed);
+        let tic = std::time::Instant::now();
+        // Only warn about pushed profiles, as the live samples are always valid
+        let warning = Self::get_skipped_lines_warning(&parsed.flamegraph);
+        self.gil_flamegraph = parsed.gil_flamegraph;
+        let flamegraph = match self.live_delta.as_mut() {
+            Some(delta) => delta.next_window(&parsed.flamegraph),
+            None => Some(parsed.flamegraph),
+        };
+        if let Some(flamegraph) = flamegraph {
+            self.replace_flamegraph(flamegraph);
+            if let Some(delta) = self.live_delta.as_mut() {
+                let view = match &self.callers_of {
+                    Some((_, view)) => view,
+                    None => &self.flamegraph_view,
+                };
+                delta.set_shown_window(&view.flamegraph);
+            }
+        }
+        if let FlameGraphInput::Listen(_, received) = &mut self.flamegraph_input {
+            *received += 1;
+            let notification = format!("Received profile {}", received);
+            self.notify(&notification);
+            if let Some(warning) = warning {
+                self.set_transient_message(&warning);
+            }
+        }
+        self.elapsed
+            .insert("replacement".to_string(), tic.elapsed());
+    }
+
+    /// Handles the live sampler having stopped, after its last flamegraph was received, along with
+    /// where its samples were recorded to or why they could not be.
+    pub fn finish_sampling(&mut self, recorded: Option<Result<String, String>>) {
+        let FlameGraphInput::Pid(pid, _) = self.flamegraph_input else {
+            return;
+        };
+        let mut text = if self.capture_duration.is_some() {
+            let sampled = self
+                .sampler_state()
+                .map(|state| state.total_sampled_duration)
+                .unwrap_or_default();
+            format!("Capture complete after {}s", sampled.as_secs())
+        } else {
+            format!("Process {} exited, sampling stopped", pid)
+        };
+        match recorded {
+            Some(Ok(path)) => text += &format!("; saved to {}", path),
+            Some(Err(e)) => text += &format!("; could not save: {}", e),
+            None => {}
+        }
+        if self.capture_duration.is_some() {
+            // Nothing comes after what was held back while frozen
+            if let Some(parsed) = self.next_flamegraph.take() {
+                self.flamegraph_view.state.freeze = false;
+                self.receive_flamegraph(parsed);
+            }
+            self.flamegraph_view.state.freeze = true;
+        }
+        self.notify(&text);
+        self.sampler_finished = Some(text);
+    }
+
+    pub fn open_snapshot_browser(&mut self) {
+        let Some(dir) = &self.snapshot_dir else {
+            self.set_transient_message("No snapshots; take them with --snapshot-dir <dir>");
+            return;
+        };
+        match list_snapshots(dir) {
+            Ok(snapshots) if snapshots.is_empty() => {
+                let text = format!("No snapshots in {} yet", dir.display());
+                self.set_transient_message(&text);
+            }
+            Ok(snapshots) => {
+                self.snapshot_browser = Some(SnapshotBrowser {
+                    snapshots,
+                    selected: 0,
+                })
+            }
+            Err(e) => {
+                let text = format!("Could not list snapshots in {}: {}", dir.display(), e);
+                self.set_transient_message(&text);
+            }
+        }
+    }
+
+    /// Show the snapshot selected in the snapshot browser, as written with the stack filters of
+    /// the live session applied. A live flamegraph is frozen to show it until unfrozen.
+    pub fn load_selected_snapshot(&mut self) {
+        let Some(snapshot) = self
+            .snapshot_browser
+            .take()
+            .and_then(|browser| browser.selected_snapshot().cloned())
+        else {
+            return;
+        };
+        let content = match std::fs::read_to_string(&snapshot.path) {
+            Ok(content) => content,
+            Err(e) => {
+                let text = format!("Could not load {}: {}", snapshot.path.display(), e);
+                self.set_transient_message(&text);
+                return;
+            }
+        };
+        let sorted = self.main_view().flamegraph.is_sorted();
+        let flamegraph = FlameGraph::from_string(content, sorted);
+        if self.is_live() {
+            self.flamegraph_view.state.freeze = true;
+            self.replace_flamegraph(flamegraph);
+            self.set_transient_message(&format!(
+                "Showing snapshot of {}; press 'z' to go back to the live flamegraph",
+                snapshot.label
+            ));
+        } else {
+            self.flamegraph_input = FlameGraphInput::File(snapshot.path.display().to_string());
+            self.replace_flamegraph(flamegraph);
+            self.set_transient_message(&format!("Showing snapshot of {}", snapshot.label));
+        }
+    }
+
+    pub fn open_process_selector(&mut self) {
+        let processes = self.flamegraph_view.flamegraph.processes();
+        if processes.is_empty() {
+            self.set_transient_message(
+                "No process frames; sample with py-spy --subprocesses, or type ':groupby process'",
+            );
+            return;
+        }
+        self.process_selector = Some(ProcessSelector {
+            processes,
+            selected: 0,
+        });
+    }
+
+    /// Zoom into the process selected in the process selector. Live updates keep the zoom on it.
+    pub fn zoom_to_selected_process(&mut self) {
+        let Some(process) = self
+            .process_selector
+            .take()
+            .and_then(|selector| selector.selected_process().cloned())
+        else {
+            return;
+        };
+        match self
+            .flamegraph_view
+            .flamegraph
+            .get_stack_id_by_full_name(&process.full_name)
+        {
+            Some(stack_id) => self.flamegraph_view.with_history(|view| {
+                view.select_id(&stack_id);
+                view.set_zoom_for_id(stack_id);
+            }),
+            None => {
+                let text = format!("{} is no longer in the flamegraph", process.name);
+                self.set_transient_message(&text);
+            }
+        }
+    }
+
+    /// Zoom into the heaviest thread frame whose name fuzzy matches the pattern, e.g. "work" for
+    /// "thread (0x7f3a): worker-1".
+    pub fn focus_thread(&mut self, pattern: &str) {
+        let threads = self.flamegraph_view.flamegraph.thread_frames();
+        let zoomed = self.flamegraph_view.zoom_to_heaviest_matching(|name| {
+            name.starts_with("thread (") && fuzzy_match(pattern, name)
+        });
+        if threads.is_empty() {
+            self.set_transient_message("No thread frames; sample with py-spy --threads");
+        } else if !zoomed {
+            self.set_transient_message(&format!("No thread matching \"{}\"", pattern));
+        }
+    }
+
+    /// Swap in a new flamegraph for the same input, keeping the current view state.
+    fn replace_flamegraph(&mut self, flamegraph: FlameGraph) {
+        // The callers view is a snapshot, keep updating the view underneath it
+        let view = match self.callers_of.as_mut() {
+            Some((_, view)) => view,
+            None => &mut self.flamegraph_view,
+        };
+        let merge_activity = !self.split_activity && flamegraph.has_activity_frames();
+        let is_reshaped = self.hide_kernel_frames
+            || self.collapse_common_prefix
+            || self.group_by.is_some()
+            || self.aggregate_lines
+            || self.root_frame.is_some()
+            || merge_activity;
+        self.unprocessed = is_reshaped.then(|| flamegraph.clone());
+        let flamegraph = match merge_activity {
+            true => flamegraph.without_activity_frames(),
+            false => flamegraph,
+        };
+        let flamegraph = match self.hide_kernel_frames {
+            true => flamegraph.without_kernel_frames(),
+            false => flamegraph,
+        };
+        let flamegraph = match self.aggregate_lines {
+            true => flamegraph
+                .aggregate_lines(|function| self.expanded_lines.iter().any(|f| f == function))
+                .unwrap_or(flamegraph),
+            false => flamegraph,
+        };
+        let flamegraph = match &self.root_frame {
+            Some(re) => flamegraph
+                .reroot(|name| re.is_match(name))
+                .unwrap_or(flamegraph),
+            None => flamegraph,
+        };
+        let flamegraph = match self.group_by {
+            Some(group_by) => flamegraph.group_by(group_by).unwrap_or(flamegraph),
+            None => flamegraph,
+        };
+        let flamegraph = match self.collapse_common_prefix {
+            true => flamegraph.collapse_common_prefix().unwrap_or(flamegraph),
+            false => flamegraph,
+        };
+        view.replace_flamegraph(flamegraph);
+    }
+
+    /// The flamegraph before hiding kernel frames, grouping or collapsing the common prefix.
+    fn unprocessed_flamegraph(&self) -> &FlameGraph {
+        self.unprocessed
+            .as_ref()
+            .unwrap_or(&self.main_view().flamegraph)
+    }
+
+    /// Show the flamegraph again after changing how it is reshaped.
+    fn reshape_flamegraph(&mut self) {
+        self.replace_flamegraph(self.unprocessed_flamegraph().clone());
+    }
+
+    /// Hide the kernel frames of a perf profile, or show them again.
+    pub fn toggle_kernel_frames(&mut self) {
+        if self.hide_kernel_frames {
+            self.hide_kernel_frames = false;
+            self.reshape_flamegraph();
+            self.set_transient_message("Showing kernel frames");
+            return;
+        }
+        if !self.unprocessed_flamegraph().has_kernel_frames() {
+            self.set_transient_message("No kernel frames in the profile");
+            return;
+        }
+        self.hide_kernel_frames = true;
+        self.reshape_flamegraph();
+        self.set_transient_message("Hiding kernel frames, their samples count for their callers");
+    }
+
+    pub fn is_hiding_kernel_frames(&self) -> bool {
+        self.hide_kernel_frames
+    }
+
+    /// Merge the frames of each function at different lines into one, or show the lines again.
+    pub fn toggle_line_aggregation(&mut self) {
+        if self.aggregate_lines {
+            self.aggregate_lines = false;
+            self.expanded_lines.clear();
+            self.reshape_flamegraph();
+            self.set_transient_message("Showing frames per line");
+            return;
+        }
+        if !self.is_live()
+            && self
+                .unprocessed_flamegraph()
+                .aggregate_lines(|_| false)
+                .is_none()
+        {
+            self.set_transient_message("No line numbers in the frames");
+            return;
+        }
+        self.aggregate_lines = true;
+        self.reshape_flamegraph();
+        self.set_transient_message("Merged the frames of each function; press '+' to expand one");
+    }
+
+    pub fn is_aggregating_lines(&self) -> bool {
+        self.aggregate_lines
+    }
+
+    /// Show the selected function at line granularity while the others are merged, or merge it
+    /// again, selecting its heaviest frame afterwards.
+    pub fn toggle_selected_lines(&mut self) {
+        if !self.aggregate_lines {
+            self.set_transient_message("Press 'F' to merge the frames of each function first");
+            return;
+        }
+        let view = &self.flamegraph_view;
+        let Some(name) = view.flamegraph.get_stack_short_name(&view.state.selected) else {
+            return;
+        };
+        let function = without_line_number(name).unwrap_or_else(|| name.to_string());
+        let expanded = match self.expanded_lines.iter().position(|f| *f == function) {
+            Some(index) => {
+                self.expanded_lines.remove(index);
+                false
+            }
+            None if source_location(&function).is_some() => {
+                self.expanded_lines.push(function.clone());
+                true
+            }
+            None => {
+                self.set_transient_message("The selected frame has no line numbers");
+                return;
+            }
+        };
+        self.reshape_flamegraph();
+        let heaviest = self
+            .flamegraph_view
+            .flamegraph
+            .get_heaviest_stack_matching(|name| {
+                without_line_number(name).unwrap_or_else(|| name.to_string()) == function
+            });
+        if let Some(stack_id) = heaviest {
+            self.flamegraph_view.select_id(&stack_id);
+            self.flamegraph_view.scroll_to_selected();
+        }
+        let text = match expanded {
+            true => format!("Showing {} per line", function),
+            false => format!("Merged the lines of {}", function),
+        };
+        self.set_transient_message(&text);
+    }
+
+    /// Show the samples of active and idle threads apart, as "[on-CPU]" and "[waiting]" halves of
+    /// the flamegraph, or merge them again.
+    pub fn toggle_activity_split(&mut self) {
+        if self.split_activity {
+            self.split_activity = false;
+            self.reshape_flamegraph();
+            self.set_transient_message("Merged the on-CPU and waiting samples");
+            return;
+        }
+        if !self.unprocessed_flamegraph().has_activity_frames() {
+            self.set_transient_message(
+                "No idle samples to split; sample with --py-spy-args \"--idle\" and py-spy built in",
+            );
+            return;
+        }
+        self.split_activity = true;
+        self.reshape_flamegraph();
+        self.set_transient_message("Split the samples into on-CPU and waiting");
+    }
+
+    pub fn is_splitting_activity(&self) -> bool {
+        self.split_activity
+    }
+
+    pub fn has_activity_frames(&self) -> bool {
+        self.unprocessed_flamegraph().has_activity_frames()
+    }
+
+    /// Collapse the frames all stacks start with, like `_start;__libc_start_main;main`, into one
+    /// to make room for the rest, or expand them again.
+    pub fn toggle_common_prefix(&mut self) {
+        if self.collapse_common_prefix {
+            self.collapse_common_prefix = false;
+            self.reshape_flamegraph();
+            self.set_transient_message("Showing the frames common to all stacks");
+            return;
+        }
+        // Live profiles may have a common prefix later on
+        if !self.is_live() && self.main_view().flamegraph.common_prefix().len() < 2 {
+            self.set_transient_message("Fewer than two frames are common to all stacks");
+            return;
+        }
+        self.collapse_common_prefix = true;
+        self.reshape_flamegraph();
+        self.set_transient_message("Collapsed the frames common to all stacks into one");
+    }
+
+    pub fn is_collapsing_common_prefix(&self) -> bool {
+        self.collapse_common_prefix
+    }
+
+    /// Regroup the stacks under their process, thread or first application frame, or stop
+    /// grouping them with `None`.
+    pub fn set_group_by(&mut self, group_by: Option<GroupBy>) {
+        let Some(group_by) = group_by else {
+            self.group_by = None;
+            self.reshape_flamegraph();
+            self.set_transient_message("Stopped grouping stacks");
+            return;
+        };
+        let name = clap::ValueEnum::to_possible_value(&group_by)
+            .map_or(String::new(), |value| value.get_name().to_string());
+        if !self.is_live() && self.unprocessed_flamegraph().group_by(group_by).is_none() {
+            self.set_transient_message(&format!("No {} frames in the profile", name));
+            return;
+        }
+        self.group_by = Some(group_by);
+        self.reshape_flamegraph();
+        self.set_transient_message(&format!("Grouped stacks by {}", name));
+    }
+
+    pub fn group_by(&self) -> Option<GroupBy> {
+        self.group_by
+    }
+
+    /// Switch between showing all samples of a live profile and only the latest window of them,
+    /// compared with the window before.
+    pub fn toggle_live_delta(&mut self) {
+        if self.live_delta.take().is_some() {
+            self.set_transient_message("Showing all samples from the next update");
+            return;
+        }
+        // The sampler sends all samples so far, while pushed profiles are windows already
+        let cumulative = matches!(self.flamegraph_input, FlameGraphInput::Pid(_, _));
+        self.live_delta = Some(LiveDelta::new(cumulative));
+        self.show_baseline = false;
+        self.show_gil = false;
+        let (hotter, cooler) = self.config.palette.change_colors();
+        self.set_transient_message(&format!(
+            "Showing the latest interval from the next update, {} where hotter and {} where cooler than the one before",
+            hotter, cooler
+        ));
+    }
+
+    pub fn is_live_delta(&self) -> bool {
+        self.live_delta.is_some()
+    }
+
+    /// Switch to other colors, e.g. for a light terminal background.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.config.palette = palette;
+        self.set_transient_message(&format!("Using the {} palette", palette.name()));
+    }
+
+    /// Compare with the baseline, in the delta column of the table and by coloring frames with B.
+    pub fn set_baseline(&mut self, baseline: Baseline) {
+        let baseline = Arc::new(baseline);
+        self.flamegraph_view
+            .flamegraph
+            .set_baseline(Some(baseline.clone()));
+        self.baseline = Some(baseline);
+    }
+
+    /// Switch coloring frames by how their share of all samples changed since the baseline.
+    pub fn toggle_baseline(&mut self) {
+        let Some(baseline) = &self.baseline else {
+            self.set_transient_message("No baseline to compare with; start with --baseline <file>");
+            return;
+        };
+        self.show_baseline = !self.show_baseline;
+        if !self.show_baseline {
+            return;
+        }
+        let (grown, shrunk) = self.config.palette.change_colors();
+        let text = format!(
+            "Comparing with {}, {} where grown and {} where shrunk",
+            baseline.name, grown, shrunk
+        );
+        // Compare all samples so far, not only the latest interval
+        self.live_delta = None;
+        self.show_gil = false;
+        self.set_transient_message(&text);
+    }
+
+    /// Switch coloring frames by the share of their samples holding the GIL, green (or blue) where
+    /// always held, with all samples so far shown.
+    pub fn toggle_gil_overlay(&mut self) {
+        if self.show_gil {
+            self.show_gil = false;
+            return;
+        }
+        if self.gil_flamegraph.is_none() {
+            self.set_transient_message(
+                "Whether samples held the GIL is only known when sampling with py-spy built in",
+            );
+            return;
+        }
+        self.show_gil = true;
+        self.show_baseline = false;
+        // The GIL samples are all samples so far, not only the latest interval
+        self.live_delta = None;
+        self.set_transient_message(&format!(
+            "Coloring frames {} where they held the GIL",
+            self.config.palette.gil_color()
+        ));
+    }
+
+    pub fn is_showing_gil(&self) -> bool {
+        self.show_gil && self.gil_flamegraph.is_some()
+    }
+
+    /// Samples of a stack whose thread held the GIL and all samples, if coloring by them.
+    pub fn gil_held_of(&self, stack: &StackInfo) -> Option<(u64, u64)> {
+        // The callers view is built from other stacks
+        if self.callers_of.is_some() || !self.show_gil {
+            return None;
+        }
+        let full_name = self.flamegraph().get_stack_full_name_from_info(stack);
+        let held = self
+            .gil_flamegraph
+            .as_ref()?
+            .get_stack_by_full_name(full_name)
+            .map_or(0, |stack| stack.total_count);
+        Some((held, stack.total_count))
+    }
+
+    pub fn is_showing_baseline(&self) -> bool {
+        self.show_baseline && self.baseline.is_some()
+    }
+
+    /// Total count of a stack in the baseline, scaled to the samples shown, and in the flamegraph
+    /// shown, if comparing them.
+    pub fn baseline_of(&self, stack: &StackInfo) -> Option<(u64, u64)> {
+        // The callers view is built from other stacks
+        if self.callers_of.is_some() || !self.show_baseline {
+            return None;
+        }
+        let full_name = self.flamegraph().get_stack_full_name_from_info(stack);
+        let baseline = self.baseline.as_ref()?;
+        let total_count = self.flamegraph().total_count();
+        Some((
+            baseline.scaled_count(full_name, total_count),
+            stack.total_count,
+        ))
+    }
+
+    /// Total count of a stack in the previous window and in the one shown, if comparing them.
+    pub fn live_delta_of(&self, stack: &StackInfo) -> Option<(u64, u64)> {
+        // The callers view is built from other stacks
+        if self.callers_of.is_some() {
+            return None;
+        }
+        let full_name = self.flamegraph().get_stack_full_name_from_info(stack);
+        let previous = self.live_delta.as_ref()?.previous_count(full_name)?;
+        Some((previous, stack.total_count))
+    }
+
+    fn get_skipped_lines_warning(flamegraph: &FlameGraph) -> Option<String> {
+        match flamegraph.num_skipped_lines() {
+            0 => None,
+            1 => Some("Skipped 1 line that is not a valid folded stack".to_string()),
+            n => Some(format!(
+                "Skipped {} lines that are not valid folded stacks",
+                n
+            )),
+        }
+    }
+
+    /// Re-read the profile from a file, e.g. after it has been regenerated.
+    pub fn reload(&mut self, filename: &str, stack_filter: &StackFilter) {
+        let content = match std::fs::read_to_string(filename) {
+            Ok(content) => content,
+            Err(e) => {
+                self.set_transient_message(&format!("Could not reload {}: {}", filename, e));
+                return;
+            }
+        };
+        let tic = std::time::Instant::now();
+        let sorted = self.main_view().flamegraph.is_sorted();
+        let metric = self.main_view().flamegraph.metric_index();
+        let flamegraph = FlameGraph::from_string_with_metric(
+            stack_filter.apply(to_folded(content)),
+            sorted,
+            metric,
+        );
+        self.add_elapsed("flamegraph", tic.elapsed());
+        let warning = Self::get_skipped_lines_warning(&flamegraph);
+        self.replace_flamegraph(flamegraph);
+        tracing::info!(filename, "Reloaded profile");
+        self.notify_if_slow(&format!("Reloaded {}", filename));
+        self.set_transient_message(&format!("Reloaded {}", filename));
+        if let Some(warning) = warning {
+            self.set_transient_message(&warning);
+        }
+    }
+
+    /// Drive the counts by another value column of a profile with several, cycling through them
+    /// without an index.
+    pub fn switch_metric(&mut self, index: Option<usize>) {
+        let flamegraph = self.unprocessed_flamegraph();
+        let num_metrics = flamegraph.metrics().len();
+        if num_metrics < 2 {
+            self.set_transient_message("The profile has a single value column");
+            return;
+        }
+        if self.root_frame.is_some() {
+            // The rerooted flamegraph only has the counts of the current metric
+            self.set_transient_message("Cannot switch metrics with a root frame set by :root");
+            return;
+        }
+        if self.live_delta.is_some() {
+            // Windows only have the counts of the current metric too
+            self.set_transient_message("Cannot switch metrics while showing the latest interval");
+            return;
         }
+        let index = index.unwrap_or((flamegraph.metric_index() + 1) % num_metrics);
+        let flamegraph = FlameGraph::from_string_with_metric(
+            flamegraph.data().to_string(),
+            flamegraph.is_sorted(),
+            index,
+        );
+        let metric = flamegraph.metric().unwrap().name.clone();
+        self.replace_flamegraph(flamegraph);
+        self.set_transient_message(&format!("Showing {}", metric));
     }
 
     /// Set running to false to quit the application.
@@ -172,6 +1084,103 @@ impl App {
         self.running = false;
     }
 
+    /// Queue a desktop notification, if enabled in the config file.
+    pub fn notify(&mut self, text: &str) {
+        if self.config.notifications != NotificationMode::Off {
+            self.notifications.push(text.to_string());
+        }
+    }
+
+    /// Notify that the profile finished loading, if it took long enough for the user to have
+    /// switched to something else in the meantime.
+    pub fn notify_if_slow(&mut self, text: &str) {
+        let elapsed = self.elapsed.get("flamegraph").copied().unwrap_or_default();
+        if elapsed >= self.config.notify_after() {
+            self.notify(&format!("{} in {:.1}s", text, elapsed.as_secs_f64()));
+        }
+    }
+
+    /// Title for the terminal window, naming what is being viewed.
+    pub fn terminal_title(&self) -> String {
+        let input = match &self.flamegraph_input {
+            FlameGraphInput::File(path) => Path::new(path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.to_string()),
+            FlameGraphInput::Pid(pid, Some(info)) => format!("pid {} [{}]", pid, info),
+            FlameGraphInput::Pid(pid, None) => format!("pid {}", pid),
+            FlameGraphInput::Listen(address, _) => format!("listening on {}", address),
+        };
+        format!("flamelens - {}", input)
+    }
+
+    /// Suspend to the shell like `Ctrl-Z` in other terminal programs.
+    pub fn suspend(&mut self) {
+        self.suspend_requested = true;
+    }
+
+    /// Select the stack of a random sample, to get a feel for what the samples look like beyond
+    /// the aggregates.
+    pub fn select_random_sample(&mut self) {
+        let Some(leaf) = self.flamegraph_view.to_random_sample() else {
+            self.set_transient_message("No samples to pick from");
+            return;
+        };
+        let flamegraph = self.flamegraph();
+        let stack = flamegraph.get_stack(&leaf).unwrap();
+        let message = format!(
+            "Random sample ending in {} ({:.2}% of samples end there), R for another",
+            flamegraph.get_stack_short_name_from_info(stack),
+            100.0 * stack.self_count as f64 / flamegraph.total_count().max(1) as f64,
+        );
+        self.set_transient_message(&message);
+    }
+
+    /// Profile file being viewed, if any.
+    pub fn input_path(&self) -> Option<&Path> {
+        match &self.flamegraph_input {
+            FlameGraphInput::File(path) => Some(Path::new(path)),
+            _ => None,
+        }
+    }
+
+    /// Open the source file of the selected frame at its line in `$VISUAL` or `$EDITOR`.
+    pub fn open_selected_in_editor(&mut self) {
+        let Some(info) = self
+            .flamegraph_view
+            .get_selected_stack()
+            .and_then(|stack| stack.frame_info.as_deref())
+        else {
+            self.set_transient_message("No source location for the selected frame");
+            return;
+        };
+        let Some(file) = info.file.clone() else {
+            self.set_transient_message("No source location for the selected frame");
+            return;
+        };
+        let line = info.line;
+        let Some(path) = resolve_source_path(&file, self.input_path()) else {
+            self.set_transient_message(&format!("Source file not found: {}", file));
+            return;
+        };
+        let Some(editor) = user_editor() else {
+            self.set_transient_message(&format!(
+                "Set $EDITOR to open {}{}",
+                path.display(),
+                line.map(|line| format!(":{}", line)).unwrap_or_default()
+            ));
+            return;
+        };
+        self.editor_requested = Some(editor_command(&editor, &path, line));
+    }
+
+    pub fn is_live(&self) -> bool {
+        matches!(
+            self.flamegraph_input,
+            FlameGraphInput::Pid(_, _) | FlameGraphInput::Listen(_, _)
+        )
+    }
+
     pub fn flamegraph(&self) -> &FlameGraph {
         &self.flamegraph_view.flamegraph
     }
@@ -180,7 +1189,6 @@ impl App {
         &self.flamegraph_view.state
     }
 
-    #[cfg(feature = "python")]
     pub fn sampler_state(&self) -> Option<SamplerState> {
         self.sampler_state
             .as_ref()
@@ -188,6 +1196,7 @@ impl App {
     }
 
     pub fn add_elapsed(&mut self, name: &str, elapsed: Duration) {
+        tracing::trace!(name, ?elapsed, "Timing");
         self.elapsed.insert(name.to_string(), elapsed);
     }
 
@@ -213,27 +1222,465 @@ impl App {
         if let Some(short_name) = short_name {
             self.set_manual_search_pattern(short_name.as_str(), false);
         }
-        self.flamegraph_view.state.toggle_view_kind();
+        self.flamegraph_view
+            .state
+            .set_view_kind(ViewKind::FlameGraph);
+    }
+
+    pub fn zoom_to_selected_row(&mut self) {
+        let short_name = self
+            .flamegraph_view
+            .get_selected_row_name()
+            .map(|s| s.to_string());
+        if let Some(short_name) = short_name {
+            self.flamegraph_view.zoom_to_heaviest(&short_name);
+        }
+    }
+
+    pub fn show_callers_of_selected(&mut self) {
+        if self.flamegraph_view.is_root_selected() {
+            return;
+        }
+        let short_name = self.flamegraph_view.get_selected_stack().map(|s| {
+            self.flamegraph()
+                .get_stack_short_name_from_info(s)
+                .to_string()
+        });
+        if let Some(short_name) = short_name {
+            self.show_callers(&short_name);
+        }
+    }
+
+    pub fn show_callers_of_selected_row(&mut self) {
+        let short_name = self
+            .flamegraph_view
+            .get_selected_row_name()
+            .map(|s| s.to_string());
+        if let Some(short_name) = short_name {
+            self.show_callers(&short_name);
+        }
+    }
+
+    /// Copy the name and counts of the function in the selected row.
+    pub fn copy_selected_row(&mut self) {
+        let Some(short_name) = self
+            .flamegraph_view
+            .get_selected_row_name()
+            .map(|s| s.to_string())
+        else {
+            return;
+        };
+        let flamegraph = self.flamegraph();
+        let Some(count) = flamegraph.get_function_count(&short_name) else {
+            return;
+        };
+        let percent = |count: u64| 100.0 * count as f64 / flamegraph.total_count().max(1) as f64;
+        let text = format!(
+            "{}: total {} ({:.2}%), own {} ({:.2}%)",
+            short_name,
+            flamegraph.format_count(count.total),
+            percent(count.total),
+            flamegraph.format_count(count.own),
+            percent(count.own)
+        );
+        self.clipboard = Some(text);
+        self.set_transient_message(&format!("Copied {} to the clipboard", short_name));
+    }
+
+    /// Copy the rows shown in the table as tab separated values.
+    pub fn copy_table(&mut self) {
+        let flamegraph = self.flamegraph();
+        let text = flamegraph.ordered_stacks.to_tsv(flamegraph.total_count());
+        let num_rows = flamegraph.ordered_stacks.num_rows;
+        self.clipboard = Some(text);
+        self.set_transient_message(&format!("Copied {} rows to the clipboard", num_rows));
+    }
+
+    /// Switch to a flamegraph of the callers of a function.
+    /// Leave out frames narrower than `min_width` from the flamegraph, including the one under the
+    /// callers view.
+    pub fn set_min_width(&mut self, min_width: MinWidth) {
+        self.flamegraph_view.set_min_width(min_width);
+        if let Some((_, view)) = self.callers_of.as_mut() {
+            view.set_min_width(min_width);
+        }
+        self.set_transient_message(&format!("Hiding frames narrower than {}", min_width));
+    }
+
+    pub fn show_callers(&mut self, short_name: &str) {
+        let mut callers = FlameGraph::from_string(
+            self.main_view().flamegraph.get_callers_folded(short_name),
+            false,
+        );
+        callers.inherit_metric(&self.main_view().flamegraph);
+        let mut callers_view = FlameGraphView::new(callers);
+        callers_view.state.min_width = self.flamegraph_view.state.min_width;
+        // Select the function itself which is the only frame at the first level
+        callers_view.to_child_stack();
+        let view = std::mem::replace(&mut self.flamegraph_view, callers_view);
+        // Only keep the outermost view if already showing callers
+        let view = match self.callers_of.take() {
+            Some((_, outermost_view)) => outermost_view,
+            None => view,
+        };
+        self.callers_of = Some((short_name.to_string(), view));
+    }
+
+    /// Go back from the callers view. Returns false if not showing callers.
+    pub fn close_callers(&mut self) -> bool {
+        match self.callers_of.take() {
+            Some((_, view)) => {
+                self.flamegraph_view = view;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The view of the whole profile, even if currently showing the callers of a function.
+    pub fn main_view(&self) -> &FlameGraphView {
+        match &self.callers_of {
+            Some((_, view)) => view,
+            None => &self.flamegraph_view,
+        }
     }
 
     pub fn set_manual_search_pattern(&mut self, pattern: &str, is_regex: bool) {
         match SearchPattern::new(pattern, is_regex, true) {
-            Ok(p) => self.flamegraph_view.set_search_pattern(p),
+            Ok(p) => self
+                .flamegraph_view
+                .with_history(|view| view.set_search_pattern(p)),
             Err(_) => {
                 self.set_transient_message(&format!("Invalid regex: {}", pattern));
             }
         }
     }
 
+    /// Run a command entered in the ":" prompt.
+    pub fn run_command(&mut self, command: &str) {
+        let (name, arg) = split_command(command);
+        match (name, arg) {
+            ("w" | "write", Some(path)) => self.save_session(path),
+            ("w" | "write", None) => self.set_transient_message("Usage: :w <session file>"),
+            ("root", Some(pattern)) => match regex::Regex::new(pattern) {
+                Ok(re) => {
+                    if !self.set_root_frame(re) {
+                        self.set_transient_message(&format!("No frame matching \"{}\"", pattern));
+                    }
+                }
+                Err(_) => self.set_transient_message(&format!("Invalid regex: {}", pattern)),
+            },
+            ("root", None) => self.clear_root_frame(),
+            ("export", Some(arg)) => match split_command(arg) {
+                (format, Some(path)) => {
+                    match <ExportFormat as clap::ValueEnum>::from_str(format, true) {
+                        Ok(format) => self.export(format, path),
+                        Err(_) => self.set_transient_message("Usage: :export <html|png> <file>"),
+                    }
+                }
+                _ => self.set_transient_message("Usage: :export <html|png> <file>"),
+            },
+            ("subtree", Some(path)) => self.save_subtree(path),
+            ("subtree", None) => self.set_transient_message("Usage: :subtree <file>"),
+            ("export", None) => self.set_transient_message("Usage: :export <html|png> <file>"),
+            ("hints", None) => self.hints = self.hints.next(),
+            ("zen", None) => self.toggle_zen(),
+            ("palette", None) => self.set_palette(self.config.palette.next()),
+            ("palette", Some(palette)) => {
+                match <Palette as clap::ValueEnum>::from_str(palette, true) {
+                    Ok(palette) => self.set_palette(palette),
+                    Err(_) => self.set_transient_message("Usage: :palette <dark|light|colorblind>"),
+                }
+            }
+            ("metric", None) => self.switch_metric(None),
+            ("metric", Some(metric)) => {
+                let metrics = self.main_view().flamegraph.metrics();
+                let index = metrics.iter().position(|m| m.name == metric).or_else(|| {
+                    metric
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|i| (1..=metrics.len()).contains(i))
+                        .map(|i| i - 1)
+                });
+                match index {
+                    Some(index) => self.switch_metric(Some(index)),
+                    None => self.set_transient_message(&format!("Unknown metric: {}", metric)),
+                }
+            }
+            ("messages", None) => self.messages.toggle_log(),
+            ("snapshots", None) => self.open_snapshot_browser(),
+            ("processes", None) => self.open_process_selector(),
+            ("thread", Some(pattern)) => self.focus_thread(pattern),
+            ("thread", None) => self.set_transient_message("Usage: :thread <name>"),
+            ("groupby", Some("off")) => self.set_group_by(None),
+            ("groupby", Some(group_by)) => {
+                match <GroupBy as clap::ValueEnum>::from_str(group_by, true) {
+                    Ok(group_by) => self.set_group_by(Some(group_by)),
+                    Err(_) => {
+                        self.set_transient_message("Usage: :groupby <process|thread|app|off>")
+                    }
+                }
+            }
+            ("groupby", None) => {
+                self.set_transient_message("Usage: :groupby <process|thread|app|off>")
+            }
+            ("minwidth", Some(min_width)) => match min_width.parse() {
+                Ok(min_width) => self.set_min_width(min_width),
+                Err(e) => self.set_transient_message(&format!("Invalid min width, {}", e)),
+            },
+            ("minwidth", None) => self.set_transient_message(&format!(
+                "Hiding frames narrower than {}; usage: :minwidth <cells|percent%>",
+                self.flamegraph_state().min_width
+            )),
+            ("hints", Some(mode)) => match <HintsMode as clap::ValueEnum>::from_str(mode, true) {
+                Ok(hints) => self.hints = hints,
+                Err(_) => self.set_transient_message("Usage: :hints [full|essential|off]"),
+            },
+            _ => self.set_transient_message(&format!("Unknown command: {}", name)),
+        }
+    }
+
+    /// Run a command received on the control socket. Besides the ":" commands, it accepts the
+    /// actions of the most common keys. Returns the message shown by the command, or "ok".
+    pub fn run_control_command(&mut self, command: &str) -> String {
+        tracing::info!(command, "Control command");
+        let first_new_message = self.messages.next_id();
+        match split_command(command) {
+            ("search", Some(pattern)) => self.set_manual_search_pattern(pattern, true),
+            ("search", None) => self.flamegraph_view.unset_manual_search_pattern(),
+            ("select", Some(pattern)) => match regex::Regex::new(pattern) {
+                Ok(re) => {
+                    if !self.flamegraph_view.select_heaviest_matching(&re) {
+                        self.set_transient_message(&format!("No frame matching \"{}\"", pattern));
+                    }
+                }
+                Err(_) => self.set_transient_message(&format!("Invalid regex: {}", pattern)),
+            },
+            ("zoom", None) => self.flamegraph_view.set_zoom(),
+            ("zoom", Some(pattern)) => match regex::Regex::new(pattern) {
+                Ok(re) => {
+                    if !self
+                        .flamegraph_view
+                        .zoom_to_heaviest_matching(|name| re.is_match(name))
+                    {
+                        self.set_transient_message(&format!("No frame matching \"{}\"", pattern));
+                    }
+                }
+                Err(_) => self.set_transient_message(&format!("Invalid regex: {}", pattern)),
+            },
+            ("unzoom", None) => self.flamegraph_view.unset_zoom(),
+            ("reset", None) => self.flamegraph_view.reset(),
+            ("quit", None) => self.quit(),
+            _ => self.run_command(command),
+        }
+        self.messages
+            .last_since(first_new_message)
+            .unwrap_or("ok")
+            .to_string()
+    }
+
+    /// Re-root the flamegraph at the heaviest frame matching the regex. Returns false if no frame
+    /// matches.
+    pub fn set_root_frame(&mut self, re: regex::Regex) -> bool {
+        if self
+            .unprocessed_flamegraph()
+            .reroot(|name| re.is_match(name))
+            .is_none()
+        {
+            return false;
+        }
+        self.root_frame = Some(re);
+        self.reshape_flamegraph();
+        true
+    }
+
+    /// Show the whole flamegraph again after `:root <regex>`.
+    pub fn clear_root_frame(&mut self) {
+        if self.root_frame.take().is_none() {
+            self.set_transient_message("No root frame set, use :root <regex>");
+            return;
+        }
+        self.reshape_flamegraph();
+        self.set_transient_message("Showing all frames");
+    }
+
+    pub fn to_session(&self) -> Session {
+        let flamegraph = &self.main_view().flamegraph;
+        let input = match &self.flamegraph_input {
+            FlameGraphInput::File(path) if std::path::Path::new(path).is_file() => {
+                let path = std::fs::canonicalize(path)
+                    .map(|p| p.to_string_lossy().to_string())
+                    .unwrap_or_else(|_| path.clone());
+                SessionInput::File(path)
+            }
+            // The root frame is applied again when loading
+            _ => SessionInput::Data(self.unprocessed_flamegraph().data().to_string()),
+        };
+        Session {
+            input,
+            sorted: flamegraph.is_sorted(),
+            state: self.main_view().save_state(),
+            root_frame: self.root_frame.as_ref().map(|re| re.as_str().to_string()),
+        }
+    }
+
+    pub fn save_session(&mut self, path: &str) {
+        match self.to_session().save(std::path::Path::new(path)) {
+            Ok(_) => self.set_transient_message(&format!("Saved session to {}", path)),
+            Err(e) => self.set_transient_message(&format!("Could not save session: {}", e)),
+        }
+    }
+
+    /// Write the subtree of the selected frame to a folded file, with the selected frame as the
+    /// only root frame and the counts unchanged, e.g. to look into one part of a huge profile on
+    /// its own.
+    pub fn save_subtree(&mut self, path: &str) {
+        let selected = self.flamegraph_state().selected;
+        let flamegraph = self.flamegraph();
+        let content = flamegraph.to_folded(&selected, |name| name.to_string());
+        let name = flamegraph
+            .get_stack_short_name(&selected)
+            .unwrap_or("all")
+            .to_string();
+        match std::fs::write(path, content) {
+            Ok(_) => self.set_transient_message(&format!("Saved {} and below to {}", name, path)),
+            Err(e) => self.set_transient_message(&format!("Could not save subtree: {}", e)),
+        }
+    }
+
+    /// Open the ":" prompt to save the subtree of the selected frame, suggesting a file next to
+    /// the input file.
+    pub fn prompt_save_subtree(&mut self) {
+        let path = match &self.flamegraph_input {
+            FlameGraphInput::File(path) if Path::new(path).is_file() => {
+                let path = Path::new(path);
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                path.with_file_name(format!("{}-subtree.txt", stem))
+                    .to_string_lossy()
+                    .to_string()
+            }
+            _ => "subtree.txt".to_string(),
+        };
+        self.input_buffer = Some(InputBuffer::with_value(
+            InputKind::Command,
+            &format!("subtree {}", path),
+        ));
+    }
+
+    /// Export the flamegraph being viewed, e.g. to attach it to a pull request.
+    pub fn export(&mut self, format: ExportFormat, path: &str) {
+        match export(self.flamegraph(), format, std::path::Path::new(path)) {
+            Ok(_) => self.set_transient_message(&format!("Exported to {}", path)),
+            Err(e) => self.set_transient_message(&format!("Could not export: {}", e)),
+        }
+    }
+
+    pub fn get_selected_note(&self) -> Option<&str> {
+        let full_name = self
+            .flamegraph()
+            .get_stack_full_name(&self.flamegraph_state().selected)?;
+        self.notes.get(full_name)
+    }
+
+    /// Open the input prompt to edit the note of the selected frame.
+    pub fn edit_selected_note(&mut self) {
+        if self.flamegraph_view.is_root_selected() {
+            self.set_transient_message("Select a frame to attach a note");
+            return;
+        }
+        if self.callers_of.is_some() {
+            self.set_transient_message("Notes are not available in the callers view");
+            return;
+        }
+        let note = self.get_selected_note().unwrap_or_default().to_string();
+        self.input_buffer = Some(InputBuffer::with_value(InputKind::Note, &note));
+    }
+
+    pub fn set_selected_note(&mut self, note: &str) {
+        let Some(full_name) = self
+            .flamegraph()
+            .get_stack_full_name(&self.flamegraph_state().selected)
+            .map(|s| s.to_string())
+        else {
+            return;
+        };
+        self.notes.set(&full_name, note);
+        match &self.notes_path {
+            Some(path) => {
+                if let Err(e) = self.notes.save(path) {
+                    self.set_transient_message(&format!("Could not save notes: {}", e));
+                }
+            }
+            None => self.set_transient_message("Notes are not saved for non-file input"),
+        }
+    }
+
+    pub fn toggle_notes(&mut self) {
+        self.show_notes = !self.show_notes;
+    }
+
+    pub fn toggle_depth_histogram(&mut self) {
+        self.show_depth_histogram = !self.show_depth_histogram;
+    }
+
+    pub fn toggle_ruler(&mut self) {
+        self.show_ruler = !self.show_ruler;
+    }
+
+    pub fn toggle_zen(&mut self) {
+        self.zen = !self.zen;
+        if self.zen {
+            self.set_transient_message("Press X to show the header and status bars again");
+        }
+    }
+
+    pub fn toggle_own_time(&mut self) {
+        self.show_own_time = !self.show_own_time;
+        self.set_transient_message(if self.show_own_time {
+            "Underlining the own time of each frame"
+        } else {
+            "Own time no longer underlined"
+        });
+    }
+
+    pub fn go_to_level(&mut self, level: &str) {
+        match level.trim().parse::<usize>() {
+            Ok(level) => self.flamegraph_view.scroll_to_level(level),
+            Err(_) => self.set_transient_message(&format!("Invalid level: {}", level)),
+        }
+    }
+
+    pub fn undo(&mut self) {
+        if !self.flamegraph_view.undo() {
+            self.set_transient_message("Nothing to undo");
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if !self.flamegraph_view.redo() {
+            self.set_transient_message("Nothing to redo");
+        }
+    }
+
+    /// Show a message in the status bar until it expires or a key is pressed. Messages set in
+    /// quick succession are shown one after the other.
     pub fn set_transient_message(&mut self, message: &str) {
-        self.transient_message = Some(message.to_string());
+        self.messages.push(message);
     }
 
     pub fn clear_transient_message(&mut self) {
-        self.transient_message = None;
+        self.messages.dismiss_all();
     }
 
     pub fn toggle_debug(&mut self) {
         self.debug = !self.debug;
     }
 }
+
+/// Split a command into its name and the rest of the line as the argument.
+fn split_command(command: &str) -> (&str, Option<&str>) {
+    match command.trim().split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, Some(arg.trim())),
+        None => (command.trim(), None),
+    }
+}
@@ -0,0 +1,194 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Writes the samples of a live session to a directory every so often, each snapshot to a file
+/// named after the process and the time in UTC, to look back at a moment of the session later.
+#[derive(Debug)]
+pub struct SnapshotArchive {
+    dir: PathBuf,
+    interval: Duration,
+    pid: u64,
+    last_written: Option<Instant>,
+}
+
+impl SnapshotArchive {
+    pub fn new(dir: &Path, interval: Duration, pid: u64) -> io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            interval,
+            pid,
+            last_written: None,
+        })
+    }
+
+    /// Write the folded stacks sampled so far if the interval has passed since the last snapshot,
+    /// returning the file written.
+    pub fn write_if_due(&mut self, data: &str) -> io::Result<Option<PathBuf>> {
+        let now = Instant::now();
+        if self
+            .last_written
+            .is_some_and(|last| now.duration_since(last) < self.interval)
+        {
+            return Ok(None);
+        }
+        self.last_written = Some(now);
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = self
+            .dir
+            .join(format!("{}-{}.txt", self.pid, format_utc_compact(secs)));
+        std::fs::write(&path, data)?;
+        Ok(Some(path))
+    }
+}
+
+/// Snapshot found in an archive directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchivedSnapshot {
+    pub path: PathBuf,
+    /// When it was taken and of which process, or the file name if not named by the archive
+    pub label: String,
+}
+
+/// Popup listing the snapshots of an archive directory to load one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotBrowser {
+    pub snapshots: Vec<ArchivedSnapshot>,
+    pub selected: usize,
+}
+
+impl SnapshotBrowser {
+    pub fn move_selection(&mut self, delta: isize) {
+        self.selected = self
+            .selected
+            .saturating_add_signed(delta)
+            .min(self.snapshots.len().saturating_sub(1));
+    }
+
+    pub fn selected_snapshot(&self) -> Option<&ArchivedSnapshot> {
+        self.snapshots.get(self.selected)
+    }
+}
+
+/// Snapshots in the directory, the most recent first.
+pub fn list_snapshots(dir: &Path) -> io::Result<Vec<ArchivedSnapshot>> {
+    let mut snapshots = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "txt"))
+        .map(|path| {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            // The time in the name sorts in the order the snapshots were taken
+            let (time, label) = match parse_snapshot_stem(&stem) {
+                Some((pid, time)) => (Some(time.clone()), format!("{}, pid {}", time, pid)),
+                None => {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    (None, name.to_string())
+                }
+            };
+            (time, ArchivedSnapshot { path, label })
+        })
+        .collect::<Vec<_>>();
+    // Snapshots named by the archive first, then other files by name
+    snapshots.sort_by(|(a_time, a), (b_time, b)| match (a_time, b_time) {
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (Some(_), Some(_)) => b_time.cmp(a_time).then_with(|| b.path.cmp(&a.path)),
+        (None, None) => a.path.cmp(&b.path),
+    });
+    Ok(snapshots
+        .into_iter()
+        .map(|(_, snapshot)| snapshot)
+        .collect())
+}
+
+/// The pid and "2026-10-18 14:40:05 UTC" from a file stem like "4242-20261018-144005".
+fn parse_snapshot_stem(stem: &str) -> Option<(u64, String)> {
+    let (pid, time) = stem.split_once('-')?;
+    let (date, time) = time.split_once('-')?;
+    let is_digits = |s: &str, len: usize| s.len() == len && s.bytes().all(|b| b.is_ascii_digit());
+    let pid = pid.parse::<u64>().ok()?;
+    if !(is_digits(date, 8) && is_digits(time, 6)) {
+        return None;
+    }
+    let time = format!(
+        "{}-{}-{} {}:{}:{} UTC",
+        &date[..4],
+        &date[4..6],
+        &date[6..],
+        &time[..2],
+        &time[2..4],
+        &time[4..]
+    );
+    Some((pid, time))
+}
+
+/// Seconds since the epoch as "YYYYMMDD-HHMMSS" in UTC.
+fn format_utc_compact(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+    // Days to a civil date, after Howard Hinnant's days_from_civil inverse
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        (secs_of_day / 60) % 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_utc_compact() {
+        assert_eq!(format_utc_compact(0), "19700101-000000");
+        assert_eq!(format_utc_compact(1792334405), "20261018-144005");
+        assert_eq!(format_utc_compact(951782400 + 86399), "20000229-235959");
+    }
+
+    #[test]
+    fn test_snapshot_archive() {
+        let dir =
+            std::env::temp_dir().join(format!("flamelens-test-archive-{}", std::process::id()));
+        let mut archive = SnapshotArchive::new(&dir, Duration::from_secs(3600), 42).unwrap();
+        let path = archive.write_if_due("main;a 1\n").unwrap().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "main;a 1\n");
+        // Not due again within the interval
+        assert_eq!(archive.write_if_due("main;a 2\n").unwrap(), None);
+
+        std::fs::write(dir.join("42-20000229-235959.txt"), "main 1\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+        std::fs::write(dir.join("my-profile.txt"), "").unwrap();
+        std::fs::write(dir.join("other.json"), "").unwrap();
+        let snapshots = list_snapshots(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            snapshots.iter().map(|s| &s.path).collect::<Vec<_>>(),
+            vec![
+                &path,
+                &dir.join("42-20000229-235959.txt"),
+                &dir.join("my-profile.txt"),
+                &dir.join("notes.txt")
+            ]
+        );
+        assert_eq!(snapshots[1].label, "2000-02-29 23:59:59 UTC, pid 42");
+        assert_eq!(snapshots[2].label, "my-profile.txt");
+    }
+}
@@ -0,0 +1,201 @@
+use crate::flame::{FlameGraph, SearchPattern};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+impl Comparison {
+    fn holds(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Greater => value > threshold,
+            Comparison::GreaterOrEqual => value >= threshold,
+            Comparison::Less => value < threshold,
+            Comparison::LessOrEqual => value <= threshold,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+        }
+    }
+}
+
+/// Condition on the share of samples spent in frames matching a regex, e.g. "serialize>5%".
+#[derive(Debug, Clone)]
+pub struct Assertion {
+    pub pattern: SearchPattern,
+    pub comparison: Comparison,
+    /// Percentage of all samples
+    pub threshold: f64,
+}
+
+impl FromStr for Assertion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || {
+            format!(
+                "expected <regex><op><percent>%, e.g. \"serialize>5%\": {}",
+                s
+            )
+        };
+        // The regex may contain "<" or ">" itself, e.g. "<module>", so the comparison is the
+        // last one followed by a number
+        let (index, _) = s
+            .rmatch_indices(['<', '>'])
+            .find(|(index, _)| {
+                let rest = s[index + 1..].trim_start_matches('=');
+                parse_percent(rest).is_some()
+            })
+            .ok_or_else(usage)?;
+        let (pattern, rest) = s.split_at(index);
+        let (comparison, threshold) = match (rest.starts_with('>'), rest[1..].strip_prefix('=')) {
+            (true, Some(threshold)) => (Comparison::GreaterOrEqual, threshold),
+            (true, None) => (Comparison::Greater, &rest[1..]),
+            (false, Some(threshold)) => (Comparison::LessOrEqual, threshold),
+            (false, None) => (Comparison::Less, &rest[1..]),
+        };
+        if pattern.is_empty() {
+            return Err(usage());
+        }
+        let pattern = SearchPattern::new(pattern, true, true)
+            .map_err(|e| format!("invalid regex \"{}\": {}", pattern, e))?;
+        Ok(Self {
+            pattern,
+            comparison,
+            threshold: parse_percent(threshold).ok_or_else(usage)?,
+        })
+    }
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}%",
+            self.pattern.pattern,
+            self.comparison.symbol(),
+            self.threshold
+        )
+    }
+}
+
+fn parse_percent(s: &str) -> Option<f64> {
+    s.trim()
+        .trim_end_matches('%')
+        .parse::<f64>()
+        .ok()
+        .filter(|p| p.is_finite())
+}
+
+/// Outcome of an assertion on a profile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckResult {
+    /// Samples with at least one frame matching the regex
+    pub count: u64,
+    pub percent: f64,
+    pub failed: bool,
+}
+
+impl Assertion {
+    pub fn evaluate(&self, flamegraph: &mut FlameGraph) -> CheckResult {
+        flamegraph.set_hits(&self.pattern);
+        let count = flamegraph.hit_coverage_count().unwrap_or(0);
+        flamegraph.clear_hits();
+        let percent = 100.0 * count as f64 / flamegraph.total_count().max(1) as f64;
+        CheckResult {
+            count,
+            percent,
+            failed: self.comparison.holds(percent, self.threshold),
+        }
+    }
+}
+
+/// Evaluate the assertions, each one failing if it holds. Returns a summary to print, and whether
+/// all of them passed.
+pub fn check(flamegraph: &mut FlameGraph, assertions: &[Assertion]) -> (String, bool) {
+    let total_count = flamegraph.total_count();
+    let mut out = String::new();
+    let mut num_failed = 0;
+    for assertion in assertions {
+        let result = assertion.evaluate(flamegraph);
+        if result.failed {
+            num_failed += 1;
+        }
+        out += &format!(
+            "{:<4}  {}  (matched {:.2}%, {} of {} samples)\n",
+            if result.failed { "FAIL" } else { "ok" },
+            assertion,
+            result.percent,
+            result.count,
+            total_count
+        );
+    }
+    out += &format!("{} of {} checks failed\n", num_failed, assertions.len());
+    (out, num_failed == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_assertion() {
+        let assertion: Assertion = "serialize>5%".parse().unwrap();
+        assert_eq!(assertion.pattern.pattern, "serialize");
+        assert_eq!(assertion.comparison, Comparison::Greater);
+        assert_eq!(assertion.threshold, 5.0);
+
+        let assertion: Assertion = "<module>>=12.5%".parse().unwrap();
+        assert_eq!(assertion.pattern.pattern, "<module>");
+        assert_eq!(assertion.comparison, Comparison::GreaterOrEqual);
+        assert_eq!(assertion.threshold, 12.5);
+        assert_eq!(assertion.to_string(), "<module>>=12.5%");
+
+        let assertion: Assertion = "a|b<1".parse().unwrap();
+        assert_eq!(assertion.comparison, Comparison::Less);
+        assert_eq!(assertion.threshold, 1.0);
+
+        assert!("serialize".parse::<Assertion>().is_err());
+        assert!(">5%".parse::<Assertion>().is_err());
+        assert!("(>5%".parse::<Assertion>().is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let content = "main;a;serialize 3\nmain;serialize;serialize 1\nmain;b 6\n".to_string();
+        let mut fg = FlameGraph::from_string(content, false);
+        let assertions = ["serialize>30%", "serialize>50%", "^b$<=60%"]
+            .iter()
+            .map(|s| s.parse::<Assertion>().unwrap())
+            .collect::<Vec<_>>();
+        // Recursive frames are only counted once
+        assert_eq!(
+            assertions[0].evaluate(&mut fg),
+            CheckResult {
+                count: 4,
+                percent: 40.0,
+                failed: true
+            }
+        );
+        let (report, passed) = check(&mut fg, &assertions);
+        assert!(!passed);
+        assert_eq!(
+            report,
+            "FAIL  serialize>30%  (matched 40.00%, 4 of 10 samples)\n\
+             ok    serialize>50%  (matched 40.00%, 4 of 10 samples)\n\
+             FAIL  ^b$<=60%  (matched 60.00%, 6 of 10 samples)\n\
+             2 of 3 checks failed\n"
+        );
+    }
+}
@@ -0,0 +1,327 @@
+use crate::recent::config_dir;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const CONFIG_FILENAME: &str = "config.toml";
+
+/// How to send desktop notifications through the terminal.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationMode {
+    #[default]
+    Off,
+    /// OSC 9, e.g. iTerm2, WezTerm, kitty and Windows Terminal
+    Osc9,
+    /// OSC 777, e.g. foot, Ghostty and VTE based terminals like GNOME Terminal
+    Osc777,
+}
+
+/// Terminal graphics protocol to draw the flamegraph as an image with, finer than the cells allow.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphicsProtocol {
+    /// Text only
+    #[default]
+    Off,
+    /// Kitty graphics protocol, e.g. kitty, WezTerm and Ghostty
+    Kitty,
+    /// Sixel, e.g. foot, WezTerm and xterm
+    Sixel,
+}
+
+/// Colors of the flamegraph, for dark or light terminal backgrounds or without red and green.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Palette {
+    /// For dark terminal backgrounds
+    #[default]
+    Dark,
+    /// For light terminal backgrounds
+    Light,
+    /// Orange and blue instead of red and green, for deuteranopia and protanopia
+    Colorblind,
+}
+
+impl Palette {
+    pub fn next(self) -> Self {
+        match self {
+            Palette::Dark => Palette::Light,
+            Palette::Light => Palette::Colorblind,
+            Palette::Colorblind => Palette::Dark,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Palette::Dark => "dark",
+            Palette::Light => "light",
+            Palette::Colorblind => "colorblind",
+        }
+    }
+
+    /// Names of the colors of frames that grew and shrunk when comparing profiles.
+    pub fn change_colors(self) -> (&'static str, &'static str) {
+        match self {
+            Palette::Dark | Palette::Light => ("red", "blue"),
+            Palette::Colorblind => ("orange", "blue"),
+        }
+    }
+
+    /// Name of the color of frames always holding the GIL.
+    pub fn gil_color(self) -> &'static str {
+        match self {
+            Palette::Dark | Palette::Light => "green",
+            Palette::Colorblind => "blue",
+        }
+    }
+}
+
+/// Fixed color for the frames whose name matches a regex, e.g. to always show locks in purple.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "RawColorRule")]
+pub struct ColorRule {
+    pub pattern: Regex,
+    pub color: [u8; 3],
+}
+
+impl PartialEq for ColorRule {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern.as_str() == other.pattern.as_str() && self.color == other.color
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawColorRule {
+    pattern: String,
+    color: String,
+}
+
+impl TryFrom<RawColorRule> for ColorRule {
+    type Error = String;
+
+    fn try_from(rule: RawColorRule) -> Result<Self, Self::Error> {
+        let pattern = Regex::new(&rule.pattern)
+            .map_err(|e| format!("invalid regex \"{}\": {}", rule.pattern, e))?;
+        let color = parse_hex_color(&rule.color)
+            .ok_or_else(|| format!("expected a color like \"#8040c0\": {}", rule.color))?;
+        Ok(Self { pattern, color })
+    }
+}
+
+fn parse_hex_color(s: &str) -> Option<[u8; 3]> {
+    let hex = s.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Settings read from `config.toml` in the config directory. Missing settings keep their
+/// defaults.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Set the terminal title to what is being viewed
+    pub terminal_title: bool,
+    /// Notify when a slow load finishes, a profile is pushed or the live sampler stops
+    pub notifications: NotificationMode,
+    /// Only notify about loads taking at least this many seconds
+    pub notify_after_secs: f64,
+    /// URL that the name of the selected frame links to when it has a source location, see
+    /// [`crate::editor::source_link`]. Empty for no link.
+    pub link_template: String,
+    /// Changes the color of every frame, which is otherwise the same across machines and sessions
+    pub color_seed: u64,
+    /// Colors for the frames matching a regex, the first matching rule winning
+    pub color_rules: Vec<ColorRule>,
+    /// Text drawn in each frame, see [`expand_frame_label`]
+    pub frame_label: String,
+    /// Frame labels for profiles driven by the metric of the given name, e.g. "alloc_space"
+    pub frame_labels: HashMap<String, String>,
+    /// Rows of each level of the flamegraph, long frame names wrapping onto the rows below
+    pub row_height: u16,
+    /// Draw the flamegraph as an image with this protocol, over the one drawn with text
+    pub graphics: GraphicsProtocol,
+    /// Colors of the flamegraph
+    pub palette: Palette,
+    /// Lines the "Selected" bar wraps over at most, scrolling through the rest over time. 0 for
+    /// no limit
+    pub selected_max_lines: u16,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            terminal_title: true,
+            notifications: NotificationMode::Off,
+            notify_after_secs: 5.0,
+            link_template: "file://{path}".to_string(),
+            color_seed: 0,
+            color_rules: vec![],
+            frame_label: "{name}".to_string(),
+            frame_labels: HashMap::new(),
+            row_height: 1,
+            graphics: GraphicsProtocol::Off,
+            palette: Palette::Dark,
+            selected_max_lines: 3,
+        }
+    }
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(CONFIG_FILENAME))
+    }
+
+    /// Load from the default location. A missing file gives the default settings.
+    pub fn load() -> io::Result<Self> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load_from(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        toml::from_str(&content).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e.message()),
+            )
+        })
+    }
+
+    /// Rows of each level, at least one.
+    pub fn row_height(&self) -> u16 {
+        self.row_height.max(1)
+    }
+
+    pub fn notify_after(&self) -> Duration {
+        Duration::from_secs_f64(self.notify_after_secs.max(0.0))
+    }
+
+    /// Frame label template for a profile driven by the named metric.
+    pub fn frame_label(&self, metric: Option<&str>) -> &str {
+        metric
+            .and_then(|metric| self.frame_labels.get(metric))
+            .unwrap_or(&self.frame_label)
+    }
+}
+
+/// Fill in a frame label template, where `{name}` is the name of the frame, `{total}` and `{own}`
+/// its count with and without its callees and `{pct}` its share of the whole profile. Returns the
+/// text before and after the first `{name}`, which is left for the caller to draw with search
+/// matches highlighted, or only the text if there is no `{name}`.
+pub fn expand_frame_label(
+    template: &str,
+    name: &str,
+    total: &str,
+    own: &str,
+    percent: f64,
+) -> (String, Option<String>) {
+    let expand = |text: &str| {
+        text.replace("{name}", name)
+            .replace("{total}", total)
+            .replace("{own}", own)
+            .replace("{pct}", &format!("{:.1}%", percent))
+    };
+    match template.split_once("{name}") {
+        Some((before, after)) => (expand(before), Some(expand(after))),
+        None => (expand(template), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        assert_eq!(toml::from_str::<Config>("").unwrap(), Config::default());
+        let config: Config =
+            toml::from_str("notifications = \"osc777\"\nnotify_after_secs = 1.5\n").unwrap();
+        assert_eq!(
+            config,
+            Config {
+                terminal_title: true,
+                notifications: NotificationMode::Osc777,
+                notify_after_secs: 1.5,
+                link_template: "file://{path}".to_string(),
+                color_seed: 0,
+                color_rules: vec![],
+                frame_label: "{name}".to_string(),
+                frame_labels: HashMap::new(),
+                row_height: 1,
+                graphics: GraphicsProtocol::Off,
+                palette: Palette::Dark,
+                selected_max_lines: 3,
+            }
+        );
+        assert_eq!(config.notify_after(), Duration::from_millis(1500));
+        let config: Config = toml::from_str("row_height = 0\n").unwrap();
+        assert_eq!(config.row_height(), 1);
+        let config: Config = toml::from_str("graphics = \"sixel\"\n").unwrap();
+        assert_eq!(config.graphics, GraphicsProtocol::Sixel);
+        let config: Config = toml::from_str("palette = \"colorblind\"\n").unwrap();
+        assert_eq!(config.palette.change_colors(), ("orange", "blue"));
+        // Typos are reported rather than silently ignored
+        assert!(toml::from_str::<Config>("terminal_titel = false").is_err());
+        assert!(toml::from_str::<Config>("notifications = \"osc8\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_color_rules() {
+        let config: Config = toml::from_str(
+            "color_seed = 42\n\
+             [[color_rules]]\n\
+             pattern = \"(?i)lock|mutex\"\n\
+             color = \"#8040C0\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.color_seed, 42);
+        assert_eq!(config.color_rules.len(), 1);
+        assert!(config.color_rules[0].pattern.is_match("pthread_mutex_lock"));
+        assert_eq!(config.color_rules[0].color, [0x80, 0x40, 0xc0]);
+        for rule in [
+            "pattern = \"(\"\ncolor = \"#8040c0\"",
+            "pattern = \"lock\"\ncolor = \"purple\"",
+            "pattern = \"lock\"\ncolor = \"#8040c\"",
+            "pattern = \"lock\"",
+        ] {
+            let content = format!("[[color_rules]]\n{}\n", rule);
+            assert!(toml::from_str::<Config>(&content).is_err(), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn test_frame_label() {
+        let config: Config = toml::from_str(
+            "frame_label = \"{name} {pct}\"\n\
+             [frame_labels]\n\
+             alloc_space = \"{name} ({own})\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.frame_label(None), "{name} {pct}");
+        assert_eq!(config.frame_label(Some("cpu")), "{name} {pct}");
+        assert_eq!(config.frame_label(Some("alloc_space")), "{name} ({own})");
+
+        assert_eq!(
+            expand_frame_label("{name} {pct}", "main", "10 samples", "1 samples", 12.345),
+            ("".to_string(), Some(" 12.3%".to_string()))
+        );
+        assert_eq!(
+            expand_frame_label("[{own}] {name} / {name}", "main", "2 KiB", "1 KiB", 50.0),
+            ("[1 KiB] ".to_string(), Some(" / main".to_string()))
+        );
+        assert_eq!(
+            expand_frame_label("{total}", "main", "2 KiB", "1 KiB", 50.0),
+            ("2 KiB".to_string(), None)
+        );
+    }
+}
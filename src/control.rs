@@ -0,0 +1,93 @@
+use crate::event::Event;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+/// Unix socket accepting one command per line, e.g. "search foo" or "quit", so that flamelens can
+/// be driven by editors, scripts and tests. Each command is answered with a single line.
+#[derive(Debug)]
+pub struct ControlSocket {
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    /// Listen on the socket path and forward the received commands as [`Event::Control`]. A stale
+    /// socket left behind at the path is replaced.
+    pub fn bind(path: &Path, sender: mpsc::Sender<Event>) -> io::Result<Self> {
+        if std::fs::metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = sender.clone();
+                thread::spawn(move || handle_connection(stream, sender));
+            }
+        });
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn handle_connection(stream: UnixStream, sender: mpsc::Sender<Event>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (reply_sender, reply_receiver) = mpsc::channel();
+        if sender
+            .send(Event::Control(line.trim().to_string(), reply_sender))
+            .is_err()
+        {
+            break;
+        }
+        let reply = reply_receiver
+            .recv()
+            .unwrap_or_else(|_| "error: no reply".to_string());
+        writeln!(writer, "{}", reply)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_control_socket() {
+        let path = std::env::temp_dir().join(format!("flamelens-test-{}.sock", std::process::id()));
+        let (sender, receiver) = mpsc::channel();
+        let socket = ControlSocket::bind(&path, sender).unwrap();
+        thread::spawn(move || {
+            while let Ok(Event::Control(command, reply)) = receiver.recv() {
+                reply.send(format!("got {}", command)).unwrap();
+            }
+        });
+
+        let stream = UnixStream::connect(&path).unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        writer.write_all(b"search foo\n\nquit\n").unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "got search foo\n");
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "got quit\n");
+
+        drop(socket);
+        assert!(!path.exists());
+    }
+}
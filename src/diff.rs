@@ -0,0 +1,302 @@
+use crate::flame::FlameGraph;
+use std::collections::HashMap;
+
+/// Change in the total samples of a function between two profiles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionDelta {
+    pub name: String,
+    pub old: u64,
+    pub new: u64,
+}
+
+impl FunctionDelta {
+    pub fn delta(&self) -> i64 {
+        self.new as i64 - self.old as i64
+    }
+}
+
+/// Per-function changes in total samples, biggest absolute change first. Functions with no change
+/// are left out.
+pub fn function_deltas(old: &FlameGraph, new: &FlameGraph) -> Vec<FunctionDelta> {
+    let mut counts: HashMap<&str, (u64, u64)> = HashMap::new();
+    for (name, count) in old.function_counts() {
+        counts.entry(name).or_default().0 = count.total;
+    }
+    for (name, count) in new.function_counts() {
+        counts.entry(name).or_default().1 = count.total;
+    }
+    let mut deltas = counts
+        .into_iter()
+        .filter(|(_, (old, new))| old != new)
+        .map(|(name, (old, new))| FunctionDelta {
+            name: name.to_string(),
+            old,
+            new,
+        })
+        .collect::<Vec<_>>();
+    deltas.sort_by(|a, b| {
+        b.delta()
+            .abs()
+            .cmp(&a.delta().abs())
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    deltas
+}
+
+/// Report of the `n` biggest regressions and improvements per function, to print in CI logs.
+pub fn format_diff_report(old: &FlameGraph, new: &FlameGraph, n: usize) -> String {
+    let deltas = function_deltas(old, new);
+    let percent = |count: u64, total: u64| 100.0 * count as f64 / total.max(1) as f64;
+    let mut out = format!(
+        "Total samples: {} -> {} ({:+})\n",
+        old.total_count(),
+        new.total_count(),
+        new.total_count() as i64 - old.total_count() as i64
+    );
+    for (title, regressed) in [("Regressions", true), ("Improvements", false)] {
+        let rows = deltas
+            .iter()
+            .filter(|d| (d.delta() > 0) == regressed)
+            .take(n)
+            .collect::<Vec<_>>();
+        out += &format!("\n{}:\n", title);
+        if rows.is_empty() {
+            out += "  (none)\n";
+            continue;
+        }
+        let width = rows
+            .iter()
+            .flat_map(|d| [d.old.to_string().len(), d.new.to_string().len() + 1])
+            .chain(["Delta".len()])
+            .max()
+            .unwrap();
+        out += &format!(
+            "  {:>width$}  {:>width$}  {:>width$}  {:>8}  Function\n",
+            "Old", "New", "Delta", "Percent"
+        );
+        for d in rows {
+            let percent_delta =
+                percent(d.new, new.total_count()) - percent(d.old, old.total_count());
+            out += &format!(
+                "  {:>width$}  {:>width$}  {:>+width$}  {:>+7.2}%  {}\n",
+                d.old,
+                d.new,
+                d.delta(),
+                percent_delta,
+                d.name
+            );
+        }
+    }
+    out
+}
+
+/// Splits the profiles of a live session into windows to compare each window with the one before
+/// it. The live sampler sends cumulative profiles, so a window is the difference from the previous
+/// profile, while each pushed profile is a window of its own.
+#[derive(Debug, Clone, Default)]
+pub struct LiveDelta {
+    cumulative: bool,
+    /// Self counts of the last cumulative profile
+    last_profile: Option<HashMap<String, u64>>,
+    /// Total counts per stack of the two latest windows shown
+    previous_window: Option<HashMap<String, u64>>,
+    latest_window: Option<HashMap<String, u64>>,
+}
+
+impl LiveDelta {
+    pub fn new(cumulative: bool) -> Self {
+        Self {
+            cumulative,
+            ..Default::default()
+        }
+    }
+
+    /// The window ending with the next profile, or `None` for the first cumulative profile, which
+    /// only marks the start of the first window.
+    pub fn next_window(&mut self, profile: &FlameGraph) -> Option<FlameGraph> {
+        if !self.cumulative {
+            return Some(profile.clone());
+        }
+        let counts = profile
+            .stack_counts(true)
+            .filter(|(_, count)| *count > 0)
+            .map(|(name, count)| (name.to_string(), count))
+            .collect::<HashMap<_, _>>();
+        let last_profile = self.last_profile.replace(counts)?;
+        let mut content = String::new();
+        for (name, count) in self.last_profile.as_ref().unwrap() {
+            // Counts going down mean the sampler started over
+            let count = count.saturating_sub(last_profile.get(name).copied().unwrap_or(0));
+            if count > 0 {
+                content += &format!("{} {}\n", name, count);
+            }
+        }
+        let mut window = FlameGraph::from_string(content, profile.is_sorted());
+        window.inherit_metric(profile);
+        Some(window)
+    }
+
+    /// Record the window being shown, which may have been rerooted, to compare the next one with.
+    pub fn set_shown_window(&mut self, window: &FlameGraph) {
+        let totals = window
+            .stack_counts(false)
+            .map(|(name, count)| (name.to_string(), count))
+            .collect();
+        self.previous_window = self.latest_window.replace(totals);
+    }
+
+    /// Total count of a stack in the window before the one shown, or `None` if there is no
+    /// window to compare with yet.
+    pub fn previous_count(&self, full_name: &str) -> Option<u64> {
+        self.previous_window
+            .as_ref()
+            .map(|totals| totals.get(full_name).copied().unwrap_or(0))
+    }
+}
+
+/// Known-good profile to compare the flamegraph shown with, stack by stack. Profiles rarely have
+/// the same number of samples, so stacks are compared by their share of all samples.
+#[derive(Debug, Clone)]
+pub struct Baseline {
+    /// File the baseline was loaded from
+    pub name: String,
+    totals: HashMap<String, u64>,
+    /// Total count of each function, for the delta column of the table
+    function_totals: HashMap<String, u64>,
+    /// Total count of each module, for the delta column of the table grouped by module
+    module_totals: HashMap<String, u64>,
+    total_count: u64,
+}
+
+impl Baseline {
+    pub fn new(name: &str, flamegraph: &FlameGraph) -> Self {
+        Self {
+            name: name.to_string(),
+            totals: flamegraph
+                .stack_counts(false)
+                .map(|(name, count)| (name.to_string(), count))
+                .collect(),
+            function_totals: flamegraph
+                .function_counts()
+                .into_iter()
+                .map(|(name, count)| (name.to_string(), count.total))
+                .collect(),
+            module_totals: flamegraph
+                .get_module_counts()
+                .into_iter()
+                .map(|(name, count)| (name, count.total))
+                .collect(),
+            total_count: flamegraph.total_count(),
+        }
+    }
+
+    /// Total count of a stack in the baseline, scaled to a profile of `total_count` samples.
+    pub fn scaled_count(&self, full_name: &str, total_count: u64) -> u64 {
+        self.scale(self.totals.get(full_name), total_count)
+    }
+
+    /// Change in the total count of a function since the baseline, in a profile of `total_count`
+    /// samples.
+    pub fn function_delta(&self, name: &str, total: u64, total_count: u64) -> i64 {
+        total as i64 - self.scale(self.function_totals.get(name), total_count) as i64
+    }
+
+    /// Change in the total count of a module since the baseline, in a profile of `total_count`
+    /// samples.
+    pub fn module_delta(&self, name: &str, total: u64, total_count: u64) -> i64 {
+        total as i64 - self.scale(self.module_totals.get(name), total_count) as i64
+    }
+
+    fn scale(&self, count: Option<&u64>, total_count: u64) -> u64 {
+        let count = count.copied().unwrap_or(0);
+        (count as f64 * total_count as f64 / self.total_count.max(1) as f64).round() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_function_deltas() {
+        let old = FlameGraph::from_string("main;a 5\nmain;b 3\nmain;c 2\n".to_string(), false);
+        let new = FlameGraph::from_string("main;a 2\nmain;b 3\nmain;d 9\n".to_string(), false);
+        let deltas = function_deltas(&old, &new);
+        assert_eq!(
+            deltas
+                .iter()
+                .map(|d| (d.name.as_str(), d.delta()))
+                .collect::<Vec<_>>(),
+            vec![("d", 9), ("main", 4), ("a", -3), ("c", -2)]
+        );
+        assert_eq!(
+            format_diff_report(&old, &new, 1),
+            [
+                "Total samples: 10 -> 14 (+4)",
+                "",
+                "Regressions:",
+                "    Old    New  Delta   Percent  Function",
+                "      0      9     +9   +64.29%  d",
+                "",
+                "Improvements:",
+                "    Old    New  Delta   Percent  Function",
+                "      5      2     -3   -35.71%  a",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_baseline() {
+        let baseline = Baseline::new(
+            "good.txt",
+            &FlameGraph::from_string("main;a 3\nmain;b 1\n".to_string(), false),
+        );
+        assert_eq!(baseline.scaled_count("main", 8), 8);
+        assert_eq!(baseline.scaled_count("main;a", 8), 6);
+        assert_eq!(baseline.scaled_count("main;b", 2), 1);
+        assert_eq!(baseline.scaled_count("main;c", 8), 0);
+        assert_eq!(baseline.function_delta("a", 2, 8), -4);
+        assert_eq!(baseline.function_delta("c", 5, 8), 5);
+    }
+
+    #[test]
+    fn test_live_delta() {
+        let mut delta = LiveDelta::new(true);
+        let profile = |content: &str| FlameGraph::from_string(content.to_string(), true);
+        assert!(delta
+            .next_window(&profile("main;a 5\nmain;b 3\n"))
+            .is_none());
+        let window = delta
+            .next_window(&profile("main;a 6\nmain;b 7\nmain 1\n"))
+            .unwrap();
+        assert_eq!(window.total_count(), 6);
+        delta.set_shown_window(&window);
+        assert_eq!(delta.previous_count("main;b"), None);
+
+        let window = delta
+            .next_window(&profile("main;a 9\nmain;b 8\nmain 1\nmain;c 2\n"))
+            .unwrap();
+        delta.set_shown_window(&window);
+        let counts = window.stack_counts(false).collect::<HashMap<_, _>>();
+        assert_eq!(
+            counts,
+            HashMap::from([
+                ("all", 6),
+                ("main", 6),
+                ("main;a", 3),
+                ("main;b", 1),
+                ("main;c", 2)
+            ])
+        );
+        assert_eq!(delta.previous_count("main;b"), Some(4));
+        assert_eq!(delta.previous_count("main;a"), Some(1));
+        assert_eq!(delta.previous_count("main;c"), Some(0));
+
+        // Pushed profiles are windows already
+        let mut delta = LiveDelta::new(false);
+        let window = delta.next_window(&profile("main;a 5\n")).unwrap();
+        assert_eq!(window.total_count(), 5);
+    }
+}
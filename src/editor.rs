@@ -0,0 +1,159 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The editor set by the user in `$VISUAL` or `$EDITOR`, if any.
+pub fn user_editor() -> Option<String> {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+}
+
+/// Program and arguments to open a file at a line with the given editor command, which may
+/// include arguments of its own, e.g. "code --wait".
+pub fn editor_args(editor: &str, path: &Path, line: Option<u32>) -> Vec<String> {
+    let mut args = editor
+        .split_whitespace()
+        .map(|arg| arg.to_string())
+        .collect::<Vec<_>>();
+    let program = Path::new(&args[0])
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default()
+        .to_string();
+    let path = path.display().to_string();
+    match line {
+        None => args.push(path),
+        // These take the line after the file name instead of the common "+line"
+        Some(line) if matches!(program.as_str(), "code" | "codium" | "cursor") => {
+            args.push("--goto".to_string());
+            args.push(format!("{}:{}", path, line));
+        }
+        Some(line) if matches!(program.as_str(), "hx" | "helix" | "subl" | "zed" | "micro") => {
+            args.push(format!("{}:{}", path, line));
+        }
+        Some(line) => {
+            args.push(format!("+{}", line));
+            args.push(path);
+        }
+    }
+    args
+}
+
+pub fn editor_command(editor: &str, path: &Path, line: Option<u32>) -> Command {
+    let args = editor_args(editor, path, line);
+    let mut command = Command::new(&args[0]);
+    command.args(&args[1..]);
+    command
+}
+
+/// Find a source file named in a frame. Relative paths are tried from the current directory and
+/// then from the directory of the profile, as profilers often record paths relative to where the
+/// program was run.
+pub fn resolve_source_path(file: &str, profile: Option<&Path>) -> Option<PathBuf> {
+    let path = PathBuf::from(file);
+    if path.is_file() {
+        return Some(path);
+    }
+    if path.is_relative() {
+        let dir = profile?.parent()?;
+        let path = dir.join(path);
+        if path.is_file() {
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// URL to the source of a frame from a template with the placeholders `{path}` for the absolute
+/// path of the file found locally, `{file}` for the file as named in the frame, and `{line}`.
+/// Returns `None` if the template is empty, or uses `{path}` but the file is not found.
+pub fn source_link(
+    template: &str,
+    file: &str,
+    line: Option<u32>,
+    profile: Option<&Path>,
+) -> Option<String> {
+    if template.is_empty() {
+        return None;
+    }
+    let mut url = template.to_string();
+    if url.contains("{path}") {
+        let path = resolve_source_path(file, profile)?.canonicalize().ok()?;
+        url = url.replace("{path}", &path.display().to_string().replace(' ', "%20"));
+    }
+    let url = url
+        .replace("{file}", &file.replace(' ', "%20"))
+        .replace("{line}", &line.unwrap_or(1).to_string());
+    // Control characters would end the escape sequence early
+    Some(url.chars().filter(|c| !c.is_control()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editor_args() {
+        let path = Path::new("src/main.rs");
+        assert_eq!(
+            editor_args("vim", path, Some(12)),
+            vec!["vim", "+12", "src/main.rs"]
+        );
+        assert_eq!(editor_args("nano", path, None), vec!["nano", "src/main.rs"]);
+        assert_eq!(
+            editor_args("/usr/bin/code --wait", path, Some(3)),
+            vec!["/usr/bin/code", "--wait", "--goto", "src/main.rs:3"]
+        );
+        assert_eq!(
+            editor_args("hx", path, Some(3)),
+            vec!["hx", "src/main.rs:3"]
+        );
+    }
+
+    #[test]
+    fn test_source_link() {
+        let profile = Path::new("tests/data/py-spy-simple.txt");
+        let cwd = std::env::current_dir().unwrap().canonicalize().unwrap();
+        assert_eq!(
+            source_link("file://{path}", "py-spy-simple.txt", Some(3), Some(profile)),
+            Some(format!(
+                "file://{}/tests/data/py-spy-simple.txt",
+                cwd.display()
+            ))
+        );
+        assert_eq!(
+            source_link("vscode://file{path}:{line}", "Cargo.toml", None, None),
+            Some(format!("vscode://file{}/Cargo.toml:1", cwd.display()))
+        );
+        assert_eq!(
+            source_link(
+                "https://github.com/org/repo/blob/main/{file}#L{line}",
+                "src/app.py",
+                Some(12),
+                None
+            ),
+            Some("https://github.com/org/repo/blob/main/src/app.py#L12".to_string())
+        );
+        assert_eq!(
+            source_link("file://{path}", "missing.py", Some(1), None),
+            None
+        );
+        assert_eq!(source_link("", "Cargo.toml", Some(1), None), None);
+    }
+
+    #[test]
+    fn test_resolve_source_path() {
+        let profile = Path::new("tests/data/py-spy-simple.txt");
+        assert_eq!(
+            resolve_source_path("py-spy-simple.txt", Some(profile)),
+            Some(PathBuf::from("tests/data/py-spy-simple.txt"))
+        );
+        assert_eq!(
+            resolve_source_path("Cargo.toml", Some(profile)),
+            Some(PathBuf::from("Cargo.toml"))
+        );
+        assert_eq!(resolve_source_path("missing.py", Some(profile)), None);
+        assert_eq!(resolve_source_path("missing.py", None), None);
+    }
+}
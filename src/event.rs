@@ -1,11 +1,12 @@
-use crate::app::AppResult;
+use crate::app::{AppResult, ParsedFlameGraph};
 use crossterm::event::{self, Event as CrosstermEvent, KeyEvent, KeyEventKind, MouseEvent};
-use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread;
 use std::time::{Duration, Instant};
 
 /// Terminal events.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum Event {
     /// Terminal tick.
     Tick,
@@ -13,8 +14,23 @@ pub enum Event {
     Key(KeyEvent),
     /// Mouse click/scroll.
     Mouse(MouseEvent),
+    /// Text pasted into the terminal.
+    Paste(String),
     /// Terminal resize.
     Resize(u16, u16),
+    /// Request to reload the input file, e.g. from SIGUSR1 or SIGHUP.
+    Reload,
+    /// Request to suspend to the shell from SIGTSTP.
+    Suspend,
+    /// Continued after being stopped, from SIGCONT.
+    Resume,
+    /// Command received on the control socket, along with where to send the reply.
+    Control(String, mpsc::Sender<String>),
+    /// New flamegraph from the live sampler or pushed to the listener.
+    FlameGraph(Box<ParsedFlameGraph>),
+    /// The live sampler stopped, along with where its samples were recorded to or why they could
+    /// not be.
+    SamplerFinished(Option<Result<String, String>>),
 }
 
 /// Terminal event handler.
@@ -27,23 +43,57 @@ pub struct EventHandler {
     receiver: mpsc::Receiver<Event>,
     /// Event handler thread.
     handler: thread::JoinHandle<()>,
+    /// Tick rate in milliseconds, shared with the handler thread.
+    tick_rate: Arc<AtomicU64>,
+    /// Whether the handler thread should stop reading the terminal.
+    paused: Arc<AtomicBool>,
+    /// Whether the handler thread may be reading the terminal.
+    polling: Arc<AtomicBool>,
 }
 
+/// Longest wait for terminal events at once, so that pausing takes effect quickly.
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
 impl EventHandler {
     /// Constructs a new instance of [`EventHandler`].
     pub fn new(tick_rate: u64) -> Self {
-        let tick_rate = Duration::from_millis(tick_rate);
         let (sender, receiver) = mpsc::channel();
+        Self::with_channel(tick_rate, sender, receiver)
+    }
+
+    /// Constructs a new instance of [`EventHandler`] on an existing channel, so that background
+    /// work started before the terminal is set up can already send its events.
+    pub fn with_channel(
+        tick_rate: u64,
+        sender: mpsc::Sender<Event>,
+        receiver: mpsc::Receiver<Event>,
+    ) -> Self {
+        let tick_rate = Arc::new(AtomicU64::new(tick_rate));
+        let paused = Arc::new(AtomicBool::new(false));
+        let polling = Arc::new(AtomicBool::new(false));
         let handler = {
             let sender = sender.clone();
+            let tick_rate = tick_rate.clone();
+            let paused = paused.clone();
+            let polling = polling.clone();
             thread::spawn(move || {
                 let mut last_tick = Instant::now();
                 loop {
+                    polling.store(true, Ordering::SeqCst);
+                    if paused.load(Ordering::SeqCst) {
+                        polling.store(false, Ordering::SeqCst);
+                        thread::sleep(MAX_POLL_INTERVAL);
+                        continue;
+                    }
+                    let tick_rate = Duration::from_millis(tick_rate.load(Ordering::Relaxed));
                     let timeout = tick_rate
                         .checked_sub(last_tick.elapsed())
-                        .unwrap_or(tick_rate);
+                        .unwrap_or(tick_rate)
+                        .min(MAX_POLL_INTERVAL);
 
-                    if event::poll(timeout).expect("failed to poll new events") {
+                    let has_event = event::poll(timeout).expect("failed to poll new events");
+                    polling.store(false, Ordering::SeqCst);
+                    if has_event {
                         match event::read().expect("unable to read event") {
                             CrosstermEvent::Key(e) => {
                                 if e.kind == KeyEventKind::Press {
@@ -56,7 +106,7 @@ impl EventHandler {
                             CrosstermEvent::Resize(w, h) => sender.send(Event::Resize(w, h)),
                             CrosstermEvent::FocusGained => Ok(()),
                             CrosstermEvent::FocusLost => Ok(()),
-                            CrosstermEvent::Paste(_) => unimplemented!(),
+                            CrosstermEvent::Paste(s) => sender.send(Event::Paste(s)),
                         }
                         .expect("failed to send terminal event")
                     }
@@ -68,13 +118,60 @@ impl EventHandler {
                 }
             })
         };
+        #[cfg(unix)]
+        {
+            use signal_hook::consts::{SIGCONT, SIGHUP, SIGTSTP, SIGUSR1};
+            let sender = sender.clone();
+            let mut signals =
+                signal_hook::iterator::Signals::new([SIGUSR1, SIGHUP, SIGTSTP, SIGCONT])
+                    .expect("failed to listen for signals");
+            thread::spawn(move || {
+                for signal in signals.forever() {
+                    let event = match signal {
+                        SIGTSTP => Event::Suspend,
+                        SIGCONT => Event::Resume,
+                        _ => Event::Reload,
+                    };
+                    if sender.send(event).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
         Self {
             sender,
             receiver,
             handler,
+            tick_rate,
+            paused,
+            polling,
+        }
+    }
+
+    /// Stop reading the terminal, e.g. while another program uses it. Returns once the handler
+    /// thread is no longer reading.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+        while self.polling.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(5));
         }
     }
 
+    pub fn unpause(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    /// Change the tick rate, e.g. to tick less often when nothing changes over time. Takes effect
+    /// from the next tick.
+    pub fn set_tick_rate(&self, tick_rate: u64) {
+        self.tick_rate.store(tick_rate, Ordering::Relaxed);
+    }
+
+    /// Sender for events coming from sources other than the terminal.
+    pub fn sender(&self) -> mpsc::Sender<Event> {
+        self.sender.clone()
+    }
+
     /// Receive the next event from the handler thread.
     ///
     /// This function will always block the current thread if
@@ -82,4 +179,9 @@ impl EventHandler {
     pub fn next(&self) -> AppResult<Event> {
         Ok(self.receiver.recv()?)
     }
+
+    /// Receive an event that is already waiting, without blocking.
+    pub fn try_next(&self) -> Option<Event> {
+        self.receiver.try_recv().ok()
+    }
 }
@@ -0,0 +1,469 @@
+use crate::anonymize::fnv1a;
+use crate::flame::{FlameGraph, StackIdentifier, ROOT_ID};
+use serde_json::{json, Value};
+use std::io;
+use std::path::Path;
+
+/// File formats the flamegraph can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// Self-contained interactive page, with the data in the d3-flame-graph JSON format
+    Html,
+    /// Image of the whole flamegraph
+    Png,
+}
+
+/// Width of the exported image in pixels.
+const PNG_WIDTH: usize = 1200;
+/// Height of each level of the exported image in pixels.
+const PNG_ROW_HEIGHT: usize = 16;
+/// Width of each character of the frame names in the exported image, including the spacing.
+const PNG_CHAR_WIDTH: usize = 6;
+
+pub fn export(flamegraph: &FlameGraph, format: ExportFormat, path: &Path) -> io::Result<()> {
+    let content = match format {
+        ExportFormat::Html => to_html(flamegraph).into_bytes(),
+        ExportFormat::Png => to_png(flamegraph)?,
+    };
+    std::fs::write(path, content)
+}
+
+/// Stacks under `stack_id` as nested `name`, `value` and `children` objects, which is what
+/// d3-flame-graph and similar viewers take as input.
+pub fn to_d3_json(flamegraph: &FlameGraph, stack_id: &StackIdentifier) -> Value {
+    let stack = flamegraph.get_stack(stack_id).unwrap();
+    let name = if *stack_id == ROOT_ID {
+        "all"
+    } else {
+        flamegraph.get_stack_short_name_from_info(stack)
+    };
+    let children: Vec<Value> = flamegraph
+        .get_children(stack)
+        .iter()
+        .map(|child_id| to_d3_json(flamegraph, child_id))
+        .collect();
+    json!({
+        "name": name,
+        "value": stack.total_count,
+        "children": children,
+    })
+}
+
+pub fn to_html(flamegraph: &FlameGraph) -> String {
+    // Keep frame names like "</script>" from ending the script early
+    let data = to_d3_json(flamegraph, &ROOT_ID)
+        .to_string()
+        .replace("</", "<\\/");
+    HTML_TEMPLATE.replace("{data}", &data)
+}
+
+/// Frame color roughly based on flamegraph.pl, stable across runs so that the same function has
+/// the same color in every export.
+fn frame_color(name: &str) -> [u8; 3] {
+    let v = fnv1a(name) as f64 / u64::MAX as f64;
+    [
+        205 + (50.0 * v) as u8,
+        (230.0 * (1.0 - v)) as u8,
+        (55.0 * v) as u8,
+    ]
+}
+
+fn text_color(color: [u8; 3]) -> [u8; 3] {
+    let [r, g, b] = color;
+    let luma = 0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64;
+    if luma > 128.0 {
+        [10, 10, 10]
+    } else {
+        [225, 225, 225]
+    }
+}
+
+/// RGB image drawn into by the PNG export, and by the flamegraph view when drawn as an image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Canvas {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Canvas {
+    /// Width of each character drawn by [`Canvas::draw_text`] at scale 1, including the spacing.
+    pub const CHAR_WIDTH: usize = PNG_CHAR_WIDTH;
+    /// Height of each character drawn by [`Canvas::draw_text`] at scale 1.
+    pub const CHAR_HEIGHT: usize = 7;
+
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![255; width * height * 3],
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 3]) {
+        for row in y..(y + height).min(self.height) {
+            for col in x..(x + width).min(self.width) {
+                let i = (row * self.width + col) * 3;
+                self.pixels[i..i + 3].copy_from_slice(&color);
+            }
+        }
+    }
+
+    pub fn draw_text(&mut self, x: usize, y: usize, text: &str, color: [u8; 3]) {
+        self.draw_scaled_text(x, y, text, color, 1);
+    }
+
+    /// Draw the text with each pixel of the font as a square `scale` pixels wide, for screens
+    /// with more pixels per cell.
+    pub fn draw_scaled_text(
+        &mut self,
+        x: usize,
+        y: usize,
+        text: &str,
+        color: [u8; 3],
+        scale: usize,
+    ) {
+        for (i, c) in text.chars().enumerate() {
+            let glyph = match c {
+                ' '..='~' => FONT_5X7[c as usize - ' ' as usize],
+                _ => FONT_5X7['?' as usize - ' ' as usize],
+            };
+            for (col, bits) in glyph.iter().enumerate() {
+                for row in 0..Self::CHAR_HEIGHT {
+                    if bits & (1 << row) != 0 {
+                        self.fill_rect(
+                            x + (i * PNG_CHAR_WIDTH + col) * scale,
+                            y + row * scale,
+                            scale,
+                            scale,
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn to_png(&self) -> io::Result<Vec<u8>> {
+        let mut out = vec![];
+        let mut encoder = png::Encoder::new(&mut out, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(out)
+    }
+}
+
+pub fn to_png(flamegraph: &FlameGraph) -> io::Result<Vec<u8>> {
+    let height = flamegraph.get_num_levels().max(1) * PNG_ROW_HEIGHT;
+    let mut canvas = Canvas::new(PNG_WIDTH, height);
+    let mut to_visit = vec![(ROOT_ID, 0.0_f64)];
+    while let Some((stack_id, x)) = to_visit.pop() {
+        let stack = flamegraph.get_stack(&stack_id).unwrap();
+        let width = PNG_WIDTH as f64 * stack.total_count as f64 / flamegraph.total_count() as f64;
+        // Leave a gap between frames so that neighbors with similar colors can be told apart
+        let (left, right) = (x.round() as usize, (x + width).round() as usize);
+        if right <= left + 1 {
+            continue;
+        }
+        let name = if stack_id == ROOT_ID {
+            "all"
+        } else {
+            flamegraph.get_stack_short_name_from_info(stack)
+        };
+        let color = frame_color(name);
+        let top = stack.level * PNG_ROW_HEIGHT;
+        canvas.fill_rect(left, top, right - left - 1, PNG_ROW_HEIGHT - 1, color);
+
+        let max_chars = (right - left).saturating_sub(4) / PNG_CHAR_WIDTH;
+        let num_chars = name.chars().count();
+        if num_chars <= max_chars {
+            canvas.draw_text(left + 2, top + 4, name, text_color(color));
+        } else if max_chars >= 3 {
+            let truncated: String = name
+                .chars()
+                .take(max_chars - 2)
+                .chain("..".chars())
+                .collect();
+            canvas.draw_text(left + 2, top + 4, &truncated, text_color(color));
+        }
+
+        let mut child_x = x;
+        for child_id in flamegraph.get_children(stack).iter() {
+            to_visit.push((*child_id, child_x));
+            let child = flamegraph.get_stack(child_id).unwrap();
+            child_x +=
+                PNG_WIDTH as f64 * child.total_count as f64 / flamegraph.total_count() as f64;
+        }
+    }
+
+    canvas.to_png()
+}
+
+/// Classic 5x7 bitmap font for the printable ASCII characters, one byte per column with the top
+/// row in the lowest bit.
+#[rustfmt::skip]
+const FONT_5X7: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // '!'
+    [0x00, 0x07, 0x00, 0x07, 0x00], // '"'
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // '#'
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // '$'
+    [0x23, 0x13, 0x08, 0x64, 0x62], // '%'
+    [0x36, 0x49, 0x55, 0x22, 0x50], // '&'
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '''
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // '('
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // ')'
+    [0x14, 0x08, 0x3E, 0x08, 0x14], // '*'
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // '+'
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ','
+    [0x08, 0x08, 0x08, 0x08, 0x08], // '-'
+    [0x00, 0x60, 0x60, 0x00, 0x00], // '.'
+    [0x20, 0x10, 0x08, 0x04, 0x02], // '/'
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // '0'
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // '1'
+    [0x42, 0x61, 0x51, 0x49, 0x46], // '2'
+    [0x21, 0x41, 0x45, 0x4B, 0x31], // '3'
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // '4'
+    [0x27, 0x45, 0x45, 0x45, 0x39], // '5'
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], // '6'
+    [0x01, 0x71, 0x09, 0x05, 0x03], // '7'
+    [0x36, 0x49, 0x49, 0x49, 0x36], // '8'
+    [0x06, 0x49, 0x49, 0x29, 0x1E], // '9'
+    [0x00, 0x36, 0x36, 0x00, 0x00], // ':'
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ';'
+    [0x08, 0x14, 0x22, 0x41, 0x00], // '<'
+    [0x14, 0x14, 0x14, 0x14, 0x14], // '='
+    [0x00, 0x41, 0x22, 0x14, 0x08], // '>'
+    [0x02, 0x01, 0x51, 0x09, 0x06], // '?'
+    [0x32, 0x49, 0x79, 0x41, 0x3E], // '@'
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], // 'A'
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // 'B'
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // 'C'
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], // 'D'
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // 'E'
+    [0x7F, 0x09, 0x09, 0x09, 0x01], // 'F'
+    [0x3E, 0x41, 0x49, 0x49, 0x7A], // 'G'
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // 'H'
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // 'I'
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // 'J'
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // 'K'
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // 'L'
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F], // 'M'
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // 'N'
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // 'O'
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // 'P'
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // 'Q'
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // 'R'
+    [0x46, 0x49, 0x49, 0x49, 0x31], // 'S'
+    [0x01, 0x01, 0x7F, 0x01, 0x01], // 'T'
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // 'U'
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // 'V'
+    [0x3F, 0x40, 0x38, 0x40, 0x3F], // 'W'
+    [0x63, 0x14, 0x08, 0x14, 0x63], // 'X'
+    [0x07, 0x08, 0x70, 0x08, 0x07], // 'Y'
+    [0x61, 0x51, 0x49, 0x45, 0x43], // 'Z'
+    [0x00, 0x7F, 0x41, 0x41, 0x00], // '['
+    [0x02, 0x04, 0x08, 0x10, 0x20], // '\'
+    [0x00, 0x41, 0x41, 0x7F, 0x00], // ']'
+    [0x04, 0x02, 0x01, 0x02, 0x04], // '^'
+    [0x40, 0x40, 0x40, 0x40, 0x40], // '_'
+    [0x00, 0x01, 0x02, 0x04, 0x00], // '`'
+    [0x20, 0x54, 0x54, 0x54, 0x78], // 'a'
+    [0x7F, 0x48, 0x44, 0x44, 0x38], // 'b'
+    [0x38, 0x44, 0x44, 0x44, 0x20], // 'c'
+    [0x38, 0x44, 0x44, 0x48, 0x7F], // 'd'
+    [0x38, 0x54, 0x54, 0x54, 0x18], // 'e'
+    [0x08, 0x7E, 0x09, 0x01, 0x02], // 'f'
+    [0x0C, 0x52, 0x52, 0x52, 0x3E], // 'g'
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // 'h'
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // 'i'
+    [0x20, 0x40, 0x44, 0x3D, 0x00], // 'j'
+    [0x7F, 0x10, 0x28, 0x44, 0x00], // 'k'
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // 'l'
+    [0x7C, 0x04, 0x18, 0x04, 0x78], // 'm'
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // 'n'
+    [0x38, 0x44, 0x44, 0x44, 0x38], // 'o'
+    [0x7C, 0x14, 0x14, 0x14, 0x08], // 'p'
+    [0x08, 0x14, 0x14, 0x18, 0x7C], // 'q'
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // 'r'
+    [0x48, 0x54, 0x54, 0x54, 0x20], // 's'
+    [0x04, 0x3F, 0x44, 0x40, 0x20], // 't'
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // 'u'
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // 'v'
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // 'w'
+    [0x44, 0x28, 0x10, 0x28, 0x44], // 'x'
+    [0x0C, 0x50, 0x50, 0x50, 0x3C], // 'y'
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // 'z'
+    [0x00, 0x08, 0x36, 0x41, 0x00], // '{'
+    [0x00, 0x00, 0x7F, 0x00, 0x00], // '|'
+    [0x00, 0x41, 0x36, 0x08, 0x00], // '}'
+    [0x08, 0x04, 0x08, 0x10, 0x08], // '~'
+];
+
+const HTML_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>flamelens</title>
+<style>
+body { font: 12px monospace; margin: 8px; }
+#toolbar { margin-bottom: 4px; }
+#details { height: 1.5em; white-space: nowrap; overflow: hidden; }
+#graph { position: relative; }
+.frame { position: absolute; height: 17px; line-height: 15px; box-sizing: border-box; padding-left: 2px;
+  border: 1px solid #fff; overflow: hidden; white-space: nowrap; text-overflow: ellipsis; cursor: pointer; }
+.frame.ancestor { opacity: 0.5; }
+.frame.match { background: #0a2396 !important; color: #e1e1e1 !important; }
+</style>
+</head>
+<body>
+<div id="toolbar">
+<input id="search" placeholder="Search (regex)" size="40">
+<button id="reset">Reset zoom</button>
+<span id="matched"></span>
+</div>
+<div id="details"></div>
+<div id="graph"></div>
+<script>
+const data = {data};
+const ROW_HEIGHT = 17;
+const graph = document.getElementById("graph");
+const details = document.getElementById("details");
+const matched = document.getElementById("matched");
+const search = document.getElementById("search");
+let zoomed = data;
+let pattern = null;
+
+// Same hash and palette as the image export, so that a function has the same color in both
+function color(name) {
+  let hash = 0xcbf29ce484222325n;
+  for (const b of new TextEncoder().encode(name)) {
+    hash = ((hash ^ BigInt(b)) * 0x100000001b3n) & 0xffffffffffffffffn;
+  }
+  const v = Number(hash) / 18446744073709551615;
+  const rgb = [205 + Math.floor(50 * v), Math.floor(230 * (1 - v)), Math.floor(55 * v)];
+  const luma = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+  return [`rgb(${rgb.join(",")})`, luma > 128 ? "#0a0a0a" : "#e1e1e1"];
+}
+
+(function setParents(node) {
+  for (const child of node.children) {
+    child.parent = node;
+    setParents(child);
+  }
+})(data);
+
+function describe(node) {
+  const percent = (100 * node.value / data.value).toFixed(2);
+  return `${node.name} [${node.value} samples, ${percent}% of all]`;
+}
+
+function addFrame(node, x, width, level, className) {
+  const div = document.createElement("div");
+  const [background, foreground] = color(node.name);
+  div.className = className;
+  div.style.left = `${x}px`;
+  div.style.top = `${level * ROW_HEIGHT}px`;
+  div.style.width = `${width}px`;
+  div.style.background = background;
+  div.style.color = foreground;
+  div.textContent = node.name;
+  div.onmouseover = () => { details.textContent = describe(node); };
+  div.onclick = () => { zoomed = node; render(); };
+  graph.appendChild(div);
+}
+
+function render() {
+  graph.textContent = "";
+  const width = graph.clientWidth;
+  const ancestors = [];
+  for (let node = zoomed.parent; node; node = node.parent) {
+    ancestors.unshift(node);
+  }
+  ancestors.forEach((node, level) => addFrame(node, 0, width, level, "frame ancestor"));
+  let depth = 0;
+  let matchedCount = 0;
+  (function draw(node, x, nodeWidth, level, inMatch) {
+    if (nodeWidth < 1) {
+      return;
+    }
+    const isMatch = pattern !== null && pattern.test(node.name);
+    if (isMatch && !inMatch) {
+      matchedCount += node.value;
+    }
+    depth = Math.max(depth, level + 1);
+    addFrame(node, x, nodeWidth, level, isMatch ? "frame match" : "frame");
+    let childX = x;
+    for (const child of node.children) {
+      const childWidth = nodeWidth * child.value / node.value;
+      draw(child, childX, childWidth, level + 1, inMatch || isMatch);
+      childX += childWidth;
+    }
+  })(zoomed, 0, width, ancestors.length, false);
+  graph.style.height = `${depth * ROW_HEIGHT}px`;
+  matched.textContent = pattern === null ? ""
+    : `Matched: ${(100 * matchedCount / zoomed.value).toFixed(2)}% of the zoomed frame`;
+}
+
+search.oninput = () => {
+  try {
+    pattern = search.value ? new RegExp(search.value) : null;
+  } catch (e) {
+    pattern = null;
+  }
+  render();
+};
+document.getElementById("reset").onclick = () => { zoomed = data; render(); };
+window.onresize = render;
+render();
+</script>
+</body>
+</html>
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_d3_json() {
+        let fg = FlameGraph::from_string("main;foo 3\nmain;bar 1\nmain 1\n".into(), false);
+        assert_eq!(
+            to_d3_json(&fg, &ROOT_ID),
+            json!({
+                "name": "all",
+                "value": 5,
+                "children": [{
+                    "name": "main",
+                    "value": 5,
+                    "children": [
+                        {"name": "foo", "value": 3, "children": []},
+                        {"name": "bar", "value": 1, "children": []},
+                    ],
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_to_html_escapes_script_end() {
+        let fg = FlameGraph::from_string("main;</script> 1\n".into(), false);
+        let html = to_html(&fg);
+        assert!(html.contains(r#""name":"<\/script>""#));
+        assert_eq!(html.matches("</script>").count(), 1);
+    }
+
+    #[test]
+    fn test_to_png() {
+        let content = std::fs::read_to_string("tests/data/py-spy-simple.txt").unwrap();
+        let fg = FlameGraph::from_string(content, true);
+        let png = to_png(&fg).unwrap();
+        let reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+        let info = reader.info();
+        assert_eq!(info.width as usize, PNG_WIDTH);
+        assert_eq!(info.height as usize, fg.get_num_levels() * PNG_ROW_HEIGHT);
+    }
+}
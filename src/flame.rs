@@ -1,11 +1,24 @@
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 use serde::Serialize;
 
+use crate::anonymize::fnv1a;
+use crate::diff::Baseline;
+use crate::progress::ParseProgress;
+use crate::self_profile;
+use crate::summary::module_name;
+
 pub type StackIdentifier = usize;
 pub static ROOT: &str = "all";
 pub static ROOT_ID: usize = 0;
 
+/// Number of lines parsed between updates of the progress, few enough to cost nothing.
+const PROGRESS_LINES: usize = 1 << 14;
+
 #[derive(Serialize, Debug, Clone, PartialEq)]
 pub struct StackInfo {
     pub id: StackIdentifier,
@@ -15,10 +28,297 @@ pub struct StackInfo {
     pub total_count: u64,
     pub self_count: u64,
     pub parent: Option<StackIdentifier>,
-    pub children: Vec<StackIdentifier>,
+    /// Range of the children in the arena of all children of the flamegraph, see
+    /// [`FlameGraph::get_children`]. Keeping them in one array rather than a `Vec` per stack
+    /// saves an allocation per stack and keeps siblings together in memory for drawing.
+    pub children: Range<usize>,
     pub level: usize,
+    /// Position of the stack among all stacks at its level, left to right
+    pub level_index: usize,
     pub width_factor: f64,
     pub hit: bool,
+    /// Details found in the frame name, if any
+    pub frame_info: Option<Box<FrameInfo>>,
+}
+
+/// Structured metadata some profilers add to the frame names.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct FrameInfo {
+    /// Source file, e.g. from `function (file.py:12)` as produced by py-spy or
+    /// `function path/file.go:12` as produced by pprof
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    /// Shared object or executable, e.g. from `function [libc.so.6]` as produced by perf or
+    /// `libc.so.6`function` as produced by dtrace
+    pub library: Option<String>,
+}
+
+impl FrameInfo {
+    pub fn parse(name: &str) -> Option<Self> {
+        let (file, line) = match source_location(name) {
+            Some((file, line)) => (Some(file.to_string()), line),
+            None => (None, None),
+        };
+        let library = library_name(name).map(|library| library.to_string());
+        if file.is_none() && library.is_none() {
+            return None;
+        }
+        Some(Self {
+            file,
+            line,
+            library,
+        })
+    }
+}
+
+/// Whether a frame runs interpreted code, like Python, or native code, like C or Rust.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeKind {
+    Interpreted,
+    Native,
+}
+
+/// Source files of interpreted code, like the Python frames of py-spy.
+const INTERPRETED_EXTENSIONS: &[&str] = &["py", "pyw", "rb", "js", "mjs", "php", "lua", "pl"];
+/// Source files of native code, like the frames of extension modules py-spy adds with `--native`.
+const NATIVE_EXTENSIONS: &[&str] = &[
+    "c", "h", "cc", "cpp", "cxx", "hpp", "pyx", "rs", "go", "zig", "f90",
+];
+
+impl FrameInfo {
+    /// What kind of code the frame runs, from the extension of its source file or, without one,
+    /// from being in a library. `None` if there is nothing to tell from.
+    pub fn code_kind(&self) -> Option<CodeKind> {
+        let extension = self
+            .file
+            .as_deref()
+            .and_then(|file| file.rsplit_once('.'))
+            .map(|(_, extension)| extension);
+        match extension {
+            Some(extension) if INTERPRETED_EXTENSIONS.contains(&extension) => {
+                Some(CodeKind::Interpreted)
+            }
+            Some(extension) if NATIVE_EXTENSIONS.contains(&extension) => Some(CodeKind::Native),
+            _ => self.library.is_some().then_some(CodeKind::Native),
+        }
+    }
+}
+
+/// The source file and line of a frame, from a trailing `(file:line)` or ` file:line`. A column
+/// after the line is ignored.
+pub fn source_location(name: &str) -> Option<(&str, Option<u32>)> {
+    let is_path = |file: &str| {
+        file.contains('/')
+            || file.rsplit_once('.').is_some_and(|(stem, extension)| {
+                !stem.is_empty()
+                    && !extension.is_empty()
+                    && extension.chars().all(|c| c.is_ascii_alphanumeric())
+            })
+    };
+    if let (Some(start), true) = (name.rfind(" ("), name.ends_with(')')) {
+        let mut parts = name[start + 2..name.len() - 1].split(':');
+        let file = parts.next().unwrap();
+        let line = parts.next().and_then(|line| line.parse::<u32>().ok());
+        return is_path(file).then_some((file, line));
+    }
+    // Without parentheses, only a path followed by a line number, so that e.g. a C++ signature is
+    // not mistaken for one
+    let (_, location) = name.rsplit_once(' ')?;
+    let mut parts = location.split(':');
+    let file = parts.next().unwrap();
+    let line = parts.next()?.parse::<u32>().ok()?;
+    is_path(file).then_some((file, Some(line)))
+}
+
+/// The frame name without the line number of its source location, e.g. `work (app.py)` for
+/// `work (app.py:12)`, or `None` if it has no line number.
+pub fn without_line_number(name: &str) -> Option<String> {
+    let (file, line) = source_location(name)?;
+    line?;
+    // The file is part of the name, followed by the line and maybe a column
+    let end = name.rfind(file)? + file.len();
+    let suffix = if name.ends_with(')') { ")" } else { "" };
+    Some(format!("{}{}", &name[..end], suffix))
+}
+
+/// The library a frame belongs to, from a `[library]` or `([library])` added by perf, a
+/// `library`function` by dtrace or a `module.dll!function` by Windows tools. Annotations like
+/// `_[k]` for kernel frames and `[unknown]` are not libraries, nor are the activity frames of the
+/// live sampler.
+pub fn library_name(name: &str) -> Option<&str> {
+    if is_activity_frame(name) {
+        return None;
+    }
+    if let Some(stripped) = name.strip_suffix("])").or_else(|| name.strip_suffix(']')) {
+        if let Some(start) = stripped.rfind('[') {
+            let is_separate = start == 0 || stripped[..start].ends_with([' ', '(']);
+            let library = &stripped[start + 1..];
+            if is_separate && !library.is_empty() && library != "unknown" {
+                return Some(library);
+            }
+        }
+    }
+    if let Some((library, _)) = name.split_once('`') {
+        return Some(library);
+    }
+    name.split_once('!')
+        .map(|(library, _)| library)
+        .filter(|library| {
+            let library = library.to_ascii_lowercase();
+            [".dll", ".exe", ".sys"]
+                .iter()
+                .any(|extension| library.ends_with(extension))
+        })
+}
+
+/// Whether a frame is in the kernel, from the `_[k]` annotation added by stackcollapse-perf.pl or
+/// the `[kernel.kallsyms]` module perf reports for it.
+pub fn is_kernel_frame(name: &str) -> bool {
+    name.ends_with("_[k]") || name.contains("[kernel.kallsyms]")
+}
+
+/// Root frame of the samples of a thread running, as recorded by the live Python sampler when idle
+/// threads are sampled too.
+pub const ON_CPU_FRAME: &str = "[on-CPU]";
+/// Root frame of the samples of an idle thread, e.g. blocked on I/O or a lock.
+pub const WAITING_FRAME: &str = "[waiting]";
+
+/// Whether the frame splits the samples by whether the thread was active or idle.
+pub fn is_activity_frame(name: &str) -> bool {
+    name == ON_CPU_FRAME || name == WAITING_FRAME
+}
+
+/// Whether a frame is an Erlang process like `<0.123.0>`, which eflambe puts in the stacks.
+pub fn is_pid_frame(name: &str) -> bool {
+    name.strip_prefix('<')
+        .and_then(|name| name.strip_suffix('>'))
+        .is_some_and(|pid| {
+            let parts = pid.split('.').collect::<Vec<_>>();
+            parts.len() == 3
+                && parts
+                    .iter()
+                    .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+        })
+}
+
+/// What to do with the Erlang process frames in stacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PidFrames {
+    /// Move the process to the root of its stacks, for a flamegraph per process
+    Group,
+    /// Drop the processes, merging the stacks of all of them
+    Strip,
+}
+
+/// Keeps or drops whole stacks based on their frames before they are parsed, and moves or drops
+/// Erlang process frames.
+#[derive(Debug, Clone, Default)]
+pub struct StackFilter {
+    /// Only keep stacks matching this
+    pub filter: Option<regex::Regex>,
+    /// Drop stacks matching this
+    pub exclude: Option<regex::Regex>,
+    pub pids: Option<PidFrames>,
+    /// Keep about this fraction of the lines, to get a rough look at profiles too large to load
+    pub sample_rate: Option<f64>,
+}
+
+impl StackFilter {
+    pub fn is_empty(&self) -> bool {
+        self.filter.is_none()
+            && self.exclude.is_none()
+            && self.pids.is_none()
+            && self.sample_rate.is_none()
+    }
+
+    /// Keep a line with a probability that grows with its samples, so that heavy stacks are
+    /// always kept, and scale up the counts of the lines kept by chance to make up for the ones
+    /// dropped. The counts are then right on average. Whether a stack is kept depends on its hash
+    /// rather than a random draw, so that the same stacks are kept in every update of a live
+    /// profile instead of light frames coming and going.
+    fn sample<'a>(&self, line: &'a str, num_columns: usize) -> Option<Cow<'a, str>> {
+        let Some(rate) = self.sample_rate.filter(|rate| *rate < 1.0) else {
+            return Some(Cow::Borrowed(line));
+        };
+        let Some((stack, counts)) = split_counts(line, num_columns) else {
+            // Lines without counts are left for the parser to skip
+            return Some(Cow::Borrowed(line));
+        };
+        let weight = counts.iter().max().copied().unwrap_or_default();
+        let probability = (weight as f64 * rate).min(1.0);
+        if probability == 1.0 {
+            return Some(Cow::Borrowed(line));
+        }
+        if stack_fraction(stack) >= probability {
+            return None;
+        }
+        let counts = counts
+            .iter()
+            .map(|count| ((*count as f64 / probability).round() as u64).to_string())
+            .collect::<Vec<_>>();
+        Some(Cow::Owned(format!("{} {}", stack, counts.join(" "))))
+    }
+
+    fn reshape_pids<'a>(&self, stack: &'a str) -> Cow<'a, str> {
+        let Some(pids) = self.pids.filter(|_| stack.split(';').any(is_pid_frame)) else {
+            return Cow::Borrowed(stack);
+        };
+        let (pid_frames, frames): (Vec<_>, Vec<_>) =
+            stack.split(';').partition(|f| is_pid_frame(f));
+        match pids {
+            PidFrames::Group => Cow::Owned([pid_frames, frames].concat().join(";")),
+            // The samples of the process itself are kept under it
+            PidFrames::Strip if frames.is_empty() => Cow::Borrowed(stack),
+            PidFrames::Strip => Cow::Owned(frames.join(";")),
+        }
+    }
+
+    /// Filter lines of folded stacks data. Matching is done on the stack without the counts.
+    pub fn apply(&self, content: String) -> String {
+        if self.is_empty() {
+            return content;
+        }
+        let num_columns = detect_metrics(&content).len().max(1);
+        let mut out = String::with_capacity(content.len());
+        for line in content.lines() {
+            let stack = split_counts(line, num_columns).map_or(line, |(stack, _)| stack);
+            if self.filter.as_ref().is_none_or(|re| re.is_match(stack))
+                && !self.exclude.as_ref().is_some_and(|re| re.is_match(stack))
+            {
+                let Some(line) = self.sample(line, num_columns) else {
+                    continue;
+                };
+                let stack = split_counts(&line, num_columns).map_or(&*line, |(stack, _)| stack);
+                out.push_str(&self.reshape_pids(stack));
+                out.push_str(&line[stack.len()..]);
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+/// Split a line into the stack and the counts of its `num_columns` value columns. Only the value
+/// columns of the profile are counts, names may end with numbers too.
+fn split_counts(line: &str, num_columns: usize) -> Option<(&str, Vec<u64>)> {
+    let mut stack = line.trim_end();
+    let mut counts = vec![0; num_columns];
+    for count in counts.iter_mut().rev() {
+        let (rest, value) = stack.rsplit_once(' ')?;
+        *count = value.parse().ok()?;
+        stack = rest;
+    }
+    Some((stack, counts))
+}
+
+/// Where the stack falls in [0, 1), spread evenly over stacks but always the same for a stack.
+fn stack_fraction(stack: &str) -> f64 {
+    // FNV-1a barely mixes the last bytes into the high bits, which similar stacks differ in
+    let mut hash = fnv1a(stack);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    (hash >> 11) as f64 / (1u64 << 53) as f64
 }
 
 #[derive(Debug, Clone)]
@@ -27,6 +327,9 @@ pub struct SearchPattern {
     pub is_regex: bool,
     pub re: regex::Regex,
     pub is_manual: bool,
+    /// Whether each frame name seen so far matches, shared by the clones of the pattern so that
+    /// each update of a live profile only runs the regex on names not seen before
+    matches: Arc<Mutex<HashMap<String, bool>>>,
 }
 
 impl SearchPattern {
@@ -42,13 +345,74 @@ impl SearchPattern {
             is_regex,
             re,
             is_manual,
+            matches: Arc::default(),
         })
     }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        let mut matches = self.matches.lock().unwrap();
+        match matches.get(name) {
+            Some(matched) => *matched,
+            None => {
+                let matched = self.re.is_match(name);
+                matches.insert(name.to_string(), matched);
+                matched
+            }
+        }
+    }
+
+    /// Match the names not seen before and cache the results. Each name is only matched once
+    /// however many frames have it, and the names are split between threads if there are many,
+    /// as complex patterns on huge profiles would otherwise pause the search noticeably.
+    fn match_new_names<'a>(
+        &self,
+        matches: &mut HashMap<String, bool>,
+        names: impl Iterator<Item = &'a str>,
+        num_threads: usize,
+    ) {
+        let new_names = names
+            .filter(|name| !matches.contains_key(*name))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let chunk_size = new_names
+            .len()
+            .div_ceil(num_threads.max(1))
+            .max(MIN_NAMES_PER_MATCH_THREAD);
+        if new_names.len() <= chunk_size {
+            for name in new_names {
+                matches.insert(name.to_string(), self.re.is_match(name));
+            }
+            return;
+        }
+        thread::scope(|scope| {
+            let handles = new_names
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|name| self.re.is_match(name))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+            for (chunk, handle) in new_names.chunks(chunk_size).zip(handles) {
+                for (name, matched) in chunk.iter().zip(handle.join().unwrap()) {
+                    matches.insert(name.to_string(), matched);
+                }
+            }
+        });
+    }
 }
 
+/// Fewest names worth a thread of their own when matching a search pattern.
+const MIN_NAMES_PER_MATCH_THREAD: usize = 2000;
+
 #[derive(Debug, Clone)]
 pub struct Hits {
     coverage_count: u64,
+    /// Ordered by level, then left to right within a level
     ids: Vec<StackIdentifier>,
 }
 
@@ -56,6 +420,11 @@ pub struct Hits {
 pub struct Count {
     pub total: u64,
     pub own: u64,
+    /// Number of distinct call paths the function appears in
+    pub call_sites: u64,
+    /// Change in the total count since the baseline, when one is loaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delta: Option<i64>,
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -69,6 +438,24 @@ pub struct CountEntry {
 pub enum SortColumn {
     Total,
     Own,
+    CallSites,
+    Name,
+    /// Only available when a baseline is loaded
+    Delta,
+}
+
+/// Aggregated counts of the functions belonging to the same module.
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub name: String,
+    pub count: Count,
+    pub expanded: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum TableRow<'a> {
+    Group(&'a Group),
+    Entry(&'a CountEntry),
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -77,30 +464,194 @@ pub struct Ordered {
     pub num_rows: usize,
     pub sorted_column: SortColumn,
     pub search_pattern_ignored_because_of_no_match: bool,
+    /// Manual search the rows are narrowed down to, kept to apply to the entries once built
+    #[serde(skip)]
+    search_pattern: Option<SearchPattern>,
+    /// Whether the entries have been counted, which is left until the table is needed as it takes
+    /// a while for huge profiles
+    #[serde(skip)]
+    built: bool,
+    /// Fuzzy filter on the names that only applies to the table
+    #[serde(skip)]
+    pub filter: Option<String>,
+    /// Rows are grouped by module if set
+    #[serde(skip)]
+    pub groups: Option<Vec<Group>>,
+    /// Indices of the entries of each module, in the order of the entries, while grouped
+    #[serde(skip)]
+    module_entries: HashMap<String, Vec<usize>>,
+    /// Total count of each function broken down per thread, ordered by count descending
+    #[serde(skip)]
+    pub thread_counts: Option<HashMap<String, Vec<(String, u64)>>>,
+    /// Profile the rows are compared with in the delta column, given with --baseline
+    #[serde(skip)]
+    pub baseline: Option<Arc<Baseline>>,
+}
+
+impl Default for Ordered {
+    fn default() -> Self {
+        Self {
+            entries: vec![],
+            num_rows: 0,
+            sorted_column: SortColumn::Own,
+            search_pattern_ignored_because_of_no_match: false,
+            search_pattern: None,
+            built: false,
+            filter: None,
+            groups: None,
+            module_entries: HashMap::new(),
+            thread_counts: None,
+            baseline: None,
+        }
+    }
 }
 
 impl Ordered {
     pub fn set_search_pattern(&mut self, p: &SearchPattern) {
         if p.is_manual {
+            self.search_pattern = Some(p.clone());
+            self.apply_search_pattern();
+        } else {
+            self.clear_search_pattern();
+        }
+    }
+
+    pub fn clear_search_pattern(&mut self) {
+        self.search_pattern = None;
+        self.apply_search_pattern();
+    }
+
+    fn apply_search_pattern(&mut self) {
+        self.search_pattern_ignored_because_of_no_match = false;
+        if let Some(p) = &self.search_pattern {
             self.entries.iter_mut().for_each(|entry| {
-                entry.visible = p.re.is_match(&entry.name);
+                entry.visible = p.is_match(&entry.name);
             });
-            self.num_rows = self.entries.iter().filter(|entry| entry.visible).count();
-            if self.num_rows == 0 {
-                self.clear_search_pattern();
+            if self.built && !self.entries.iter().any(|entry| entry.visible) {
                 self.search_pattern_ignored_because_of_no_match = true;
             }
+        }
+        if self.search_pattern.is_none() || self.search_pattern_ignored_because_of_no_match {
+            self.entries.iter_mut().for_each(|entry| {
+                entry.visible = true;
+            });
+        }
+        self.update_num_rows();
+    }
+
+    pub fn is_built(&self) -> bool {
+        self.built
+    }
+
+    /// Set the table filter. An empty filter shows all rows.
+    pub fn set_filter(&mut self, filter: &str) {
+        self.filter = if filter.is_empty() {
+            None
         } else {
-            self.clear_search_pattern();
+            Some(filter.to_string())
+        };
+        self.update_num_rows();
+    }
+
+    /// Whether the entry is shown in the table, i.e. matching both the search pattern and the
+    /// table filter.
+    pub fn is_shown(&self, entry: &CountEntry) -> bool {
+        entry.visible
+            && self
+                .filter
+                .as_ref()
+                .is_none_or(|filter| fuzzy_match(filter, &entry.name))
+    }
+
+    pub fn shown_entries(&self) -> impl Iterator<Item = &CountEntry> {
+        self.entries.iter().filter(|entry| self.is_shown(entry))
+    }
+
+    /// Number of rows matching the search pattern but hidden by the table filter.
+    pub fn num_hidden_by_filter(&self) -> usize {
+        self.entries.iter().filter(|entry| entry.visible).count() - self.shown_entries().count()
+    }
+
+    /// Rows to display in the table, taking grouping into account.
+    pub fn rows(&self) -> Vec<TableRow<'_>> {
+        let Some(groups) = &self.groups else {
+            return self.shown_entries().map(TableRow::Entry).collect();
+        };
+        let mut rows = vec![];
+        for group in groups.iter() {
+            let mut members = self
+                .module_entries
+                .get(&group.name)
+                .into_iter()
+                .flatten()
+                .map(|i| &self.entries[*i])
+                .filter(|entry| self.is_shown(entry))
+                .peekable();
+            if members.peek().is_none() {
+                continue;
+            }
+            rows.push(TableRow::Group(group));
+            if group.expanded {
+                rows.extend(members.map(TableRow::Entry));
+            }
         }
+        rows
     }
 
-    pub fn clear_search_pattern(&mut self) {
-        self.entries.iter_mut().for_each(|entry| {
-            entry.visible = true;
-        });
-        self.num_rows = self.entries.len();
-        self.search_pattern_ignored_because_of_no_match = false;
+    /// Bucket the entries by module for the grouped rows, once the entries or their order change
+    /// rather than on every draw.
+    fn bucket_entries(&mut self) {
+        self.module_entries.clear();
+        if self.groups.is_none() {
+            return;
+        }
+        for (i, entry) in self.entries.iter().enumerate() {
+            self.module_entries
+                .entry(module_name(&entry.name).to_string())
+                .or_default()
+                .push(i);
+        }
+    }
+
+    pub fn is_grouped(&self) -> bool {
+        self.groups.is_some()
+    }
+
+    pub fn toggle_group_expanded(&mut self, name: &str) {
+        if let Some(group) = self
+            .groups
+            .as_mut()
+            .and_then(|groups| groups.iter_mut().find(|g| g.name == name))
+        {
+            group.expanded = !group.expanded;
+        }
+        self.update_num_rows();
+    }
+
+    fn update_num_rows(&mut self) {
+        self.num_rows = self.rows().len();
+    }
+
+    /// Rows shown in the table as tab separated values with a header, to paste into spreadsheets.
+    pub fn to_tsv(&self, total_count: u64) -> String {
+        let percent = |count: u64| 100.0 * count as f64 / total_count.max(1) as f64;
+        let mut out = "Name\tTotal\tTotal %\tOwn\tOwn %\tCall sites\n".to_string();
+        for row in self.rows() {
+            let (name, count) = match row {
+                TableRow::Group(group) => (&group.name, &group.count),
+                TableRow::Entry(entry) => (&entry.name, &entry.count),
+            };
+            out += &format!(
+                "{}\t{}\t{:.2}\t{}\t{:.2}\t{}\n",
+                name,
+                count.total,
+                percent(count.total),
+                count.own,
+                percent(count.own),
+                count.call_sites
+            );
+        }
+        out
     }
 
     pub fn set_sort_column(&mut self, column: SortColumn) {
@@ -108,17 +659,289 @@ impl Ordered {
             return;
         }
         self.sorted_column = column;
+        Self::sort_by_column(&mut self.entries, column, |e| (&e.name, &e.count));
+        if let Some(groups) = self.groups.as_mut() {
+            Self::sort_by_column(groups, column, |g| (&g.name, &g.count));
+        }
+        self.bucket_entries();
+    }
+
+    fn sort_by_column<T, F>(items: &mut [T], column: SortColumn, key: F)
+    where
+        F: Fn(&T) -> (&String, &Count),
+    {
         match column {
             SortColumn::Total => {
-                self.entries
-                    .sort_by_key(|entry| (entry.count.total, entry.name.clone()));
+                items.sort_by_key(|item| {
+                    let (name, count) = key(item);
+                    (count.total, name.clone())
+                });
             }
             SortColumn::Own => {
-                self.entries
-                    .sort_by_key(|entry| (entry.count.own, entry.name.clone()));
+                items.sort_by_key(|item| {
+                    let (name, count) = key(item);
+                    (count.own, name.clone())
+                });
+            }
+            SortColumn::CallSites => {
+                items.sort_by_key(|item| {
+                    let (name, count) = key(item);
+                    (count.call_sites, count.total, name.clone())
+                });
+            }
+            SortColumn::Delta => {
+                // Most grown first, most shrunk last
+                items.sort_by_key(|item| {
+                    let (name, count) = key(item);
+                    (count.delta.unwrap_or(0), count.total, name.clone())
+                });
+            }
+            SortColumn::Name => {
+                // Names are sorted in ascending order
+                items.sort_by(|a, b| {
+                    let (a_name, a_count) = key(a);
+                    let (b_name, b_count) = key(b);
+                    b_name.cmp(a_name).then(a_count.own.cmp(&b_count.own))
+                });
+            }
+        }
+        items.reverse();
+    }
+}
+
+/// Whether the frame is a thread or process frame as inserted by py-spy with `--threads` or
+/// `--subprocesses`.
+pub fn is_thread_frame(name: &str) -> bool {
+    name.starts_with("thread (") || name.starts_with("process ")
+}
+
+/// Whether the frame is most likely in the code of the application rather than in the runtime, a
+/// library or the kernel, judging from the library or source file in its name.
+pub fn is_app_frame(name: &str) -> bool {
+    if is_thread_frame(name) || is_pid_frame(name) || is_kernel_frame(name) {
+        return false;
+    }
+    if library_name(name).is_some() || name.starts_with('[') || name.starts_with("0x") {
+        return false;
+    }
+    match source_location(name) {
+        Some((file, _)) => ![
+            "site-packages/",
+            "dist-packages/",
+            "/lib/python",
+            "/usr/",
+            "<",
+        ]
+        .iter()
+        .any(|dir| file.contains(dir)),
+        None => true,
+    }
+}
+
+/// Process frame at the top of the flamegraph, as inserted by py-spy with `--subprocesses` along
+/// with the frames of its parent processes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessFrame {
+    pub full_name: String,
+    pub name: String,
+    /// Number of parent processes above it
+    pub depth: usize,
+    pub total_count: u64,
+}
+
+/// Which frame of each stack becomes its root when grouping stacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GroupBy {
+    /// The process, from py-spy with `--subprocesses` or an Erlang pid
+    Process,
+    /// The thread, from py-spy with `--threads`
+    Thread,
+    /// The first frame in the application, leaving out the runtime frames above it
+    App,
+}
+
+impl GroupBy {
+    fn missing_frame(&self) -> &'static str {
+        match self {
+            GroupBy::Process => "[no process]",
+            GroupBy::Thread => "[no thread]",
+            GroupBy::App => "[no app frame]",
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            GroupBy::Process => name.starts_with("process ") || is_pid_frame(name),
+            GroupBy::Thread => name.starts_with("thread ("),
+            GroupBy::App => is_app_frame(name),
+        }
+    }
+}
+
+/// Whether all characters of the pattern appear in the text in order. Case insensitive unless the
+/// pattern contains uppercase characters.
+pub fn fuzzy_match(pattern: &str, text: &str) -> bool {
+    let case_sensitive = pattern.chars().any(|c| c.is_uppercase());
+    let mut text_chars = text.chars();
+    pattern.chars().all(|p| {
+        text_chars.any(|t| {
+            if case_sensitive {
+                t == p
+            } else {
+                t.to_lowercase().eq(p.to_lowercase())
+            }
+        })
+    })
+}
+
+/// One of several value columns of a profile, e.g. CPU time or allocated bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metric {
+    pub name: String,
+    pub unit: Option<String>,
+}
+
+impl Metric {
+    /// Parse a `name/unit` pair as in pprof sample types, e.g. `alloc_space/bytes`.
+    fn parse(s: &str) -> Self {
+        match s.split_once('/') {
+            Some((name, unit)) => Self {
+                name: name.to_string(),
+                unit: Some(unit.to_string()),
+            },
+            None => Self {
+                name: s.to_string(),
+                unit: None,
+            },
+        }
+    }
+
+    /// Human readable count in the unit of the metric.
+    pub fn format_count(&self, count: u64) -> String {
+        let scaled = |units: &[(&str, f64)]| {
+            let (suffix, factor) = units
+                .iter()
+                .rev()
+                .find(|(_, factor)| count as f64 >= *factor)
+                .unwrap_or(&units[0]);
+            if *factor == 1.0 {
+                format!("{} {}", count, suffix)
+            } else {
+                format!("{:.1} {}", count as f64 / factor, suffix)
+            }
+        };
+        match self.unit.as_deref() {
+            Some("bytes") => scaled(&[
+                ("B", 1.0),
+                ("KiB", 1024.0),
+                ("MiB", 1024.0 * 1024.0),
+                ("GiB", 1024.0 * 1024.0 * 1024.0),
+            ]),
+            Some("nanoseconds") => scaled(&[("ns", 1.0), ("µs", 1e3), ("ms", 1e6), ("s", 1e9)]),
+            Some("microseconds") => scaled(&[("µs", 1.0), ("ms", 1e3), ("s", 1e6)]),
+            Some("milliseconds") => scaled(&[("ms", 1.0), ("s", 1e3)]),
+            Some("count") | None => format!("{} {}", count, self.name),
+            Some(unit) => format!("{} {}", count, unit),
+        }
+    }
+}
+
+/// Value columns of a profile with more than one, from the first stack line, named by a
+/// `# metrics: name/unit ...` comment line if there is one. Empty for the usual single count,
+/// unless the comment line names it.
+fn detect_metrics(content: &str) -> Vec<Metric> {
+    let mut names = None;
+    for line in content.lines() {
+        if let Some(header) = line.strip_prefix("# metrics:") {
+            names = Some(header.split_whitespace().collect::<Vec<_>>());
+            continue;
+        }
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let mut num_columns = 0;
+        let mut stack = line;
+        while let Some((rest, count)) = stack.rsplit_once(' ') {
+            if rest.is_empty() || count.parse::<u64>().is_err() {
+                break;
+            }
+            num_columns += 1;
+            stack = rest;
+        }
+        if let Some([name]) = names.as_deref() {
+            if num_columns > 0 {
+                return vec![Metric::parse(name)];
             }
         }
-        self.entries.reverse();
+        if num_columns < 2 {
+            return vec![];
+        }
+        return match names {
+            Some(names) if names.len() == num_columns => {
+                names.into_iter().map(Metric::parse).collect()
+            }
+            _ => (1..=num_columns)
+                .map(|i| Metric {
+                    name: format!("metric {}", i),
+                    unit: None,
+                })
+                .collect(),
+        };
+    }
+    vec![]
+}
+
+/// Split a line into the stack and its count in the `column`-th of `num_columns` value columns.
+fn split_count(line: &str, num_columns: usize, column: usize) -> Option<(&str, u64)> {
+    let mut stack = line;
+    let mut selected = 0;
+    for i in (0..num_columns).rev() {
+        let (rest, count) = stack.rsplit_once(' ')?;
+        let count = count.parse::<u64>().ok()?;
+        if i == column {
+            selected = count;
+        }
+        stack = rest;
+    }
+    if stack.is_empty() {
+        return None;
+    }
+    Some((stack, selected))
+}
+
+/// Children of each stack while parsing, as linked lists through flat arrays rather than a `Vec`
+/// per stack. They are laid out in the arena of children once parsing is done.
+#[derive(Debug, Default)]
+struct ChildLinks {
+    first: Vec<StackIdentifier>,
+    last: Vec<StackIdentifier>,
+    next_sibling: Vec<StackIdentifier>,
+}
+
+/// End of a list in [`ChildLinks`].
+const NO_STACK: StackIdentifier = StackIdentifier::MAX;
+
+impl ChildLinks {
+    fn push_stack(&mut self) {
+        self.first.push(NO_STACK);
+        self.last.push(NO_STACK);
+        self.next_sibling.push(NO_STACK);
+    }
+
+    fn add_child(&mut self, parent_id: StackIdentifier, child_id: StackIdentifier) {
+        match self.last[parent_id] {
+            NO_STACK => self.first[parent_id] = child_id,
+            last => self.next_sibling[last] = child_id,
+        }
+        self.last[parent_id] = child_id;
+    }
+
+    /// Children of the stack in the order they were added.
+    fn children(&self, stack_id: StackIdentifier) -> impl Iterator<Item = StackIdentifier> + '_ {
+        let first = Some(self.first[stack_id]).filter(|id| *id != NO_STACK);
+        std::iter::successors(first, |id| {
+            Some(self.next_sibling[*id]).filter(|id| *id != NO_STACK)
+        })
     }
 }
 
@@ -126,19 +949,57 @@ impl Ordered {
 pub struct FlameGraph {
     data: String,
     stacks: Vec<StackInfo>,
+    /// Children of all stacks, with those of each stack next to each other
+    children: Vec<StackIdentifier>,
     levels: Vec<Vec<StackIdentifier>>,
     pub ordered_stacks: Ordered,
     hits: Option<Hits>,
     sorted: bool,
+    num_skipped_lines: usize,
+    /// Value columns if there are several, along with the one driving the counts
+    metrics: Vec<Metric>,
+    metric: usize,
 }
 
 impl FlameGraph {
-    pub fn from_string(mut content: String, sorted: bool) -> Self {
+    pub fn from_string(content: String, sorted: bool) -> Self {
+        Self::from_string_with_metric(content, sorted, 0)
+    }
+
+    /// Like [`FlameGraph::from_string`], with the counts taken from the `metric`-th value column
+    /// for profiles with several of them.
+    pub fn from_string_with_metric(content: String, sorted: bool, metric: usize) -> Self {
+        Self::parse(content, sorted, metric, None)
+    }
+
+    /// Like [`FlameGraph::from_string_with_metric`], reporting how far it has got as it goes for
+    /// a progress bar to show.
+    pub fn from_string_with_progress(
+        content: String,
+        sorted: bool,
+        metric: usize,
+        progress: &ParseProgress,
+    ) -> Self {
+        Self::parse(content, sorted, metric, Some(progress))
+    }
+
+    fn parse(
+        mut content: String,
+        sorted: bool,
+        metric: usize,
+        progress: Option<&ParseProgress>,
+    ) -> Self {
+        let _phase = self_profile::phase("parse");
         // Make sure content ends with newline to simplify parsing
         if !content.ends_with('\n') {
             content.push('\n');
         }
+        let metrics = detect_metrics(&content);
+        let num_columns = metrics.len().max(1);
+        let metric = metric.min(num_columns - 1);
         let mut stacks = Vec::<StackInfo>::new();
+        let mut children = ChildLinks::default();
+        children.push_stack();
         stacks.push(StackInfo {
             id: ROOT_ID,
             line_index: 0,
@@ -148,41 +1009,48 @@ impl FlameGraph {
             self_count: 0,
             width_factor: 0.0,
             parent: None,
-            children: Vec::<StackIdentifier>::new(),
+            children: 0..0,
             level: 0,
+            level_index: 0,
             hit: false,
+            frame_info: None,
         });
         let mut last_line_index = 0;
-        let mut counts: HashMap<String, Count> = HashMap::new();
+        let mut line_number = 0;
+        let mut num_skipped_lines = 0;
         for line_index in content
             .char_indices()
             .filter(|(_, c)| *c == '\n')
             .map(|(i, _)| i)
         {
             let line = &content[last_line_index..line_index];
-            #[allow(clippy::unnecessary_unwrap)]
-            let line_and_count = match line.rsplit_once(' ') {
-                Some((line, count)) => {
-                    let parsed_count = count.parse::<u64>();
-                    if line.is_empty() || parsed_count.is_err() {
-                        None
-                    } else {
-                        Some((line, parsed_count.unwrap()))
+            line_number += 1;
+            if let (Some(progress), 0) = (progress, line_number % PROGRESS_LINES) {
+                progress.update(line_index, stacks.len());
+            }
+            let line_and_count = split_count(line, num_columns, metric);
+            if line_and_count.is_none() || line.starts_with('#') {
+                if !line.trim().is_empty() && !line.starts_with('#') {
+                    // Only log the first one as there may be many, e.g. in a file of another format
+                    if num_skipped_lines == 0 {
+                        tracing::warn!(line_number, line, "Skipping line without a valid count");
                     }
+                    num_skipped_lines += 1;
                 }
-                _ => None,
-            };
-            if line_and_count.is_none() || line.starts_with('#') {
                 last_line_index = line_index + 1;
                 continue;
             }
             let (line, count) = line_and_count.unwrap();
+            // Stacks without any of the metric, e.g. no allocations, would only add empty frames
+            if count == 0 && num_columns > 1 {
+                last_line_index = line_index + 1;
+                continue;
+            }
 
             stacks[ROOT_ID].total_count += count;
             let mut parent_id = ROOT_ID;
             let mut level = 1;
             let mut last_delim_index = 0;
-            let mut counted_names = HashSet::<String>::new();
             for delim_index in line
                 .char_indices()
                 .filter(|(_, c)| *c == ';')
@@ -190,8 +1058,7 @@ impl FlameGraph {
             {
                 let stack_id = FlameGraph::update_one(
                     &mut stacks,
-                    &mut counts,
-                    &mut counted_names,
+                    &mut children,
                     &content,
                     count,
                     last_line_index,
@@ -207,8 +1074,7 @@ impl FlameGraph {
             }
             FlameGraph::update_one(
                 &mut stacks,
-                &mut counts,
-                &mut counted_names,
+                &mut children,
                 &content,
                 count,
                 last_line_index,
@@ -221,45 +1087,103 @@ impl FlameGraph {
             last_line_index = line_index + 1;
         }
 
-        let ordered = FlameGraph::get_ordered_stacks(&counts);
+        if num_skipped_lines > 0 {
+            tracing::warn!(num_skipped_lines, "Skipped lines without a valid count");
+        }
+
+        let stacks_len = stacks.len();
         let mut out = Self {
             data: content,
             stacks,
+            children: Vec::with_capacity(stacks_len.saturating_sub(1)),
             levels: vec![],
-            ordered_stacks: ordered,
+            ordered_stacks: Ordered::default(),
             hits: None,
             sorted,
+            num_skipped_lines,
+            metrics,
+            metric,
         };
-        out.populate_levels(&ROOT_ID, 0, None);
+        out.populate_levels(&children, &ROOT_ID, 0, None);
         out
     }
 
-    fn get_ordered_stacks(counts: &HashMap<String, Count>) -> Ordered {
-        let mut counts = counts.iter().collect::<Vec<_>>();
-        counts.sort_by_key(|(short_name, count)| (count.own, short_name.to_string()));
-        let ordered_by_self_count = counts
-            .iter()
-            .rev()
-            .map(|x| CountEntry {
-                name: x.0.to_string(),
-                count: x.1.clone(),
+    /// Count the rows of the table of functions, if not done yet. Parsing leaves this out so that
+    /// huge profiles, and each update of a live one, load faster when the table is not shown.
+    pub fn build_ordered_stacks(&mut self) {
+        if self.ordered_stacks.built {
+            return;
+        }
+        let _phase = self_profile::phase("ordered_stacks");
+        let mut entries = self
+            .get_function_counts(None)
+            .into_iter()
+            .map(|(name, count)| CountEntry {
+                name: name.to_string(),
+                count,
                 visible: true,
             })
             .collect::<Vec<_>>();
-        let num_rows = ordered_by_self_count.len();
-        Ordered {
-            entries: ordered_by_self_count,
-            num_rows,
-            sorted_column: SortColumn::Own,
-            search_pattern_ignored_because_of_no_match: false,
+        if let Some(baseline) = &self.ordered_stacks.baseline {
+            let total_count = self.total_count();
+            for entry in entries.iter_mut() {
+                entry.count.delta =
+                    Some(baseline.function_delta(&entry.name, entry.count.total, total_count));
+            }
         }
+        Ordered::sort_by_column(&mut entries, self.ordered_stacks.sorted_column, |e| {
+            (&e.name, &e.count)
+        });
+        self.ordered_stacks.entries = entries;
+        self.ordered_stacks.built = true;
+        self.ordered_stacks.bucket_entries();
+        self.ordered_stacks.apply_search_pattern();
     }
 
-    #[allow(clippy::too_many_arguments)]
-    fn update_one(
-        stacks: &mut Vec<StackInfo>,
-        counts: &mut HashMap<String, Count>,
-        counted_names: &mut HashSet<String>,
+    /// Counts of every function over all the stacks it appears in. A sample is counted once in
+    /// the total count of a function even if the function appears multiple times in the stack.
+    pub fn function_counts(&self) -> HashMap<&str, Count> {
+        self.get_function_counts(None)
+    }
+
+    fn get_function_counts(&self, only: Option<&str>) -> HashMap<&str, Count> {
+        let mut counts: HashMap<&str, Count> = HashMap::new();
+        let mut on_stack: HashMap<&str, usize> = HashMap::new();
+        let mut to_visit = vec![(ROOT_ID, false)];
+        while let Some((stack_id, exiting)) = to_visit.pop() {
+            let stack = self.get_stack(&stack_id).unwrap();
+            let name = self.get_stack_short_name_from_info(stack);
+            let counted = stack_id != ROOT_ID && only.is_none_or(|only| only == name);
+            if exiting {
+                if counted {
+                    *on_stack.get_mut(name).unwrap() -= 1;
+                }
+                continue;
+            }
+            if counted {
+                let depth = on_stack.entry(name).or_default();
+                let count = counts.entry(name).or_default();
+                if *depth == 0 {
+                    count.total += stack.total_count;
+                }
+                count.own += stack.self_count;
+                count.call_sites += 1;
+                *depth += 1;
+            }
+            to_visit.push((stack_id, true));
+            to_visit.extend(
+                self.get_children(stack)
+                    .iter()
+                    .map(|child_id| (*child_id, false)),
+            );
+        }
+        counts
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn update_one(
+        stacks: &mut Vec<StackInfo>,
+        children: &mut ChildLinks,
         content: &str,
         count: u64,
         line_index: usize,
@@ -274,17 +1198,11 @@ impl FlameGraph {
         // Invariant: parent always exists. We can just check the short name to
         // check if the parent already contains the child, since the prior
         // prefixes should always match (definition of a parent).
-        let parent_stack = stacks.get(parent_id).unwrap();
-
         // Add or update the current stack
-        let current_stack_id_if_exists = parent_stack
-            .children
-            .iter()
-            .find(|child_id| {
-                let child = stacks.get(**child_id).unwrap();
-                &content[child.start_index..child.end_index] == short_name
-            })
-            .cloned();
+        let current_stack_id_if_exists = children.children(parent_id).find(|child_id| {
+            let child = stacks.get(*child_id).unwrap();
+            &content[child.start_index..child.end_index] == short_name
+        });
         let stack_id = if let Some(stack_id) = current_stack_id_if_exists {
             stack_id
         } else {
@@ -297,12 +1215,15 @@ impl FlameGraph {
                 self_count: 0,
                 width_factor: 0.0,
                 parent: Some(parent_id),
-                children: Vec::<StackIdentifier>::new(),
+                children: 0..0,
                 level,
+                level_index: 0,
                 hit: false,
+                frame_info: FrameInfo::parse(short_name).map(Box::new),
             });
             let stack_id = stacks.len() - 1;
-            stacks.get_mut(parent_id).unwrap().children.push(stack_id);
+            children.push_stack();
+            children.add_child(parent_id, stack_id);
             stack_id
         };
         let info = stacks.get_mut(stack_id).unwrap();
@@ -311,21 +1232,12 @@ impl FlameGraph {
             info.self_count += count;
         }
 
-        // Update summarized counts
-        let summarized_count = counts.entry(short_name.to_string()).or_default();
-        if !counted_names.contains(short_name) {
-            counted_names.insert(short_name.to_string());
-            summarized_count.total += count;
-        }
-        if is_self {
-            summarized_count.own += count;
-        }
-
         stack_id
     }
 
     fn populate_levels(
         &mut self,
+        children: &ChildLinks,
         stack_id: &StackIdentifier,
         level: usize,
         parent_total_count_and_width_factor: Option<(u64, f64)>,
@@ -334,6 +1246,7 @@ impl FlameGraph {
         if self.levels.len() <= level {
             self.levels.push(vec![]);
         }
+        let level_index = self.levels[level].len();
         self.levels[level].push(*stack_id);
 
         // Calculate width_factor of the current stack
@@ -347,38 +1260,75 @@ impl FlameGraph {
             1.0
         };
 
-        // Sort children
-        let sorted_children = if self.sorted {
-            let mut sorted_children = stack.children.clone();
-            sorted_children.sort_by_key(|child_id| {
-                self.stacks
+        // Lay out the children in the arena, then sort them in place
+        let start = self.children.len();
+        self.children.extend(children.children(*stack_id));
+        let end = self.children.len();
+        if self.sorted {
+            let stacks = &self.stacks;
+            let stack_children = &mut self.children[start..end];
+            stack_children.sort_by_key(|child_id| {
+                stacks
                     .get(*child_id)
                     .map(|child| child.total_count)
                     .unwrap_or(0)
             });
-            sorted_children.reverse();
-            Some(sorted_children)
-        } else {
-            None
-        };
+            stack_children.reverse();
+        }
 
         // Make the updates to the current stack
         let stack = self.stacks.get_mut(*stack_id).unwrap();
         stack.width_factor = width_factor;
-        if let Some(sorted_children) = sorted_children {
-            stack.children = sorted_children;
-        }
+        stack.level_index = level_index;
+        stack.children = start..end;
 
         // Move on to children
-        for child_id in stack.children.clone().iter() {
-            self.populate_levels(child_id, level + 1, Some((total_count, width_factor)));
+        for i in start..end {
+            let child_id = self.children[i];
+            self.populate_levels(
+                children,
+                &child_id,
+                level + 1,
+                Some((total_count, width_factor)),
+            );
         }
     }
 
+    /// Raw folded stacks data the flamegraph was built from.
+    pub fn data(&self) -> &str {
+        &self.data
+    }
+
+    pub fn is_sorted(&self) -> bool {
+        self.sorted
+    }
+
+    /// Full name and count of every stack, with either the total or the self count. The self
+    /// counts are the folded stacks the flamegraph could be rebuilt from.
+    pub fn stack_counts(&self, self_count: bool) -> impl Iterator<Item = (&str, u64)> {
+        self.stacks.iter().map(move |stack| {
+            let count = if self_count {
+                stack.self_count
+            } else {
+                stack.total_count
+            };
+            (self.get_stack_full_name_from_info(stack), count)
+        })
+    }
+
+    /// Number of non-empty lines that were not valid folded stacks and were skipped.
+    pub fn num_skipped_lines(&self) -> usize {
+        self.num_skipped_lines
+    }
+
     pub fn get_stack(&self, stack_id: &StackIdentifier) -> Option<&StackInfo> {
         self.stacks.get(*stack_id)
     }
 
+    pub fn get_children(&self, stack: &StackInfo) -> &[StackIdentifier] {
+        &self.children[stack.children.clone()]
+    }
+
     pub fn get_stack_short_name(&self, stack_id: &StackIdentifier) -> Option<&str> {
         self.get_stack(stack_id)
             .map(|stack| self.get_stack_short_name_from_info(stack))
@@ -445,25 +1395,663 @@ impl FlameGraph {
         ancestors
     }
 
+    /// Deepest stack that is an ancestor of (or the same as) all the given stacks.
+    pub fn get_lowest_common_ancestor(
+        &self,
+        stack_ids: &[StackIdentifier],
+    ) -> Option<StackIdentifier> {
+        let (first, rest) = stack_ids.split_first()?;
+        // Path from the root down to the first stack, shortened to the prefix shared by the others
+        let mut common_path = self.get_ancestors(first);
+        common_path.reverse();
+        for stack_id in rest {
+            let ancestors = self.get_ancestors(stack_id);
+            let shared = common_path
+                .iter()
+                .take_while(|id| ancestors.contains(id))
+                .count();
+            common_path.truncate(shared);
+        }
+        common_path.last().copied()
+    }
+
+    /// The frame in which the `sample`-th sample under `stack_id` ends, counting the samples ending
+    /// in a frame before those of its children. Picking `sample` uniformly at random picks stacks
+    /// in proportion to their samples.
+    pub fn get_stack_of_sample(
+        &self,
+        stack_id: &StackIdentifier,
+        mut sample: u64,
+    ) -> Option<StackIdentifier> {
+        let mut stack = self.get_stack(stack_id)?;
+        if sample >= stack.total_count {
+            return None;
+        }
+        'descend: loop {
+            if sample < stack.self_count {
+                return Some(stack.id);
+            }
+            sample -= stack.self_count;
+            for child in self
+                .get_children(stack)
+                .iter()
+                .filter_map(|id| self.get_stack(id))
+            {
+                if sample < child.total_count {
+                    stack = child;
+                    continue 'descend;
+                }
+                sample -= child.total_count;
+            }
+            // Only if the counts are inconsistent, e.g. an empty flamegraph
+            return None;
+        }
+    }
+
     pub fn get_descendants(&self, stack_id: &StackIdentifier) -> Vec<StackIdentifier> {
         let mut descendants = vec![];
         let mut stack_ids = vec![*stack_id];
         while let Some(stack_id) = stack_ids.pop() {
             descendants.push(stack_id);
             if let Some(stack) = self.get_stack(&stack_id) {
-                stack_ids.extend(stack.children.iter().copied());
+                stack_ids.extend(self.get_children(stack).iter().copied());
             }
         }
         descendants
     }
 
+    /// Counts of a function over all the stacks it appears in, as in the table view.
+    pub fn get_function_count(&self, short_name: &str) -> Option<Count> {
+        if self.ordered_stacks.built {
+            self.ordered_stacks
+                .entries
+                .iter()
+                .find(|entry| entry.name == short_name)
+                .map(|entry| entry.count.clone())
+        } else {
+            self.get_function_counts(Some(short_name))
+                .remove(short_name)
+        }
+    }
+
+    /// Occurrence of a function with the most samples.
+    pub fn get_heaviest_stack_by_short_name(&self, short_name: &str) -> Option<StackIdentifier> {
+        self.get_heaviest_stack_matching(|name| name == short_name)
+    }
+
+    /// Flamegraph of only the subtree of the heaviest stack whose short name satisfies `matches`,
+    /// with that stack as the single child of the root.
+    pub fn reroot<F>(&self, matches: F) -> Option<FlameGraph>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let stack_id = self.get_heaviest_stack_matching(matches)?;
+        let content = self.to_folded(&stack_id, |name| name.to_string());
+        let mut flamegraph = FlameGraph::from_string(content, self.sorted);
+        flamegraph.inherit_metric(self);
+        Some(flamegraph)
+    }
+
+    /// Frames all samples pass through from the root, e.g. `_start;__libc_start_main;main`, down
+    /// to the first one with several callees or samples of its own.
+    pub fn common_prefix(&self) -> Vec<StackIdentifier> {
+        let mut prefix = vec![];
+        let mut stack = self.root();
+        while let ([child_id], 0) = (self.get_children(stack), stack.self_count) {
+            prefix.push(*child_id);
+            stack = self.get_stack(child_id).unwrap();
+        }
+        prefix
+    }
+
+    /// Flamegraph with the frames common to all stacks collapsed into one named after the first and
+    /// last of them, or `None` if there are fewer than two.
+    pub fn collapse_common_prefix(&self) -> Option<FlameGraph> {
+        let prefix = self.common_prefix();
+        let (first, last) = match prefix.as_slice() {
+            [first, .., last] => (*first, *last),
+            _ => return None,
+        };
+        let name = format!(
+            "{} .. {} ({} frames)",
+            self.get_stack_short_name(&first)?,
+            self.get_stack_short_name(&last)?,
+            prefix.len()
+        );
+        // The last common frame comes first, the rest are its callees
+        let mut is_last_common = true;
+        let content = self.to_folded(&last, |frame| {
+            if std::mem::take(&mut is_last_common) {
+                name.clone()
+            } else {
+                frame.to_string()
+            }
+        });
+        let mut flamegraph = FlameGraph::from_string(content, self.sorted);
+        flamegraph.inherit_metric(self);
+        Some(flamegraph)
+    }
+
+    /// Flamegraph with the stacks grouped under the frame of each picked by `group_by`, or `None` if
+    /// no stack has one. Process and thread frames are moved to the root, while the frames above
+    /// the first application frame are left out. Stacks without such a frame are grouped under
+    /// `[no process]`, `[no thread]` or `[no app frame]`.
+    pub fn group_by(&self, group_by: GroupBy) -> Option<FlameGraph> {
+        let mut content = String::new();
+        let mut found = false;
+        let mut frames = vec![];
+        let mut to_visit = vec![(ROOT_ID, false)];
+        while let Some((stack_id, exiting)) = to_visit.pop() {
+            let stack = self.get_stack(&stack_id).unwrap();
+            if stack_id != ROOT_ID {
+                if exiting {
+                    frames.pop();
+                    continue;
+                }
+                frames.push(self.get_stack_short_name_from_info(stack));
+            }
+            if stack.self_count > 0 && !frames.is_empty() {
+                let stack_frames = match frames.iter().position(|name| group_by.matches(name)) {
+                    Some(index) if group_by == GroupBy::App => frames[index..].to_vec(),
+                    // The process and its parent processes keep their order, as a process tree
+                    Some(_) if group_by == GroupBy::Process => {
+                        let (mut stack_frames, others): (Vec<_>, Vec<_>) =
+                            frames.iter().partition(|name| group_by.matches(name));
+                        stack_frames.extend(others);
+                        stack_frames
+                    }
+                    Some(index) => {
+                        let mut stack_frames = frames.clone();
+                        let group = stack_frames.remove(index);
+                        stack_frames.insert(0, group);
+                        stack_frames
+                    }
+                    None => {
+                        let mut stack_frames = frames.clone();
+                        stack_frames.insert(0, group_by.missing_frame());
+                        stack_frames
+                    }
+                };
+                found |= stack_frames[0] != group_by.missing_frame();
+                content += &format!("{} {}\n", stack_frames.join(";"), stack.self_count);
+            }
+            if stack_id != ROOT_ID {
+                to_visit.push((stack_id, true));
+            }
+            to_visit.extend(self.get_children(stack).iter().rev().map(|id| (*id, false)));
+        }
+        if !found {
+            return None;
+        }
+        let mut flamegraph = FlameGraph::from_string(content, self.sorted);
+        flamegraph.inherit_metric(self);
+        Some(flamegraph)
+    }
+
+    /// Process frames at the top of the flamegraph, each followed by its child processes, the
+    /// ones with the most samples first.
+    pub fn processes(&self) -> Vec<ProcessFrame> {
+        let mut processes = vec![];
+        let mut to_visit = vec![(ROOT_ID, 0)];
+        while let Some((stack_id, depth)) = to_visit.pop() {
+            let stack = self.get_stack(&stack_id).unwrap();
+            if stack_id != ROOT_ID {
+                processes.push(ProcessFrame {
+                    full_name: self.get_stack_full_name_from_info(stack).to_string(),
+                    name: self.get_stack_short_name_from_info(stack).to_string(),
+                    depth,
+                    total_count: stack.total_count,
+                });
+            }
+            let mut children = self
+                .get_children(stack)
+                .iter()
+                .filter_map(|id| self.get_stack(id).map(|child| (*id, child)))
+                .filter(|(_, child)| {
+                    GroupBy::Process.matches(self.get_stack_short_name_from_info(child))
+                })
+                .collect::<Vec<_>>();
+            // Popped in reverse, the heaviest first
+            children.sort_by_key(|(_, child)| child.total_count);
+            let depth = if stack_id == ROOT_ID { 0 } else { depth + 1 };
+            to_visit.extend(children.into_iter().map(|(id, _)| (id, depth)));
+        }
+        processes
+    }
+
+    /// Thread frames at the top of the flamegraph, or under the process frames there, from left to
+    /// right.
+    pub fn thread_frames(&self) -> Vec<StackIdentifier> {
+        let mut threads = vec![];
+        let mut to_visit = vec![ROOT_ID];
+        while let Some(stack_id) = to_visit.pop() {
+            let stack = self.get_stack(&stack_id).unwrap();
+            let name = self.get_stack_short_name_from_info(stack);
+            if stack_id != ROOT_ID && GroupBy::Thread.matches(name) {
+                threads.push(stack_id);
+            } else if stack_id == ROOT_ID || GroupBy::Process.matches(name) {
+                to_visit.extend(self.get_children(stack).iter().rev());
+            }
+        }
+        threads
+    }
+
+    /// Value columns of the profile, empty if it only has the usual count.
+    pub fn metrics(&self) -> &[Metric] {
+        &self.metrics
+    }
+
+    pub fn metric_index(&self) -> usize {
+        self.metric
+    }
+
+    /// Metric driving the counts, if the profile has several.
+    pub fn metric(&self) -> Option<&Metric> {
+        self.metrics.get(self.metric)
+    }
+
+    /// Keep showing the metric of the flamegraph this one is derived from through the folded
+    /// format, which only carries the counts of that metric.
+    pub fn inherit_metric(&mut self, other: &FlameGraph) {
+        self.metrics = other.metrics.clone();
+        self.metric = other.metric;
+    }
+
+    pub fn has_kernel_frames(&self) -> bool {
+        self.stacks
+            .iter()
+            .skip(1)
+            .any(|stack| is_kernel_frame(self.get_stack_short_name_from_info(stack)))
+    }
+
+    /// The flamegraph without kernel frames, their samples counting as samples of their nearest
+    /// caller in user space. Stacks entirely in the kernel are left out.
+    pub fn without_kernel_frames(&self) -> FlameGraph {
+        let num_columns = self.metrics.len().max(1);
+        let mut content = String::with_capacity(self.data.len());
+        for line in self.data.lines() {
+            let Some((stack, _)) = split_count(line, num_columns, 0) else {
+                // Comments such as the metrics header
+                content.push_str(line);
+                content.push('\n');
+                continue;
+            };
+            let frames = stack
+                .split(';')
+                .filter(|frame| !is_kernel_frame(frame))
+                .collect::<Vec<_>>();
+            if frames.is_empty() {
+                continue;
+            }
+            content += &frames.join(";");
+            content += &line[stack.len()..];
+            content.push('\n');
+        }
+        FlameGraph::from_string_with_metric(content, self.sorted, self.metric)
+    }
+
+    pub fn has_activity_frames(&self) -> bool {
+        self.get_children(self.root())
+            .iter()
+            .filter_map(|id| self.get_stack_short_name(id))
+            .any(is_activity_frame)
+    }
+
+    /// The flamegraph with the on-CPU and waiting samples merged again, without the frames
+    /// splitting them.
+    pub fn without_activity_frames(&self) -> FlameGraph {
+        let num_columns = self.metrics.len().max(1);
+        let mut content = String::with_capacity(self.data.len());
+        for line in self.data.lines() {
+            let line = match split_count(line, num_columns, 0) {
+                Some((stack, _)) => match stack.split_once(';') {
+                    Some((first, _)) if is_activity_frame(first) => &line[first.len() + 1..],
+                    _ => line,
+                },
+                None => line,
+            };
+            content.push_str(line);
+            content.push('\n');
+        }
+        FlameGraph::from_string_with_metric(content, self.sorted, self.metric)
+    }
+
+    /// The flamegraph with the frames of a function at different lines merged into one, except
+    /// for the functions to `keep` at line granularity, named without the line number. Returns
+    /// `None` if no frame has a line number.
+    pub fn aggregate_lines<F>(&self, keep: F) -> Option<FlameGraph>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let num_columns = self.metrics.len().max(1);
+        let mut content = String::with_capacity(self.data.len());
+        let mut found = false;
+        for line in self.data.lines() {
+            let Some((stack, _)) = split_count(line, num_columns, 0) else {
+                content.push_str(line);
+                content.push('\n');
+                continue;
+            };
+            let frames = stack
+                .split(';')
+                .map(|frame| match without_line_number(frame) {
+                    Some(function) => {
+                        found = true;
+                        if keep(&function) {
+                            frame.to_string()
+                        } else {
+                            function
+                        }
+                    }
+                    None => frame.to_string(),
+                })
+                .collect::<Vec<_>>();
+            content += &frames.join(";");
+            content += &line[stack.len()..];
+            content.push('\n');
+        }
+        if !found {
+            return None;
+        }
+        Some(FlameGraph::from_string_with_metric(
+            content,
+            self.sorted,
+            self.metric,
+        ))
+    }
+
+    /// Whether the stack runs a different kind of code than its caller, e.g. where Python calls
+    /// into an extension module or native code calls back into Python.
+    pub fn is_code_kind_boundary(&self, stack: &StackInfo) -> bool {
+        let code_kind = |stack: &StackInfo| stack.frame_info.as_ref()?.code_kind();
+        let Some(parent) = stack.parent.and_then(|id| self.get_stack(&id)) else {
+            return false;
+        };
+        match (code_kind(parent), code_kind(stack)) {
+            (Some(parent_kind), Some(kind)) => parent_kind != kind,
+            _ => false,
+        }
+    }
+
+    /// Count in the unit of the metric, or as samples.
+    pub fn format_count(&self, count: u64) -> String {
+        match self.metric() {
+            Some(metric) => metric.format_count(count),
+            None => format!("{} samples", count),
+        }
+    }
+
+    /// Stack with the most samples among those whose short name satisfies `matches`.
+    pub fn get_heaviest_stack_matching<F>(&self, matches: F) -> Option<StackIdentifier>
+    where
+        F: Fn(&str) -> bool,
+    {
+        self.stacks
+            .iter()
+            .filter(|stack| {
+                stack.id != ROOT_ID && matches(self.get_stack_short_name_from_info(stack))
+            })
+            .max_by_key(|stack| stack.total_count)
+            .map(|stack| stack.id)
+    }
+
+    /// Folded stacks of the callers of a function, i.e. the stacks leading to the function
+    /// inverted so that the function becomes the single root frame. Recursive calls are only
+    /// counted once, at the outermost occurrence.
+    pub fn get_callers_folded(&self, short_name: &str) -> String {
+        let mut out = String::new();
+        for stack in self.stacks.iter().filter(|stack| {
+            stack.id != ROOT_ID && self.get_stack_short_name_from_info(stack) == short_name
+        }) {
+            let names = self
+                .get_ancestors(&stack.id)
+                .iter()
+                .filter(|id| **id != ROOT_ID)
+                .map(|id| self.get_stack_short_name(id).unwrap())
+                .collect::<Vec<_>>();
+            if names[1..].contains(&short_name) {
+                continue;
+            }
+            out.push_str(&format!("{} {}\n", names.join(";"), stack.total_count));
+        }
+        out
+    }
+
+    /// Compare the rows of the table with the baseline in a delta column, or stop comparing them.
+    /// The table is counted again if it was already.
+    pub fn set_baseline(&mut self, baseline: Option<Arc<Baseline>>) {
+        let ordered = &mut self.ordered_stacks;
+        ordered.baseline = baseline;
+        if ordered.baseline.is_none() && ordered.sorted_column == SortColumn::Delta {
+            ordered.sorted_column = SortColumn::Own;
+        }
+        if !ordered.built {
+            return;
+        }
+        let expanded = ordered.groups.as_ref().map(|groups| {
+            groups
+                .iter()
+                .filter(|g| g.expanded)
+                .map(|g| g.name.clone())
+                .collect::<Vec<_>>()
+        });
+        ordered.built = false;
+        self.build_ordered_stacks();
+        if let Some(expanded) = expanded {
+            self.set_grouped(true, &expanded);
+        }
+    }
+
+    /// Break the total counts of functions down per thread, using the thread (or process) frames
+    /// inserted by profilers like py-spy. Returns false if there are no such frames.
+    pub fn set_thread_breakdown(&mut self, enabled: bool) -> bool {
+        self.ordered_stacks.thread_counts = None;
+        if !enabled {
+            return true;
+        }
+        let thread_counts = self.get_thread_counts();
+        if thread_counts.is_empty() {
+            return false;
+        }
+        self.ordered_stacks.thread_counts = Some(thread_counts);
+        true
+    }
+
+    fn get_thread_counts(&self) -> HashMap<String, Vec<(String, u64)>> {
+        let mut counts: HashMap<&str, HashMap<&str, u64>> = HashMap::new();
+        let mut on_stack: HashMap<&str, usize> = HashMap::new();
+        // Depth first traversal keeping track of the innermost thread and the functions on the
+        // current stack
+        let mut to_visit = vec![(ROOT_ID, None, false)];
+        while let Some((stack_id, thread, exiting)) = to_visit.pop() {
+            let stack = self.get_stack(&stack_id).unwrap();
+            let name = self.get_stack_short_name_from_info(stack);
+            let is_thread = stack_id != ROOT_ID && is_thread_frame(name);
+            let function = if stack_id == ROOT_ID || is_thread {
+                None
+            } else {
+                Some(name)
+            };
+            if exiting {
+                if let Some(function) = function {
+                    *on_stack.get_mut(function).unwrap() -= 1;
+                }
+                continue;
+            }
+            if let Some(function) = function {
+                let depth = on_stack.entry(function).or_default();
+                if let (0, Some(thread)) = (*depth, thread) {
+                    *counts
+                        .entry(function)
+                        .or_default()
+                        .entry(thread)
+                        .or_default() += stack.total_count;
+                }
+                *depth += 1;
+            }
+            let thread = if is_thread { Some(name) } else { thread };
+            to_visit.push((stack_id, thread, true));
+            to_visit.extend(
+                self.get_children(stack)
+                    .iter()
+                    .map(|child_id| (*child_id, thread, false)),
+            );
+        }
+        counts
+            .into_iter()
+            .map(|(function, threads)| {
+                let mut threads = threads
+                    .into_iter()
+                    .map(|(thread, count)| (thread.to_string(), count))
+                    .collect::<Vec<_>>();
+                threads.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                (function.to_string(), threads)
+            })
+            .collect()
+    }
+
+    /// Group the table rows by module. Groups listed in `expanded` start expanded.
+    pub fn set_grouped(&mut self, grouped: bool, expanded: &[String]) {
+        if !grouped {
+            self.ordered_stacks.groups = None;
+            self.ordered_stacks.bucket_entries();
+            self.ordered_stacks.update_num_rows();
+            return;
+        }
+        self.build_ordered_stacks();
+        let mut groups = self
+            .get_module_counts()
+            .into_iter()
+            .map(|(name, count)| Group {
+                expanded: expanded.contains(&name),
+                name,
+                count,
+            })
+            .collect::<Vec<_>>();
+        if let Some(baseline) = &self.ordered_stacks.baseline {
+            let total_count = self.total_count();
+            for group in groups.iter_mut() {
+                group.count.delta =
+                    Some(baseline.module_delta(&group.name, group.count.total, total_count));
+            }
+        }
+        Ordered::sort_by_column(&mut groups, self.ordered_stacks.sorted_column, |g| {
+            (&g.name, &g.count)
+        });
+        self.ordered_stacks.groups = Some(groups);
+        self.ordered_stacks.bucket_entries();
+        self.ordered_stacks.update_num_rows();
+    }
+
+    /// Counts aggregated by module. Similar to functions, a sample is counted once in the total
+    /// count of a module even if the module appears multiple times in the stack.
+    pub(crate) fn get_module_counts(&self) -> HashMap<String, Count> {
+        let mut counts: HashMap<String, Count> = HashMap::new();
+        let mut on_stack: HashMap<&str, usize> = HashMap::new();
+        // Depth first traversal keeping track of the modules on the current stack
+        let mut to_visit = vec![(ROOT_ID, false)];
+        while let Some((stack_id, exiting)) = to_visit.pop() {
+            let stack = self.get_stack(&stack_id).unwrap();
+            let module = if stack_id == ROOT_ID {
+                None
+            } else {
+                Some(module_name(self.get_stack_short_name_from_info(stack)))
+            };
+            if exiting {
+                if let Some(module) = module {
+                    *on_stack.get_mut(module).unwrap() -= 1;
+                }
+                continue;
+            }
+            if let Some(module) = module {
+                let count = counts.entry(module.to_string()).or_default();
+                let depth = on_stack.entry(module).or_default();
+                if *depth == 0 {
+                    count.total += stack.total_count;
+                }
+                *depth += 1;
+                count.own += stack.self_count;
+                count.call_sites += 1;
+            }
+            to_visit.push((stack_id, true));
+            to_visit.extend(
+                self.get_children(stack)
+                    .iter()
+                    .map(|child_id| (*child_id, false)),
+            );
+        }
+        counts
+    }
+
+    /// Serialize the stacks under `stack_id` back into the folded format, mapping each frame name
+    /// with `rename`.
+    pub fn to_folded<F>(&self, stack_id: &StackIdentifier, mut rename: F) -> String
+    where
+        F: FnMut(&str) -> String,
+    {
+        let mut out = String::new();
+        let mut prefix = vec![];
+        self._write_folded(*stack_id, &mut prefix, &mut rename, &mut out);
+        out
+    }
+
+    fn _write_folded<F>(
+        &self,
+        stack_id: StackIdentifier,
+        prefix: &mut Vec<String>,
+        rename: &mut F,
+        out: &mut String,
+    ) where
+        F: FnMut(&str) -> String,
+    {
+        let stack = self.get_stack(&stack_id).unwrap();
+        let is_root = stack_id == ROOT_ID;
+        if !is_root {
+            prefix.push(rename(self.get_stack_short_name_from_info(stack)));
+            if stack.self_count > 0 {
+                out.push_str(&format!("{} {}\n", prefix.join(";"), stack.self_count));
+            }
+        }
+        for child_id in self.get_children(stack).iter() {
+            self._write_folded(*child_id, prefix, rename, out);
+        }
+        if !is_root {
+            prefix.pop();
+        }
+    }
+
     pub fn set_hits(&mut self, p: &SearchPattern) {
+        self.set_hits_under(p, ROOT_ID);
+    }
+
+    /// Mark the stacks matching the pattern, only considering the subtree under `stack_id`.
+    pub fn set_hits_under(&mut self, p: &SearchPattern, stack_id: StackIdentifier) {
+        let scope = if stack_id == ROOT_ID {
+            None
+        } else {
+            Some(
+                self.get_descendants(&stack_id)
+                    .into_iter()
+                    .collect::<HashSet<_>>(),
+            )
+        };
+        let in_scope =
+            |stack: &StackInfo| scope.as_ref().is_none_or(|scope| scope.contains(&stack.id));
+        let mut matches = p.matches.lock().unwrap();
+        p.match_new_names(
+            &mut matches,
+            self.stacks
+                .iter()
+                .filter(|stack| in_scope(stack))
+                .map(|stack| &self.data[stack.start_index..stack.end_index]),
+            thread::available_parallelism().map_or(1, |n| n.get()),
+        );
         self.stacks.iter_mut().for_each(|stack| {
-            stack.hit =
-                p.re.is_match(&self.data[stack.start_index..stack.end_index]);
+            stack.hit = in_scope(stack) && matches[&self.data[stack.start_index..stack.end_index]];
         });
+        drop(matches);
         self.hits = Some(Hits {
-            coverage_count: self._count_hit_coverage(ROOT_ID),
+            coverage_count: self._count_hit_coverage(stack_id),
             ids: self._collect_hit_ids(),
         });
         self.ordered_stacks.set_search_pattern(p);
@@ -483,13 +2071,29 @@ impl FlameGraph {
         self.hits.as_ref().map(|h| &h.ids)
     }
 
+    /// Matching stacks before and after a stack, in the order of the hit ids. Found by binary
+    /// search as the hit ids are ordered by level and then left to right.
+    pub fn split_hit_ids_at(
+        &self,
+        stack_id: &StackIdentifier,
+    ) -> (&[StackIdentifier], &[StackIdentifier]) {
+        let ids = self.hit_ids().map(|ids| ids.as_slice()).unwrap_or_default();
+        let Some(stack) = self.get_stack(stack_id) else {
+            return (&[], &[]);
+        };
+        let key = |id: &StackIdentifier| (self.stacks[*id].level, self.stacks[*id].level_index);
+        let before = ids.partition_point(|id| key(id) < (stack.level, stack.level_index));
+        let after = ids.partition_point(|id| key(id) <= (stack.level, stack.level_index));
+        (&ids[..before], &ids[after..])
+    }
+
     fn _count_hit_coverage(&self, stack_id: StackIdentifier) -> u64 {
         let stack = self.get_stack(&stack_id).unwrap();
         if stack.hit {
             return stack.total_count;
         }
         let mut count = 0;
-        for child_id in stack.children.iter() {
+        for child_id in self.get_children(stack).iter() {
             count += self._count_hit_coverage(*child_id);
         }
         count
@@ -534,7 +2138,7 @@ mod tests {
     }
 
     impl FlameGraph {
-        pub fn to_readable_stacks(&self) -> Vec<StackInfoReadable> {
+        pub fn to_readable_stacks(&self) -> Vec<StackInfoReadable<'_>> {
             self.stacks
                 .iter()
                 .map(|stack| StackInfoReadable {
@@ -545,7 +2149,7 @@ mod tests {
                     total_count: stack.total_count,
                     self_count: stack.self_count,
                     parent: stack.parent,
-                    children: stack.children.clone(),
+                    children: self.get_children(stack).to_vec(),
                     level: stack.level,
                     width_factor: stack.width_factor,
                     hit: stack.hit,
@@ -558,7 +2162,8 @@ mod tests {
 
     fn check_result<P: AsRef<std::path::Path>>(data_filename: P) -> FlameGraph {
         let content = std::fs::read_to_string(&data_filename).unwrap();
-        let fg = FlameGraph::from_string(content, true);
+        let mut fg = FlameGraph::from_string(content, true);
+        fg.build_ordered_stacks();
 
         // Location to store all the fixtures for this test data
         let tag = data_filename
@@ -589,7 +2194,7 @@ mod tests {
         let expected = std::fs::read_to_string(&filename).unwrap();
         assert_eq!(serialized, expected);
 
-        assert_eq!(UPDATE_FIXTURES, false, "Set UPDATE_FIXTURES to false");
+        const { assert!(!UPDATE_FIXTURES, "Set UPDATE_FIXTURES to false") };
         fg
     }
 
@@ -608,11 +2213,14 @@ mod tests {
                 self_count: 0,
                 width_factor: 1.0,
                 parent: None,
-                children: vec![3, 1, 5],
+                children: 0..3,
                 level: 0,
+                level_index: 0,
                 hit: false,
+                frame_info: None,
             }
         );
+        assert_eq!(fg.get_children(fg.root()), [3, 1, 5]);
     }
 
     #[test]
@@ -630,4 +2238,832 @@ mod tests {
     fn test_recursive() {
         check_result("tests/data/recursive.txt");
     }
+
+    #[test]
+    fn test_sort_by_name() {
+        let content = std::fs::read_to_string("tests/data/readable.txt").unwrap();
+        let mut fg = FlameGraph::from_string(content, false);
+        fg.ordered_stacks.set_sort_column(SortColumn::Name);
+        fg.build_ordered_stacks();
+        let names = fg
+            .ordered_stacks
+            .entries
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect::<Vec<_>>();
+        let mut expected = names.clone();
+        expected.sort();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn test_call_sites() {
+        let content = "main;a;f 3\nmain;b;f 5\nmain;b;f;g;f 1\nmain;f 1\nmain;a;f 2\n".to_string();
+        let mut fg = FlameGraph::from_string(content, false);
+        // Counted from the stacks before the table is built
+        let count = fg.get_function_count("f").unwrap();
+        assert_eq!((count.total, count.own, count.call_sites), (12, 12, 4));
+        fg.build_ordered_stacks();
+        fg.ordered_stacks.set_sort_column(SortColumn::CallSites);
+        let call_sites = fg
+            .ordered_stacks
+            .entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.count.call_sites))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            call_sites,
+            vec![("f", 4), ("main", 1), ("b", 1), ("a", 1), ("g", 1)]
+        );
+        let count = fg.get_function_count("g").unwrap();
+        assert_eq!((count.total, count.own), (1, 0));
+        let count = fg.get_function_count("f").unwrap();
+        assert_eq!((count.total, count.own), (12, 12));
+        assert!(fg.get_function_count("h").is_none());
+    }
+
+    #[test]
+    fn test_build_ordered_stacks() {
+        let content = "main;a;f 3\nmain;b;f 5\nmain;b 2\n".to_string();
+        let mut fg = FlameGraph::from_string(content, false);
+        assert!(!fg.ordered_stacks.is_built());
+        assert_eq!(fg.ordered_stacks.num_rows, 0);
+
+        // The search and sort column set before are applied once built
+        fg.set_hits(&SearchPattern::new("^[ab]$", true, true).unwrap());
+        fg.ordered_stacks.set_sort_column(SortColumn::Total);
+        fg.build_ordered_stacks();
+        assert_eq!(fg.ordered_stacks.num_rows, 2);
+        let names = fg
+            .ordered_stacks
+            .shown_entries()
+            .map(|entry| entry.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["b", "a"]);
+
+        fg.set_hits(&SearchPattern::new("nothing", true, true).unwrap());
+        assert!(fg.ordered_stacks.search_pattern_ignored_because_of_no_match);
+        assert_eq!(fg.ordered_stacks.num_rows, 4);
+    }
+
+    #[test]
+    fn test_to_tsv() {
+        let content = "main;a;f 3\nmain;b;f 5\nmain;b 2\n".to_string();
+        let mut fg = FlameGraph::from_string(content, false);
+        fg.ordered_stacks.set_sort_column(SortColumn::Total);
+        fg.ordered_stacks.set_filter("b");
+        fg.build_ordered_stacks();
+        assert_eq!(
+            fg.ordered_stacks.to_tsv(fg.total_count()),
+            "Name\tTotal\tTotal %\tOwn\tOwn %\tCall sites\n\
+             b\t7\t70.00\t2\t20.00\t1\n"
+        );
+    }
+
+    #[test]
+    fn test_multiple_metrics() {
+        let content = "# metrics: cpu/nanoseconds alloc_space/bytes\n\
+                       main;a 1000 0\n\
+                       main;b 3000 4096\n"
+            .to_string();
+        let fg = FlameGraph::from_string(content.clone(), false);
+        assert_eq!(fg.metrics().len(), 2);
+        assert_eq!(fg.metric().unwrap().name, "cpu");
+        assert_eq!(fg.total_count(), 4000);
+        assert_eq!(fg.format_count(fg.total_count()), "4.0 µs");
+
+        // Stacks without any of the metric are left out
+        let fg = FlameGraph::from_string_with_metric(content, false, 1);
+        assert_eq!(fg.total_count(), 4096);
+        assert_eq!(fg.get_stack_id_by_full_name("main;a"), None);
+        assert_eq!(fg.format_count(512), "512 B");
+        assert_eq!(fg.format_count(fg.total_count()), "4.0 KiB");
+
+        // Generic names without a header
+        let fg = FlameGraph::from_string_with_metric("main 1 2 3\n".to_string(), false, 2);
+        assert_eq!(
+            fg.metrics()
+                .iter()
+                .map(|m| m.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["metric 1", "metric 2", "metric 3"]
+        );
+        assert_eq!(fg.total_count(), 3);
+        assert_eq!(fg.format_count(3), "3 metric 3");
+
+        let fg = FlameGraph::from_string("main 1\n".to_string(), false);
+        assert!(fg.metrics().is_empty());
+        assert_eq!(fg.format_count(1), "1 samples");
+
+        // A single column with a unit
+        let fg = FlameGraph::from_string("# metrics: cpu/nanoseconds\nmain 1500\n".into(), false);
+        assert_eq!(fg.metrics().len(), 1);
+        assert_eq!(fg.format_count(fg.total_count()), "1.5 µs");
+    }
+
+    #[test]
+    fn test_code_kind_boundary() {
+        let content = "main (app.py:1);loads (json/__init__.py:5);scanstring (_json.c:120);\
+                       object_hook (app.py:9) 1\n\
+                       main (app.py:1);write [libc.so.6] 1\n\
+                       main (app.py:1);helper 1\n"
+            .to_string();
+        let fg = FlameGraph::from_string(content, false);
+        let is_boundary = |full_name: &str| {
+            fg.is_code_kind_boundary(fg.get_stack_by_full_name(full_name).unwrap())
+        };
+        assert!(!is_boundary("main (app.py:1)"));
+        assert!(!is_boundary("main (app.py:1);loads (json/__init__.py:5)"));
+        assert!(is_boundary(
+            "main (app.py:1);loads (json/__init__.py:5);scanstring (_json.c:120)"
+        ));
+        assert!(is_boundary(
+            "main (app.py:1);loads (json/__init__.py:5);scanstring (_json.c:120);object_hook (app.py:9)"
+        ));
+        assert!(is_boundary("main (app.py:1);write [libc.so.6]"));
+        // Nothing to tell what kind of code it runs from
+        assert!(!is_boundary("main (app.py:1);helper"));
+
+        // Samples split by activity, under frames that are neither native nor interpreted
+        let content = format!(
+            "{};main (app.py:1);work (app.py:2) 2\n{};main (app.py:1) 1\n",
+            ON_CPU_FRAME, WAITING_FRAME
+        );
+        let fg = FlameGraph::from_string(content, false);
+        assert_eq!(library_name(ON_CPU_FRAME), None);
+        for activity_frame in [ON_CPU_FRAME, WAITING_FRAME] {
+            let stack = fg.get_stack_by_full_name(activity_frame).unwrap();
+            assert_eq!(stack.frame_info.as_ref().and_then(|f| f.code_kind()), None);
+            let main = format!("{};main (app.py:1)", activity_frame);
+            assert!(!fg.is_code_kind_boundary(fg.get_stack_by_full_name(&main).unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_aggregate_lines() {
+        assert_eq!(
+            without_line_number("work (app.py:12)").as_deref(),
+            Some("work (app.py)")
+        );
+        assert_eq!(
+            without_line_number("main.work /src/main.go:12:3").as_deref(),
+            Some("main.work /src/main.go")
+        );
+        assert_eq!(without_line_number("work (app.py)"), None);
+        assert_eq!(without_line_number("main"), None);
+
+        let content = "main (app.py:1);work (app.py:12) 3\n\
+                       main (app.py:1);work (app.py:14) 2\n\
+                       main (app.py:2);load (io.py:5) 1\n"
+            .to_string();
+        let fg = FlameGraph::from_string(content, false);
+        let by_function = fg.aggregate_lines(|_| false).unwrap();
+        assert_eq!(
+            by_function
+                .get_stack_by_full_name("main (app.py);work (app.py)")
+                .unwrap()
+                .total_count,
+            5
+        );
+        assert_eq!(by_function.get_children(by_function.root()).len(), 1);
+
+        let expanded = fg.aggregate_lines(|name| name == "work (app.py)").unwrap();
+        assert_eq!(
+            expanded
+                .get_stack_by_full_name("main (app.py);work (app.py:14)")
+                .unwrap()
+                .total_count,
+            2
+        );
+        assert!(FlameGraph::from_string("main 1".to_string(), false)
+            .aggregate_lines(|_| false)
+            .is_none());
+    }
+
+    #[test]
+    fn test_without_activity_frames() {
+        let content = "[on-CPU];main;work 3\n[waiting];main;recv 2\n[waiting];main;work 1\n";
+        let fg = FlameGraph::from_string(content.to_string(), false);
+        assert!(fg.has_activity_frames());
+        let merged = fg.without_activity_frames();
+        assert!(!merged.has_activity_frames());
+        assert_eq!(merged.total_count(), 6);
+        assert_eq!(
+            merged
+                .get_stack_by_full_name("main;work")
+                .unwrap()
+                .total_count,
+            4
+        );
+        assert!(!FlameGraph::from_string("main 1".to_string(), false).has_activity_frames());
+    }
+
+    #[test]
+    fn test_without_kernel_frames() {
+        let content = "# metrics: cpu/nanoseconds alloc_space/bytes\n\
+                       main;read;sys_read_[k];vfs_read_[k] 5 0\n\
+                       main;read 1 8\n\
+                       secondary_startup_64_[k];do_idle_[k] 3 0\n\
+                       main;work 2 0\n"
+            .to_string();
+        let fg = FlameGraph::from_string(content, false);
+        assert!(fg.has_kernel_frames());
+        assert!(is_kernel_frame("do_idle_[k]"));
+        assert!(is_kernel_frame("ksys_write ([kernel.kallsyms])"));
+        assert!(!is_kernel_frame("write ([libc.so.6])"));
+
+        let user = fg.without_kernel_frames();
+        assert!(!user.has_kernel_frames());
+        assert_eq!(user.total_count(), 8);
+        assert_eq!(
+            user.get_stack_by_full_name("main;read").unwrap().self_count,
+            6
+        );
+        assert_eq!(user.get_children(user.root()).len(), 1);
+        assert_eq!(user.metrics().len(), 2);
+        let user = FlameGraph::from_string_with_metric(user.data().to_string(), false, 1);
+        assert_eq!(user.total_count(), 8);
+    }
+
+    #[test]
+    fn test_heaviest_stack_and_callers() {
+        let content = "main;a;f 3\nmain;b;f 5\nmain;b;f;g;f 1\nmain;f 1\n".to_string();
+        let fg = FlameGraph::from_string(content, false);
+        let heaviest = fg.get_heaviest_stack_by_short_name("f").unwrap();
+        assert_eq!(fg.get_stack_full_name(&heaviest), Some("main;b;f"));
+        assert_eq!(fg.get_heaviest_stack_by_short_name("unknown"), None);
+
+        let callers = FlameGraph::from_string(fg.get_callers_folded("f"), false);
+        assert_eq!(callers.total_count(), 10);
+        assert_eq!(callers.get_stack_by_full_name("f").unwrap().total_count, 10);
+        assert_eq!(
+            callers.get_stack_by_full_name("f;b").unwrap().total_count,
+            6
+        );
+        assert_eq!(
+            callers
+                .get_stack_by_full_name("f;a;main")
+                .unwrap()
+                .total_count,
+            3
+        );
+        assert_eq!(
+            callers.get_stack_by_full_name("f;main").unwrap().self_count,
+            1
+        );
+    }
+
+    #[test]
+    fn test_thread_breakdown() {
+        let content = [
+            "process 1:\"app\";thread (0x1): MainThread;main;work;work 6",
+            "process 1:\"app\";thread (0x1): MainThread;main;idle 1",
+            "process 1:\"app\";thread (0x2): Worker;run;work 2",
+        ]
+        .join("\n");
+        let mut fg = FlameGraph::from_string(content, false);
+        assert!(fg.set_thread_breakdown(true));
+        let thread_counts = fg.ordered_stacks.thread_counts.as_ref().unwrap();
+        assert_eq!(
+            thread_counts["work"],
+            vec![
+                ("thread (0x1): MainThread".to_string(), 6),
+                ("thread (0x2): Worker".to_string(), 2)
+            ]
+        );
+        assert_eq!(
+            thread_counts["main"],
+            vec![("thread (0x1): MainThread".to_string(), 7)]
+        );
+        assert!(!thread_counts.contains_key("thread (0x2): Worker"));
+
+        let mut fg = FlameGraph::from_string("main;work 1\n".to_string(), false);
+        assert!(!fg.set_thread_breakdown(true));
+        assert!(fg.ordered_stacks.thread_counts.is_none());
+    }
+
+    #[test]
+    fn test_lowest_common_ancestor() {
+        let content = "main;a;b;x 1\nmain;a;c;x 1\nmain;a;c 1\nmain;d 1\n".to_string();
+        let fg = FlameGraph::from_string(content, false);
+        let id = |name: &str| fg.get_stack_id_by_full_name(name).unwrap();
+        assert_eq!(
+            fg.get_lowest_common_ancestor(&[id("main;a;b;x"), id("main;a;c;x")]),
+            Some(id("main;a"))
+        );
+        assert_eq!(
+            fg.get_lowest_common_ancestor(&[id("main;a;c;x"), id("main;a;c")]),
+            Some(id("main;a;c"))
+        );
+        assert_eq!(
+            fg.get_lowest_common_ancestor(&[id("main;a;b"), id("main;d")]),
+            Some(id("main"))
+        );
+        assert_eq!(fg.get_lowest_common_ancestor(&[]), None);
+    }
+
+    #[test]
+    fn test_set_hits_under() {
+        let content = "main;a;x 1\nmain;b;x 2\nmain;b;y;x 4\n".to_string();
+        let mut fg = FlameGraph::from_string(content, false);
+        let p = SearchPattern::new("x", false, true).unwrap();
+        fg.set_hits(&p);
+        assert_eq!(fg.hit_ids().unwrap().len(), 3);
+        assert_eq!(fg.hit_coverage_count(), Some(7));
+
+        let scope = fg.get_stack_id_by_full_name("main;b").unwrap();
+        fg.set_hits_under(&p, scope);
+        assert_eq!(fg.hit_ids().unwrap().len(), 2);
+        assert_eq!(fg.hit_coverage_count(), Some(6));
+        assert!(!fg.get_stack_by_full_name("main;a;x").unwrap().hit);
+    }
+
+    #[test]
+    fn test_split_hit_ids_at() {
+        let content = "main;x;a 1\nmain;b;x 2\nmain;c;x 4\nx 1\n".to_string();
+        let mut fg = FlameGraph::from_string(content, false);
+        for level in 0..fg.get_num_levels() {
+            for (level_index, id) in fg.get_stacks_at_level(level).unwrap().iter().enumerate() {
+                assert_eq!(fg.get_stack(id).unwrap().level_index, level_index);
+            }
+        }
+        let (before, after) = fg.split_hit_ids_at(&1);
+        assert!(before.is_empty() && after.is_empty());
+
+        fg.set_hits(&SearchPattern::new("x", false, true).unwrap());
+        let id = |full_name: &str| fg.get_stack_id_by_full_name(full_name).unwrap();
+        let (before, after) = fg.split_hit_ids_at(&id("main;b;x"));
+        assert_eq!(before, [id("x"), id("main;x")]);
+        assert_eq!(after, [id("main;c;x")]);
+        let (before, after) = fg.split_hit_ids_at(&id("main;b"));
+        assert_eq!(before, [id("x"), id("main;x")]);
+        assert_eq!(after, [id("main;b;x"), id("main;c;x")]);
+    }
+
+    #[test]
+    fn test_search_matches_cached() {
+        let p = SearchPattern::new("x", false, true).unwrap();
+        let mut fg = FlameGraph::from_string("main;a;x 1\nmain;b;x 2\n".to_string(), false);
+        fg.set_hits(&p.clone());
+        assert_eq!(p.matches.lock().unwrap().len(), 5);
+
+        // Names seen before are not matched again, as with each update of a live profile
+        p.matches.lock().unwrap().insert("a".to_string(), true);
+        let mut fg = FlameGraph::from_string("main;a;x 1\nmain;c 2\n".to_string(), false);
+        fg.set_hits(&p);
+        assert_eq!(p.matches.lock().unwrap().len(), 6);
+        assert!(fg.get_stack_by_full_name("main;a").unwrap().hit);
+        assert!(!fg.get_stack_by_full_name("main;c").unwrap().hit);
+        assert!(!p.is_match("main"));
+    }
+
+    #[test]
+    fn test_set_hits_many_names() {
+        let content = (0..3 * MIN_NAMES_PER_MATCH_THREAD)
+            .map(|i| format!("main;f{} 1\n", i))
+            .collect::<String>();
+        let mut fg = FlameGraph::from_string(content, false);
+        let p = SearchPattern::new("7$", true, true).unwrap();
+        fg.set_hits(&p);
+        assert_eq!(
+            fg.hit_ids().unwrap().len(),
+            3 * MIN_NAMES_PER_MATCH_THREAD / 10
+        );
+        assert!(fg.get_stack_by_full_name("main;f5977").unwrap().hit);
+        assert!(!fg.get_stack_by_full_name("main;f5978").unwrap().hit);
+        assert_eq!(
+            p.matches.lock().unwrap().len(),
+            3 * MIN_NAMES_PER_MATCH_THREAD + 2
+        );
+
+        // Split between threads, with each name matched once
+        let names = (0..3 * MIN_NAMES_PER_MATCH_THREAD)
+            .map(|i| format!("f{}", i % (2 * MIN_NAMES_PER_MATCH_THREAD + 1)))
+            .collect::<Vec<_>>();
+        let mut matches = HashMap::new();
+        p.match_new_names(&mut matches, names.iter().map(|name| name.as_str()), 4);
+        assert_eq!(matches.len(), 2 * MIN_NAMES_PER_MATCH_THREAD + 1);
+        assert_eq!(matches.values().filter(|matched| **matched).count(), 400);
+        assert_eq!(matches.get("f17"), Some(&true));
+    }
+
+    #[test]
+    fn test_stack_filter_sample_rate() {
+        let mut content = "main;heavy 100\nmain;light;both 5 2\n".to_string();
+        for i in 0..2000 {
+            content += &format!("main;light;f{} 1\n", i);
+        }
+        let filter = StackFilter {
+            sample_rate: Some(0.1),
+            ..Default::default()
+        };
+        let sampled = filter.apply(content.clone());
+        // The same stacks are kept every time, e.g. in each update of a live profile
+        assert_eq!(filter.apply(content), sampled);
+        // Heavy stacks are always kept as they are, light ones scaled up when kept
+        assert!(sampled.starts_with("main;heavy 100\n"));
+        let light = sampled
+            .lines()
+            .filter(|line| line.starts_with("main;light;f"))
+            .collect::<Vec<_>>();
+        assert!((150..250).contains(&light.len()), "{}", light.len());
+        assert!(light.iter().all(|line| line.ends_with(" 10")));
+        assert!(sampled
+            .lines()
+            .all(|line| !line.starts_with("main;light;both") || line.ends_with("both 10 4")));
+
+        let filter = StackFilter {
+            sample_rate: Some(1.0),
+            ..Default::default()
+        };
+        assert_eq!(filter.apply("main 1\n".to_string()), "main 1\n");
+
+        // Numbers at the end of frame names are not taken for counts when the profile has a single
+        // value column
+        let filter = StackFilter {
+            sample_rate: Some(0.5),
+            ..Default::default()
+        };
+        let content = "main;idle 2\n".to_string()
+            + &(0..100)
+                .map(|i| format!("main;thread {} 1\n", i))
+                .collect::<String>();
+        let sampled = filter.apply(content);
+        let threads = sampled
+            .lines()
+            .filter(|line| line.starts_with("main;thread "))
+            .collect::<Vec<_>>();
+        assert!(!threads.is_empty());
+        assert!(
+            threads.iter().all(|line| line.ends_with(" 2")),
+            "{:?}",
+            threads
+        );
+    }
+
+    #[test]
+    fn test_stack_filter() {
+        let content = "main;net;recv 3\nmain;db;query 2\nmain;db;net 1\nmain 1\n".to_string();
+        let filter = StackFilter {
+            filter: Some(regex::Regex::new("db").unwrap()),
+            exclude: Some(regex::Regex::new(";net$").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(filter.apply(content.clone()), "main;db;query 2\n");
+
+        // The count is not matched against
+        let filter = StackFilter {
+            filter: None,
+            exclude: Some(regex::Regex::new("3").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(filter.apply(content.clone()).lines().count(), 4);
+        assert_eq!(StackFilter::default().apply(content.clone()), content);
+
+        let content = "<0.84.0>;gen_server:loop/7;app:work/1 3\n\
+                       erlang:apply/2;<0.90.0>;app:work/1 2\n\
+                       <0.84.0> 1\n"
+            .to_string();
+        let filter = StackFilter {
+            pids: Some(PidFrames::Group),
+            ..Default::default()
+        };
+        assert_eq!(
+            filter.apply(content.clone()),
+            "<0.84.0>;gen_server:loop/7;app:work/1 3\n\
+             <0.90.0>;erlang:apply/2;app:work/1 2\n\
+             <0.84.0> 1\n"
+        );
+        let filter = StackFilter {
+            pids: Some(PidFrames::Strip),
+            ..Default::default()
+        };
+        assert_eq!(
+            filter.apply(content),
+            "gen_server:loop/7;app:work/1 3\nerlang:apply/2;app:work/1 2\n<0.84.0> 1\n"
+        );
+
+        // Neither the matching nor the pid frames see the other value columns of the profile
+        let content = "main;db;<0.84.0> 3 4\nmain;net 1 2\n".to_string();
+        let filter = StackFilter {
+            filter: Some(regex::Regex::new(">$").unwrap()),
+            pids: Some(PidFrames::Group),
+            ..Default::default()
+        };
+        assert_eq!(filter.apply(content.clone()), "<0.84.0>;main;db 3 4\n");
+        let filter = StackFilter {
+            exclude: Some(regex::Regex::new("net$").unwrap()),
+            pids: Some(PidFrames::Strip),
+            ..Default::default()
+        };
+        assert_eq!(filter.apply(content), "main;db 3 4\n");
+
+        assert!(is_pid_frame("<0.84.0>"));
+        assert!(!is_pid_frame("<module>"));
+        assert!(!is_pid_frame("<0.84>"));
+    }
+
+    #[test]
+    fn test_reroot() {
+        let content = "_start;main;a;b 3\n_start;main;a 1\n_start;main;c 2\n_start;init 4\n";
+        let fg = FlameGraph::from_string(content.to_string(), false);
+        let rerooted = fg.reroot(|name| name == "main").unwrap();
+        assert_eq!(rerooted.total_count(), 6);
+        assert_eq!(rerooted.get_children(rerooted.root()).len(), 1);
+        assert_eq!(
+            rerooted
+                .get_stack_by_full_name("main;a")
+                .unwrap()
+                .total_count,
+            4
+        );
+        assert!(rerooted.get_stack_by_full_name("init").is_none());
+        assert!(fg.reroot(|name| name == "unknown").is_none());
+    }
+
+    #[test]
+    fn test_frame_info() {
+        let info = |name: &str| FrameInfo::parse(name);
+        assert_eq!(
+            info("work (long_running.py:8)"),
+            Some(FrameInfo {
+                file: Some("long_running.py".to_string()),
+                line: Some(8),
+                library: None,
+            })
+        );
+        assert_eq!(
+            info("<module> (long_running.py)").unwrap().file.as_deref(),
+            Some("long_running.py")
+        );
+        assert_eq!(
+            info("main.work /home/user/src/main.go:42:7"),
+            Some(FrameInfo {
+                file: Some("/home/user/src/main.go".to_string()),
+                line: Some(42),
+                library: None,
+            })
+        );
+        assert_eq!(
+            info("__libc_start_main [libc.so.6]"),
+            Some(FrameInfo {
+                file: None,
+                line: None,
+                library: Some("libc.so.6".to_string()),
+            })
+        );
+        assert_eq!(
+            info("[libc.so.6]").unwrap().library.as_deref(),
+            Some("libc.so.6")
+        );
+        assert_eq!(
+            info("0x1234 ([libfoo.so])").unwrap().library.as_deref(),
+            Some("libfoo.so")
+        );
+        assert_eq!(
+            info("libc.so.6`__libc_start_main")
+                .unwrap()
+                .library
+                .as_deref(),
+            Some("libc.so.6")
+        );
+        assert_eq!(
+            info("ntdll.dll!RtlUserThreadStart")
+                .unwrap()
+                .library
+                .as_deref(),
+            Some("ntdll.dll")
+        );
+        assert_eq!(info("main"), None);
+        assert_eq!(info("macro!"), None);
+        assert_eq!(info("do_syscall_64_[k]"), None);
+        assert_eq!(info("[unknown]"), None);
+        assert_eq!(info("foo (inlined)"), None);
+        assert_eq!(info("operator() const"), None);
+
+        let fg = FlameGraph::from_string("main;work (a.py:3) 1\n".to_string(), false);
+        let id = fg.get_stack_id_by_full_name("main;work (a.py:3)").unwrap();
+        assert_eq!(
+            fg.get_stack(&id).unwrap().frame_info.as_ref().unwrap().line,
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn test_to_folded_subtree() {
+        let content = "main;a;b 3\nmain;a 1\nmain;a;c;b 2\nmain;d 4\n";
+        let fg = FlameGraph::from_string(content.to_string(), false);
+        let stack_id = fg.get_stack_id_by_full_name("main;a").unwrap();
+        let subtree = fg.to_folded(&stack_id, |name| name.to_string());
+        assert_eq!(subtree, "a 1\na;b 3\na;c;b 2\n");
+        let subtree_fg = FlameGraph::from_string(subtree, false);
+        assert_eq!(
+            subtree_fg.total_count(),
+            fg.get_stack(&stack_id).unwrap().total_count
+        );
+    }
+
+    #[test]
+    fn test_collapse_common_prefix() {
+        let content = "_start;libc_start_main;main;a 3\n_start;libc_start_main;main;b;c 2\n";
+        let fg = FlameGraph::from_string(content.to_string(), false);
+        assert_eq!(fg.common_prefix().len(), 3);
+        let collapsed = fg.collapse_common_prefix().unwrap();
+        assert_eq!(
+            collapsed.to_folded(&ROOT_ID, |name| name.to_string()),
+            "_start .. main (3 frames);a 3\n_start .. main (3 frames);b;c 2\n"
+        );
+        assert_eq!(collapsed.total_count(), 5);
+
+        // Samples of a frame of its own end the prefix
+        let content = "_start;main;a 3\n_start;main 1\n_start;main;b 2\n";
+        let fg = FlameGraph::from_string(content.to_string(), false);
+        assert_eq!(fg.common_prefix().len(), 2);
+        let fg = FlameGraph::from_string("_start 1\n_start;main;a 3\n".to_string(), false);
+        assert_eq!(fg.common_prefix().len(), 1);
+        assert!(fg.collapse_common_prefix().is_none());
+    }
+
+    #[test]
+    fn test_group_by() {
+        let content = [
+            "process 12:\"gunicorn\";thread (0x1): MainThread;<module> (app.py:3);run (app.py:9) 3",
+            "process 12:\"gunicorn\";thread (0x2): worker;bootstrap (/usr/lib/python3.12/threading.py:1);handle (app.py:20) 2",
+            "process 13:\"gunicorn\";thread (0x2): worker;bootstrap (/usr/lib/python3.12/threading.py:1) 1",
+        ]
+        .join("\n");
+        let fg = FlameGraph::from_string(content, false);
+        let folded = |fg: FlameGraph| fg.to_folded(&ROOT_ID, |name| name.to_string());
+
+        let by_thread = fg.group_by(GroupBy::Thread).unwrap();
+        assert_eq!(
+            folded(by_thread),
+            [
+                "thread (0x1): MainThread;process 12:\"gunicorn\";<module> (app.py:3);run (app.py:9) 3",
+                "thread (0x2): worker;process 12:\"gunicorn\";bootstrap (/usr/lib/python3.12/threading.py:1);handle (app.py:20) 2",
+                "thread (0x2): worker;process 13:\"gunicorn\";bootstrap (/usr/lib/python3.12/threading.py:1) 1",
+                "",
+            ]
+            .join("\n")
+        );
+
+        let by_app = fg.group_by(GroupBy::App).unwrap();
+        assert_eq!(
+            folded(by_app),
+            [
+                "<module> (app.py:3);run (app.py:9) 3",
+                "handle (app.py:20) 2",
+                "[no app frame];process 13:\"gunicorn\";thread (0x2): worker;bootstrap (/usr/lib/python3.12/threading.py:1) 1",
+                "",
+            ]
+            .join("\n")
+        );
+
+        let fg = FlameGraph::from_string(
+            [
+                "thread (0x1): MainThread;process 1:\"bash\";process 12:\"python\";main 2",
+                "process 1:\"bash\";process 13:\"python\";main 3",
+                "process 1:\"bash\";wait 1",
+            ]
+            .join("\n"),
+            false,
+        );
+        let by_process = fg.group_by(GroupBy::Process).unwrap();
+        assert_eq!(
+            by_process
+                .processes()
+                .iter()
+                .map(|p| (p.name.as_str(), p.depth, p.total_count))
+                .collect::<Vec<_>>(),
+            vec![
+                ("process 1:\"bash\"", 0, 6),
+                ("process 13:\"python\"", 1, 3),
+                ("process 12:\"python\"", 1, 2),
+            ]
+        );
+        assert_eq!(
+            by_process.processes()[2].full_name,
+            "process 1:\"bash\";process 12:\"python\""
+        );
+        // Without grouping, the process under a thread frame is not found
+        assert_eq!(fg.processes().len(), 2);
+
+        let fg = FlameGraph::from_string("main;a 1\n".to_string(), false);
+        assert!(fg.group_by(GroupBy::Process).is_none());
+        assert!(fg.processes().is_empty());
+        assert!(is_app_frame("work (app.py:12)"));
+        assert!(!is_app_frame("memcpy [libc.so.6]"));
+        assert!(!is_app_frame(
+            "get (/venv/lib/site-packages/requests/api.py:3)"
+        ));
+    }
+
+    #[test]
+    fn test_sort_by_delta() {
+        let old = FlameGraph::from_string("main;a 5\nmain;b 3\nmain;c 2\n".to_string(), false);
+        let mut fg = FlameGraph::from_string("main;a 2\nmain;b 3\nmain;d 5\n".to_string(), false);
+        fg.build_ordered_stacks();
+        fg.set_baseline(Some(Arc::new(Baseline::new("old", &old))));
+        fg.ordered_stacks.set_sort_column(SortColumn::Delta);
+        let deltas = fg
+            .ordered_stacks
+            .entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.count.delta))
+            .collect::<Vec<_>>();
+        // Functions gone since the baseline have no row
+        assert_eq!(
+            deltas,
+            [
+                ("d", Some(5)),
+                ("main", Some(0)),
+                ("b", Some(0)),
+                ("a", Some(-3))
+            ]
+        );
+
+        // Without the baseline, the table goes back to the default sort
+        fg.set_baseline(None);
+        assert_eq!(fg.ordered_stacks.sorted_column, SortColumn::Own);
+        assert!(fg
+            .ordered_stacks
+            .entries
+            .iter()
+            .all(|e| e.count.delta.is_none()));
+    }
+
+    #[test]
+    fn test_grouped_rows() {
+        let content = "a.f1;a.f2;b.f1 3\na.f1;b.f2;a.f3 2\nc.f1 1\n".to_string();
+        let mut fg = FlameGraph::from_string(content, false);
+        fg.set_grouped(true, &[]);
+        let groups = fg.ordered_stacks.groups.as_ref().unwrap();
+        let group_counts = groups
+            .iter()
+            .map(|g| (g.name.as_str(), g.count.total, g.count.own))
+            .collect::<Vec<_>>();
+        assert_eq!(group_counts, vec![("b", 5, 3), ("a", 5, 2), ("c", 1, 1)]);
+        assert_eq!(fg.ordered_stacks.num_rows, 3);
+
+        fg.ordered_stacks.toggle_group_expanded("a");
+        assert_eq!(fg.ordered_stacks.num_rows, 6);
+        let rows = fg.ordered_stacks.rows();
+        assert!(matches!(rows[1], TableRow::Group(g) if g.name == "a"));
+        assert!(matches!(rows[2], TableRow::Entry(e) if e.name == "a.f3"));
+
+        // Members follow the order of the entries after sorting again
+        fg.ordered_stacks.set_sort_column(SortColumn::Name);
+        let rows = fg.ordered_stacks.rows();
+        let names = rows
+            .iter()
+            .map(|row| match row {
+                TableRow::Group(g) => g.name.as_str(),
+                TableRow::Entry(e) => e.name.as_str(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a", "a.f1", "a.f2", "a.f3", "b", "c"]);
+
+        // Groups without any matching rows are hidden
+        fg.ordered_stacks.set_filter("c.");
+        assert_eq!(fg.ordered_stacks.num_rows, 1);
+
+        fg.set_grouped(false, &[]);
+        fg.ordered_stacks.set_filter("");
+        assert_eq!(fg.ordered_stacks.num_rows, 6);
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert!(fuzzy_match("", "anything"));
+        assert!(fuzzy_match("lrp", "long_running.py"));
+        assert!(fuzzy_match("work", "quick_WORK (long_running.py:16)"));
+        assert!(!fuzzy_match("Work", "quick_work (long_running.py:16)"));
+        assert!(!fuzzy_match("krow", "work"));
+    }
+
+    #[test]
+    fn test_ordered_filter() {
+        let content = std::fs::read_to_string("tests/data/py-spy-simple.txt").unwrap();
+        let mut fg = FlameGraph::from_string(content, true);
+        fg.build_ordered_stacks();
+        assert_eq!(fg.ordered_stacks.num_rows, 7);
+
+        fg.ordered_stacks.set_filter("quick");
+        assert_eq!(fg.ordered_stacks.num_rows, 2);
+        assert_eq!(fg.ordered_stacks.num_hidden_by_filter(), 5);
+        assert!(fg
+            .ordered_stacks
+            .shown_entries()
+            .all(|entry| entry.name.starts_with("quick_work")));
+
+        // Combined with a search pattern
+        fg.set_hits(&SearchPattern::new(":16", true, true).unwrap());
+        assert_eq!(fg.ordered_stacks.num_rows, 1);
+        assert_eq!(fg.ordered_stacks.num_hidden_by_filter(), 0);
+
+        fg.ordered_stacks.set_filter("");
+        assert_eq!(fg.ordered_stacks.num_rows, 1);
+        fg.clear_hits();
+        assert_eq!(fg.ordered_stacks.num_rows, 7);
+    }
 }
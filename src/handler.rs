@@ -1,14 +1,23 @@
 use std::time::Instant;
 
 use crate::{
-    app::{App, AppResult, InputBuffer},
+    app::{App, AppResult, InputBuffer, InputKind},
     state::ViewKind,
 };
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
 use tui_input::backend::crossterm::EventHandler;
+use tui_input::InputRequest;
+
+/// Number of siblings to jump over with `Ctrl` and the arrow keys.
+const SIBLING_JUMP: usize = 10;
 
 /// Handles the key events and updates the state of [`App`].
 pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
+    // Raw mode turns `Ctrl-Z` into a key instead of SIGTSTP, so suspend from any prompt too
+    if key_event.code == KeyCode::Char('z') && key_event.modifiers == KeyModifiers::CONTROL {
+        app.suspend();
+        return Ok(());
+    }
     if app.input_buffer.is_none() {
         let tic = Instant::now();
         handle_command(key_event, app)?;
@@ -21,20 +30,87 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
 
 /// Handle key events as commands
 pub fn handle_command(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
-    let mut key_handled = handle_command_generic(key_event, app)?;
+    if app.messages.show_log {
+        handle_message_log(key_event, app);
+        return Ok(());
+    }
+    if app.snapshot_browser.is_some() {
+        handle_snapshot_browser(key_event, app);
+        return Ok(());
+    }
+    if app.process_selector.is_some() {
+        handle_process_selector(key_event, app);
+        return Ok(());
+    }
+    // Any handled key dismisses the current transient messages, but not the ones it sets
+    let first_new_message = app.messages.next_id();
+    let mut key_handled = match app.pending_key.take() {
+        Some(prefix) => handle_key_sequence(prefix, key_event, app)?,
+        None => false,
+    };
     if !key_handled {
-        if app.flamegraph_state().view_kind == ViewKind::FlameGraph {
-            key_handled = handle_command_flamegraph(key_event, app)?;
-        } else {
-            key_handled = handle_command_table(key_event, app)?;
-        }
+        key_handled = handle_command_generic(key_event, app)?;
+    }
+    if !key_handled {
+        key_handled = match app.flamegraph_state().view_kind {
+            ViewKind::FlameGraph => handle_command_flamegraph(key_event, app)?,
+            ViewKind::Table => handle_command_table(key_event, app)?,
+            ViewKind::Summary => handle_command_summary(key_event, app)?,
+        };
     }
-    if key_handled && app.transient_message.is_some() {
-        app.clear_transient_message();
+    if key_handled {
+        app.messages.dismiss_before(first_new_message);
     }
     Ok(())
 }
 
+/// Handle keys while the message log popup is shown.
+fn handle_message_log(key_event: KeyEvent, app: &mut App) {
+    match key_event.code {
+        KeyCode::Down | KeyCode::Char('j') => app.messages.scroll_log(1),
+        KeyCode::Up | KeyCode::Char('k') => app.messages.scroll_log(-1),
+        KeyCode::Char('g') => app.messages.log_offset = 0,
+        KeyCode::Char('G') => app.messages.log_offset = usize::MAX,
+        KeyCode::Esc | KeyCode::Char('M') => app.messages.toggle_log(),
+        KeyCode::Char('q') => app.quit(),
+        _ => {}
+    }
+}
+
+/// Handle keys while the snapshot browser popup is shown.
+fn handle_snapshot_browser(key_event: KeyEvent, app: &mut App) {
+    let Some(browser) = app.snapshot_browser.as_mut() else {
+        return;
+    };
+    match key_event.code {
+        KeyCode::Down | KeyCode::Char('j') => browser.move_selection(1),
+        KeyCode::Up | KeyCode::Char('k') => browser.move_selection(-1),
+        KeyCode::Char('g') => browser.selected = 0,
+        KeyCode::Char('G') => browser.move_selection(isize::MAX),
+        KeyCode::Enter => app.load_selected_snapshot(),
+        KeyCode::Esc => app.snapshot_browser = None,
+        KeyCode::Char('q') => app.quit(),
+        _ => {}
+    }
+}
+
+/// Handle keys while the process selector popup is shown.
+fn handle_process_selector(key_event: KeyEvent, app: &mut App) {
+    let Some(selector) = app.process_selector.as_mut() else {
+        return;
+    };
+    match key_event.code {
+        KeyCode::Down | KeyCode::Char('j') => selector.move_selection(1),
+        KeyCode::Up | KeyCode::Char('k') => selector.move_selection(-1),
+        KeyCode::Char('g') => selector.selected = 0,
+        KeyCode::Char('G') => selector.move_selection(isize::MAX),
+        KeyCode::Enter => app.zoom_to_selected_process(),
+        KeyCode::Esc | KeyCode::Char('p') => app.process_selector = None,
+        KeyCode::Char('q') => app.quit(),
+        _ => {}
+    }
+}
+
 pub fn handle_command_generic(key_event: KeyEvent, app: &mut App) -> AppResult<bool> {
     let mut key_handled = true;
     match key_event.code {
@@ -43,26 +119,85 @@ pub fn handle_command_generic(key_event: KeyEvent, app: &mut App) -> AppResult<b
             app.quit();
         }
         // Exit application on `Ctrl-C`
-        KeyCode::Char('c') | KeyCode::Char('C') => {
-            if key_event.modifiers == KeyModifiers::CONTROL {
-                app.quit();
-            }
+        KeyCode::Char('c') | KeyCode::Char('C') if key_event.modifiers == KeyModifiers::CONTROL => {
+            app.quit();
         }
-        KeyCode::Char('z') => {
+        // In live mode `z` freezes the flamegraph instead of starting a `zz`-style command
+        KeyCode::Char('z') if app.is_live() => {
             app.flamegraph_view.state.toggle_freeze();
         }
+        KeyCode::Char('K') => {
+            app.toggle_kernel_frames();
+        }
+        KeyCode::Char('P') => {
+            app.toggle_common_prefix();
+        }
+        KeyCode::Char('D') if app.is_live() => {
+            app.toggle_live_delta();
+        }
+        KeyCode::Char('B') => {
+            app.toggle_baseline();
+        }
+        KeyCode::Char('W') if app.is_live() => {
+            app.toggle_gil_overlay();
+        }
+        KeyCode::Char('p') => {
+            app.open_process_selector();
+        }
+        KeyCode::Char('I') => {
+            app.toggle_activity_split();
+        }
         KeyCode::Tab => {
-            app.flamegraph_view.state.toggle_view_kind();
+            app.flamegraph_view.toggle_view_kind();
         }
         KeyCode::Char('/') => {
-            app.input_buffer = Some(InputBuffer {
-                buffer: tui_input::Input::new("".to_string()),
-                cursor: None,
-            });
+            app.input_buffer = Some(InputBuffer::new(InputKind::Search));
+        }
+        KeyCode::Char(':') => {
+            app.input_buffer = Some(InputBuffer::new(InputKind::Command));
         }
         KeyCode::Char('?') => {
             app.toggle_debug();
         }
+        KeyCode::Char('M') => {
+            app.messages.toggle_log();
+        }
+        KeyCode::Char('v') => {
+            app.switch_metric(None);
+        }
+        KeyCode::Char('V') => {
+            app.set_palette(app.config.palette.next());
+        }
+        KeyCode::Char('X') => {
+            app.toggle_zen();
+        }
+        KeyCode::Char('u') if key_event.modifiers != KeyModifiers::CONTROL => {
+            app.undo();
+        }
+        KeyCode::Char('r') if key_event.modifiers == KeyModifiers::CONTROL => {
+            app.redo();
+        }
+        _ => {
+            key_handled = false;
+        }
+    }
+    Ok(key_handled)
+}
+
+/// Handle the second key of a multi-key command. Returns false if the sequence is unknown, in
+/// which case the key is handled on its own.
+fn handle_key_sequence(prefix: char, key_event: KeyEvent, app: &mut App) -> AppResult<bool> {
+    let mut key_handled = true;
+    match (prefix, key_event.code) {
+        ('z', KeyCode::Char('z')) => {
+            app.flamegraph_view.scroll_selected_to_center();
+        }
+        ('z', KeyCode::Char('t')) => {
+            app.flamegraph_view.scroll_selected_to_top();
+        }
+        ('z', KeyCode::Char('b')) => {
+            app.flamegraph_view.scroll_selected_to_bottom();
+        }
         _ => {
             key_handled = false;
         }
@@ -73,6 +208,12 @@ pub fn handle_command_generic(key_event: KeyEvent, app: &mut App) -> AppResult<b
 fn handle_command_flamegraph(key_event: KeyEvent, app: &mut App) -> AppResult<bool> {
     let mut key_handled = true;
     match key_event.code {
+        KeyCode::Right if key_event.modifiers == KeyModifiers::CONTROL => {
+            app.flamegraph_view.to_nth_next_sibling(SIBLING_JUMP);
+        }
+        KeyCode::Left if key_event.modifiers == KeyModifiers::CONTROL => {
+            app.flamegraph_view.to_nth_previous_sibling(SIBLING_JUMP);
+        }
         KeyCode::Right | KeyCode::Char('l') => {
             app.flamegraph_view.to_next_sibling();
         }
@@ -85,6 +226,42 @@ fn handle_command_flamegraph(key_event: KeyEvent, app: &mut App) -> AppResult<bo
         KeyCode::Up | KeyCode::Char('k') => {
             app.flamegraph_view.to_parent_stack();
         }
+        KeyCode::Char('J') => {
+            app.flamegraph_view.to_heaviest_leaf();
+        }
+        KeyCode::Char('>') => {
+            let min_width = app.flamegraph_state().min_width.wider();
+            app.set_min_width(min_width);
+        }
+        KeyCode::Char('<') => {
+            let min_width = app.flamegraph_state().min_width.narrower();
+            app.set_min_width(min_width);
+        }
+        KeyCode::Char('[') => {
+            if !app.flamegraph_view.to_same_name_ancestor() {
+                app.set_transient_message("No ancestor with the same name");
+            }
+        }
+        KeyCode::Char(']') => {
+            if !app.flamegraph_view.to_same_name_descendant() {
+                app.set_transient_message("No descendant with the same name");
+            }
+        }
+        KeyCode::Char('F') => {
+            app.toggle_line_aggregation();
+        }
+        KeyCode::Char('+') => {
+            app.toggle_selected_lines();
+        }
+        KeyCode::Char('}') | KeyCode::Char('{') => {
+            let forward = key_event.code == KeyCode::Char('}');
+            if !app.flamegraph_view.to_adjacent_thread(forward) {
+                app.set_transient_message("No thread frames; sample with py-spy --threads");
+            }
+        }
+        KeyCode::Char('T') => {
+            app.input_buffer = Some(InputBuffer::with_value(InputKind::Command, "thread "));
+        }
         KeyCode::Char('G') => {
             app.flamegraph_view.scroll_bottom();
         }
@@ -97,17 +274,43 @@ fn handle_command_flamegraph(key_event: KeyEvent, app: &mut App) -> AppResult<bo
         KeyCode::Char('b') => {
             app.flamegraph_view.page_up();
         }
+        KeyCode::Char('d') if key_event.modifiers == KeyModifiers::CONTROL => {
+            app.flamegraph_view.half_page_down();
+        }
+        KeyCode::Char('u') if key_event.modifiers == KeyModifiers::CONTROL => {
+            app.flamegraph_view.half_page_up();
+        }
+        KeyCode::Char('e') if key_event.modifiers == KeyModifiers::CONTROL => {
+            app.flamegraph_view.scroll_down(1);
+        }
+        KeyCode::Char('y') if key_event.modifiers == KeyModifiers::CONTROL => {
+            app.flamegraph_view.scroll_up(1);
+        }
         KeyCode::Char('n') => {
             app.flamegraph_view.to_next_search_result();
         }
         KeyCode::Char('N') => {
             app.flamegraph_view.to_previous_search_result();
         }
+        // Only told apart from `Enter` by terminals with the kitty keyboard protocol
+        KeyCode::Enter if key_event.modifiers == KeyModifiers::SHIFT => {
+            if !app.flamegraph_view.zoom_to_parent() {
+                app.set_transient_message("Not zoomed in");
+            }
+        }
         KeyCode::Enter => {
             app.flamegraph_view.set_zoom();
         }
         KeyCode::Esc => {
-            app.flamegraph_view.unset_zoom();
+            if app.flamegraph_state().zoom.is_some() || !app.close_callers() {
+                app.flamegraph_view.unset_zoom();
+            }
+        }
+        KeyCode::Char('c') => {
+            app.show_callers_of_selected();
+        }
+        KeyCode::Char('z') => {
+            app.pending_key = Some('z');
         }
         KeyCode::Char('r') => {
             app.flamegraph_view.reset();
@@ -115,6 +318,46 @@ fn handle_command_flamegraph(key_event: KeyEvent, app: &mut App) -> AppResult<bo
         KeyCode::Char('#') => {
             app.search_selected();
         }
+        KeyCode::Char('s') => {
+            app.flamegraph_view.toggle_search_in_zoom();
+            app.set_transient_message(if app.flamegraph_state().search_in_zoom {
+                "Searching within the zoomed frame"
+            } else {
+                "Searching the whole flamegraph"
+            });
+        }
+        KeyCode::Char('Z') => {
+            if !app.flamegraph_view.zoom_to_search_coverage() {
+                app.set_transient_message("No search matches sharing a common ancestor to zoom to");
+            }
+        }
+        KeyCode::Char('a') => {
+            app.edit_selected_note();
+        }
+        KeyCode::Char('A') => {
+            app.toggle_notes();
+        }
+        KeyCode::Char('H') => {
+            app.toggle_depth_histogram();
+        }
+        KeyCode::Char('O') => {
+            app.toggle_own_time();
+        }
+        KeyCode::Char('%') => {
+            app.toggle_ruler();
+        }
+        KeyCode::Char('L') => {
+            app.input_buffer = Some(InputBuffer::new(InputKind::Level));
+        }
+        KeyCode::Char('S') => {
+            app.prompt_save_subtree();
+        }
+        KeyCode::Char('e') => {
+            app.open_selected_in_editor();
+        }
+        KeyCode::Char('R') => {
+            app.select_random_sample();
+        }
         _ => {
             key_handled = false;
         }
@@ -137,17 +380,103 @@ fn handle_command_table(key_event: KeyEvent, app: &mut App) -> AppResult<bool> {
         KeyCode::Char('b') => {
             app.flamegraph_view.scroll_previous_rows();
         }
+        KeyCode::Char('d') if key_event.modifiers == KeyModifiers::CONTROL => {
+            app.flamegraph_view.half_page_next_rows();
+        }
+        KeyCode::Char('u') if key_event.modifiers == KeyModifiers::CONTROL => {
+            app.flamegraph_view.half_page_previous_rows();
+        }
+        KeyCode::Home => {
+            app.flamegraph_view.to_first_row();
+        }
+        KeyCode::End => {
+            app.flamegraph_view.to_last_row();
+        }
         KeyCode::Char('1') => {
             app.flamegraph_view.set_sort_by_total();
         }
         KeyCode::Char('2') => {
             app.flamegraph_view.set_sort_by_own();
         }
+        KeyCode::Char('3') => {
+            app.flamegraph_view.set_sort_by_name();
+        }
+        KeyCode::Char('4') => {
+            app.flamegraph_view.set_sort_by_call_sites();
+        }
+        KeyCode::Char('5') => {
+            if !app.flamegraph_view.set_sort_by_delta() {
+                app.set_transient_message(
+                    "No baseline to compare with; start with --baseline <file>",
+                );
+            }
+        }
         KeyCode::Char('r') => {
             app.flamegraph_view.reset();
         }
+        KeyCode::Char('m') => {
+            app.flamegraph_view.toggle_grouped();
+        }
+        KeyCode::Char('Z') => {
+            app.zoom_to_selected_row();
+        }
+        KeyCode::Char('t') => {
+            if !app.flamegraph_view.toggle_thread_breakdown() {
+                app.set_transient_message("No thread or process frames found");
+            }
+        }
+        KeyCode::Char('c') => {
+            app.show_callers_of_selected_row();
+        }
+        KeyCode::Char('y') if key_event.modifiers != KeyModifiers::CONTROL => {
+            app.copy_selected_row();
+        }
+        KeyCode::Char('Y') => {
+            app.copy_table();
+        }
         KeyCode::Enter => {
-            app.search_selected_row();
+            if !app.flamegraph_view.toggle_selected_group() {
+                app.search_selected_row();
+            }
+        }
+        KeyCode::Char('F') => {
+            let filter = app
+                .flamegraph()
+                .ordered_stacks
+                .filter
+                .clone()
+                .unwrap_or_default();
+            app.input_buffer = Some(InputBuffer::with_value(InputKind::TableFilter, &filter));
+        }
+        _ => {
+            key_handled = false;
+        }
+    }
+    Ok(key_handled)
+}
+
+fn handle_command_summary(key_event: KeyEvent, app: &mut App) -> AppResult<bool> {
+    let mut key_handled = true;
+    match key_event.code {
+        KeyCode::Down | KeyCode::Char('j') => {
+            app.flamegraph_view.scroll_summary(1);
+        }
+        KeyCode::Up | KeyCode::Char('k') => {
+            app.flamegraph_view.scroll_summary(-1);
+        }
+        KeyCode::Char('f') => {
+            app.flamegraph_view
+                .scroll_summary(app.flamegraph_state().frame_height.unwrap_or(10) as isize);
+        }
+        KeyCode::Char('b') => {
+            app.flamegraph_view
+                .scroll_summary(-(app.flamegraph_state().frame_height.unwrap_or(10) as isize));
+        }
+        KeyCode::Char('g') => {
+            app.flamegraph_view.state.summary_offset = 0;
+        }
+        KeyCode::Char('r') => {
+            app.flamegraph_view.reset();
         }
         _ => {
             key_handled = false;
@@ -160,21 +489,67 @@ pub fn handle_input_buffer(key_event: KeyEvent, app: &mut App) -> AppResult<()>
     if let Some(input) = app.input_buffer.as_mut() {
         match key_event.code {
             KeyCode::Esc => {
+                if input.kind == InputKind::TableFilter {
+                    app.flamegraph_view.set_table_filter("");
+                }
                 app.input_buffer = None;
             }
             KeyCode::Enter => {
-                if input.buffer.value().is_empty() {
-                    app.flamegraph_view.unset_manual_search_pattern();
-                } else {
-                    let re_pattern = input.buffer.value().to_string();
-                    app.set_manual_search_pattern(re_pattern.as_str(), true);
+                let value = input.buffer.value().to_string();
+                match input.kind {
+                    InputKind::Search => {
+                        if value.is_empty() {
+                            app.flamegraph_view.unset_manual_search_pattern();
+                        } else {
+                            app.set_manual_search_pattern(value.as_str(), true);
+                        }
+                    }
+                    InputKind::Command => {
+                        if !value.is_empty() {
+                            app.run_command(value.as_str());
+                        }
+                    }
+                    InputKind::Note => {
+                        app.set_selected_note(value.as_str());
+                    }
+                    InputKind::Level => {
+                        if !value.is_empty() {
+                            app.go_to_level(value.as_str());
+                        }
+                    }
+                    InputKind::TableFilter => {
+                        // Already applied while typing
+                    }
                 }
                 app.input_buffer = None;
             }
             _ => {
                 input.buffer.handle_event(&Event::Key(key_event));
+                if input.kind == InputKind::TableFilter {
+                    let filter = input.buffer.value().to_string();
+                    app.flamegraph_view.set_table_filter(&filter);
+                }
             }
         }
     }
     Ok(())
 }
+
+/// Handles text pasted into the terminal, which goes into the prompt being typed in as a whole
+/// rather than as separate keys that could trigger commands.
+pub fn handle_paste(text: &str, app: &mut App) {
+    let Some(input) = app.input_buffer.as_mut() else {
+        app.set_transient_message("Paste into a prompt, e.g. press / to search");
+        return;
+    };
+    // Prompts are single line, e.g. a pasted line ending would otherwise be lost or submit early
+    let text = text.trim_end_matches(['\r', '\n']);
+    for c in text.chars() {
+        let c = if c.is_control() { ' ' } else { c };
+        input.buffer.handle(InputRequest::InsertChar(c));
+    }
+    if input.kind == InputKind::TableFilter {
+        let filter = input.buffer.value().to_string();
+        app.flamegraph_view.set_table_filter(&filter);
+    }
+}
@@ -0,0 +1,1241 @@
+use crate::self_profile;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// Formats of profiles exported by other tools that are converted to folded stacks on load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    /// Folded stacks as produced by stackcollapse scripts, inferno and py-spy
+    Folded,
+    /// Stack tree exported as CSV from Windows Performance Analyzer, e.g. from the "CPU Usage
+    /// (Sampled)" table with the Stack column expanded
+    WpaCsv,
+    /// Call tree of the Instruments Time Profiler copied with "Deep Copy"
+    InstrumentsDeepCopy,
+    /// Flat profile and call graph printed by gprof
+    Gprof,
+    /// Call tree printed by `callgrind_annotate --tree=calling`
+    CallgrindAnnotate,
+    /// Traces printed by phpspy by default, one frame per line
+    Phpspy,
+    /// Folded stacks from perf on the BEAM with its perf map enabled, where Erlang functions are
+    /// named like `$module:function/arity`
+    BeamPerf,
+    /// OpenTelemetry profiles in the JSON encoding of OTLP, as sent by a collector
+    OtlpJson,
+}
+
+impl InputFormat {
+    /// Guess the format from the first lines of the content, falling back to folded stacks.
+    pub fn detect(content: &str) -> Self {
+        let first_line = content
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or_default();
+        if first_line.starts_with('{') && content.contains("\"resourceProfiles\"") {
+            return InputFormat::OtlpJson;
+        }
+        if first_line.contains(',') && wpa_columns(&split_csv_line(first_line)).is_some() {
+            return InputFormat::WpaCsv;
+        }
+        if first_line.contains('\t') && first_line.contains("Symbol Name") {
+            return InputFormat::InstrumentsDeepCopy;
+        }
+        if first_line.trim() == "Flat profile:" || first_line.trim().starts_with("Call graph") {
+            return InputFormat::Gprof;
+        }
+        // Header of callgrind_annotate, or the header of its cost columns
+        if content.lines().take(10).any(|line| {
+            line.starts_with("Profile data file '")
+                || line.starts_with("Events recorded:")
+                || line.trim_end().ends_with(" file:function")
+        }) {
+            return InputFormat::CallgrindAnnotate;
+        }
+        let first_frame = content
+            .lines()
+            .find(|line| !line.trim().is_empty() && !line.starts_with('#'));
+        if first_frame
+            .and_then(phpspy_frame)
+            .is_some_and(|(depth, _)| depth == 0)
+        {
+            return InputFormat::Phpspy;
+        }
+        let has_beam_frames = content.lines().take(100).any(|line| {
+            let stack = line.rsplit_once(' ').map_or(line, |(stack, _)| stack);
+            stack.split(';').any(|frame| beam_function(frame).is_some())
+        });
+        if has_beam_frames {
+            return InputFormat::BeamPerf;
+        }
+        InputFormat::Folded
+    }
+}
+
+/// Convert the content to folded stacks if it is in another format that can be detected.
+pub fn to_folded(content: String) -> String {
+    let _phase = self_profile::phase("import");
+    match InputFormat::detect(&content) {
+        InputFormat::Folded => content,
+        InputFormat::WpaCsv => wpa_csv_to_folded(&content),
+        InputFormat::InstrumentsDeepCopy => instruments_to_folded(&content),
+        InputFormat::Gprof => gprof_to_folded(&content),
+        InputFormat::CallgrindAnnotate => callgrind_annotate_to_folded(&content),
+        InputFormat::Phpspy => phpspy_to_folded(&content),
+        InputFormat::BeamPerf => beam_perf_to_folded(&content),
+        InputFormat::OtlpJson => otlp_json_to_folded(&content),
+    }
+}
+
+/// A node of a call tree with its inclusive weights, listed depth first.
+#[derive(Debug, Clone, PartialEq)]
+struct TreeNode {
+    depth: usize,
+    name: String,
+    weights: Vec<u64>,
+}
+
+/// Folded stacks from a call tree with inclusive weights, where the weights of a node not found
+/// in its children are its own. Several weights become value columns named by `metrics`.
+fn tree_to_folded(nodes: &[TreeNode], metrics: &[&str]) -> String {
+    let mut out = String::new();
+    out += &format!("# metrics: {}\n", metrics.join(" "));
+    let mut self_weights = nodes
+        .iter()
+        .map(|node| node.weights.clone())
+        .collect::<Vec<_>>();
+    // Index of the nodes on the path to the current one
+    let mut path: Vec<usize> = vec![];
+    for (i, node) in nodes.iter().enumerate() {
+        path.truncate(node.depth);
+        if let Some(parent) = path.last() {
+            for (weight, child_weight) in self_weights[*parent].iter_mut().zip(&node.weights) {
+                *weight = weight.saturating_sub(*child_weight);
+            }
+        }
+        path.push(i);
+    }
+    let mut names: Vec<&str> = vec![];
+    for (node, weights) in nodes.iter().zip(self_weights) {
+        names.truncate(node.depth);
+        names.push(&node.name);
+        if weights.iter().any(|weight| *weight > 0) {
+            out += &names.join(";");
+            for weight in weights {
+                out += &format!(" {}", weight);
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Split a CSV line into fields, handling quoted fields with commas and doubled quotes.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Numbers in WPA exports may have thousands separators, e.g. "1,234.50".
+fn parse_number(field: &str) -> Option<f64> {
+    field.trim().replace(',', "").parse::<f64>().ok()
+}
+
+/// Indices of the stack, process, count and weight columns in a WPA CSV header. Needs the stack
+/// column and at least one of the count and weight columns.
+fn wpa_columns(header: &[String]) -> Option<HashMap<&'static str, usize>> {
+    let mut columns = HashMap::new();
+    for (i, name) in header.iter().enumerate() {
+        let name = name.trim();
+        let key = match name {
+            "Stack" => "stack",
+            "Process" => "process",
+            "Count" | "Sample Count" => "count",
+            _ if name.starts_with("Weight") && name.contains("(ms)") => "weight",
+            _ => continue,
+        };
+        columns.entry(key).or_insert(i);
+    }
+    let has_values = columns.contains_key("count") || columns.contains_key("weight");
+    (columns.contains_key("stack") && has_values).then_some(columns)
+}
+
+/// WPA draws the stack tree with "|-" before the frames with siblings, while a frame that is the
+/// only child of the one above is written below it at the same indentation:
+///
+/// ```text
+/// [Root]
+///   |- ntdll.dll!RtlUserThreadStart
+///   |    kernel32.dll!BaseThreadInitThunk
+///   |    |- app.exe!work
+///   |    |- app.exe!idle
+/// ```
+fn wpa_csv_to_folded(content: &str) -> String {
+    let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+    let header = split_csv_line(lines.next().unwrap_or_default());
+    let Some(columns) = wpa_columns(&header) else {
+        return String::new();
+    };
+    let metrics = match (columns.get("count"), columns.get("weight")) {
+        (Some(_), Some(_)) => vec!["samples/count", "weight/microseconds"],
+        (Some(_), None) => vec!["samples/count"],
+        _ => vec!["weight/microseconds"],
+    };
+    let mut nodes = vec![];
+    // Column where the name starts and the depth of the frames above
+    let mut ancestors: Vec<(usize, usize)> = vec![];
+    for line in lines {
+        let fields = split_csv_line(line);
+        let field = |key: &str| columns.get(key).and_then(|i| fields.get(*i));
+        let Some(stack) = field("stack") else {
+            continue;
+        };
+        let weights = [
+            field("count")
+                .and_then(|f| parse_number(f))
+                .map(|c| c as u64),
+            field("weight")
+                .and_then(|f| parse_number(f))
+                .map(|ms| (ms * 1000.0).round() as u64),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        if weights.len() != metrics.len() {
+            continue;
+        }
+        let name = stack.trim_start_matches([' ', '|', '-']).trim_end();
+        let column = stack.len() - stack.trim_start_matches([' ', '|', '-']).len();
+        let is_branch = stack[..column].trim_end().ends_with("|-");
+        let process = field("process").map(|p| p.trim()).filter(|p| !p.is_empty());
+        if name == "[Root]" || name.is_empty() {
+            // The root of each process, named after it if known
+            ancestors.clear();
+            if let Some(process) = process {
+                nodes.push(TreeNode {
+                    depth: 0,
+                    name: process.to_string(),
+                    weights,
+                });
+                ancestors.push((column, 0));
+            }
+            continue;
+        }
+        if is_branch {
+            while ancestors.last().is_some_and(|(c, _)| *c >= column) {
+                ancestors.pop();
+            }
+        }
+        let depth = ancestors.last().map_or(0, |(_, depth)| depth + 1);
+        ancestors.push((column, depth));
+        nodes.push(TreeNode {
+            depth,
+            name: name.to_string(),
+            weights,
+        });
+    }
+    tree_to_folded(&nodes, &metrics)
+}
+
+/// Weight of an Instruments call tree row such as "10.06 s  100.0%", "1234.0ms" or "1234  45.6%"
+/// for sample counts. Returns the weight in nanoseconds if it is a time, along with whether it is.
+fn parse_instruments_weight(field: &str) -> Option<(u64, bool)> {
+    let field = field
+        .split_whitespace()
+        .filter(|token| !token.ends_with('%'))
+        .collect::<String>();
+    let split = field
+        .find(|c: char| !c.is_ascii_digit() && c != '.' && c != ',')
+        .unwrap_or(field.len());
+    let value = parse_number(&field[..split])?;
+    let scale = match &field[split..] {
+        "" => return Some((value.round() as u64, false)),
+        "ns" => 1.0,
+        "µs" | "us" => 1e3,
+        "ms" => 1e6,
+        "s" => 1e9,
+        "min" => 60e9,
+        _ => return None,
+    };
+    Some(((value * scale).round() as u64, true))
+}
+
+/// The "Deep Copy" of the Instruments call tree has tab separated weight, self weight and symbol
+/// columns, with the symbols indented by a space per level:
+///
+/// ```text
+/// Weight\tSelf Weight\t\tSymbol Name
+/// 10.06 s  100.0%\t0 s\t \tMyApp (12345)
+/// 10.06 s  100.0%\t0 s\t \t Main Thread  0x2c5a1
+/// 9.91 s  98.5%\t0 s\t \t  start
+/// ```
+fn instruments_to_folded(content: &str) -> String {
+    let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+    let header = lines
+        .next()
+        .unwrap_or_default()
+        .split('\t')
+        .collect::<Vec<_>>();
+    let symbol_column = header
+        .iter()
+        .position(|name| name.trim() == "Symbol Name")
+        .unwrap_or(header.len().saturating_sub(1));
+    let mut rows = vec![];
+    for line in lines {
+        let fields = line.split('\t').collect::<Vec<_>>();
+        let (Some(weight), Some(symbol)) = (fields.first(), fields.get(symbol_column)) else {
+            continue;
+        };
+        let Some((weight, is_time)) = parse_instruments_weight(weight) else {
+            continue;
+        };
+        let name = symbol.trim_start_matches(' ');
+        let indent = symbol.len() - name.len();
+        rows.push((indent, name.trim_end(), weight, is_time));
+    }
+    let min_indent = rows.iter().map(|row| row.0).min().unwrap_or(0);
+    let metric = if rows.iter().any(|row| row.3) {
+        "weight/nanoseconds"
+    } else {
+        "samples/count"
+    };
+    let nodes = rows
+        .into_iter()
+        .map(|(indent, name, weight, _)| TreeNode {
+            depth: indent - min_indent,
+            name: name.to_string(),
+            weights: vec![weight],
+        })
+        .collect::<Vec<_>>();
+    tree_to_folded(&nodes, &[metric])
+}
+
+/// A function of a call graph with its own weight and the weights of the calls it makes.
+#[derive(Debug, Clone, Default)]
+struct CallGraphNode {
+    self_weight: f64,
+    callees: Vec<(String, f64)>,
+}
+
+impl CallGraphNode {
+    fn total_weight(&self) -> f64 {
+        self.self_weight + self.callees.iter().map(|(_, weight)| weight).sum::<f64>()
+    }
+}
+
+/// Folded stacks unrolled from the functions of a call graph that no other function calls. A call
+/// graph does not know which callers the calls of a function came from, so the weight of a function
+/// is split between it and its callees the same way wherever it is called, as gprof does itself.
+/// Recursive calls and calls lighter than a ten thousandth of the total are counted as the caller's
+/// own weight.
+fn call_graph_to_folded(graph: &HashMap<String, CallGraphNode>, metric: &str) -> String {
+    let called = graph
+        .values()
+        .flat_map(|node| node.callees.iter().map(|(name, _)| name.as_str()))
+        .collect::<HashSet<_>>();
+    let mut roots = graph
+        .keys()
+        .map(|name| name.as_str())
+        .filter(|name| !called.contains(name))
+        .collect::<Vec<_>>();
+    if roots.is_empty() {
+        // Every function is part of a cycle
+        roots.extend(
+            graph
+                .iter()
+                .max_by(|a, b| a.1.total_weight().total_cmp(&b.1.total_weight()))
+                .map(|(name, _)| name.as_str()),
+        );
+    }
+    roots.sort();
+    let total_weight = roots
+        .iter()
+        .map(|root| graph[*root].total_weight())
+        .sum::<f64>();
+    let mut counts: HashMap<String, f64> = HashMap::new();
+    let mut path = vec![];
+    for root in roots {
+        unroll_call_graph(
+            graph,
+            root,
+            graph[root].total_weight(),
+            total_weight / 10000.0,
+            &mut path,
+            &mut counts,
+        );
+    }
+    let mut counts = counts
+        .into_iter()
+        .map(|(stack, count)| (stack, count.round() as u64))
+        .filter(|(_, count)| *count > 0)
+        .collect::<Vec<_>>();
+    counts.sort();
+    let mut out = format!("# metrics: {}\n", metric);
+    for (stack, count) in counts {
+        out += &format!("{} {}\n", stack, count);
+    }
+    out
+}
+
+fn unroll_call_graph<'a>(
+    graph: &'a HashMap<String, CallGraphNode>,
+    name: &'a str,
+    weight: f64,
+    min_weight: f64,
+    path: &mut Vec<&'a str>,
+    counts: &mut HashMap<String, f64>,
+) {
+    path.push(name);
+    let mut self_weight = weight;
+    if let Some(node) = graph.get(name).filter(|node| node.total_weight() > 0.0) {
+        self_weight = weight * node.self_weight / node.total_weight();
+        for (callee, callee_weight) in node.callees.iter() {
+            let callee_weight = weight * callee_weight / node.total_weight();
+            if callee_weight < min_weight || path.contains(&callee.as_str()) {
+                self_weight += callee_weight;
+            } else {
+                unroll_call_graph(graph, callee, callee_weight, min_weight, path, counts);
+            }
+        }
+    }
+    *counts.entry(path.join(";")).or_default() += self_weight;
+    path.pop();
+}
+
+/// Split the numbers at the start of a line of a text report from the rest, e.g. the self and
+/// children times and the call counts "1/2" or "3+1" of a gprof call graph line.
+fn split_numbers(line: &str) -> (Vec<&str>, &str) {
+    let is_number = |token: &str| {
+        token.chars().any(|c| c.is_ascii_digit())
+            && token
+                .chars()
+                .all(|c| c.is_ascii_digit() || ".,/+".contains(c))
+    };
+    let mut numbers = vec![];
+    let mut rest = line.trim_start();
+    while let Some(token) = rest.split_whitespace().next().filter(|t| is_number(t)) {
+        numbers.push(token);
+        rest = rest[token.len()..].trim_start();
+    }
+    (numbers, rest.trim_end())
+}
+
+/// Name of a function in gprof output without its index and cycle, e.g. "foo <cycle 1> [3]".
+fn gprof_function_name(name: &str) -> &str {
+    let mut name = name.trim();
+    if let Some((rest, index)) = name.rsplit_once(" [") {
+        if index
+            .trim_end_matches(']')
+            .chars()
+            .all(|c| c.is_ascii_digit())
+        {
+            name = rest.trim_end();
+        }
+    }
+    match name.rsplit_once(" <cycle ") {
+        Some((rest, _)) if name.ends_with('>') => rest,
+        _ => name,
+    }
+}
+
+/// gprof prints the time in seconds of each function and of the calls to each of its children in
+/// the call graph, after a flat profile with the time of each function alone:
+///
+/// ```text
+/// index % time    self  children    called     name
+///                                                  <spontaneous>
+/// [1]    100.0    0.00    0.50                 main [1]
+///                 0.30    0.10       1/1           foo [2]
+/// -----------------------------------------------
+/// ```
+///
+/// The flat profile becomes one frame per function if there is no call graph.
+fn gprof_to_folded(content: &str) -> String {
+    #[derive(PartialEq)]
+    enum Section {
+        Flat,
+        CallGraph,
+    }
+    let mut section = None;
+    let mut has_rows = false;
+    let mut graph: HashMap<String, CallGraphNode> = HashMap::new();
+    let mut flat: HashMap<String, CallGraphNode> = HashMap::new();
+    // Function of the call graph entry, which follows the lines of its parents
+    let mut current: Option<&str> = None;
+    for line in content.lines() {
+        let line = line.trim_matches(|c: char| c.is_whitespace() || c == '\u{c}');
+        if line.starts_with("Flat profile") || (line.starts_with("index") && line.contains("name"))
+        {
+            section = Some(if line.starts_with("index") {
+                Section::CallGraph
+            } else {
+                Section::Flat
+            });
+            has_rows = false;
+            continue;
+        }
+        if line.is_empty() {
+            // A table ends with a blank line, before the explanation of its columns
+            if has_rows {
+                section = None;
+            }
+            continue;
+        }
+        if line.starts_with("---") {
+            current = None;
+            continue;
+        }
+        let seconds = |number: &str| parse_number(number).map(|n| n * 1e6);
+        match section {
+            Some(Section::Flat) => {
+                let (numbers, name) = split_numbers(line);
+                let Some(self_time) = numbers.get(2).and_then(|n| seconds(n)) else {
+                    continue;
+                };
+                has_rows = true;
+                flat.entry(gprof_function_name(name).to_string())
+                    .or_default()
+                    .self_weight += self_time;
+            }
+            Some(Section::CallGraph) => {
+                let is_primary = line.starts_with('[');
+                let line = match line.split_once(']') {
+                    Some((_, rest)) if is_primary => rest,
+                    _ => line,
+                };
+                let (numbers, name) = split_numbers(line);
+                let name = gprof_function_name(name);
+                has_rows = true;
+                if is_primary {
+                    let Some(self_time) = numbers.get(1).and_then(|n| seconds(n)) else {
+                        continue;
+                    };
+                    current = Some(name);
+                    graph.entry(name.to_string()).or_default().self_weight = self_time;
+                } else if let Some(current) = current {
+                    // Calls within a cycle only have counts
+                    let (Some(self_time), Some(children_time)) = (
+                        numbers.first().and_then(|n| seconds(n)),
+                        numbers.get(1).and_then(|n| seconds(n)),
+                    ) else {
+                        continue;
+                    };
+                    graph
+                        .entry(current.to_string())
+                        .or_default()
+                        .callees
+                        .push((name.to_string(), self_time + children_time));
+                }
+            }
+            None => {}
+        }
+    }
+    let graph = if graph.is_empty() { flat } else { graph };
+    call_graph_to_folded(&graph, "cpu/microseconds")
+}
+
+/// Name of a function in callgrind_annotate output without its file, object and call count, e.g.
+/// "src/a.c:foo (2x) [/usr/bin/a]".
+fn callgrind_function_name(name: &str) -> &str {
+    let mut name = name.trim();
+    if let Some((rest, _)) = name.rsplit_once(" [").filter(|_| name.ends_with(']')) {
+        name = rest;
+    }
+    if let Some((rest, _)) = name.rsplit_once(" (").filter(|_| name.ends_with("x)")) {
+        name = rest;
+    }
+    name.split_once(':').map_or(name, |(_, function)| function)
+}
+
+/// With `--tree=calling`, callgrind_annotate prints the cost of each function marked with "*",
+/// followed by the inclusive costs of the functions it calls marked with ">":
+///
+/// ```text
+/// Ir                 file:function
+/// 8,000,012 (100.0%)  *  a.c:main [/tmp/a.out]
+/// 6,000,000 (75.00%)  >   a.c:work (2x) [/tmp/a.out]
+/// ```
+///
+/// The cost of a function is its own unless `--inclusive=yes` is given, which is told apart by
+/// some function costing less than its calls. Only the first event is read.
+fn callgrind_annotate_to_folded(content: &str) -> String {
+    let mut event = "Ir";
+    let mut graph: HashMap<String, CallGraphNode> = HashMap::new();
+    let mut current: Option<&str> = None;
+    for line in content.lines() {
+        if let Some(header) = line.trim().strip_suffix("file:function") {
+            event = header.split_whitespace().next().unwrap_or(event);
+            continue;
+        }
+        let (numbers, rest) = split_numbers(line);
+        let Some(cost) = numbers.first().and_then(|n| parse_number(n)) else {
+            continue;
+        };
+        // Share of the total, e.g. "( 3.59%)"
+        let rest = match rest.strip_prefix('(') {
+            Some(rest) => rest.split_once(')').map_or(rest, |(_, rest)| rest),
+            None => rest,
+        }
+        .trim_start();
+        if let Some(name) = rest.strip_prefix('*') {
+            let name = callgrind_function_name(name);
+            current = Some(name);
+            graph.entry(name.to_string()).or_default().self_weight += cost;
+        } else if let (Some(name), Some(current)) = (rest.strip_prefix('>'), current) {
+            graph
+                .entry(current.to_string())
+                .or_default()
+                .callees
+                .push((callgrind_function_name(name).to_string(), cost));
+        }
+    }
+    let inclusive = graph
+        .values()
+        .all(|node| 2.0 * node.self_weight >= node.total_weight());
+    if inclusive {
+        for node in graph.values_mut() {
+            let callees = node.total_weight() - node.self_weight;
+            node.self_weight = (node.self_weight - callees).max(0.0);
+        }
+    }
+    call_graph_to_folded(&graph, &format!("{}/count", event))
+}
+
+/// A frame of a phpspy trace with its depth from the innermost one, e.g.
+/// "1 Foo::bar /srv/app/Foo.php:12". Functions of PHP itself have no source, e.g.
+/// "0 sleep <internal>:-1", and are named alone.
+fn phpspy_frame(line: &str) -> Option<(usize, &str)> {
+    let (depth, frame) = line.trim().split_once(' ')?;
+    let depth = depth.parse::<usize>().ok()?;
+    let (function, location) = frame.rsplit_once(' ')?;
+    let (_, line) = location.rsplit_once(':')?;
+    line.parse::<i64>().ok()?;
+    if location.starts_with("<internal>") {
+        Some((depth, function))
+    } else {
+        Some((depth, frame))
+    }
+}
+
+/// phpspy prints the frames of each trace from the innermost one, followed by a blank line.
+/// Lines starting with "#" hold the request info and memory usage asked for with its options,
+/// and are left out.
+///
+/// ```text
+/// 0 sleep <internal>:-1
+/// 1 Foo::wait /srv/app/Foo.php:12
+/// 2 <main> /srv/app/index.php:5
+/// # uri = /index.php
+/// ```
+fn phpspy_to_folded(content: &str) -> String {
+    let mut counts: HashMap<String, u64> = HashMap::new();
+    let mut frames = vec![];
+    for line in content.lines().chain([""]) {
+        let frame = phpspy_frame(line);
+        let is_new_trace = frame.is_some_and(|(depth, _)| depth == 0);
+        if (line.trim().is_empty() || is_new_trace) && !frames.is_empty() {
+            frames.reverse();
+            *counts.entry(frames.join(";")).or_default() += 1;
+            frames.clear();
+        }
+        if let Some((_, frame)) = frame {
+            frames.push(frame);
+        }
+    }
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort();
+    counts
+        .into_iter()
+        .map(|(stack, count)| format!("{} {}\n", stack, count))
+        .collect()
+}
+
+/// The Erlang function of a frame named by the perf map of the BEAM JIT, e.g. "lists:foldl/3"
+/// for "$lists:foldl/3".
+fn beam_function(frame: &str) -> Option<&str> {
+    let function = frame.strip_prefix('$')?;
+    let (_, arity) = function.split_once(':')?.1.rsplit_once('/')?;
+    arity.parse::<u32>().ok()?;
+    Some(function)
+}
+
+/// Name the Erlang functions as eflambe does, so that profiles from both look the same.
+fn beam_perf_to_folded(content: &str) -> String {
+    let mut out = String::with_capacity(content.len());
+    for line in content.lines() {
+        let (stack, count) = line.rsplit_once(' ').unwrap_or((line, ""));
+        let frames = stack
+            .split(';')
+            .map(|frame| beam_function(frame).unwrap_or(frame))
+            .collect::<Vec<_>>();
+        out += &frames.join(";");
+        if !count.is_empty() {
+            out.push(' ');
+            out += count;
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// A field of an OTLP JSON object, which is in lowerCamelCase but may also be in the snake_case
+/// of the protobuf definitions.
+fn otlp_field<'a>(value: &'a Value, name: &str) -> Option<&'a Value> {
+    value.get(name).or_else(|| {
+        let mut snake_case = String::new();
+        for c in name.chars() {
+            if c.is_ascii_uppercase() {
+                snake_case.push('_');
+            }
+            snake_case.push(c.to_ascii_lowercase());
+        }
+        value.get(snake_case)
+    })
+}
+
+fn otlp_array<'a>(value: &'a Value, name: &str) -> &'a [Value] {
+    otlp_field(value, name)
+        .and_then(|array| array.as_array())
+        .map_or(&[], |array| array.as_slice())
+}
+
+/// 64 bit integers are strings in OTLP JSON, though smaller ones may be numbers.
+fn otlp_int(value: &Value) -> Option<u64> {
+    value
+        .as_u64()
+        .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+}
+
+fn otlp_int_field(value: &Value, name: &str) -> Option<u64> {
+    otlp_field(value, name).and_then(otlp_int)
+}
+
+/// Lookup tables of OTLP profiles, shared by all of them in the dictionary of the request since
+/// the v1development protocol, and in each profile before.
+struct OtlpTables<'a> {
+    profile: &'a Value,
+    dictionary: Option<&'a Value>,
+}
+
+impl<'a> OtlpTables<'a> {
+    fn table(&self, name: &str) -> &'a [Value] {
+        let table = otlp_array(self.profile, name);
+        match self.dictionary {
+            Some(dictionary) if table.is_empty() => otlp_array(dictionary, name),
+            _ => table,
+        }
+    }
+
+    fn entry(&self, name: &str, index: Option<u64>) -> Option<&'a Value> {
+        self.table(name).get(index.unwrap_or(0) as usize)
+    }
+
+    fn string(&self, index: Option<u64>) -> &'a str {
+        self.entry("stringTable", index)
+            .and_then(|s| s.as_str())
+            .unwrap_or_default()
+    }
+
+    /// Indices into the location table of a sample, from the leaf. A range of locations beyond
+    /// the tables makes the sample malformed, with no locations, as pushed profiles come from the
+    /// network.
+    fn sample_locations(&self, sample: &Value) -> Vec<u64> {
+        if let Some(stack) = otlp_field(sample, "stackIndex") {
+            let stack = self.entry("stackTable", otlp_int(stack));
+            return stack.map_or(vec![], |stack| {
+                otlp_array(stack, "locationIndices")
+                    .iter()
+                    .filter_map(otlp_int)
+                    .collect()
+            });
+        }
+        let start = otlp_int_field(sample, "locationsStartIndex").unwrap_or(0);
+        let length = otlp_int_field(sample, "locationsLength").unwrap_or(0);
+        let indices = self.table("locationIndices");
+        let num_locations = match indices {
+            [] => self.table("locationTable").len(),
+            indices => indices.len(),
+        };
+        let Some(end) = start
+            .checked_add(length)
+            .filter(|end| *end <= num_locations as u64)
+        else {
+            return vec![];
+        };
+        (start..end)
+            .filter_map(|i| {
+                if indices.is_empty() {
+                    Some(i)
+                } else {
+                    indices.get(i as usize).and_then(otlp_int)
+                }
+            })
+            .collect()
+    }
+
+    /// Frames of a location, more than one if functions were inlined into it, from the innermost.
+    fn location_frames(&self, index: u64) -> Vec<String> {
+        let Some(location) = self.entry("locationTable", Some(index)) else {
+            return vec![];
+        };
+        let lines = match otlp_array(location, "line") {
+            [] => otlp_array(location, "lines"),
+            lines => lines,
+        };
+        if lines.is_empty() {
+            let address = otlp_int_field(location, "address").unwrap_or(0);
+            return vec![format!("0x{:x}", address)];
+        }
+        lines
+            .iter()
+            .map(|line| {
+                let function = self.entry("functionTable", otlp_int_field(line, "functionIndex"));
+                let name = function.map_or("", |f| self.string(otlp_int_field(f, "nameStrindex")));
+                let file =
+                    function.map_or("", |f| self.string(otlp_int_field(f, "filenameStrindex")));
+                let name = if name.is_empty() { "[unknown]" } else { name };
+                match otlp_int_field(line, "line") {
+                    Some(line) if line > 0 && !file.is_empty() => {
+                        format!("{} {}:{}", name, file, line)
+                    }
+                    _ => name.to_string(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The OTLP profiles signal is based on pprof, with a value per sample type for each sample, and
+/// function names and files in a string table. Each profile in the request may have other sample
+/// types, which become metrics to switch between.
+fn otlp_json_to_folded(content: &str) -> String {
+    // The file exporter of the collector writes a request per line
+    let requests = serde_json::Deserializer::from_str(content)
+        .into_iter::<Value>()
+        .map_while(Result::ok)
+        .collect::<Vec<_>>();
+    let mut metrics: Vec<String> = vec![];
+    let mut counts: HashMap<String, Vec<u64>> = HashMap::new();
+    for request in requests.iter() {
+        let dictionary = otlp_field(request, "dictionary");
+        let profiles = otlp_array(request, "resourceProfiles")
+            .iter()
+            .flat_map(|resource| otlp_array(resource, "scopeProfiles"))
+            .flat_map(|scope| otlp_array(scope, "profiles"));
+        for profile in profiles {
+            let tables = OtlpTables {
+                profile,
+                dictionary,
+            };
+            let sample_types = match otlp_field(profile, "sampleType") {
+                Some(Value::Array(types)) => types.iter().collect(),
+                Some(sample_type) => vec![sample_type],
+                None => vec![],
+            };
+            let mut columns = sample_types
+                .iter()
+                .map(|sample_type| {
+                    let name = tables.string(otlp_int_field(sample_type, "typeStrindex"));
+                    let unit = tables.string(otlp_int_field(sample_type, "unitStrindex"));
+                    let metric = format!("{}/{}", name, unit);
+                    metrics
+                        .iter()
+                        .position(|m| *m == metric)
+                        .unwrap_or_else(|| {
+                            metrics.push(metric);
+                            metrics.len() - 1
+                        })
+                })
+                .collect::<Vec<_>>();
+            if columns.is_empty() {
+                let metric = "samples/count".to_string();
+                columns.push(
+                    metrics
+                        .iter()
+                        .position(|m| *m == metric)
+                        .unwrap_or_else(|| {
+                            metrics.push(metric);
+                            metrics.len() - 1
+                        }),
+                );
+            }
+            for sample in otlp_array(profile, "sample") {
+                let mut values = match otlp_array(sample, "values") {
+                    [] => otlp_array(sample, "value"),
+                    values => values,
+                }
+                .iter()
+                .filter_map(otlp_int)
+                .collect::<Vec<_>>();
+                if values.is_empty() {
+                    // Samples may only have the times they were taken at
+                    values.push(otlp_array(sample, "timestampsUnixNano").len().max(1) as u64);
+                }
+                let mut frames = tables
+                    .sample_locations(sample)
+                    .into_iter()
+                    .flat_map(|location| tables.location_frames(location))
+                    .collect::<Vec<_>>();
+                if frames.is_empty() {
+                    continue;
+                }
+                frames.reverse();
+                let stack_counts = counts.entry(frames.join(";")).or_default();
+                for (column, value) in columns.iter().zip(values) {
+                    if stack_counts.len() <= *column {
+                        stack_counts.resize(column + 1, 0);
+                    }
+                    stack_counts[*column] += value;
+                }
+            }
+        }
+    }
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort();
+    let mut out = format!("# metrics: {}\n", metrics.join(" "));
+    for (stack, mut values) in counts {
+        values.resize(metrics.len(), 0);
+        out += &stack;
+        for value in values {
+            out += &format!(" {}", value);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tree_to_folded() {
+        let node = |depth: usize, name: &str, weight: u64| TreeNode {
+            depth,
+            name: name.to_string(),
+            weights: vec![weight],
+        };
+        let nodes = vec![
+            node(0, "main", 10),
+            node(1, "a", 6),
+            node(2, "b", 6),
+            node(1, "c", 3),
+        ];
+        assert_eq!(
+            tree_to_folded(&nodes, &["samples/count"]),
+            "# metrics: samples/count\nmain 1\nmain;a;b 6\nmain;c 3\n"
+        );
+    }
+
+    #[test]
+    fn test_wpa_csv() {
+        let content = [
+            "Line #,Process,Stack,Count,Weight (in view) (ms),% Weight",
+            "1,app.exe (1234),[Root],10,\"10,000.50\",100.00",
+            "2,app.exe (1234),  |- ntdll.dll!RtlUserThreadStart,9,9000,90.00",
+            "3,app.exe (1234),  |    kernel32.dll!BaseThreadInitThunk,9,9000,90.00",
+            "4,app.exe (1234),  |    |- app.exe!work,6,6000,60.00",
+            "5,app.exe (1234),  |    |    \"app.exe!std::map<int,int>::find\",4,4000,40.00",
+            "6,app.exe (1234),  |    |- app.exe!idle,2,2000,20.00",
+            "7,app.exe (1234),  |- ntdll.dll!LdrInitializeThunk,1,1000,10.00",
+        ]
+        .join("\n");
+        assert_eq!(InputFormat::detect(&content), InputFormat::WpaCsv);
+        assert_eq!(InputFormat::detect("main;a 1\n"), InputFormat::Folded);
+        let start = "app.exe (1234);ntdll.dll!RtlUserThreadStart;kernel32.dll!BaseThreadInitThunk";
+        assert_eq!(
+            to_folded(content),
+            [
+                "# metrics: samples/count weight/microseconds".to_string(),
+                "app.exe (1234) 0 500".to_string(),
+                format!("{} 1 1000000", start),
+                format!("{};app.exe!work 2 2000000", start),
+                format!(
+                    "{};app.exe!work;app.exe!std::map<int,int>::find 4 4000000",
+                    start
+                ),
+                format!("{};app.exe!idle 2 2000000", start),
+                "app.exe (1234);ntdll.dll!LdrInitializeThunk 1 1000000".to_string(),
+                "".to_string(),
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_instruments_deep_copy() {
+        let content = [
+            "Weight\tSelf Weight\t\tSymbol Name",
+            "10.06 s  100.0%\t0 s\t \tMyApp (12345)",
+            "10.06 s  100.0%\t0 s\t \t Main Thread  0x2c5a1",
+            "10.00 s  99.4%\t0 s\t \t  start",
+            "8.00 s  79.5%\t8.00 s\t \t   work()",
+            "1.5ms  0.0%\t1.5ms\t \t   -[NSObject init]",
+        ]
+        .join("\n");
+        assert_eq!(
+            InputFormat::detect(&content),
+            InputFormat::InstrumentsDeepCopy
+        );
+        let start = "MyApp (12345);Main Thread  0x2c5a1;start";
+        assert_eq!(
+            to_folded(content),
+            [
+                "# metrics: weight/nanoseconds".to_string(),
+                "MyApp (12345);Main Thread  0x2c5a1 60000000".to_string(),
+                format!("{} 1998500000", start),
+                format!("{};work() 8000000000", start),
+                format!("{};-[NSObject init] 1500000", start),
+                "".to_string(),
+            ]
+            .join("\n")
+        );
+
+        let content =
+            "Sample Count\tSelf\t\tSymbol Name\n12  100.0%\t2\t \tmain\n10  83.3%\t10\t \t work\n";
+        assert_eq!(
+            to_folded(content.to_string()),
+            "# metrics: samples/count\nmain 2\nmain;work 10\n"
+        );
+    }
+
+    #[test]
+    fn test_gprof() {
+        let flat = [
+            "Flat profile:",
+            "",
+            "Each sample counts as 0.01 seconds.",
+            "  %   cumulative   self              self     total           ",
+            " time   seconds   seconds    calls  ms/call  ms/call  name    ",
+            " 60.00      0.30     0.30        2   150.00   200.00  foo",
+            " 40.00      0.50     0.20        3    66.67    66.67  bar",
+            "",
+            " %         the percentage of the total running time of the",
+            "",
+        ]
+        .join("\n");
+        let call_graph = [
+            "\t\t     Call graph (explanation follows)",
+            "",
+            "",
+            "granularity: each sample hit covers 2 byte(s) for 2.00% of 0.50 seconds",
+            "",
+            "index % time    self  children    called     name",
+            "                                                 <spontaneous>",
+            "[1]    100.0    0.00    0.50                 main [1]",
+            "                0.30    0.10       2/2           foo [2]",
+            "                0.10    0.00       1/3           bar <cycle 1> [3]",
+            "-----------------------------------------------",
+            "                0.30    0.10       2/2           main [1]",
+            "[2]     80.0    0.30    0.10       2         foo [2]",
+            "                0.10    0.00       2/3           bar <cycle 1> [3]",
+            "-----------------------------------------------",
+            "                0.10    0.00       1/3           main [1]",
+            "                0.10    0.00       2/3           foo [2]",
+            "[3]     40.0    0.20    0.00       3         bar <cycle 1> [3]",
+            "-----------------------------------------------",
+            "",
+            " This table describes the call tree of the program, and was sorted by",
+            "",
+            "Index by function name",
+            "",
+            "   [3] bar                     [2] foo                     [1] main",
+        ]
+        .join("\n");
+        let content = format!("{}\n\u{c}\n{}", flat, call_graph);
+        assert_eq!(InputFormat::detect(&content), InputFormat::Gprof);
+        assert_eq!(
+            to_folded(content),
+            "# metrics: cpu/microseconds\nmain;bar 100000\nmain;foo 300000\nmain;foo;bar 100000\n"
+        );
+        assert_eq!(
+            to_folded(flat),
+            "# metrics: cpu/microseconds\nbar 200000\nfoo 300000\n"
+        );
+    }
+
+    #[test]
+    fn test_callgrind_annotate() {
+        let inclusive = [
+            "--------------------------------------------------------------------------------",
+            "Profile data file 'callgrind.out.1234' (creator: callgrind-3.18.1)",
+            "--------------------------------------------------------------------------------",
+            "Ir                 file:function",
+            "--------------------------------------------------------------------------------",
+            "8,000 (100.0%)  *  a.c:main [/tmp/a.out]",
+            "6,000 (75.00%)  >   a.c:work (2x) [/tmp/a.out]",
+            "1,000 (12.50%)  >   ???:puts (1x) [/usr/lib/libc.so.6]",
+            "",
+            "6,000 (75.00%)  *  a.c:work [/tmp/a.out]",
+            "1,000 (12.50%)  >   ???:puts (1x) [/usr/lib/libc.so.6]",
+            "",
+            "2,000 (25.00%)  *  ???:puts [/usr/lib/libc.so.6]",
+        ]
+        .join("\n");
+        assert_eq!(
+            InputFormat::detect(&inclusive),
+            InputFormat::CallgrindAnnotate
+        );
+        let expected =
+            "# metrics: Ir/count\nmain 1000\nmain;puts 1000\nmain;work 5000\nmain;work;puts 1000\n";
+        assert_eq!(to_folded(inclusive.clone()), expected);
+
+        // The same costs without --inclusive=yes
+        let exclusive = inclusive
+            .replace("8,000 (100.0%)", "1,000 (12.50%)")
+            .replace("6,000 (75.00%)  *", "5,000 (62.50%)  *");
+        assert_eq!(to_folded(exclusive), expected);
+
+        // Only the header tells, not frames named after callgrind
+        let folded = "main;run_callgrind 3\nmain;valgrind/callgrind.out 2\n";
+        assert_eq!(InputFormat::detect(folded), InputFormat::Folded);
+        assert_eq!(to_folded(folded.to_string()), folded);
+    }
+
+    #[test]
+    fn test_phpspy() {
+        let content = [
+            "0 sleep <internal>:-1",
+            "1 Foo::wait /srv/app/Foo.php:12",
+            "2 <main> /srv/app/index.php:5",
+            "# uri = /index.php",
+            "# mem 2097152 2097152",
+            "",
+            "0 Foo::wait /srv/app/Foo.php:13",
+            "1 <main> /srv/app/index.php:5",
+            "0 sleep <internal>:-1",
+            "1 Foo::wait /srv/app/Foo.php:12",
+            "2 <main> /srv/app/index.php:5",
+            "",
+        ]
+        .join("\n");
+        assert_eq!(InputFormat::detect(&content), InputFormat::Phpspy);
+        assert_eq!(
+            to_folded(content),
+            [
+                "<main> /srv/app/index.php:5;Foo::wait /srv/app/Foo.php:12;sleep 2",
+                "<main> /srv/app/index.php:5;Foo::wait /srv/app/Foo.php:13 1",
+                "",
+            ]
+            .join("\n")
+        );
+
+        // Excimer's ExcimerLog::formatCollapsed() writes folded stacks already
+        let excimer = "/srv/app/index.php;Foo::wait;{closure} 3\n/srv/app/index.php;sleep 1\n";
+        assert_eq!(InputFormat::detect(excimer), InputFormat::Folded);
+    }
+
+    #[test]
+    fn test_beam_perf() {
+        let content =
+            "beam.smp;erts_schedule 2\nbeam.smp;$gen_server:loop/7;$app:work/1;memcpy 3\n";
+        assert_eq!(InputFormat::detect(content), InputFormat::BeamPerf);
+        assert_eq!(
+            to_folded(content.to_string()),
+            "beam.smp;erts_schedule 2\nbeam.smp;gen_server:loop/7;app:work/1;memcpy 3\n"
+        );
+        let eflambe = "<0.84.0>;gen_server:loop/7;app:work/1 3\n";
+        assert_eq!(InputFormat::detect(eflambe), InputFormat::Folded);
+    }
+
+    #[test]
+    fn test_otlp_json() {
+        // Tables in the dictionary of the request, as in the v1development protocol
+        let content = r#"{
+            "resourceProfiles": [{
+                "scopeProfiles": [{
+                    "profiles": [
+                        {
+                            "sampleType": {"typeStrindex": 1, "unitStrindex": 2},
+                            "sample": [
+                                {"stackIndex": 0, "values": ["3"]},
+                                {"stackIndex": 1, "timestampsUnixNano": ["1", "2"]}
+                            ]
+                        },
+                        {
+                            "sampleType": {"typeStrindex": 3, "unitStrindex": 4},
+                            "sample": [{"stackIndex": 1, "values": [2048]}]
+                        }
+                    ]
+                }]
+            }],
+            "dictionary": {
+                "stringTable": ["", "samples", "count", "alloc_space", "bytes", "main", "main.go", "work"],
+                "functionTable": [{}, {"nameStrindex": 5, "filenameStrindex": 6}, {"nameStrindex": 7}],
+                "locationTable": [
+                    {"lines": [{"functionIndex": 1, "line": "12"}]},
+                    {"lines": [{"functionIndex": 2}, {"functionIndex": 1, "line": 14}]},
+                    {"address": "4096"}
+                ],
+                "stackTable": [{"locationIndices": [0]}, {"locationIndices": [2, 1]}]
+            }
+        }"#;
+        assert_eq!(InputFormat::detect(content), InputFormat::OtlpJson);
+        assert_eq!(
+            to_folded(content.to_string()),
+            [
+                "# metrics: samples/count alloc_space/bytes",
+                "main main.go:12 3 0",
+                "main main.go:14;work;0x1000 2 2048",
+                "",
+            ]
+            .join("\n")
+        );
+
+        // Tables in each profile, as in earlier versions
+        let content = r#"{"resourceProfiles": [{"scopeProfiles": [{"profiles": [{
+            "sampleType": [{"typeStrindex": 1, "unitStrindex": 2}],
+            "sample": [{"locationsStartIndex": 0, "locationsLength": 2, "value": [5]}],
+            "locationIndices": [1, 0],
+            "locationTable": [{"line": [{"functionIndex": 0}]}, {"line": [{"functionIndex": 1}]}],
+            "functionTable": [{"nameStrindex": 3}, {"nameStrindex": 4}],
+            "stringTable": ["", "cpu", "nanoseconds", "main", "work"]
+        }]}]}]}"#;
+        assert_eq!(
+            to_folded(content.to_string()),
+            "# metrics: cpu/nanoseconds\nmain;work 5\n"
+        );
+        let content = format!(
+            "{}\n{}\n",
+            content.replace('\n', ""),
+            content.replace('\n', "")
+        );
+        assert_eq!(
+            to_folded(content),
+            "# metrics: cpu/nanoseconds\nmain;work 10\n"
+        );
+
+        // Ranges of locations beyond the tables are left out rather than followed
+        for (start, length) in [(0, 4_000_000_000u64), (u64::MAX, 2), (1, 2)] {
+            let content = format!(
+                r#"{{"resourceProfiles": [{{"scopeProfiles": [{{"profiles": [{{
+                    "sampleType": [{{"typeStrindex": 1, "unitStrindex": 2}}],
+                    "sample": [
+                        {{"locationsStartIndex": {}, "locationsLength": {}, "value": [5]}},
+                        {{"locationsStartIndex": 0, "locationsLength": 1, "value": [1]}}
+                    ],
+                    "locationTable": [{{"line": [{{"functionIndex": 0}}]}}],
+                    "functionTable": [{{"nameStrindex": 3}}],
+                    "stringTable": ["", "cpu", "nanoseconds", "main"]
+                }}]}}]}}]}}"#,
+                start, length
+            );
+            assert_eq!(to_folded(content), "# metrics: cpu/nanoseconds\nmain 1\n");
+        }
+    }
+}
@@ -19,8 +19,57 @@ pub mod state;
 
 pub mod view;
 
+pub mod recent;
+
+pub mod session;
+
+pub mod notes;
+
+pub mod anonymize;
+
+pub mod summary;
+
+pub mod sampler;
+
 #[cfg(feature = "python")]
 pub mod py_spy;
 
 #[cfg(feature = "python")]
 pub mod py_spy_flamegraph;
+
+pub mod py_spy_subprocess;
+
+pub mod listen;
+
+pub mod logging;
+
+pub mod messages;
+
+pub mod config;
+
+pub mod export;
+
+pub mod check;
+
+pub mod diff;
+
+pub mod editor;
+
+pub mod import;
+
+pub mod self_profile;
+
+pub mod progress;
+
+pub mod memory;
+
+pub mod snapshot;
+
+pub mod archive;
+
+#[cfg(unix)]
+pub mod control;
+
+/// Drive the app with key events and render it in memory, for end-to-end tests
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
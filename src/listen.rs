@@ -0,0 +1,492 @@
+use flate2::read::GzDecoder;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// Largest OTLP/HTTP request body taken, so that a client can't make us allocate whatever
+/// `Content-Length` it claims
+const MAX_BODY: usize = 256 * 1024 * 1024;
+
+const PAYLOAD_TOO_LARGE: &str = "413 Payload Too Large";
+
+/// Where pushed profiles are received from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddress {
+    /// Each TCP connection sends one profile and closes the connection, or sends OTLP/HTTP
+    /// requests with profiles
+    Tcp(SocketAddr),
+    /// Each writer sends one profile and closes the FIFO, e.g. `cat profile.txt > fifo`
+    Fifo(PathBuf),
+}
+
+impl FromStr for ListenAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(addr) = s.parse::<SocketAddr>() {
+            return Ok(ListenAddress::Tcp(addr));
+        }
+        if let Some(port) = s.strip_prefix(':') {
+            let port = port
+                .parse::<u16>()
+                .map_err(|_| format!("invalid port: {}", port))?;
+            return Ok(ListenAddress::Tcp(SocketAddr::from(([127, 0, 0, 1], port))));
+        }
+        Ok(ListenAddress::Fifo(PathBuf::from(s)))
+    }
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddress::Tcp(addr) => write!(f, "{}", addr),
+            ListenAddress::Fifo(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Profiles being received in the background, until this is stopped or dropped.
+#[derive(Debug)]
+pub struct Listener {
+    /// Address actually listened on, e.g. with the port filled in if it was 0
+    pub address: ListenAddress,
+    stop: Arc<AtomicBool>,
+}
+
+impl Listener {
+    /// Stop receiving profiles. The thread waiting for the next connection or writer is woken up
+    /// by connecting to the address or opening the FIFO, and exits once it sees the stop flag.
+    /// Connections already being received are finished.
+    pub fn stop(&self) {
+        if self.stop.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        match &self.address {
+            ListenAddress::Tcp(addr) => {
+                let mut addr = *addr;
+                if addr.ip().is_unspecified() {
+                    addr.set_ip(match addr.ip() {
+                        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+                    });
+                }
+                let _ = TcpStream::connect(addr);
+            }
+            ListenAddress::Fifo(path) => {
+                let _ = open_fifo_writer(path);
+            }
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Start receiving profiles in the background, calling `on_profile` with the content of each one.
+pub fn listen<F>(address: &ListenAddress, on_profile: F) -> io::Result<Listener>
+where
+    F: Fn(String) + Send + Sync + 'static,
+{
+    match address {
+        ListenAddress::Tcp(addr) => {
+            let listener = TcpListener::bind(addr)?;
+            let local_addr = listener.local_addr()?;
+            let on_profile = Arc::new(on_profile);
+            let stop = Arc::new(AtomicBool::new(false));
+            let stopped = stop.clone();
+            thread::spawn(move || {
+                // Each connection gets its own thread so that a slow or idle client doesn't hold
+                // up the others
+                for stream in listener.incoming().flatten() {
+                    if stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let on_profile = on_profile.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = receive(stream, on_profile.as_ref()) {
+                            tracing::warn!(error = %e, "Could not receive profile");
+                        }
+                    });
+                }
+            });
+            Ok(Listener {
+                address: ListenAddress::Tcp(local_addr),
+                stop,
+            })
+        }
+        ListenAddress::Fifo(path) => {
+            check_fifo(path)?;
+            let path = path.clone();
+            let stop = Arc::new(AtomicBool::new(false));
+            let stopped = stop.clone();
+            thread::spawn(move || loop {
+                // Opening blocks until there is a writer, and reading stops once it closes the
+                // FIFO
+                let mut content = String::new();
+                let read = File::open(&path).and_then(|mut f| f.read_to_string(&mut content));
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                match read {
+                    Ok(size) if size > 0 => {
+                        tracing::info!(size, "Received profile");
+                        on_profile(content);
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        tracing::error!(error = %e, path = %path.display(), "Could not read FIFO");
+                        break;
+                    }
+                }
+            });
+            Ok(Listener {
+                address: address.clone(),
+                stop,
+            })
+        }
+    }
+}
+
+/// Receive the profile sent over a TCP connection, or answer the OTLP/HTTP requests sent over it,
+/// e.g. by the otlphttp exporter of an OpenTelemetry collector.
+fn receive<F>(stream: TcpStream, on_profile: &F) -> io::Result<()>
+where
+    F: Fn(String),
+{
+    let peer = stream.peer_addr().ok();
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut content = String::new();
+    reader.read_line(&mut content)?;
+    if !content.starts_with("POST ") {
+        reader.read_to_string(&mut content)?;
+        let size = content.len();
+        if size > 0 {
+            tracing::info!(?peer, size, "Received profile");
+            on_profile(content);
+        }
+        return Ok(());
+    }
+    let mut request_line = content;
+    let mut stream = stream;
+    // Requests keep coming over the same connection until the client closes it
+    while !request_line.is_empty() {
+        let mut close = false;
+        let response = match read_otlp_request(&request_line, &mut reader)? {
+            Ok(content) => {
+                tracing::info!(?peer, size = content.len(), "Received OTLP profiles");
+                on_profile(content);
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n{}"
+                    .to_string()
+            }
+            Err((status, message)) => {
+                tracing::warn!(?peer, status, message, "Rejected OTLP request");
+                // The body was left unread, so the connection can't carry another request
+                close = status == PAYLOAD_TOO_LARGE;
+                format!(
+                    "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
+                    status,
+                    message.len(),
+                    message
+                )
+            }
+        };
+        stream.write_all(response.as_bytes())?;
+        if close {
+            break;
+        }
+        request_line.clear();
+        reader.read_line(&mut request_line)?;
+    }
+    Ok(())
+}
+
+/// Read the headers and body of an OTLP/HTTP request after its request line. The body is read so
+/// that the next request can follow, unless it is larger than [`MAX_BODY`], and an HTTP status
+/// with a message is returned if the request can't be taken.
+fn read_otlp_request<R: BufRead>(
+    request_line: &str,
+    reader: &mut R,
+) -> io::Result<Result<String, (&'static str, String)>> {
+    let mut content_length = None;
+    let mut content_type = String::new();
+    let mut content_encoding = String::new();
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().to_ascii_lowercase();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => content_length = value.parse::<usize>().ok(),
+            "content-type" => content_type = value,
+            "content-encoding" => content_encoding = value,
+            _ => {}
+        }
+    }
+    let Some(content_length) = content_length else {
+        // Without the length there is no telling where the next request starts
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "OTLP request without a Content-Length",
+        ));
+    };
+    if content_length > MAX_BODY {
+        return Ok(Err((
+            PAYLOAD_TOO_LARGE,
+            format!("The body can be at most {} bytes", MAX_BODY),
+        )));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+    if !path.ends_with("/profiles") {
+        return Ok(Err((
+            "404 Not Found",
+            format!("Only profiles are accepted, not {}", path),
+        )));
+    }
+    if !content_type.starts_with("application/json") {
+        return Ok(Err((
+            "415 Unsupported Media Type",
+            "Only the JSON encoding of OTLP is supported, set \"encoding: json\" in the exporter"
+                .to_string(),
+        )));
+    }
+    let mut content = String::new();
+    let decoded = match content_encoding.as_str() {
+        "" | "identity" => body.as_slice().read_to_string(&mut content),
+        "gzip" => GzDecoder::new(body.as_slice()).read_to_string(&mut content),
+        encoding => {
+            return Ok(Err((
+                "415 Unsupported Media Type",
+                format!("Unsupported compression: {}", encoding),
+            )))
+        }
+    };
+    Ok(match decoded {
+        Ok(_) => Ok(content),
+        Err(e) => Err(("400 Bad Request", format!("Could not read the body: {}", e))),
+    })
+}
+
+#[cfg(unix)]
+fn check_fifo(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+    if std::fs::metadata(path)?.file_type().is_fifo() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a FIFO, create one with mkfifo", path.display()),
+        ))
+    }
+}
+
+#[cfg(not(unix))]
+fn check_fifo(path: &Path) -> io::Result<()> {
+    std::fs::metadata(path).map(|_| ())
+}
+
+/// Open the FIFO for writing without waiting for a reader, failing instead if there is none.
+#[cfg(unix)]
+fn open_fifo_writer(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn open_fifo_writer(path: &Path) -> io::Result<File> {
+    std::fs::OpenOptions::new().write(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::{Shutdown, TcpStream};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(
+            "127.0.0.1:7777".parse::<ListenAddress>().unwrap(),
+            ListenAddress::Tcp(SocketAddr::from(([127, 0, 0, 1], 7777)))
+        );
+        assert_eq!(
+            ":7777".parse::<ListenAddress>().unwrap(),
+            ListenAddress::Tcp(SocketAddr::from(([127, 0, 0, 1], 7777)))
+        );
+        assert!(":http".parse::<ListenAddress>().is_err());
+        assert_eq!(
+            "/tmp/flamelens.fifo".parse::<ListenAddress>().unwrap(),
+            ListenAddress::Fifo(PathBuf::from("/tmp/flamelens.fifo"))
+        );
+    }
+
+    #[test]
+    fn test_listen_tcp() {
+        let (sender, receiver) = mpsc::channel();
+        let listener = listen(&":0".parse().unwrap(), move |content| {
+            sender.send(content).unwrap();
+        })
+        .unwrap();
+        let ListenAddress::Tcp(addr) = listener.address else {
+            panic!("expected a TCP address");
+        };
+        assert_ne!(addr.port(), 0);
+
+        // Connections are received concurrently, so wait for each profile before the next push
+        let timeout = Duration::from_secs(5);
+        for data in ["a;b 1\n", "", "a;c 2\n"] {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(data.as_bytes()).unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+            if !data.is_empty() {
+                assert_eq!(receiver.recv_timeout(timeout).unwrap(), data);
+            }
+        }
+        // Empty pushes are ignored
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn test_listen_tcp_concurrent() {
+        let (sender, receiver) = mpsc::channel();
+        let listener = listen(&":0".parse().unwrap(), move |content| {
+            sender.send(content).unwrap();
+        })
+        .unwrap();
+        let ListenAddress::Tcp(addr) = listener.address else {
+            panic!("expected a TCP address");
+        };
+        // A client that hasn't finished its push doesn't hold up the next one
+        let mut idle = TcpStream::connect(addr).unwrap();
+        idle.write_all(b"a;b 1\n").unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"a;c 2\n").unwrap();
+        stream.shutdown(Shutdown::Write).unwrap();
+        let timeout = Duration::from_secs(5);
+        assert_eq!(receiver.recv_timeout(timeout).unwrap(), "a;c 2\n");
+        idle.shutdown(Shutdown::Write).unwrap();
+        assert_eq!(receiver.recv_timeout(timeout).unwrap(), "a;b 1\n");
+    }
+
+    #[test]
+    fn test_listen_otlp_http() {
+        let (sender, receiver) = mpsc::channel();
+        let listener = listen(&":0".parse().unwrap(), move |content| {
+            sender.send(content).unwrap();
+        })
+        .unwrap();
+        let ListenAddress::Tcp(addr) = listener.address else {
+            panic!("expected a TCP address");
+        };
+        let body = r#"{"resourceProfiles": []}"#;
+        let request = |path: &str, content_type: &str| {
+            format!(
+                "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+                path,
+                addr,
+                content_type,
+                body.len(),
+                body
+            )
+        };
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let requests = [
+            request("/v1development/profiles", "application/json"),
+            request("/v1/traces", "application/json"),
+            request("/v1development/profiles", "application/x-protobuf"),
+        ];
+        stream.write_all(requests.concat().as_bytes()).unwrap();
+        stream.shutdown(Shutdown::Write).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        // Bodies don't end with a newline, so each status line follows the body before it
+        let statuses = response
+            .split("HTTP/1.1 ")
+            .skip(1)
+            .map(|response| response.lines().next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            ["200 OK", "404 Not Found", "415 Unsupported Media Type"]
+        );
+        let timeout = Duration::from_secs(5);
+        assert_eq!(receiver.recv_timeout(timeout).unwrap(), body);
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+
+        // An oversized body is refused without being read, and the connection is closed
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let request = format!(
+            "POST /v1development/profiles HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn test_listener_stop_tcp() {
+        let listener = listen(&":0".parse().unwrap(), |_| {}).unwrap();
+        let ListenAddress::Tcp(addr) = listener.address else {
+            panic!("expected a TCP address");
+        };
+        drop(listener);
+        // The socket is closed once the woken thread exits
+        let closed = (0..50).any(|_| {
+            std::thread::sleep(Duration::from_millis(100));
+            TcpStream::connect(addr).is_err()
+        });
+        assert!(closed);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_listener_stop_fifo() {
+        let path = std::env::temp_dir().join(format!("flamelens-test-{}.fifo", std::process::id()));
+        let c_path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+        let listener = listen(&ListenAddress::Fifo(path.clone()), |_| {}).unwrap();
+        // Wait for the thread to be waiting for a writer
+        assert!((0..50).any(|_| {
+            std::thread::sleep(Duration::from_millis(100));
+            open_fifo_writer(&path).is_ok()
+        }));
+        drop(listener);
+        // Without a reader left, opening for writing fails
+        let closed = (0..50).any(|_| {
+            std::thread::sleep(Duration::from_millis(100));
+            open_fifo_writer(&path).is_err()
+        });
+        std::fs::remove_file(&path).unwrap();
+        assert!(closed);
+    }
+
+    #[test]
+    fn test_listen_fifo_requires_fifo() {
+        let path = std::env::temp_dir().join(format!("flamelens-test-{}.txt", std::process::id()));
+        std::fs::write(&path, "a;b 1\n").unwrap();
+        assert!(listen(&ListenAddress::Fifo(path.clone()), |_| {}).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,24 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing_subscriber::EnvFilter;
+
+/// Environment variable to set the log file when not given with --log-file.
+pub const LOG_FILE_ENV: &str = "FLAMELENS_LOG_FILE";
+
+/// Environment variable to set which events are logged, in the `RUST_LOG` syntax.
+pub const LOG_LEVEL_ENV: &str = "FLAMELENS_LOG";
+
+/// Write logs to a file, as stderr is taken by the terminal user interface.
+pub fn init(path: &Path) -> std::io::Result<()> {
+    let file = File::create(path)?;
+    let filter = EnvFilter::try_from_env(LOG_LEVEL_ENV)
+        .unwrap_or_else(|_| EnvFilter::new("flamelens=debug"));
+    tracing_subscriber::fmt()
+        .with_writer(Mutex::new(file))
+        .with_ansi(false)
+        .with_thread_names(true)
+        .with_env_filter(filter)
+        .init();
+    Ok(())
+}
@@ -1,17 +1,55 @@
-use clap::{command, Parser};
-use flamelens::app::{App, AppResult};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum, ValueHint};
+use flamelens::anonymize::{anonymize, AnonymizeMode};
+use flamelens::app::{App, AppResult, FlameGraphInput, HintsMode};
+use flamelens::archive::{list_snapshots, SnapshotArchive};
+use flamelens::check::{check, Assertion};
+use flamelens::config::{Config, GraphicsProtocol};
+#[cfg(unix)]
+use flamelens::control::ControlSocket;
+use flamelens::diff::{format_diff_report, Baseline};
 use flamelens::event::{Event, EventHandler};
-use flamelens::flame::FlameGraph;
-use flamelens::handler::handle_key_events;
-use flamelens::tui::Tui;
+use flamelens::export::{export, ExportFormat};
+use flamelens::flame::{FlameGraph, PidFrames, StackFilter};
+use flamelens::handler::{handle_key_events, handle_paste};
+use flamelens::import::to_folded;
+use flamelens::listen::ListenAddress;
+use flamelens::logging::{self, LOG_FILE_ENV};
+use flamelens::memory::parse_memory_size;
+use flamelens::progress::ProgressBar;
+#[cfg(feature = "python")]
+use flamelens::py_spy::PySpySampler;
+use flamelens::py_spy_subprocess::PySpySubprocess;
+use flamelens::recent::{RecentFile, RecentFiles};
+use flamelens::sampler::{parse_duration, Sampler, SamplerOptions};
+use flamelens::self_profile::{self, SelfProfiler};
+use flamelens::session::{Session, SessionInput};
+use flamelens::snapshot::{render_to_buffer, to_ansi, to_plain_text};
+use flamelens::state::MinWidth;
+use flamelens::summary::format_top_stacks;
+use flamelens::tui::{CrashReport, Tui};
 use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
-use std::io::{self, Read};
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Tick rate in milliseconds while something on screen changes over time, e.g. a message waiting
+/// to expire or the duration of a running sampler.
+const TICK_RATE: u64 = 250;
+/// Tick rate in milliseconds otherwise. Nothing is redrawn until a key is pressed or an update
+/// comes in, so ticks are only there to notice when the app stops being idle.
+const IDLE_TICK_RATE: u64 = 2000;
+/// Profiles at least this large show a progress bar while they are parsed.
+const PROGRESS_MIN_BYTES: usize = 32 << 20;
 
 #[derive(Parser, Debug)]
-#[command(version)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Profile data filename
+    #[clap(value_hint = ValueHint::FilePath)]
     filename: Option<String>,
 
     /// Whether to sort the stacks by time spent
@@ -22,21 +60,319 @@ struct Args {
     #[clap(long, action, value_name = "echo")]
     echo: bool,
 
+    /// Only load stacks with a frame matching the regex
+    #[clap(long, value_name = "regex", value_parser = regex::Regex::new)]
+    filter: Option<regex::Regex>,
+
+    /// Skip stacks with a frame matching the regex
+    #[clap(long, value_name = "regex", value_parser = regex::Regex::new)]
+    exclude: Option<regex::Regex>,
+
+    /// Load about this fraction of the stacks, like "0.1", to get a quick look at profiles too
+    /// large to load fully. Stacks with many samples are always kept, and the counts of the others
+    /// scaled up, so counts are estimates
+    #[clap(long, value_name = "fraction", value_parser = parse_sample_rate)]
+    sample_rate: Option<f64>,
+
+    /// What to do with Erlang process frames like "<0.123.0>" in stacks from eflambe
+    #[clap(long, value_name = "mode")]
+    erlang_pids: Option<PidFrames>,
+
     /// Pid for live flamegraph
-    #[cfg(feature = "python")]
     #[clap(long, value_name = "pid")]
     pid: Option<String>,
 
     /// Additional arguments to pass to "py-spy record" command
-    #[cfg(feature = "python")]
     #[clap(long, value_name = "py-spy-args")]
     py_spy_args: Option<String>,
 
-    /// Show debug info
+    /// Sample the live flamegraph by running this py-spy executable rather than the py-spy built
+    /// into flamelens, e.g. to use the one installed with pip. Builds without the python feature
+    /// always run one, "py-spy" by default
+    #[clap(long, value_name = "path", value_hint = ValueHint::ExecutablePath, requires = "pid")]
+    py_spy_binary: Option<String>,
+
+    /// Once flamelens uses more memory than this, like "2G", stop keeping more distinct stacks of
+    /// the live flamegraph, folding the ones with the fewest samples into their callers. Useful
+    /// for sessions of many hours
+    #[clap(long, value_name = "size", value_parser = parse_memory_size)]
+    max_memory: Option<u64>,
+
+    /// Sample the live flamegraph for this long, like "60s" or "5m", then freeze it, for unattended
+    /// captures
+    #[clap(long, value_name = "duration", value_parser = parse_duration, requires = "pid")]
+    duration: Option<Duration>,
+
+    /// Write the samples of the live flamegraph as folded stacks to this file once sampling stops
+    #[clap(long, value_name = "path", value_hint = ValueHint::FilePath, requires = "pid")]
+    record: Option<String>,
+
+    /// With --pid, write the samples so far to a new file in this directory every
+    /// --snapshot-interval, named after the pid and the time in UTC. Without it, browse the
+    /// snapshots in the directory, also with ":snapshots"
+    #[clap(long, value_name = "dir", value_hint = ValueHint::DirPath, conflicts_with = "listen")]
+    snapshot_dir: Option<String>,
+
+    /// How often to write a snapshot to --snapshot-dir, like "30s" or "5m"
+    #[clap(long, value_name = "duration", default_value = "60s", value_parser = parse_duration, requires = "snapshot_dir")]
+    snapshot_interval: Duration,
+
+    /// Known-good profile to compare with, coloring frames by how much they grew or shrank
+    /// relative to it when pressing 'B'
+    #[clap(long, value_name = "path", value_hint = ValueHint::FilePath)]
+    baseline: Option<String>,
+
+    /// Select the frame with the most samples matching the regex on startup
+    #[clap(long, value_name = "regex", value_parser = regex::Regex::new)]
+    select: Option<regex::Regex>,
+
+    /// Zoom in on the frame with the most samples matching the regex on startup
+    #[clap(long, value_name = "regex", value_parser = regex::Regex::new)]
+    zoom: Option<regex::Regex>,
+
+    /// Only show the subtree of the frame with the most samples matching the regex, e.g. to trim
+    /// runtime frames like "_start"
+    #[clap(long, value_name = "regex", value_parser = regex::Regex::new)]
+    root_frame: Option<regex::Regex>,
+
+    /// Collapse the frames all stacks start with, like "_start;__libc_start_main;main", into one.
+    /// Toggle it with "P"
+    #[clap(long, action)]
+    collapse_prefix: bool,
+
+    /// Reopen the most recently viewed file and restore its selection, zoom and search
+    #[clap(long, action, conflicts_with = "filename")]
+    resume: bool,
+
+    /// Load a session saved with the ":w <file>" command
+    #[clap(long, value_name = "session", value_hint = ValueHint::FilePath, conflicts_with_all = ["filename", "resume"])]
+    session: Option<String>,
+
+    /// Print the profile with all frame names anonymized to stdout instead of viewing it
+    #[clap(
+        long,
+        value_name = "mode",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "rename"
+    )]
+    anonymize: Option<AnonymizeMode>,
+
+    /// Print the N heaviest full stacks with their samples and percentages instead of viewing the
+    /// profile, e.g. in CI logs
+    #[clap(long, value_name = "n", conflicts_with = "listen")]
+    top_stacks: Option<usize>,
+
+    /// Write the profile to a file instead of viewing it, as "html" for a self-contained
+    /// interactive page or "png" for an image, e.g. "--export html out.html"
+    #[clap(long, num_args = 2, value_names = ["format", "path"], conflicts_with = "listen")]
+    export: Option<Vec<String>>,
+
+    /// Draw the flamegraph once, as it would first appear, to a file ("-" for stdout) instead of
+    /// viewing it, with ANSI colors unless --plain is given, e.g. for docs or to share in chat
+    #[clap(long, value_name = "path", value_hint = ValueHint::FilePath, conflicts_with = "listen")]
+    render_ansi: Option<String>,
+
+    /// Width in cells of the screen drawn by --render-ansi
+    #[clap(
+        long,
+        value_name = "cells",
+        default_value = "120",
+        requires = "render_ansi"
+    )]
+    width: u16,
+
+    /// Height in cells of the screen drawn by --render-ansi
+    #[clap(
+        long,
+        value_name = "cells",
+        default_value = "40",
+        requires = "render_ansi"
+    )]
+    height: u16,
+
+    /// Leave out the colors in --render-ansi, to write plain text
+    #[clap(long, action, requires = "render_ansi")]
+    plain: bool,
+
+    /// Show profiles pushed to a TCP address like "127.0.0.1:7777" (or just ":7777"), or written to
+    /// an existing FIFO, each one replacing the previous
+    #[clap(long, value_name = "address", conflicts_with_all = ["filename", "resume", "session"])]
+    listen: Option<ListenAddress>,
+
+    /// Listen on a Unix socket for commands such as "search <regex>", "zoom" and "quit", one per
+    /// line, to drive flamelens from scripts
+    #[cfg(unix)]
+    #[clap(long, value_name = "path", value_hint = ValueHint::FilePath)]
+    control_socket: Option<String>,
+
+    /// Write logs such as parser warnings, sampler events and timings to a file. Set FLAMELENS_LOG
+    /// to change what is logged, e.g. "flamelens=trace" to include render timings
+    #[clap(long, value_name = "path", env = LOG_FILE_ENV, value_hint = ValueHint::FilePath)]
+    log_file: Option<String>,
+
+    /// Leave out frames narrower than this many cells, or this percentage of the width like "0.5%",
+    /// to draw enormous profiles faster. Adjust it with "<" and ">"
+    #[clap(long, value_name = "width", default_value = "1")]
+    min_width: MinWidth,
+
+    /// Draw each level of the flamegraph this many rows high, wrapping long frame names onto the
+    /// rows below, e.g. 2 on tall terminals. Overrides row_height in the config file
+    #[clap(long, value_name = "rows", value_parser = clap::value_parser!(u16).range(1..=8))]
+    row_height: Option<u16>,
+
+    /// Also draw the flamegraph as an image with a terminal graphics protocol, for frames as wide
+    /// as their samples to the pixel. Overrides graphics in the config file
+    #[clap(long, value_name = "protocol")]
+    graphics: Option<GraphicsProtocol>,
+
+    /// Profile flamelens itself, sampling which of its phases such as parsing and drawing it is in
+    /// every millisecond, and write the samples to a folded file on exit. Attach it to reports of
+    /// flamelens being slow
+    #[clap(long, value_name = "path", value_hint = ValueHint::FilePath)]
+    self_profile: Option<String>,
+
+    /// Which key hints to show at the bottom
+    #[clap(long, value_name = "mode", default_value = "full")]
+    hints: HintsMode,
+
+    /// Show debug info: timings, and how the selected frame is laid out
     #[clap(long)]
     debug: bool,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print a shell completion script to stdout
+    Completions { shell: clap_complete::Shell },
+    /// Print the man page in roff format to stdout
+    Man,
+    /// Check performance budgets on a profile, exiting with 1 if any of them is exceeded
+    Check {
+        /// Profile data filename
+        #[clap(value_hint = ValueHint::FilePath)]
+        filename: String,
+        /// Fail if the share of samples in frames matching the regex meets the condition, e.g.
+        /// "serialize>5%". Can be given multiple times
+        #[clap(long, value_name = "condition", required = true)]
+        fail_if: Vec<Assertion>,
+    },
+    /// Compare two profiles
+    Diff {
+        /// Profile data filename of the baseline
+        #[clap(value_hint = ValueHint::FilePath)]
+        old: String,
+        /// Profile data filename to compare against the baseline
+        #[clap(value_hint = ValueHint::FilePath)]
+        new: String,
+        /// Print the biggest regressions and improvements per function instead of viewing the
+        /// difference. This is the only mode for now
+        #[clap(long, required = true)]
+        report: bool,
+        /// Number of functions to print for each of the regressions and improvements
+        #[clap(long, value_name = "n", default_value_t = 20)]
+        top: usize,
+    },
+}
+
+/// Handle a single event from the event loop. Returns whether the screen needs to be redrawn.
+fn handle_event(event: Event, app: &mut App, args: &Args) -> AppResult<bool> {
+    let _phase = self_profile::phase("handle_event");
+    match event {
+        Event::Tick => return Ok(app.tick()),
+        Event::Key(key_event) => handle_key_events(key_event, app)?,
+        Event::Mouse(_) => return Ok(false),
+        Event::Paste(text) => handle_paste(&text, app),
+        Event::Resize(_, _) => {}
+        Event::Reload => {
+            if let (Some(filename), FlameGraphInput::File(_)) =
+                (&args.filename, &app.flamegraph_input)
+            {
+                app.reload(filename, &get_stack_filter(args));
+            }
+        }
+        Event::Suspend => app.suspend(),
+        // Needs the terminal, handled by the event loop
+        Event::Resume => {}
+        Event::FlameGraph(parsed) => app.receive_flamegraph(*parsed),
+        Event::SamplerFinished(recorded) => app.finish_sampling(recorded),
+        Event::Control(command, reply) => {
+            let _ = reply.send(app.run_control_command(&command));
+        }
+    }
+    Ok(true)
+}
+
+/// Let the user pick one of the recent files when started interactively without any input.
+fn pick_recent_file(recent: &RecentFiles) -> Option<&RecentFile> {
+    if recent.files.is_empty() {
+        return None;
+    }
+    let mut stderr = io::stderr();
+    writeln!(stderr, "Recent files:").ok()?;
+    for (i, file) in recent.files.iter().enumerate() {
+        writeln!(stderr, "  {:>2}) {}", i + 1, file.path).ok()?;
+    }
+    // Ask again until the choice is one of the files, or give up at the end of the input
+    loop {
+        write!(stderr, "Select a file [1]: ").ok()?;
+        stderr.flush().ok()?;
+        let mut choice = String::new();
+        if io::stdin().lock().read_line(&mut choice).ok()? == 0 {
+            return None;
+        }
+        let choice = choice.trim();
+        let file = if choice.is_empty() {
+            recent.files.first()
+        } else {
+            choice
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| recent.files.get(i.checked_sub(1)?))
+        };
+        match file {
+            Some(file) => return Some(file),
+            None => writeln!(
+                stderr,
+                "Invalid selection, enter a number from 1 to {}",
+                recent.files.len()
+            )
+            .ok()?,
+        }
+    }
+}
+
+fn parse_sample_rate(s: &str) -> Result<f64, String> {
+    s.parse::<f64>()
+        .ok()
+        .filter(|rate| *rate > 0.0 && *rate <= 1.0)
+        .ok_or_else(|| format!("expected a fraction greater than 0 and at most 1: {}", s))
+}
+
+/// The py-spy built into flamelens, unless asked to run a py-spy executable or not built in.
+fn get_sampler(args: &Args, pid: u64) -> Box<dyn Sampler> {
+    let options = SamplerOptions {
+        py_spy_args: args.py_spy_args.clone(),
+        max_memory: args.max_memory,
+        duration: args.duration,
+    };
+    #[cfg(feature = "python")]
+    if args.py_spy_binary.is_none() {
+        return Box::new(PySpySampler::new(pid, options));
+    }
+    let program = args.py_spy_binary.as_deref().unwrap_or("py-spy");
+    Box::new(PySpySubprocess::new(program, pid, options))
+}
+
+fn get_stack_filter(args: &Args) -> StackFilter {
+    StackFilter {
+        filter: args.filter.clone(),
+        exclude: args.exclude.clone(),
+        pids: args.erlang_pids,
+        sample_rate: args.sample_rate,
+    }
+}
+
 fn get_app_from_filename_or_stdin(args: &Args, echo: bool) -> App {
     let (filename, content) = if let Some(filename) = &args.filename {
         (
@@ -51,57 +387,358 @@ fn get_app_from_filename_or_stdin(args: &Args, echo: bool) -> App {
         let content = String::from_utf8(buf).expect("Could not parse stdin");
         ("stdin", content)
     };
+    get_app_from_content(
+        filename,
+        content,
+        args.sorted,
+        echo,
+        &get_stack_filter(args),
+    )
+}
+
+fn get_app_from_content(
+    filename: &str,
+    content: String,
+    sorted: bool,
+    echo: bool,
+    stack_filter: &StackFilter,
+) -> App {
     if echo {
         println!("{}", content);
     }
     let tic = std::time::Instant::now();
-    let flamegraph = FlameGraph::from_string(content, args.sorted);
+    let content = stack_filter.apply(to_folded(content));
+    let flamegraph = if content.len() >= PROGRESS_MIN_BYTES && io::stderr().is_terminal() {
+        let progress_bar = ProgressBar::start(&format!("Loading {}", filename), content.len());
+        FlameGraph::from_string_with_progress(content, sorted, 0, progress_bar.progress())
+    } else {
+        FlameGraph::from_string(content, sorted)
+    };
+    tracing::info!(filename, total_count = flamegraph.total_count(), elapsed = ?tic.elapsed(), "Loaded profile");
     let mut app = App::with_flamegraph(filename, flamegraph);
     app.add_elapsed("flamegraph", tic.elapsed());
     app
 }
 
 fn main() -> AppResult<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    match &args.command {
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(*shell, &mut Args::command(), "flamelens", &mut io::stdout());
+            return Ok(());
+        }
+        Some(Command::Man) => {
+            clap_mangen::Man::new(Args::command()).render(&mut io::stdout())?;
+            return Ok(());
+        }
+        Some(Command::Check { filename, fail_if }) => {
+            let content = std::fs::read_to_string(filename).expect("Could not read file");
+            let mut flamegraph = FlameGraph::from_string(to_folded(content), false);
+            let (report, passed) = check(&mut flamegraph, fail_if);
+            print!("{}", report);
+            std::process::exit(if passed { 0 } else { 1 });
+        }
+        Some(Command::Diff { old, new, top, .. }) => {
+            let read = |filename: &String| {
+                let content = std::fs::read_to_string(filename).expect("Could not read file");
+                FlameGraph::from_string(to_folded(content), false)
+            };
+            print!("{}", format_diff_report(&read(old), &read(new), *top));
+            return Ok(());
+        }
+        None => {}
+    }
+    if let Some(log_file) = &args.log_file {
+        logging::init(std::path::Path::new(log_file)).expect("Could not open log file");
+        tracing::info!(
+            version = env!("CARGO_PKG_VERSION"),
+            args = ?std::env::args().collect::<Vec<_>>(),
+            "Starting flamelens"
+        );
+    }
+
+    let _self_profiler = args
+        .self_profile
+        .as_ref()
+        .map(|path| SelfProfiler::start(path.into(), std::time::Duration::from_millis(1)));
+
+    let config = Config::load().expect("Could not load config file");
+
+    // Browse the snapshots of an earlier live session, starting with the most recent
+    let is_live = args.listen.is_some() || args.pid.is_some();
+    if let (Some(dir), false) = (&args.snapshot_dir, is_live) {
+        if args.filename.is_none() && !args.resume && args.session.is_none() {
+            let snapshots = list_snapshots(std::path::Path::new(dir)).unwrap_or_else(|e| {
+                eprintln!("Could not list snapshots in {}: {}", dir, e);
+                std::process::exit(1);
+            });
+            let Some(latest) = snapshots.first() else {
+                eprintln!("No snapshots in {}", dir);
+                std::process::exit(1);
+            };
+            args.filename = Some(latest.path.display().to_string());
+        }
+    }
+
+    // Resolve which recent file to reopen, if any
+    let mut recent_files = RecentFiles::load();
+    let resumed = if args.resume {
+        let Some(resumed) = recent_files.most_recent() else {
+            eprintln!("No recent files to resume");
+            std::process::exit(1);
+        };
+        Some(resumed.clone())
+    } else if args.filename.is_none()
+        && args.session.is_none()
+        && !is_live
+        && io::stdin().is_terminal()
+    {
+        pick_recent_file(&recent_files).cloned()
+    } else {
+        None
+    };
+    let mut saved_state = None;
+    if let Some(resumed) = resumed {
+        args.filename = Some(resumed.path);
+        saved_state = Some(resumed.state);
+    }
+
+    // Load a saved session
+    let mut session_data = None;
+    if let Some(session_filename) = &args.session {
+        let session =
+            Session::load(std::path::Path::new(session_filename)).expect("Could not load session");
+        args.sorted = session.sorted;
+        match session.input {
+            SessionInput::File(path) => args.filename = Some(path),
+            SessionInput::Data(data) => session_data = Some((session_filename.clone(), data)),
+        }
+        saved_state = Some(session.state);
+        if let (None, Some(pattern)) = (&args.root_frame, &session.root_frame) {
+            match regex::Regex::new(pattern) {
+                Ok(re) => args.root_frame = Some(re),
+                Err(e) => {
+                    eprintln!("Invalid root frame regex in the session: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+
+    // Background work such as the live sampler sends its events here before the terminal is up
+    let (event_sender, event_receiver) = mpsc::channel();
 
     // Create an application.
-    cfg_if::cfg_if! {
-        if #[cfg(feature = "python")] {
-            let mut app = if let Some(_pid) = args.pid {
-                App::with_pid(
-                    _pid.parse().expect("Could not parse pid"),
-                    args.py_spy_args.clone(),
+    let mut app = if let Some(pid) = &args.pid {
+        let sampler = get_sampler(&args, pid.parse().expect("Could not parse pid"));
+        let record = args.record.as_ref().map(std::path::PathBuf::from);
+        let archive = args.snapshot_dir.as_ref().map(|dir| {
+            SnapshotArchive::new(
+                std::path::Path::new(dir),
+                args.snapshot_interval,
+                sampler.pid(),
+            )
+            .expect("Could not create the snapshot directory")
+        });
+        let mut app = App::with_sampler(
+            sampler,
+            get_stack_filter(&args),
+            record,
+            archive,
+            event_sender.clone(),
+        );
+        app.capture_duration = args.duration;
+        app
+    } else if let Some(address) = &args.listen {
+        App::with_listener(
+            address,
+            args.sorted,
+            get_stack_filter(&args),
+            event_sender.clone(),
+        )
+        .expect("Could not listen for profiles")
+    } else if let Some((session_filename, data)) = session_data {
+        get_app_from_content(
+            &session_filename,
+            data,
+            args.sorted,
+            args.echo,
+            &get_stack_filter(&args),
+        )
+    } else {
+        get_app_from_filename_or_stdin(&args, args.echo)
+    };
+    app.debug = args.debug;
+    app.sample_rate = args.sample_rate.filter(|rate| *rate < 1.0);
+    app.hints = args.hints;
+    app.flamegraph_view.set_min_width(args.min_width);
+    app.config = config;
+    if let Some(row_height) = args.row_height {
+        app.config.row_height = row_height;
+    }
+    if let Some(graphics) = args.graphics {
+        app.config.graphics = graphics;
+    }
+    if let FlameGraphInput::File(filename) = &app.flamegraph_input {
+        let text = format!("Loaded {}", filename);
+        app.notify_if_slow(&text);
+    }
+    if let Some(re) = &args.root_frame {
+        if app.is_live() {
+            // Applied to each profile as it comes in
+            app.root_frame = Some(re.clone());
+        } else if !app.set_root_frame(re.clone()) {
+            eprintln!("No frame matching --root-frame \"{}\"", re);
+            std::process::exit(1);
+        }
+    }
+    if args.collapse_prefix {
+        app.toggle_common_prefix();
+    }
+
+    if let Some(mode) = args.anonymize {
+        print!("{}", anonymize(app.flamegraph(), mode));
+        return Ok(());
+    }
+    if let Some(n) = args.top_stacks {
+        print!("{}", format_top_stacks(app.flamegraph(), n));
+        return Ok(());
+    }
+    if let Some([format, path]) = args.export.as_deref() {
+        let format = ExportFormat::from_str(format, true).unwrap_or_else(|_| {
+            Args::command()
+                .error(
+                    clap::error::ErrorKind::InvalidValue,
+                    format!("invalid export format '{}', expected html or png", format),
                 )
-            } else {
-                get_app_from_filename_or_stdin(&args, args.echo)
-            };
+                .exit()
+        });
+        export(app.flamegraph(), format, std::path::Path::new(path))
+            .expect("Could not export the profile");
+        return Ok(());
+    }
+    if let Some(saved_state) = &saved_state {
+        app.flamegraph_view.restore_state(saved_state);
+    }
+    if let Some(re) = &args.zoom {
+        if !app
+            .flamegraph_view
+            .zoom_to_heaviest_matching(|name| re.is_match(name))
+        {
+            app.set_transient_message(&format!("No frame matching \"{}\" to zoom to", re));
+        }
+    }
+    if let Some(re) = &args.select {
+        if !app.flamegraph_view.select_heaviest_matching(re) {
+            app.set_transient_message(&format!("No frame matching \"{}\" to select", re));
+        }
+    }
+    if let Some(path) = &args.baseline {
+        let content = std::fs::read_to_string(path).expect("Could not read baseline");
+        let content = get_stack_filter(&args).apply(to_folded(content));
+        app.set_baseline(Baseline::new(
+            path,
+            &FlameGraph::from_string(content, false),
+        ));
+    }
+    if let Some(dir) = &args.snapshot_dir {
+        app.snapshot_dir = Some(dir.into());
+        if !app.is_live() {
+            app.open_snapshot_browser();
+        }
+    }
+
+    if let Some(path) = &args.render_ansi {
+        let buffer = render_to_buffer(&mut app, args.width, args.height)?;
+        let content = if args.plain {
+            to_plain_text(&buffer)
         } else {
-            let mut app = get_app_from_filename_or_stdin(&args, args.echo);
+            to_ansi(&buffer)
+        };
+        if path == "-" {
+            print!("{}", content);
+        } else {
+            std::fs::write(path, content).expect("Could not write the rendered flamegraph");
         }
+        return Ok(());
     }
-    app.debug = args.debug;
 
     // Initialize the terminal user interface.
     let backend = CrosstermBackend::new(io::stderr());
     let terminal = Terminal::new(backend)?;
-    let events = EventHandler::new(250);
+    let events = EventHandler::with_channel(TICK_RATE, event_sender, event_receiver);
+    #[cfg(unix)]
+    let _control_socket = args.control_socket.as_ref().map(|path| {
+        ControlSocket::bind(std::path::Path::new(path), events.sender())
+            .expect("Could not listen on control socket")
+    });
     let mut tui = Tui::new(terminal, events);
-    tui.init()?;
+    tui.init(CrashReport::new(app.flamegraph_input.to_string()))?;
+    if app.config.terminal_title {
+        tui.set_title(&app.terminal_title())?;
+    }
 
     // Start the main loop.
+    let mut needs_redraw = true;
     while app.running {
-        // Render the user interface.
-        tui.draw(&mut app)?;
-        // Handle events.
-        match tui.events.next()? {
-            Event::Tick => app.tick(),
-            Event::Key(key_event) => handle_key_events(key_event, &mut app)?,
-            Event::Mouse(_) => {}
-            Event::Resize(_, _) => {}
+        // Render the user interface, only if something changed since the last time.
+        if needs_redraw {
+            tui.draw(&mut app)?;
+            needs_redraw = false;
+        }
+        tui.events.set_tick_rate(if app.needs_frequent_ticks() {
+            TICK_RATE
+        } else {
+            IDLE_TICK_RATE
+        });
+        // Handle events, along with any others already waiting so that a burst of them (e.g. key
+        // repeats or live updates) is drawn once.
+        let mut event = Some(tui.events.next()?);
+        while let Some(current) = event {
+            if let Event::Resume = current {
+                // The only place setting the terminal up again after Tui::suspend
+                tui.resume()?;
+                needs_redraw = true;
+            } else {
+                needs_redraw |= handle_event(current, &mut app, &args)?;
+            }
+            if !app.running {
+                break;
+            }
+            if app.suspend_requested {
+                app.suspend_requested = false;
+                tui.suspend()?;
+                needs_redraw = true;
+            }
+            if let Some(mut command) = app.editor_requested.take() {
+                match tui.run_interactive(&mut command)? {
+                    Ok(status) if !status.success() => {
+                        app.set_transient_message(&format!("Editor exited with {}", status))
+                    }
+                    Ok(_) => {}
+                    Err(e) => app.set_transient_message(&format!("Could not run editor: {}", e)),
+                }
+                needs_redraw = true;
+            }
+            event = tui.events.try_next();
+        }
+        for notification in std::mem::take(&mut app.notifications) {
+            tui.notify(app.config.notifications, &notification)?;
+        }
+        if let Some(text) = app.clipboard.take() {
+            tui.copy_to_clipboard(&text)?;
         }
     }
 
     // Exit the user interface.
     tui.exit()?;
+
+    // Remember the file and where we left off
+    if let (Some(filename), FlameGraphInput::File(_)) = (&args.filename, &app.flamegraph_input) {
+        recent_files.add(filename, app.main_view().save_state());
+        if let Err(e) = recent_files.save() {
+            eprintln!("Could not save recent files: {}", e);
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+/// Resident memory of flamelens in bytes, where the platform tells it.
+pub fn resident_memory() -> Option<u64> {
+    if cfg!(target_os = "linux") {
+        parse_vm_rss(&std::fs::read_to_string("/proc/self/status").ok()?)
+    } else {
+        None
+    }
+}
+
+/// Resident memory from the contents of `/proc/self/status`, given in kB there.
+fn parse_vm_rss(status: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes = line["VmRSS:".len()..]
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Parse a size like "512M", "2G" or "1.5GiB", in multiples of 1024, or a number of bytes.
+pub fn parse_memory_size(s: &str) -> Result<u64, String> {
+    let usage = || format!("expected a size like \"512M\" or \"2G\": {}", s);
+    let s = s.trim();
+    let number_end = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(number_end);
+    let factor: u64 = match unit
+        .trim()
+        .to_ascii_uppercase()
+        .trim_end_matches("IB")
+        .trim_end_matches('B')
+    {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(usage()),
+    };
+    let number = number.parse::<f64>().map_err(|_| usage())?;
+    Ok((number * factor as f64) as u64)
+}
+
+pub fn format_memory(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1 << 20) as f64)
+}
+
+/// Fold the stacks with the fewest samples into their callers until at most `max_stacks` remain,
+/// trading detail in rarely seen frames for bounded memory. Counts are the samples of each stack
+/// of semicolon separated frames, so the totals of the callers stay the same.
+pub fn compact_stacks(counts: &mut HashMap<String, usize>, max_stacks: usize) {
+    while counts.len() > max_stacks {
+        let mut lightest = counts
+            .iter()
+            .filter(|(stack, _)| stack.contains(';'))
+            .map(|(stack, count)| (*count, stack.clone()))
+            .collect::<Vec<_>>();
+        if lightest.is_empty() {
+            // Only single frames are left to fold, which have no caller
+            return;
+        }
+        let excess = counts.len() - max_stacks;
+        lightest.sort_unstable();
+        for (_, stack) in lightest.into_iter().take(excess) {
+            // Up to date with the stacks folded into this one in the same round
+            let count = counts.remove(&stack).unwrap();
+            let (caller, _) = stack.rsplit_once(';').unwrap();
+            *counts.entry(caller.to_string()).or_insert(0) += count;
+        }
+    }
+    counts.shrink_to_fit();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_vm_rss() {
+        let status = "Name:\tflamelens\nVmPeak:\t  20000 kB\nVmRSS:\t   12345 kB\nThreads:\t4\n";
+        assert_eq!(parse_vm_rss(status), Some(12345 * 1024));
+        assert_eq!(parse_vm_rss("Name:\tflamelens\n"), None);
+        if cfg!(target_os = "linux") {
+            assert!(resident_memory().is_some_and(|rss| rss > 0));
+        }
+    }
+
+    #[test]
+    fn test_parse_memory_size() {
+        assert_eq!(parse_memory_size("512M"), Ok(512 << 20));
+        assert_eq!(parse_memory_size("2g"), Ok(2 << 30));
+        assert_eq!(parse_memory_size("1.5GiB"), Ok(3 << 29));
+        assert_eq!(parse_memory_size("64 KB"), Ok(64 << 10));
+        assert_eq!(parse_memory_size("1000"), Ok(1000));
+        assert!(parse_memory_size("2X").is_err());
+        assert!(parse_memory_size("G").is_err());
+    }
+
+    #[test]
+    fn test_compact_stacks() {
+        let mut counts = HashMap::from([
+            ("main;a;x".to_string(), 1),
+            ("main;a;y".to_string(), 2),
+            ("main;b".to_string(), 10),
+            ("main;a".to_string(), 4),
+            ("idle".to_string(), 1),
+        ]);
+        compact_stacks(&mut counts, 4);
+        assert_eq!(counts.len(), 4);
+        assert_eq!(counts["main;a"], 5);
+        assert_eq!(counts.values().sum::<usize>(), 18);
+
+        compact_stacks(&mut counts, 2);
+        assert_eq!(
+            counts,
+            HashMap::from([("main".to_string(), 17), ("idle".to_string(), 1)])
+        );
+
+        // Single frames are kept as they are
+        compact_stacks(&mut counts, 1);
+        assert_eq!(counts.len(), 2);
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long a message stays in the status bar if no key is pressed.
+pub const MESSAGE_TTL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// Increasing number to tell which messages were added after a given point
+    pub id: usize,
+    pub text: String,
+    /// When the message was added
+    pub time: Instant,
+}
+
+/// Messages shown in the status bar one at a time, along with a log of all of them.
+#[derive(Debug)]
+pub struct Messages {
+    /// Messages waiting to be shown, the first one being shown
+    queue: VecDeque<Message>,
+    /// When the first message in the queue started being shown
+    shown_since: Option<Instant>,
+    /// All messages so far, oldest first
+    pub log: Vec<Message>,
+    /// Whether to show the message log popup
+    pub show_log: bool,
+    /// Scroll offset of the message log popup
+    pub log_offset: usize,
+    /// When the messages started, to show the time of each message relative to it
+    pub start: Instant,
+    next_id: usize,
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            shown_since: None,
+            log: vec![],
+            show_log: false,
+            log_offset: 0,
+            start: Instant::now(),
+            next_id: 0,
+        }
+    }
+}
+
+impl Messages {
+    pub fn push(&mut self, text: &str) {
+        let message = Message {
+            id: self.next_id,
+            text: text.to_string(),
+            time: Instant::now(),
+        };
+        self.next_id += 1;
+        if self.queue.is_empty() {
+            self.shown_since = Some(message.time);
+        }
+        self.queue.push_back(message.clone());
+        self.log.push(message);
+    }
+
+    /// Message being shown.
+    pub fn current(&self) -> Option<&str> {
+        self.queue.front().map(|m| m.text.as_str())
+    }
+
+    /// Number of messages waiting behind the one being shown.
+    pub fn num_queued(&self) -> usize {
+        self.queue.len().saturating_sub(1)
+    }
+
+    /// Id the next message will get. Used with [`Messages::dismiss_before`] and
+    /// [`Messages::last_since`] to tell apart the messages added by an action.
+    pub fn next_id(&self) -> usize {
+        self.next_id
+    }
+
+    /// Stop showing the messages added before the given id.
+    pub fn dismiss_before(&mut self, id: usize) {
+        let len = self.queue.len();
+        self.queue.retain(|m| m.id >= id);
+        if self.queue.len() != len {
+            self.shown_since = self.queue.front().map(|_| Instant::now());
+        }
+    }
+
+    pub fn dismiss_all(&mut self) {
+        self.dismiss_before(self.next_id);
+    }
+
+    /// Last message added since the given id.
+    pub fn last_since(&self, id: usize) -> Option<&str> {
+        self.log
+            .last()
+            .filter(|m| m.id >= id)
+            .map(|m| m.text.as_str())
+    }
+
+    /// Move on to the next message once the current one has been shown for long enough. Returns
+    /// whether the message being shown changed.
+    pub fn expire(&mut self, now: Instant) -> bool {
+        if let Some(shown_since) = self.shown_since {
+            if now.duration_since(shown_since) >= MESSAGE_TTL {
+                self.queue.pop_front();
+                self.shown_since = self.queue.front().map(|_| now);
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn toggle_log(&mut self) {
+        self.show_log = !self.show_log;
+        // Start with the latest messages in view
+        self.log_offset = usize::MAX;
+    }
+
+    pub fn scroll_log(&mut self, delta: isize) {
+        let offset = self.log_offset.min(self.log.len().saturating_sub(1));
+        self.log_offset = offset.saturating_add_signed(delta);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_and_expire() {
+        let mut messages = Messages::default();
+        messages.push("first");
+        messages.push("second");
+        assert_eq!(messages.current(), Some("first"));
+        assert_eq!(messages.num_queued(), 1);
+
+        let start = messages.shown_since.unwrap();
+        assert!(!messages.expire(start + MESSAGE_TTL / 2));
+        assert_eq!(messages.current(), Some("first"));
+        assert!(messages.expire(start + MESSAGE_TTL));
+        assert_eq!(messages.current(), Some("second"));
+        assert!(!messages.expire(start + MESSAGE_TTL * 3 / 2));
+        assert_eq!(messages.current(), Some("second"));
+        assert!(messages.expire(start + MESSAGE_TTL * 2));
+        assert_eq!(messages.current(), None);
+        assert!(!messages.expire(start + MESSAGE_TTL * 3));
+
+        // Everything is kept in the log
+        assert_eq!(
+            messages
+                .log
+                .iter()
+                .map(|m| m.text.as_str())
+                .collect::<Vec<_>>(),
+            vec!["first", "second"]
+        );
+    }
+
+    #[test]
+    fn test_dismiss_before_and_last_since() {
+        let mut messages = Messages::default();
+        messages.push("old");
+        let id = messages.next_id();
+        assert_eq!(messages.last_since(id), None);
+        messages.push("new");
+        messages.dismiss_before(id);
+        assert_eq!(messages.current(), Some("new"));
+        assert_eq!(messages.num_queued(), 0);
+        assert_eq!(messages.last_since(id), Some("new"));
+        messages.dismiss_all();
+        assert_eq!(messages.current(), None);
+    }
+}
@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Free-text notes attached to frames, keyed by the full name of the stack.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Notes {
+    pub notes: BTreeMap<String, String>,
+}
+
+impl Notes {
+    /// Location of the notes file stored next to the profile.
+    pub fn sidecar_path(profile_path: &Path) -> PathBuf {
+        let mut path = profile_path.as_os_str().to_owned();
+        path.push(".notes.json");
+        PathBuf::from(path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if self.notes.is_empty() && !path.exists() {
+            return Ok(());
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn get(&self, full_name: &str) -> Option<&str> {
+        self.notes.get(full_name).map(|s| s.as_str())
+    }
+
+    /// Set the note of a frame. An empty note removes it.
+    pub fn set(&mut self, full_name: &str, note: &str) {
+        let note = note.trim();
+        if note.is_empty() {
+            self.notes.remove(full_name);
+        } else {
+            self.notes.insert(full_name.to_string(), note.to_string());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sidecar_path() {
+        assert_eq!(
+            Notes::sidecar_path(Path::new("/tmp/perf.folded")),
+            PathBuf::from("/tmp/perf.folded.notes.json")
+        );
+    }
+
+    #[test]
+    fn test_set_save_and_load() {
+        let mut notes = Notes::default();
+        notes.set("a;b", "  slow path  ");
+        notes.set("a;c", "check this");
+        notes.set("a;c", "");
+        assert_eq!(notes.get("a;b"), Some("slow path"));
+        assert_eq!(notes.get("a;c"), None);
+
+        let path = std::env::temp_dir().join(format!(
+            "flamelens-test-notes-{}.notes.json",
+            std::process::id()
+        ));
+        notes.save(&path).unwrap();
+        let loaded = Notes::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, notes);
+    }
+}
@@ -0,0 +1,161 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often the progress bar is redrawn.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+const BAR_WIDTH: usize = 20;
+
+/// Whether a progress bar is on the screen, to clear it before exiting on Ctrl-C.
+static DRAWN: AtomicBool = AtomicBool::new(false);
+
+/// How far parsing a profile has got, updated by the parser and read by the progress bar.
+#[derive(Debug, Default)]
+pub struct ParseProgress {
+    bytes: AtomicUsize,
+    stacks: AtomicUsize,
+}
+
+impl ParseProgress {
+    pub fn update(&self, bytes: usize, stacks: usize) {
+        self.bytes.store(bytes, Ordering::Relaxed);
+        self.stacks.store(stacks, Ordering::Relaxed);
+    }
+}
+
+/// Progress bar on stderr while a huge profile is parsed, before the terminal user interface is
+/// up and would otherwise leave a blank terminal. The bar is cleared when dropped.
+pub struct ProgressBar {
+    progress: Arc<ParseProgress>,
+    stop: Option<Sender<()>>,
+    drawer: Option<JoinHandle<()>>,
+}
+
+impl ProgressBar {
+    pub fn start(label: &str, total_bytes: usize) -> Self {
+        clear_on_interrupt();
+        let progress = Arc::new(ParseProgress::default());
+        let (stop, stopped) = mpsc::channel();
+        let drawer = {
+            let progress = progress.clone();
+            let label = label.to_string();
+            let started_at = Instant::now();
+            thread::spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(REDRAW_INTERVAL) {
+                    let line = format_progress(
+                        &label,
+                        progress.bytes.load(Ordering::Relaxed),
+                        total_bytes,
+                        progress.stacks.load(Ordering::Relaxed),
+                        started_at.elapsed(),
+                    );
+                    DRAWN.store(true, Ordering::Relaxed);
+                    let _ = write!(io::stderr(), "\r\x1b[2K{}", line);
+                }
+                clear_line();
+            })
+        };
+        Self {
+            progress,
+            stop: Some(stop),
+            drawer: Some(drawer),
+        }
+    }
+
+    pub fn progress(&self) -> &ParseProgress {
+        &self.progress
+    }
+}
+
+impl Drop for ProgressBar {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(drawer) = self.drawer.take() {
+            let _ = drawer.join();
+        }
+    }
+}
+
+fn clear_line() {
+    if DRAWN.swap(false, Ordering::Relaxed) {
+        let _ = write!(io::stderr(), "\r\x1b[2K");
+    }
+}
+
+/// Clear the progress bar on Ctrl-C before exiting as usual, so that it does not run into the
+/// shell prompt.
+#[cfg(unix)]
+fn clear_on_interrupt() {
+    use signal_hook::consts::SIGINT;
+    let Ok(mut signals) = signal_hook::iterator::Signals::new([SIGINT]) else {
+        return;
+    };
+    thread::spawn(move || {
+        if signals.forever().next().is_some() {
+            clear_line();
+            let _ = signal_hook::low_level::emulate_default_handler(SIGINT);
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn clear_on_interrupt() {}
+
+fn format_megabytes(bytes: usize) -> String {
+    format!("{:.1} MB", bytes as f64 / 1e6)
+}
+
+pub fn format_progress(
+    label: &str,
+    bytes: usize,
+    total_bytes: usize,
+    stacks: usize,
+    elapsed: Duration,
+) -> String {
+    let fraction = (bytes as f64 / total_bytes.max(1) as f64).min(1.0);
+    let filled = (fraction * BAR_WIDTH as f64) as usize;
+    let eta = if bytes > 0 {
+        let remaining = elapsed.as_secs_f64() * (1.0 - fraction) / fraction;
+        format!(", about {:.0}s left", remaining.ceil())
+    } else {
+        String::new()
+    };
+    format!(
+        "{} [{}{}] {:.0}% ({} of {}, {} stacks{}), Ctrl-C to cancel",
+        label,
+        "#".repeat(filled),
+        "-".repeat(BAR_WIDTH - filled),
+        100.0 * fraction,
+        format_megabytes(bytes),
+        format_megabytes(total_bytes),
+        stacks,
+        eta
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_progress() {
+        assert_eq!(
+            format_progress(
+                "Loading big.txt",
+                50_000_000,
+                200_000_000,
+                12345,
+                Duration::from_secs(2)
+            ),
+            "Loading big.txt [#####---------------] 25% (50.0 MB of 200.0 MB, 12345 stacks, \
+             about 6s left), Ctrl-C to cancel"
+        );
+        assert_eq!(
+            format_progress("Loading", 0, 0, 0, Duration::ZERO),
+            "Loading [--------------------] 0% (0.0 MB of 0.0 MB, 0 stacks), Ctrl-C to cancel"
+        );
+    }
+}
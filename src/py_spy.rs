@@ -24,71 +24,105 @@ LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 SOFTWARE.
 */
+use crate::flame::{ON_CPU_FRAME, WAITING_FRAME};
 use crate::py_spy_flamegraph::Flamegraph as PySpyFlamegraph;
+use crate::sampler::{
+    MemoryLimit, ProfilerOutput, Sampler, SamplerOptions, SamplerState, SamplerStatus,
+};
 use anyhow::Error;
 use py_spy::config::RecordDuration;
 use py_spy::sampler;
 use py_spy::Config;
 use py_spy::Frame;
 use remoteprocess;
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex};
 use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone, Default)]
-pub enum SamplerStatus {
-    #[default]
-    Running,
-    Error(String),
-    Done,
-}
-
-#[derive(Debug, Clone, Default)]
-pub struct SamplerState {
-    pub status: SamplerStatus,
-    pub total_sampled_duration: Duration,
-    pub late: Option<Duration>,
+/// Live sampler of a Python process using py-spy.
+pub struct PySpySampler {
+    pid: remoteprocess::Pid,
+    config: Config,
+    max_memory: Option<u64>,
 }
 
-impl SamplerState {
-    pub fn set_status(&mut self, status: SamplerStatus) {
-        self.status = status;
+impl PySpySampler {
+    /// Sampler configured as `py-spy record` would be, with extra arguments like "--idle".
+    pub fn new(pid: u64, options: SamplerOptions) -> Self {
+        // Note: mimic a record command's invocation vs simply getting default Config as
+        // from_args does a lot of heavy lifting
+        let mut args = [
+            "py-spy",
+            "record",
+            "--pid",
+            pid.to_string().as_str(),
+            "--format",
+            "raw",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<String>>();
+        if let Some(duration) = options.duration {
+            // py-spy takes whole seconds
+            let seconds = duration.as_secs_f64().ceil() as u64;
+            args.extend(["--duration".to_string(), seconds.to_string()]);
+        }
+        if let Some(py_spy_args) = options.py_spy_args {
+            args.extend(py_spy_args.split_whitespace().map(|s| s.to_string()));
+        }
+        Self {
+            pid: pid as remoteprocess::Pid,
+            config: Config::from_args(&args).unwrap(),
+            max_memory: options.max_memory,
+        }
     }
+}
 
-    pub fn set_total_sampled_duration(&mut self, total_sampled_duration: Duration) {
-        self.total_sampled_duration = total_sampled_duration;
+impl Sampler for PySpySampler {
+    fn pid(&self) -> u64 {
+        self.pid as u64
     }
 
-    pub fn set_late(&mut self, late: Duration) {
-        self.late = Some(late);
+    fn command_line(&self) -> Option<String> {
+        remoteprocess::Process::new(self.pid)
+            .and_then(|p| p.cmdline())
+            .ok()
+            .map(|c| c.join(" "))
     }
 
-    pub fn unset_late(&mut self) {
-        self.late = None;
+    fn record(
+        self: Box<Self>,
+        output: mpsc::Sender<ProfilerOutput>,
+        state: Arc<Mutex<SamplerState>>,
+    ) {
+        record_samples(self.pid, &self.config, output, state, self.max_memory);
     }
 }
 
-#[derive(Debug)]
-pub struct ProfilerOutput {
-    pub data: String,
-}
-
 pub fn record_samples(
     pid: remoteprocess::Pid,
     config: &Config,
-    output_data: Arc<Mutex<Option<ProfilerOutput>>>,
+    output_data: mpsc::Sender<ProfilerOutput>,
     state: Arc<Mutex<SamplerState>>,
+    max_memory: Option<u64>,
 ) {
+    tracing::info!(pid, "Sampler started");
     state.lock().unwrap().set_status(SamplerStatus::Running);
-    let result = run(pid, config, output_data, state.clone());
+    let result = run(pid, config, output_data, state.clone(), max_memory);
     match result {
         Ok(_) => {
+            tracing::info!(pid, "Sampler finished");
             state.lock().unwrap().set_status(SamplerStatus::Done);
         }
         Err(e) => {
+            tracing::error!(pid, error = ?e, "Sampler failed");
             state
                 .lock()
                 .unwrap()
-                .set_status(SamplerStatus::Error(format!("{:?}", e)));
+                .set_status(SamplerStatus::Error(format!(
+                    "py-spy sampler exited with error: {:?}\n\nYou likely need to rerun this \
+                     program with sudo.",
+                    e
+                )));
         }
     }
 }
@@ -96,10 +130,13 @@ pub fn record_samples(
 pub fn run(
     pid: remoteprocess::Pid,
     config: &Config,
-    output_data: Arc<Mutex<Option<ProfilerOutput>>>,
+    output_data: mpsc::Sender<ProfilerOutput>,
     state: Arc<Mutex<SamplerState>>,
+    max_memory: Option<u64>,
 ) -> Result<(), Error> {
     let mut output = PySpyFlamegraph::new(config.show_line_numbers);
+    let mut gil_output = PySpyFlamegraph::new(config.show_line_numbers);
+    let mut gil_samples = 0;
 
     let start_tic = std::time::Instant::now();
     let sampler = sampler::Sampler::new(pid, config)?;
@@ -111,10 +148,11 @@ pub fn run(
 
     let mut _errors = 0;
     let mut intervals = 0;
-    let mut _samples = 0;
+    let mut samples = 0;
 
     let mut last_late_message = std::time::Instant::now();
     let mut last_data_dump: Option<Instant> = None;
+    let mut memory_limit = MemoryLimit::new(max_memory);
 
     for mut sample in sampler {
         if let Some(delay) = sample.late {
@@ -122,6 +160,7 @@ pub fn run(
                 let now = std::time::Instant::now();
                 if now - last_late_message > Duration::from_secs(1) {
                     last_late_message = now;
+                    tracing::warn!(?delay, "Sampler is behind");
                     state.lock().unwrap().set_late(delay);
                 }
             } else {
@@ -175,12 +214,36 @@ pub fn run(
                 }
             }
 
-            _samples += 1;
+            // Before the activity frame, to match the stacks as shown by default
+            if trace.owns_gil {
+                gil_samples += 1;
+                gil_output.increment(trace)?;
+            }
+
+            // Split the samples into the active and idle ones, merged again unless asked for
+            if config.include_idle {
+                let name = if trace.active {
+                    ON_CPU_FRAME
+                } else {
+                    WAITING_FRAME
+                };
+                trace.frames.push(Frame {
+                    name: name.to_string(),
+                    filename: String::from(""),
+                    module: None,
+                    short_filename: None,
+                    line: 0,
+                    locals: None,
+                });
+            }
+
+            samples += 1;
             output.increment(trace)?;
         }
 
         if let Some(sampling_errors) = sample.sampling_errors {
-            for (_pid, _e) in sampling_errors {
+            for (pid, e) in sampling_errors {
+                tracing::debug!(pid, error = ?e, "Sampling error");
                 _errors += 1;
             }
         }
@@ -194,11 +257,18 @@ pub fn run(
         };
         if should_dump {
             last_data_dump = Some(Instant::now());
+            memory_limit.apply(&mut output.counts, &state);
+            memory_limit.apply(&mut gil_output.counts, &state);
             let data = output.get_data();
             // let mut file = std::fs::File::create("data.txt")?;
             // std::io::Write::write_all(&mut file, data.as_bytes())?;
-            let profiler_output = ProfilerOutput { data };
-            output_data.lock().unwrap().replace(profiler_output);
+            let gil_data = Some(gil_output.get_data());
+            let profiler_output = ProfilerOutput { data, gil_data };
+            state.lock().unwrap().set_gil_held(gil_samples, samples);
+            if output_data.send(profiler_output).is_err() {
+                // Nobody is looking at the samples anymore
+                break;
+            }
             state
                 .lock()
                 .unwrap()
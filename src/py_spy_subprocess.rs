@@ -0,0 +1,180 @@
+use crate::sampler::{
+    MemoryLimit, ProfilerOutput, Sampler, SamplerOptions, SamplerState, SamplerStatus,
+};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Seconds each run of py-spy samples for, which is how often the live flamegraph is updated.
+const WINDOW_SECS: u64 = 1;
+
+/// Live sampler running an installed py-spy executable, for builds without the `python` feature
+/// or where py-spy linked into flamelens does not work. py-spy only writes its output when a
+/// recording ends, so it records one short window after another, each adding to the samples.
+pub struct PySpySubprocess {
+    program: String,
+    pid: u64,
+    py_spy_args: Vec<String>,
+    max_memory: Option<u64>,
+    duration: Option<Duration>,
+}
+
+impl PySpySubprocess {
+    /// Sampler running `program` as `py-spy record` with extra arguments like "--idle".
+    pub fn new(program: &str, pid: u64, options: SamplerOptions) -> Self {
+        Self {
+            program: program.to_string(),
+            pid,
+            py_spy_args: options
+                .py_spy_args
+                .map(|args| args.split_whitespace().map(|s| s.to_string()).collect())
+                .unwrap_or_default(),
+            max_memory: options.max_memory,
+            duration: options.duration,
+        }
+    }
+
+    fn record_window(&self, path: &Path) -> Result<(), String> {
+        let output = Command::new(&self.program)
+            .args(["record", "--pid", &self.pid.to_string(), "--format", "raw"])
+            .args(["--duration", &WINDOW_SECS.to_string(), "--output"])
+            .arg(path)
+            .args(&self.py_spy_args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|e| {
+                format!(
+                    "Could not run {}: {}\n\nInstall py-spy, e.g. with \"pip install py-spy\", or \
+                     give its path with --py-spy-binary.",
+                    self.program, e
+                )
+            })?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} exited with {}: {}\n\nYou likely need to rerun this program with sudo.",
+                self.program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    }
+}
+
+impl Sampler for PySpySubprocess {
+    fn pid(&self) -> u64 {
+        self.pid
+    }
+
+    fn record(
+        self: Box<Self>,
+        output: mpsc::Sender<ProfilerOutput>,
+        state: Arc<Mutex<SamplerState>>,
+    ) {
+        let pid = self.pid;
+        tracing::info!(pid, program = self.program, "Sampler started");
+        state.lock().unwrap().set_status(SamplerStatus::Running);
+        let path = std::env::temp_dir().join(format!(
+            "flamelens-py-spy-{}-{}.txt",
+            std::process::id(),
+            pid
+        ));
+        let start_tic = Instant::now();
+        let mut counts = HashMap::new();
+        let mut memory_limit = MemoryLimit::new(self.max_memory);
+        let mut windows = 0;
+        let status = loop {
+            if self
+                .duration
+                .is_some_and(|duration| start_tic.elapsed() >= duration)
+            {
+                tracing::info!(pid, "Sampler finished");
+                break SamplerStatus::Done;
+            }
+            if let Err(e) = self.record_window(&path) {
+                if windows == 0 {
+                    tracing::error!(pid, error = e, "Sampler failed");
+                    break SamplerStatus::Error(e);
+                }
+                // Having sampled the process before, it most likely exited
+                tracing::info!(pid, error = e, "Sampler finished");
+                break SamplerStatus::Done;
+            }
+            windows += 1;
+            add_folded_stacks(
+                &mut counts,
+                &std::fs::read_to_string(&path).unwrap_or_default(),
+            );
+            memory_limit.apply(&mut counts, &state);
+            let data = counts
+                .iter()
+                .map(|(stack, count)| format!("{} {}\n", stack, count))
+                .collect();
+            // py-spy does not write whether the samples held the GIL
+            let gil_data = None;
+            if output.send(ProfilerOutput { data, gil_data }).is_err() {
+                // Nobody is looking at the samples anymore
+                break SamplerStatus::Done;
+            }
+            state
+                .lock()
+                .unwrap()
+                .set_total_sampled_duration(start_tic.elapsed());
+        };
+        let _ = std::fs::remove_file(&path);
+        state.lock().unwrap().set_status(status);
+    }
+}
+
+/// Add the samples of folded stacks like "main;work 3" to the counts per stack.
+fn add_folded_stacks(counts: &mut HashMap<String, usize>, content: &str) {
+    for line in content.lines() {
+        let Some((stack, count)) = line.rsplit_once(' ') else {
+            continue;
+        };
+        if let Ok(count) = count.parse::<usize>() {
+            *counts.entry(stack.to_string()).or_insert(0) += count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_folded_stacks() {
+        let mut counts = HashMap::new();
+        add_folded_stacks(&mut counts, "main;a 3\nmain;b (app.py:2) 1\n\nbad\n");
+        add_folded_stacks(&mut counts, "main;a 2\n");
+        assert_eq!(
+            counts,
+            HashMap::from([
+                ("main;a".to_string(), 5),
+                ("main;b (app.py:2)".to_string(), 1)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_missing_program() {
+        let sampler = PySpySubprocess::new(
+            "flamelens-test-no-such-py-spy",
+            1,
+            SamplerOptions::default(),
+        );
+        let (sender, receiver) = mpsc::channel();
+        let state = Arc::new(Mutex::new(SamplerState::default()));
+        Box::new(sampler).record(sender, state.clone());
+        assert!(receiver.try_recv().is_err());
+        let SamplerStatus::Error(e) = &state.lock().unwrap().status else {
+            panic!("Expected the sampler to fail");
+        };
+        assert!(e.starts_with("Could not run flamelens-test-no-such-py-spy"));
+    }
+}
@@ -0,0 +1,135 @@
+use crate::state::SavedViewState;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+
+const MAX_RECENT_FILES: usize = 20;
+const RECENT_FILES_FILENAME: &str = "recent.json";
+
+/// Directory to store persisted data such as the recent files list.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME") {
+        PathBuf::from(dir)
+    } else if let Some(dir) = std::env::var_os("APPDATA") {
+        PathBuf::from(dir)
+    } else {
+        PathBuf::from(std::env::var_os("HOME")?).join(".config")
+    };
+    Some(base.join("flamelens"))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecentFile {
+    pub path: String,
+    pub state: SavedViewState,
+}
+
+/// Recently opened files, most recent first.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RecentFiles {
+    pub files: Vec<RecentFile>,
+}
+
+impl RecentFiles {
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(RECENT_FILES_FILENAME))
+    }
+
+    /// Load from the default location. A missing or unreadable file gives an empty list.
+    pub fn load() -> Self {
+        Self::default_path()
+            .and_then(|path| Self::load_from(&path).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn load_from(path: &Path) -> io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::default_path().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "Could not find config directory")
+        })?;
+        self.save_to(&path)
+    }
+
+    pub fn save_to(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Record a file as the most recently opened one together with its view state.
+    pub fn add(&mut self, path: &str, state: SavedViewState) {
+        let path = Self::normalize_path(path);
+        self.files.retain(|f| f.path != path);
+        self.files.insert(0, RecentFile { path, state });
+        self.files.truncate(MAX_RECENT_FILES);
+    }
+
+    pub fn get(&self, path: &str) -> Option<&RecentFile> {
+        let path = Self::normalize_path(path);
+        self.files.iter().find(|f| f.path == path)
+    }
+
+    pub fn most_recent(&self) -> Option<&RecentFile> {
+        self.files.first()
+    }
+
+    fn normalize_path(path: &str) -> String {
+        std::fs::canonicalize(path)
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|_| path.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_with_selected(name: &str) -> SavedViewState {
+        SavedViewState {
+            selected: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_add_moves_file_to_front() {
+        let mut recent = RecentFiles::default();
+        recent.add("a.txt", state_with_selected("a1"));
+        recent.add("b.txt", state_with_selected("b1"));
+        recent.add("a.txt", state_with_selected("a2"));
+        assert_eq!(
+            recent
+                .files
+                .iter()
+                .map(|f| f.path.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a.txt", "b.txt"]
+        );
+        assert_eq!(
+            recent.most_recent().unwrap().state,
+            state_with_selected("a2")
+        );
+        assert_eq!(
+            recent.get("b.txt").unwrap().state,
+            state_with_selected("b1")
+        );
+    }
+
+    #[test]
+    fn test_add_truncates() {
+        let mut recent = RecentFiles::default();
+        for i in 0..MAX_RECENT_FILES + 5 {
+            recent.add(&format!("{}.txt", i), SavedViewState::default());
+        }
+        assert_eq!(recent.files.len(), MAX_RECENT_FILES);
+        assert_eq!(
+            recent.most_recent().unwrap().path,
+            format!("{}.txt", MAX_RECENT_FILES + 4)
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use crate::memory::{compact_stacks, resident_memory};
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+/// Profiler sampling a running process for a live flamegraph. The app only deals with this
+/// trait, so that builds without a profiler need none of the native libraries profilers link to.
+pub trait Sampler: Send {
+    /// Process being sampled.
+    fn pid(&self) -> u64;
+
+    /// Command line of the process, shown in the header.
+    fn command_line(&self) -> Option<String> {
+        None
+    }
+
+    /// Sample until the process exits or nobody receives the output anymore. Each output holds
+    /// the folded stacks of all samples so far. Progress and errors go to `state`, the error
+    /// being the message to exit with.
+    fn record(
+        self: Box<Self>,
+        output: mpsc::Sender<ProfilerOutput>,
+        state: Arc<Mutex<SamplerState>>,
+    );
+}
+
+/// How to sample, the same for all samplers.
+#[derive(Debug, Clone, Default)]
+pub struct SamplerOptions {
+    /// Additional arguments for "py-spy record"
+    pub py_spy_args: Option<String>,
+    /// Resident memory of flamelens over which to stop keeping more distinct stacks
+    pub max_memory: Option<u64>,
+    /// How long to sample for, or until the process exits if not given
+    pub duration: Option<Duration>,
+}
+
+/// Parse a duration like "90", "90s", "5m" or "1.5h", in seconds if no unit is given.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let usage = || format!("expected a duration like \"60s\", \"5m\" or \"1h\": {}", s);
+    let s = s.trim();
+    let (number, factor) = match s.char_indices().last() {
+        Some((i, 's')) => (&s[..i], 1.0),
+        Some((i, 'm')) => (&s[..i], 60.0),
+        Some((i, 'h')) => (&s[..i], 3600.0),
+        _ => (s, 1.0),
+    };
+    let seconds = number.trim().parse::<f64>().map_err(|_| usage())? * factor;
+    if seconds > 0.0 && seconds.is_finite() {
+        Ok(Duration::from_secs_f64(seconds))
+    } else {
+        Err(usage())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum SamplerStatus {
+    #[default]
+    Running,
+    Error(String),
+    Done,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SamplerState {
+    pub status: SamplerStatus,
+    pub total_sampled_duration: Duration,
+    pub late: Option<Duration>,
+    /// Most stacks kept since going over the memory limit, the lightest ones being folded into
+    /// their callers
+    pub max_stacks: Option<usize>,
+    /// Samples whose thread held the GIL and all samples, if the sampler can tell
+    pub gil_held: Option<(u64, u64)>,
+}
+
+impl SamplerState {
+    pub fn set_status(&mut self, status: SamplerStatus) {
+        self.status = status;
+    }
+
+    pub fn set_total_sampled_duration(&mut self, total_sampled_duration: Duration) {
+        self.total_sampled_duration = total_sampled_duration;
+    }
+
+    pub fn set_late(&mut self, late: Duration) {
+        self.late = Some(late);
+    }
+
+    pub fn unset_late(&mut self) {
+        self.late = None;
+    }
+
+    pub fn set_max_stacks(&mut self, max_stacks: usize) {
+        self.max_stacks = Some(max_stacks);
+    }
+
+    pub fn set_gil_held(&mut self, held: u64, total: u64) {
+        self.gil_held = Some((held, total));
+    }
+}
+
+#[derive(Debug)]
+pub struct ProfilerOutput {
+    pub data: String,
+    /// Folded stacks of the samples whose thread held the GIL, if the sampler can tell
+    pub gil_data: Option<String>,
+}
+
+/// Keeps the samples of a live session from taking more memory than `--max-memory`.
+#[derive(Debug, Default)]
+pub struct MemoryLimit {
+    max_memory: Option<u64>,
+    max_stacks: Option<usize>,
+}
+
+impl MemoryLimit {
+    pub fn new(max_memory: Option<u64>) -> Self {
+        Self {
+            max_memory,
+            max_stacks: None,
+        }
+    }
+
+    /// Called with the samples per stack before each output. Over the memory limit, keep the
+    /// number of distinct stacks from growing any further for the rest of the session.
+    pub fn apply(&mut self, counts: &mut HashMap<String, usize>, state: &Mutex<SamplerState>) {
+        if let (Some(max_memory), None) = (self.max_memory, self.max_stacks) {
+            if let Some(rss) = resident_memory().filter(|rss| *rss > max_memory) {
+                let limit = (counts.len() / 2).max(1);
+                tracing::warn!(rss, max_memory, max_stacks = limit, "Over the memory limit");
+                state.lock().unwrap().set_max_stacks(limit);
+                self.max_stacks = Some(limit);
+            }
+        }
+        if let Some(max_stacks) = self.max_stacks {
+            compact_stacks(counts, max_stacks);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+    use crate::event::Event;
+    use crate::flame::StackFilter;
+
+    struct FakeSampler;
+
+    impl Sampler for FakeSampler {
+        fn pid(&self) -> u64 {
+            42
+        }
+
+        fn record(
+            self: Box<Self>,
+            output: mpsc::Sender<ProfilerOutput>,
+            state: Arc<Mutex<SamplerState>>,
+        ) {
+            let data = "main;a 3\nmain;b 1\n".to_string();
+            let gil_data = Some("main;a 2\n".to_string());
+            output.send(ProfilerOutput { data, gil_data }).unwrap();
+            state.lock().unwrap().set_status(SamplerStatus::Done);
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("60s"), Ok(Duration::from_secs(60)));
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1.5h"), Ok(Duration::from_secs(5400)));
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("10d").is_err());
+    }
+
+    #[test]
+    fn test_with_sampler() {
+        let (sender, receiver) = mpsc::channel();
+        let record =
+            std::env::temp_dir().join(format!("flamelens-test-record-{}.txt", std::process::id()));
+        let mut app = App::with_sampler(
+            Box::new(FakeSampler),
+            StackFilter::default(),
+            Some(record.clone()),
+            None,
+            sender,
+        );
+        app.capture_duration = Some(Duration::from_secs(1));
+        let Ok(Event::FlameGraph(parsed)) = receiver.recv() else {
+            panic!("Expected a flamegraph from the sampler");
+        };
+        app.receive_flamegraph(*parsed);
+        assert_eq!(app.flamegraph().total_count(), 4);
+        app.toggle_gil_overlay();
+        let stack = app.flamegraph().get_stack_by_full_name("main;a").unwrap();
+        assert_eq!(app.gil_held_of(stack), Some((2, 3)));
+        let stack = app.flamegraph().get_stack_by_full_name("main;b").unwrap();
+        assert_eq!(app.gil_held_of(stack), Some((0, 1)));
+        assert_eq!(app.terminal_title(), "flamelens - pid 42");
+
+        let Ok(Event::SamplerFinished(recorded)) = receiver.recv() else {
+            panic!("Expected the sampler to finish");
+        };
+        assert_eq!(recorded, Some(Ok(record.display().to_string())));
+        app.finish_sampling(recorded);
+        assert!(app.flamegraph_state().freeze);
+        assert!(app
+            .sampler_finished
+            .as_ref()
+            .is_some_and(|text| text.starts_with("Capture complete after 0s; saved to ")));
+        let content = std::fs::read_to_string(&record).unwrap();
+        std::fs::remove_file(&record).unwrap();
+        let mut lines = content.lines().collect::<Vec<_>>();
+        lines.sort();
+        assert_eq!(lines, vec!["main;a 3", "main;b 1"]);
+    }
+}
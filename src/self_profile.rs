@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Whether phases are being recorded, so that they cost nothing unless profiling flamelens itself.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Phases each thread is currently in, outermost first, keyed by thread name.
+static PHASES: Mutex<BTreeMap<String, Vec<&'static str>>> = Mutex::new(BTreeMap::new());
+
+fn thread_name() -> String {
+    let current = thread::current();
+    match current.name() {
+        // Semicolons would split the frame in the folded format
+        Some(name) => name.replace(';', "_"),
+        None => format!("{:?}", current.id()),
+    }
+}
+
+/// Marks the current thread as being in a phase, like "parse" or "render", until dropped.
+#[must_use]
+pub struct Phase {
+    recorded: bool,
+}
+
+impl Drop for Phase {
+    fn drop(&mut self) {
+        if self.recorded {
+            if let Some(phases) = PHASES.lock().unwrap().get_mut(&thread_name()) {
+                phases.pop();
+            }
+        }
+    }
+}
+
+/// Enter a phase of flamelens's own work, which shows up as a frame in the profile written with
+/// `--self-profile`.
+pub fn phase(name: &'static str) -> Phase {
+    let recorded = ENABLED.load(Ordering::Relaxed);
+    if recorded {
+        PHASES
+            .lock()
+            .unwrap()
+            .entry(thread_name())
+            .or_default()
+            .push(name);
+    }
+    Phase { recorded }
+}
+
+/// Samples the phases of all threads at a fixed interval, and writes them as a folded profile
+/// when dropped. Time outside of any phase, such as waiting for keys, is left out.
+pub struct SelfProfiler {
+    path: PathBuf,
+    counts: Arc<Mutex<HashMap<String, u64>>>,
+    stop: Option<Sender<()>>,
+    sampler: Option<JoinHandle<()>>,
+}
+
+impl SelfProfiler {
+    pub fn start(path: PathBuf, interval: Duration) -> Self {
+        ENABLED.store(true, Ordering::Relaxed);
+        let counts = Arc::new(Mutex::new(HashMap::new()));
+        let (stop, stopped) = mpsc::channel();
+        let sampler = {
+            let counts = counts.clone();
+            thread::spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    let phases = PHASES.lock().unwrap();
+                    let mut counts = counts.lock().unwrap();
+                    for (thread, phases) in phases.iter().filter(|(_, p)| !p.is_empty()) {
+                        let stack = format!("flamelens;{};{}", thread, phases.join(";"));
+                        *counts.entry(stack).or_insert(0) += 1;
+                    }
+                }
+            })
+        };
+        Self {
+            path,
+            counts,
+            stop: Some(stop),
+            sampler: Some(sampler),
+        }
+    }
+
+    /// Samples so far in the folded format, heaviest stack first.
+    pub fn to_folded(&self) -> String {
+        let counts = self.counts.lock().unwrap();
+        let mut stacks = counts.iter().collect::<Vec<_>>();
+        stacks.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        stacks
+            .iter()
+            .map(|(stack, count)| format!("{} {}\n", stack, count))
+            .collect()
+    }
+}
+
+impl Drop for SelfProfiler {
+    fn drop(&mut self) {
+        ENABLED.store(false, Ordering::Relaxed);
+        drop(self.stop.take());
+        if let Some(sampler) = self.sampler.take() {
+            let _ = sampler.join();
+        }
+        match std::fs::write(&self.path, self.to_folded()) {
+            Ok(()) => eprintln!("Wrote the profile of flamelens to {}", self.path.display()),
+            Err(e) => eprintln!(
+                "Could not write the profile of flamelens to {}: {}",
+                self.path.display(),
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_profiler() {
+        let path = std::env::temp_dir().join(format!(
+            "flamelens-test-self-profile-{}.txt",
+            std::process::id()
+        ));
+        let profiler = SelfProfiler::start(path.clone(), Duration::from_millis(1));
+        {
+            let _parse = phase("parse");
+            thread::sleep(Duration::from_millis(30));
+            let _sort = phase("ordered_stacks");
+            thread::sleep(Duration::from_millis(30));
+        }
+        let folded = profiler.to_folded();
+        let prefix = format!("flamelens;{};", thread_name());
+        let stacks = folded
+            .lines()
+            .filter_map(|line| line.strip_prefix(&prefix))
+            .filter_map(|line| line.rsplit_once(' '))
+            .map(|(stack, _)| stack)
+            .collect::<Vec<_>>();
+        assert!(stacks.contains(&"parse"), "{}", folded);
+        assert!(stacks.contains(&"parse;ordered_stacks"), "{}", folded);
+
+        drop(profiler);
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.contains(&format!("{}parse;ordered_stacks ", prefix)));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,63 @@
+use crate::state::SavedViewState;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+
+/// Where the profile data of a session comes from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum SessionInput {
+    /// Path to a folded stacks file
+    File(String),
+    /// Folded stacks data embedded in the session itself, e.g. when the input was piped in
+    Data(String),
+}
+
+/// A named snapshot of a flamelens session that can be reloaded later or shared.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Session {
+    pub input: SessionInput,
+    pub sorted: bool,
+    pub state: SavedViewState,
+    /// Regex of the frame set as the root with `:root` or `--root-frame`
+    #[serde(default)]
+    pub root_frame: Option<String>,
+}
+
+impl Session {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        let session = Session {
+            input: SessionInput::Data("a;b 1\na;c 2\n".to_string()),
+            sorted: true,
+            state: SavedViewState {
+                selected: Some("a;c".to_string()),
+                zoom: Some("a".to_string()),
+                search: None,
+                level_offset: 0,
+            },
+            root_frame: Some("^a$".to_string()),
+        };
+        let path = std::env::temp_dir().join(format!(
+            "flamelens-test-session-{}.flamelens",
+            std::process::id()
+        ));
+        session.save(&path).unwrap();
+        let loaded = Session::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, session);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::app::{App, AppResult};
+use crate::ui;
+use crossterm::queue;
+use crossterm::style::{Attribute, Print, SetAttribute, SetBackgroundColor, SetForegroundColor};
+use ratatui::backend::TestBackend;
+use ratatui::buffer::{Buffer, Cell};
+use ratatui::style::{Color, Modifier};
+use ratatui::Terminal;
+
+const ATTRIBUTES: [(Modifier, Attribute); 9] = [
+    (Modifier::BOLD, Attribute::Bold),
+    (Modifier::DIM, Attribute::Dim),
+    (Modifier::ITALIC, Attribute::Italic),
+    (Modifier::UNDERLINED, Attribute::Underlined),
+    (Modifier::SLOW_BLINK, Attribute::SlowBlink),
+    (Modifier::RAPID_BLINK, Attribute::RapidBlink),
+    (Modifier::REVERSED, Attribute::Reverse),
+    (Modifier::HIDDEN, Attribute::Hidden),
+    (Modifier::CROSSED_OUT, Attribute::CrossedOut),
+];
+
+/// Draw the app once into a screen of the given size that only exists in memory, as it would
+/// appear in the terminal.
+pub fn render_to_buffer(app: &mut App, width: u16, height: u16) -> AppResult<Buffer> {
+    let mut terminal = Terminal::new(TestBackend::new(width, height))?;
+    terminal.draw(|frame| {
+        ui::render(app, frame);
+    })?;
+    Ok(terminal.backend().buffer().clone())
+}
+
+/// Rows of text in the buffer, without styles.
+pub fn to_lines(buffer: &Buffer) -> Vec<String> {
+    buffer
+        .content
+        .chunks(buffer.area.width as usize)
+        .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+        .collect()
+}
+
+pub fn to_plain_text(buffer: &Buffer) -> String {
+    to_lines(buffer)
+        .iter()
+        .map(|line| line.trim_end().to_string() + "\n")
+        .collect()
+}
+
+/// Text of the buffer with ANSI escape codes for its colors and styles, to print with `cat` or
+/// paste where ANSI colors are shown. Styles are reset at the end of each line.
+pub fn to_ansi(buffer: &Buffer) -> String {
+    let mut out = Vec::new();
+    for row in buffer.content.chunks(buffer.area.width as usize) {
+        let mut previous: Option<&Cell> = None;
+        for cell in row {
+            let changed = previous.is_none_or(|previous| {
+                (previous.fg, previous.bg, previous.modifier) != (cell.fg, cell.bg, cell.modifier)
+            });
+            if changed {
+                // Writing to a Vec cannot fail
+                queue!(out, SetAttribute(Attribute::Reset)).unwrap();
+                if cell.fg != Color::Reset {
+                    queue!(out, SetForegroundColor(cell.fg.into())).unwrap();
+                }
+                if cell.bg != Color::Reset {
+                    queue!(out, SetBackgroundColor(cell.bg.into())).unwrap();
+                }
+                for (modifier, attribute) in ATTRIBUTES {
+                    if cell.modifier.contains(modifier) {
+                        queue!(out, SetAttribute(attribute)).unwrap();
+                    }
+                }
+            }
+            queue!(out, Print(cell.symbol())).unwrap();
+            previous = Some(cell);
+        }
+        queue!(out, SetAttribute(Attribute::Reset), Print("\n")).unwrap();
+    }
+    String::from_utf8(out).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flame::FlameGraph;
+    use ratatui::layout::Rect;
+    use ratatui::style::{Style, Stylize};
+
+    #[test]
+    fn test_to_ansi() {
+        let mut buffer = Buffer::empty(Rect::new(0, 0, 4, 2));
+        buffer.set_string(0, 0, "ab", Style::new().fg(Color::Red).bold());
+        buffer.set_string(2, 0, "c", Style::new().bg(Color::Rgb(1, 2, 3)));
+        buffer.set_string(0, 1, "d", Style::new());
+        assert_eq!(
+            to_ansi(&buffer),
+            "\x1b[0m\x1b[38;5;1m\x1b[1mab\x1b[0m\x1b[48;2;1;2;3mc\x1b[0m \x1b[0m\n\
+             \x1b[0md   \x1b[0m\n"
+        );
+        assert_eq!(to_plain_text(&buffer), "abc\nd\n");
+    }
+
+    #[test]
+    fn test_render_to_buffer() {
+        let flamegraph = FlameGraph::from_string("main;a 3\nmain;b 1\n".to_string(), false);
+        let mut app = App::with_flamegraph("test", flamegraph);
+        let buffer = render_to_buffer(&mut app, 60, 12).unwrap();
+        let lines = to_lines(&buffer);
+        assert_eq!(lines.len(), 12);
+        assert!(lines.iter().all(|line| line.chars().count() == 60));
+        assert!(lines.iter().any(|line| line.starts_with(" main ")));
+    }
+}
@@ -1,4 +1,5 @@
-use crate::flame::{FlameGraph, SearchPattern, StackIdentifier, ROOT_ID};
+use crate::flame::{FlameGraph, ProcessFrame, SearchPattern, StackIdentifier, ROOT_ID};
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone)]
 pub struct ZoomState {
@@ -14,10 +15,105 @@ impl ZoomState {
     }
 }
 
+/// Popup listing the processes of a profile sampled with `--subprocesses`, to zoom into one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessSelector {
+    pub processes: Vec<ProcessFrame>,
+    pub selected: usize,
+}
+
+impl ProcessSelector {
+    pub fn move_selection(&mut self, delta: isize) {
+        self.selected = self
+            .selected
+            .saturating_add_signed(delta)
+            .min(self.processes.len().saturating_sub(1));
+    }
+
+    pub fn selected_process(&self) -> Option<&ProcessFrame> {
+        self.processes.get(self.selected)
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ViewKind {
     FlameGraph,
     Table,
+    Summary,
+}
+
+/// Frames narrower than this are left out of the flamegraph, as a number of cells or a percentage
+/// of the width of the flamegraph. Leaving more out makes enormous profiles faster to draw.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MinWidth {
+    Cells(f64),
+    Percent(f64),
+}
+
+impl Default for MinWidth {
+    fn default() -> Self {
+        MinWidth::Cells(1.0)
+    }
+}
+
+impl MinWidth {
+    /// Width in cells for a flamegraph `frame_width` cells wide. Never less than a cell, as
+    /// narrower frames cannot be drawn.
+    pub fn cells(&self, frame_width: u16) -> f64 {
+        let cells = match self {
+            MinWidth::Cells(cells) => *cells,
+            MinWidth::Percent(percent) => percent / 100.0 * frame_width as f64,
+        };
+        cells.max(1.0)
+    }
+
+    pub fn wider(self) -> Self {
+        match self {
+            MinWidth::Cells(cells) => MinWidth::Cells(cells * 2.0),
+            MinWidth::Percent(percent) => MinWidth::Percent((percent * 2.0).min(100.0)),
+        }
+    }
+
+    pub fn narrower(self) -> Self {
+        match self {
+            MinWidth::Cells(cells) => MinWidth::Cells((cells / 2.0).max(1.0)),
+            MinWidth::Percent(percent) => MinWidth::Percent(percent / 2.0),
+        }
+    }
+}
+
+impl std::str::FromStr for MinWidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |value: &str| {
+            value
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite() && *v >= 0.0)
+                .ok_or_else(|| {
+                    format!(
+                        "expected a number of cells or a percentage like \"0.5%\": {}",
+                        s
+                    )
+                })
+        };
+        match s.trim().strip_suffix('%') {
+            Some(percent) => Ok(MinWidth::Percent(parse(percent)?)),
+            None => Ok(MinWidth::Cells(parse(s)?)),
+        }
+    }
+}
+
+impl std::fmt::Display for MinWidth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MinWidth::Cells(cells) if *cells == 1.0 => write!(f, "1 cell"),
+            MinWidth::Cells(cells) => write!(f, "{} cells", cells),
+            MinWidth::Percent(percent) => write!(f, "{}% of the width", percent),
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone)]
@@ -33,6 +129,56 @@ impl TableState {
     }
 }
 
+/// Snapshot of the view state keyed by stack names instead of StackIdentifiers, so that it can be
+/// persisted and restored against a newly parsed flamegraph.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SavedViewState {
+    pub selected: Option<String>,
+    pub zoom: Option<String>,
+    pub search: Option<SavedSearchPattern>,
+    pub level_offset: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavedSearchPattern {
+    pub pattern: String,
+    pub is_regex: bool,
+}
+
+const MAX_HISTORY_SIZE: usize = 100;
+
+/// Undo / redo stacks of saved view states.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    undo: Vec<SavedViewState>,
+    redo: Vec<SavedViewState>,
+}
+
+impl History {
+    /// Record the state before a change. Any redo history is discarded.
+    pub fn push(&mut self, state: SavedViewState) {
+        self.undo.push(state);
+        if self.undo.len() > MAX_HISTORY_SIZE {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// Pop the state to go back to, given the current state to be redone later.
+    pub fn undo(&mut self, current: SavedViewState) -> Option<SavedViewState> {
+        let previous = self.undo.pop()?;
+        self.redo.push(current);
+        Some(previous)
+    }
+
+    /// Pop the state to go forward to, given the current state to be undone later.
+    pub fn redo(&mut self, current: SavedViewState) -> Option<SavedViewState> {
+        let next = self.redo.pop()?;
+        self.undo.push(current);
+        Some(next)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FlameGraphState {
     pub selected: StackIdentifier,
@@ -41,9 +187,13 @@ pub struct FlameGraphState {
     pub frame_width: Option<u16>,
     pub zoom: Option<ZoomState>,
     pub search_pattern: Option<SearchPattern>,
+    /// Whether searches only match frames under the zoomed frame
+    pub search_in_zoom: bool,
     pub freeze: bool,
     pub view_kind: ViewKind,
     pub table_state: TableState,
+    pub summary_offset: usize,
+    pub min_width: MinWidth,
 }
 
 impl Default for FlameGraphState {
@@ -55,9 +205,12 @@ impl Default for FlameGraphState {
             frame_width: None,
             zoom: None,
             search_pattern: None,
+            search_in_zoom: false,
             freeze: false,
             view_kind: ViewKind::FlameGraph,
             table_state: TableState::default(),
+            summary_offset: 0,
+            min_width: MinWidth::default(),
         }
     }
 }
@@ -94,10 +247,15 @@ impl FlameGraphState {
     pub fn toggle_view_kind(&mut self) {
         self.view_kind = match self.view_kind {
             ViewKind::FlameGraph => ViewKind::Table,
-            ViewKind::Table => ViewKind::FlameGraph,
+            ViewKind::Table => ViewKind::Summary,
+            ViewKind::Summary => ViewKind::FlameGraph,
         };
     }
 
+    pub fn set_view_kind(&mut self, view_kind: ViewKind) {
+        self.view_kind = view_kind;
+    }
+
     /// Update StackIdentifiers to point to the correct ones in the new flamegraph
     pub fn handle_flamegraph_replacement(&mut self, old: &FlameGraph, new: &mut FlameGraph) {
         if self.selected != ROOT_ID {
@@ -114,8 +272,8 @@ impl FlameGraphState {
                 self.unset_zoom();
             }
         }
-        // Preserve search pattern. If expensive, can move this to next flamegraph construction
-        // thread and share SearchPattern via Arc but let's keep it simple for now.
+        // Preserve search pattern. Its cached matches carry over, so only the frame names new to
+        // this flamegraph are matched against the regex.
         if let Some(p) = &self.search_pattern {
             new.set_hits(p);
         }
@@ -0,0 +1,194 @@
+use crate::flame::{library_name, source_location, FlameGraph};
+use std::collections::HashMap;
+
+/// Summary statistics of a flamegraph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub total_count: u64,
+    pub num_unique_frames: usize,
+    pub num_stacks: usize,
+    pub max_depth: usize,
+    pub num_root_children: usize,
+    /// Number of samples whose stack ends at each depth, starting from depth 1
+    pub samples_by_depth: Vec<u64>,
+    /// Modules ordered by own samples, descending
+    pub top_modules: Vec<(String, u64)>,
+}
+
+impl Summary {
+    pub fn new(flamegraph: &FlameGraph) -> Self {
+        let num_levels = flamegraph.get_num_levels();
+        let samples_by_depth = samples_by_level(flamegraph).into_iter().skip(1).collect();
+        let num_stacks = (1..num_levels)
+            .map(|level| flamegraph.get_stacks_at_level(level).unwrap().len())
+            .sum();
+
+        let function_counts = flamegraph.function_counts();
+        let mut modules: HashMap<&str, u64> = HashMap::new();
+        for (name, count) in function_counts.iter() {
+            *modules.entry(module_name(name)).or_default() += count.own;
+        }
+        let mut top_modules = modules
+            .into_iter()
+            .map(|(name, count)| (name.to_string(), count))
+            .collect::<Vec<_>>();
+        top_modules.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        Self {
+            total_count: flamegraph.total_count(),
+            num_unique_frames: function_counts.len(),
+            num_stacks,
+            max_depth: num_levels.saturating_sub(1),
+            num_root_children: flamegraph.get_children(flamegraph.root()).len(),
+            samples_by_depth,
+            top_modules,
+        }
+    }
+}
+
+/// Number of samples whose stack ends at each level. The root is at level 0.
+pub fn samples_by_level(flamegraph: &FlameGraph) -> Vec<u64> {
+    (0..flamegraph.get_num_levels())
+        .map(|level| {
+            flamegraph
+                .get_stacks_at_level(level)
+                .unwrap()
+                .iter()
+                .filter_map(|stack_id| flamegraph.get_stack(stack_id))
+                .map(|stack| stack.self_count)
+                .sum()
+        })
+        .collect()
+}
+
+/// The `n` heaviest full stacks from the root to a leaf, i.e. by the samples ending in their last
+/// frame, with their frames joined by ";".
+pub fn top_stacks(flamegraph: &FlameGraph, n: usize) -> Vec<(String, u64)> {
+    let mut stacks = (1..flamegraph.get_num_levels())
+        .flat_map(|level| flamegraph.get_stacks_at_level(level).unwrap())
+        .filter_map(|stack_id| flamegraph.get_stack(stack_id))
+        .filter(|stack| stack.self_count > 0)
+        .map(|stack| {
+            (
+                flamegraph.get_stack_full_name_from_info(stack),
+                stack.self_count,
+            )
+        })
+        .collect::<Vec<_>>();
+    stacks.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    stacks
+        .into_iter()
+        .take(n)
+        .map(|(name, count)| (name.to_string(), count))
+        .collect()
+}
+
+/// Table of the heaviest stacks with their samples and share of all samples, to print in CI
+/// logs.
+pub fn format_top_stacks(flamegraph: &FlameGraph, n: usize) -> String {
+    let total_count = flamegraph.total_count();
+    let stacks = top_stacks(flamegraph, n);
+    let width = stacks
+        .iter()
+        .map(|(_, count)| count.to_string().len())
+        .chain(["Samples".len()])
+        .max()
+        .unwrap();
+    let mut out = format!("{:>width$}  {:>7}  Stack\n", "Samples", "Percent");
+    for (name, count) in stacks {
+        let percent = 100.0 * count as f64 / total_count.max(1) as f64;
+        out += &format!("{:>width$}  {:>6.2}%  {}\n", count, percent, name);
+    }
+    out
+}
+
+/// Best effort guess of the module or file a frame belongs to, based on common frame name
+/// formats:
+///
+/// - `function (file.py:12)` as produced by py-spy gives `file.py`
+/// - `function [libc.so.6]` as produced by perf gives `libc.so.6`
+/// - `crate::module::function` gives `crate`
+/// - `library.so`function` as produced by dtrace gives `library.so`
+/// - `app.exe!std::function` as produced by Windows tools gives `app.exe`
+/// - `com.example.Class.method` gives `com.example.Class`
+pub fn module_name(name: &str) -> &str {
+    if let Some((file, _)) = source_location(name) {
+        return file;
+    }
+    if let Some(library) = library_name(name).filter(|_| !name.contains('`')) {
+        return library;
+    }
+    let trimmed = name.trim_start_matches(['<', '&', '*']);
+    if let Some((module, _)) = trimmed.split_once("::") {
+        return module;
+    }
+    if let Some((module, _)) = name.split_once('`') {
+        return module;
+    }
+    if !name.contains(' ') {
+        if let Some((module, _)) = name.rsplit_once('.') {
+            if !module.is_empty() {
+                return module;
+            }
+        }
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_name() {
+        assert_eq!(module_name("work (long_running.py:7)"), "long_running.py");
+        assert_eq!(module_name("<module> (long_running.py)"), "long_running.py");
+        assert_eq!(module_name("std::rt::lang_start"), "std");
+        assert_eq!(
+            module_name("<alloc::vec::Vec<T> as core::ops::Drop>::drop"),
+            "alloc"
+        );
+        assert_eq!(module_name("libc.so.6`__libc_start_main"), "libc.so.6");
+        assert_eq!(module_name("__libc_start_main [libc.so.6]"), "libc.so.6");
+        assert_eq!(module_name("java.lang.Thread.run"), "java.lang.Thread");
+        assert_eq!(module_name("main"), "main");
+    }
+
+    #[test]
+    fn test_summary() {
+        let content = std::fs::read_to_string("tests/data/py-spy-simple.txt").unwrap();
+        let fg = FlameGraph::from_string(content, true);
+        let summary = Summary::new(&fg);
+        assert_eq!(summary.total_count, 657);
+        assert_eq!(summary.max_depth, 2);
+        assert_eq!(summary.num_root_children, 3);
+        assert_eq!(summary.samples_by_depth, vec![1, 656]);
+        assert_eq!(
+            summary.samples_by_depth.iter().sum::<u64>(),
+            summary.total_count
+        );
+        assert_eq!(
+            summary.top_modules,
+            vec![("long_running.py".to_string(), 657)]
+        );
+    }
+
+    #[test]
+    fn test_top_stacks() {
+        let content = "main;a;b 3\nmain;a 1\nmain;c;b 3\nmain;d 10\n".to_string();
+        let fg = FlameGraph::from_string(content, false);
+        assert_eq!(
+            top_stacks(&fg, 3),
+            vec![
+                ("main;d".to_string(), 10),
+                ("main;a;b".to_string(), 3),
+                ("main;c;b".to_string(), 3),
+            ]
+        );
+        assert_eq!(top_stacks(&fg, 10).len(), 4);
+        assert_eq!(
+            format_top_stacks(&fg, 2),
+            "Samples  Percent  Stack\n     10   58.82%  main;d\n      3   17.65%  main;a;b\n"
+        );
+    }
+}
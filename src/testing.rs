@@ -0,0 +1,113 @@
+//! Drive flamelens with synthetic key events and render it into a [`TestBackend`], to write
+//! end-to-end tests of navigation and rendering without a terminal.
+
+use crate::app::{App, AppResult};
+use crate::flame::FlameGraph;
+use crate::handler::{handle_key_events, handle_paste};
+use crate::{snapshot, ui};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::backend::TestBackend;
+use ratatui::buffer::Buffer;
+use ratatui::Terminal;
+
+/// An [`App`] with a terminal of a fixed size that only exists in memory.
+pub struct TestApp {
+    pub app: App,
+    terminal: Terminal<TestBackend>,
+}
+
+impl TestApp {
+    /// Show folded stacks data, e.g. "main;a 3\nmain;b 1", in a terminal of the given size.
+    pub fn new(folded: &str, width: u16, height: u16) -> AppResult<Self> {
+        let flamegraph = FlameGraph::from_string(folded.to_string(), false);
+        Self::with_app(App::with_flamegraph("test", flamegraph), width, height)
+    }
+
+    pub fn with_app(app: App, width: u16, height: u16) -> AppResult<Self> {
+        let mut test_app = Self {
+            app,
+            terminal: Terminal::new(TestBackend::new(width, height))?,
+        };
+        // Navigation depends on the size of the flamegraph, which is known once drawn
+        test_app.render()?;
+        Ok(test_app)
+    }
+
+    /// Handle a key as if pressed, then draw the result.
+    pub fn press(&mut self, key: KeyEvent) -> AppResult<&mut Self> {
+        handle_key_events(key, &mut self.app)?;
+        self.render()?;
+        Ok(self)
+    }
+
+    /// Handle a sequence of keys without modifiers, where each character is a key, e.g. "jjl".
+    pub fn press_keys(&mut self, keys: &str) -> AppResult<&mut Self> {
+        for c in keys.chars() {
+            self.press(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))?;
+        }
+        Ok(self)
+    }
+
+    pub fn press_code(&mut self, code: KeyCode) -> AppResult<&mut Self> {
+        self.press(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    pub fn paste(&mut self, text: &str) -> AppResult<&mut Self> {
+        handle_paste(text, &mut self.app);
+        self.render()?;
+        Ok(self)
+    }
+
+    pub fn render(&mut self) -> AppResult<()> {
+        self.terminal.draw(|frame| {
+            ui::render(&mut self.app, frame);
+        })?;
+        Ok(())
+    }
+
+    /// What was last drawn, with colors and other styles.
+    pub fn buffer(&self) -> &Buffer {
+        self.terminal.backend().buffer()
+    }
+
+    /// Rows of text last drawn, without styles.
+    pub fn lines(&self) -> Vec<String> {
+        snapshot::to_lines(self.buffer())
+    }
+
+    /// Whether any row last drawn contains the text.
+    pub fn shows(&self, text: &str) -> bool {
+        self.lines().iter().any(|line| line.contains(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_navigate_and_search() {
+        let mut test_app = TestApp::new("main;parse;lex 3\nmain;render 5\n", 80, 24).unwrap();
+        assert!(test_app.shows(" all "));
+        assert!(test_app.shows(" render "));
+
+        test_app.press_keys("jj").unwrap();
+        assert_eq!(
+            test_app
+                .app
+                .flamegraph()
+                .get_stack_full_name(&test_app.app.flamegraph_state().selected),
+            Some("main;render")
+        );
+        test_app.press_keys("h").unwrap();
+        assert!(test_app.shows("parse [Total: 3 samples, 37.50% of all]"));
+
+        test_app.press_keys("/lex").unwrap();
+        test_app.press_code(KeyCode::Enter).unwrap();
+        assert!(test_app.shows("lex"));
+        assert!(test_app.app.flamegraph().hit_coverage_count() == Some(3));
+
+        test_app.press_keys("q").unwrap();
+        assert!(!test_app.app.running);
+    }
+}
@@ -1,12 +1,218 @@
 use crate::app::{App, AppResult};
+use crate::config::{GraphicsProtocol, NotificationMode};
 use crate::event::EventHandler;
-use crate::ui;
-use crossterm::event::DisableMouseCapture;
-use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crate::export::Canvas;
+use crate::self_profile;
+use crate::ui::{self, FlameGraphImage};
+use crossterm::cursor::{MoveTo, RestorePosition, SavePosition};
+use crossterm::event::{
+    DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::Print;
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen, SetTitle};
 use ratatui::backend::Backend;
 use ratatui::Terminal;
-use std::io;
+use std::backtrace::Backtrace;
+use std::fmt::Write as _;
+use std::io::{self, Write};
 use std::panic;
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+
+/// Whether the title from before was saved on the terminal's title stack, to restore it on exit
+/// including from the panic hook.
+static TITLE_SAVED: AtomicBool = AtomicBool::new(false);
+
+/// Whether keyboard enhancement flags were pushed, to pop them on exit.
+static KEYBOARD_ENHANCED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the terminal supports the kitty keyboard protocol. Asked once, as terminals that do not
+/// answer make the query wait for a timeout.
+fn supports_keyboard_enhancement() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+    *SUPPORTED.get_or_init(|| terminal::supports_keyboard_enhancement().unwrap_or(false))
+}
+
+/// OSC 8 escape sequence showing the text as a link to the URL.
+fn hyperlink_sequence(url: &str, text: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
+
+/// Id of the flamegraph image for the kitty graphics protocol, the only image flamelens shows.
+const KITTY_IMAGE_ID: u32 = 1;
+
+/// Largest chunk of base64 data in each kitty graphics escape sequence, as the protocol requires.
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+/// Pixels of each cell assumed when the terminal does not tell, which is good enough for the kitty
+/// graphics protocol as it scales images to the cells they are shown over.
+const DEFAULT_CELL_SIZE: (u16, u16) = (10, 20);
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// OSC 52 escape sequence asking the terminal to put the text in the clipboard, which also works
+/// over SSH unlike calling a clipboard program.
+fn clipboard_sequence(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64(text.as_bytes()))
+}
+
+/// Kitty graphics protocol escape sequences showing the PNG image stretched over `columns` by
+/// `rows` cells from the cursor, without moving the cursor.
+fn kitty_image_sequence(png: &[u8], columns: u16, rows: u16) -> String {
+    let encoded = base64(png);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK_SIZE).collect();
+    let mut sequence = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        // Base64 is ASCII
+        let chunk = std::str::from_utf8(chunk).unwrap();
+        if i == 0 {
+            write!(
+                sequence,
+                "\x1b_Ga=T,f=100,i={},c={},r={},C=1,q=2,m={};{}\x1b\\",
+                KITTY_IMAGE_ID, columns, rows, more, chunk
+            )
+        } else {
+            write!(sequence, "\x1b_Gm={};{}\x1b\\", more, chunk)
+        }
+        .unwrap();
+    }
+    sequence
+}
+
+/// Kitty graphics protocol escape sequence removing the image from the screen and from memory.
+fn kitty_delete_sequence() -> String {
+    format!("\x1b_Ga=d,d=I,i={},q=2\x1b\\", KITTY_IMAGE_ID)
+}
+
+/// Sixel escape sequence drawing the image from the cursor. Colors are rounded to a 6x6x6 cube, as
+/// terminals only have so many color registers.
+fn sixel_sequence(canvas: &Canvas) -> String {
+    let level = |c: u8| (c as usize * 5 + 127) / 255;
+    let registers: Vec<usize> = canvas
+        .pixels
+        .chunks(3)
+        .map(|rgb| level(rgb[0]) * 36 + level(rgb[1]) * 6 + level(rgb[2]))
+        .collect();
+    let mut sequence = format!("\x1bP0;1;0q\"1;1;{};{}", canvas.width, canvas.height);
+    for register in 0..216 {
+        let (r, g, b) = (register / 36, register / 6 % 6, register % 6);
+        write!(sequence, "#{};2;{};{};{}", register, r * 20, g * 20, b * 20).unwrap();
+    }
+    // Each band of six rows is drawn once per color in it, a character per column telling which
+    // of the six pixels have that color
+    for band_top in (0..canvas.height).step_by(6) {
+        let mut bands: Vec<Option<Vec<u8>>> = vec![None; 216];
+        for row in band_top..(band_top + 6).min(canvas.height) {
+            for column in 0..canvas.width {
+                let register = registers[row * canvas.width + column];
+                bands[register].get_or_insert_with(|| vec![0; canvas.width])[column] |=
+                    1 << (row - band_top);
+            }
+        }
+        let mut first = true;
+        for (register, band) in bands.iter().enumerate() {
+            let Some(band) = band else {
+                continue;
+            };
+            if !first {
+                // Back to the start of the band
+                sequence.push('$');
+            }
+            first = false;
+            write!(sequence, "#{}", register).unwrap();
+            for run in band.chunk_by(|a, b| a == b) {
+                let c = char::from(63 + run[0]);
+                if run.len() > 3 {
+                    write!(sequence, "!{}{}", run.len(), c).unwrap();
+                } else {
+                    sequence.extend(std::iter::repeat_n(c, run.len()));
+                }
+            }
+        }
+        sequence.push('-');
+    }
+    sequence.push_str("\x1b\\");
+    sequence
+}
+
+/// Pixels of each cell of the terminal, if it tells.
+fn cell_size() -> Option<(u16, u16)> {
+    let size = terminal::window_size().ok()?;
+    if size.width == 0 || size.height == 0 || size.columns == 0 || size.rows == 0 {
+        return None;
+    }
+    Some((size.width / size.columns, size.height / size.rows))
+}
+
+/// Escape sequence for a desktop notification, or `None` if notifications are off.
+fn notification_sequence(mode: NotificationMode, text: &str) -> Option<String> {
+    // Control characters would end the sequence early
+    let text: String = text.chars().filter(|c| !c.is_control()).collect();
+    match mode {
+        NotificationMode::Off => None,
+        NotificationMode::Osc9 => Some(format!("\x1b]9;flamelens: {}\x07", text)),
+        NotificationMode::Osc777 => Some(format!("\x1b]777;notify;flamelens;{}\x07", text)),
+    }
+}
+
+/// Context printed along with a panic, and saved to a report file to attach to bug reports.
+#[derive(Debug, Clone, Default)]
+pub struct CrashReport {
+    /// What is being viewed, e.g. the input file
+    pub input: String,
+    /// Command line arguments
+    pub args: Vec<String>,
+    /// Where to save the report
+    pub path: Option<PathBuf>,
+}
+
+impl CrashReport {
+    pub fn new(input: String) -> Self {
+        Self {
+            input,
+            args: std::env::args().collect(),
+            path: Some(
+                std::env::temp_dir().join(format!("flamelens-crash-{}.txt", std::process::id())),
+            ),
+        }
+    }
+
+    fn render(&self, message: &str, location: &str, backtrace: &str) -> String {
+        format!(
+            "flamelens v{} panicked at {}:\n{}\n\nInput: {}\nArguments: {}\n\nBacktrace:\n{}\n",
+            env!("CARGO_PKG_VERSION"),
+            location,
+            message,
+            self.input,
+            self.args.join(" "),
+            backtrace,
+        )
+    }
+}
 
 /// Representation of a terminal user interface.
 ///
@@ -18,27 +224,62 @@ pub struct Tui<B: Backend> {
     terminal: Terminal<B>,
     /// Terminal event handler.
     pub events: EventHandler,
+    /// Title set for the terminal window, if any.
+    title: Option<String>,
+    /// Flamegraph image on the screen, if any, and how it was drawn.
+    image: Option<(GraphicsProtocol, FlameGraphImage)>,
 }
 
 impl<B: Backend> Tui<B> {
     /// Constructs a new instance of [`Tui`].
     pub fn new(terminal: Terminal<B>, events: EventHandler) -> Self {
-        Self { terminal, events }
+        Self {
+            terminal,
+            events,
+            title: None,
+            image: None,
+        }
     }
 
     /// Initializes the terminal interface.
     ///
     /// It enables the raw mode and sets terminal properties.
-    pub fn init(&mut self) -> AppResult<()> {
-        terminal::enable_raw_mode()?;
-        crossterm::execute!(io::stderr(), EnterAlternateScreen)?;
+    pub fn init(&mut self, crash_report: CrashReport) -> AppResult<()> {
+        Self::enter()?;
 
         // Define a custom panic hook to reset the terminal properties.
         // This way, you won't have your terminal messed up if an unexpected error happens.
         let panic_hook = panic::take_hook();
         panic::set_hook(Box::new(move |panic| {
             Self::reset().expect("failed to reset the terminal");
+            crossterm::execute!(io::stderr(), crossterm::cursor::Show).ok();
             panic_hook(panic);
+
+            let message = panic
+                .payload()
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.payload().downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            let location = panic.location().map(|l| l.to_string()).unwrap_or_default();
+            let backtrace = Backtrace::force_capture().to_string();
+            let report = crash_report.render(&message, &location, &backtrace);
+            eprintln!("\nInput: {}", crash_report.input);
+            if let Some(path) = &crash_report.path {
+                if std::fs::write(path, report).is_ok() {
+                    eprintln!(
+                        "A crash report was saved to {}. Please attach it when reporting the issue at {}/issues",
+                        path.display(),
+                        env!("CARGO_PKG_REPOSITORY")
+                    );
+                }
+            }
+
+            // A panic in a background thread, e.g. the sampler, would otherwise leave the app
+            // running on a terminal that is no longer set up for it
+            if thread::current().name() != Some("main") {
+                std::process::exit(101);
+            }
         }));
 
         self.terminal.hide_cursor()?;
@@ -46,19 +287,167 @@ impl<B: Backend> Tui<B> {
         Ok(())
     }
 
+    /// Suspends the process to the shell, restoring the terminal first so that the shell is
+    /// usable. Once continued, e.g. with `fg`, SIGCONT arrives as [`Event::Resume`], whose handling
+    /// calls [`Self::resume`] to set the terminal up again. That is the only place doing so, as
+    /// setting it up here too would do it twice.
+    ///
+    /// [`Event::Resume`]: crate::event::Event::Resume
+    pub fn suspend(&mut self) -> AppResult<()> {
+        #[cfg(unix)]
+        {
+            self.exit()?;
+            signal_hook::low_level::emulate_default_handler(signal_hook::consts::SIGTSTP)?;
+        }
+        Ok(())
+    }
+
+    /// Runs an interactive program such as an editor in the terminal, restoring the terminal
+    /// while it runs and setting it up again once it exits. Failing to run the program is
+    /// returned separately from failing to handle the terminal.
+    pub fn run_interactive(&mut self, command: &mut Command) -> AppResult<io::Result<ExitStatus>> {
+        // Otherwise the event handler would take input meant for the program
+        self.events.pause();
+        self.exit()?;
+        let status = command.status();
+        self.resume()?;
+        self.events.unpause();
+        Ok(status)
+    }
+
+    /// Sets the terminal up again after the process was continued, as the shell may have reset
+    /// it in the meantime, and redraws everything on the next draw.
+    pub fn resume(&mut self) -> AppResult<()> {
+        Self::enter()?;
+        // Gone along with the alternate screen
+        self.image = None;
+        if let Some(title) = self.title.take() {
+            self.set_title(&title)?;
+        }
+        self.terminal.hide_cursor()?;
+        self.terminal.clear()?;
+        Ok(())
+    }
+
+    /// Sets the title of the terminal window. The previous title is restored on exit.
+    pub fn set_title(&mut self, title: &str) -> AppResult<()> {
+        if !TITLE_SAVED.swap(true, Ordering::Relaxed) {
+            // Push the current title on the xterm title stack
+            write!(io::stderr(), "\x1b[22;0t")?;
+        }
+        crossterm::execute!(io::stderr(), SetTitle(title))?;
+        self.title = Some(title.to_string());
+        Ok(())
+    }
+
+    /// Sends a desktop notification through the terminal.
+    pub fn notify(&mut self, mode: NotificationMode, text: &str) -> AppResult<()> {
+        if let Some(sequence) = notification_sequence(mode, text) {
+            let mut stderr = io::stderr();
+            write!(stderr, "{}", sequence)?;
+            stderr.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn copy_to_clipboard(&mut self, text: &str) -> AppResult<()> {
+        let mut stderr = io::stderr();
+        write!(stderr, "{}", clipboard_sequence(text))?;
+        stderr.flush()?;
+        Ok(())
+    }
+
     /// [`Draw`] the terminal interface by [`rendering`] the widgets.
     ///
     /// [`Draw`]: ratatui::Terminal::draw
     /// [`rendering`]: crate::ui::render
     pub fn draw(&mut self, app: &mut App) -> AppResult<()> {
+        let _phase = self_profile::phase("render");
+        let protocol = app.config.graphics;
+        app.cell_size = match protocol {
+            GraphicsProtocol::Off => None,
+            GraphicsProtocol::Kitty => Some(cell_size().unwrap_or(DEFAULT_CELL_SIZE)),
+            // Sixel images are as large as their pixels
+            GraphicsProtocol::Sixel => cell_size(),
+        };
+        let mut overlays = ui::Overlays::default();
         self.terminal.draw(|frame| {
-            ui::render(app, frame);
+            overlays = ui::render(app, frame);
             if let Some(input_buffer) = &app.input_buffer {
                 if let Some(cursor) = input_buffer.cursor {
                     frame.set_cursor(cursor.0, cursor.1);
                 }
             }
         })?;
+        let image = overlays.image.map(|image| (protocol, image));
+        if image != self.image {
+            if let (Some((GraphicsProtocol::Sixel, _)), None) = (&self.image, &image) {
+                // Sixel images replace the cells they are drawn over, which are only drawn again
+                // once ratatui no longer expects them to be on the screen
+                self.image = None;
+                self.terminal.clear()?;
+                return self.draw(app);
+            }
+            self.draw_image(image)?;
+        }
+        if let Some(hyperlink) = overlays.hyperlink {
+            // Written over the same text, keeping the cursor where it was
+            let mut stderr = io::stderr();
+            crossterm::queue!(
+                stderr,
+                SavePosition,
+                MoveTo(hyperlink.x, hyperlink.y),
+                Print(hyperlink_sequence(&hyperlink.url, &hyperlink.text)),
+                RestorePosition
+            )?;
+            stderr.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Shows the flamegraph image in place of the one on the screen, if any.
+    fn draw_image(&mut self, image: Option<(GraphicsProtocol, FlameGraphImage)>) -> AppResult<()> {
+        let mut stderr = io::stderr();
+        if let Some((GraphicsProtocol::Kitty, _)) = self.image.take() {
+            write!(stderr, "{}", kitty_delete_sequence())?;
+        }
+        if let Some((protocol, image)) = &image {
+            let sequence = match protocol {
+                GraphicsProtocol::Off => String::new(),
+                GraphicsProtocol::Kitty => kitty_image_sequence(
+                    &image.canvas.to_png()?,
+                    image.area.width,
+                    image.area.height,
+                ),
+                GraphicsProtocol::Sixel => sixel_sequence(&image.canvas),
+            };
+            crossterm::queue!(
+                stderr,
+                SavePosition,
+                MoveTo(image.area.x, image.area.y),
+                Print(sequence),
+                RestorePosition
+            )?;
+        }
+        stderr.flush()?;
+        self.image = image;
+        Ok(())
+    }
+
+    /// Enables the raw mode and switches to the alternate screen.
+    fn enter() -> AppResult<()> {
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(io::stderr(), EnterAlternateScreen, EnableBracketedPaste)?;
+        // Tells apart keys such as `Shift-Enter` from `Enter`, and `Esc` from the start of an
+        // escape sequence. Only pushed once, as reset() pops once, e.g. when continued after
+        // SIGSTOP without having reset the terminal
+        if !KEYBOARD_ENHANCED.load(Ordering::Relaxed) && supports_keyboard_enhancement() {
+            crossterm::execute!(
+                io::stderr(),
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+            )?;
+            KEYBOARD_ENHANCED.store(true, Ordering::Relaxed);
+        }
         Ok(())
     }
 
@@ -67,8 +456,20 @@ impl<B: Backend> Tui<B> {
     /// This function is also used for the panic hook to revert
     /// the terminal properties if unexpected errors occur.
     fn reset() -> AppResult<()> {
+        if KEYBOARD_ENHANCED.swap(false, Ordering::Relaxed) {
+            crossterm::execute!(io::stderr(), PopKeyboardEnhancementFlags)?;
+        }
         terminal::disable_raw_mode()?;
-        crossterm::execute!(io::stderr(), LeaveAlternateScreen, DisableMouseCapture)?;
+        crossterm::execute!(
+            io::stderr(),
+            LeaveAlternateScreen,
+            DisableMouseCapture,
+            DisableBracketedPaste
+        )?;
+        if TITLE_SAVED.swap(false, Ordering::Relaxed) {
+            // Pop the title from before off the xterm title stack
+            write!(io::stderr(), "\x1b[23;0t")?;
+        }
         Ok(())
     }
 
@@ -76,8 +477,71 @@ impl<B: Backend> Tui<B> {
     ///
     /// It disables the raw mode and reverts back the terminal properties.
     pub fn exit(&mut self) -> AppResult<()> {
+        if let Some((GraphicsProtocol::Kitty, _)) = self.image {
+            write!(io::stderr(), "{}", kitty_delete_sequence())?;
+        }
         Self::reset()?;
         self.terminal.show_cursor()?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notification_sequence() {
+        assert_eq!(notification_sequence(NotificationMode::Off, "done"), None);
+        assert_eq!(
+            notification_sequence(NotificationMode::Osc9, "done"),
+            Some("\x1b]9;flamelens: done\x07".to_string())
+        );
+        assert_eq!(
+            notification_sequence(NotificationMode::Osc777, "bad\x07 name\n"),
+            Some("\x1b]777;notify;flamelens;bad name\x07".to_string())
+        );
+    }
+
+    #[test]
+    fn test_clipboard_sequence() {
+        assert_eq!(clipboard_sequence(""), "\x1b]52;c;\x07");
+        assert_eq!(clipboard_sequence("f"), "\x1b]52;c;Zg==\x07");
+        assert_eq!(clipboard_sequence("fo"), "\x1b]52;c;Zm8=\x07");
+        assert_eq!(clipboard_sequence("foo\tbar"), "\x1b]52;c;Zm9vCWJhcg==\x07");
+    }
+
+    #[test]
+    fn test_kitty_image_sequence() {
+        assert_eq!(
+            kitty_image_sequence(b"png", 80, 20),
+            "\x1b_Ga=T,f=100,i=1,c=80,r=20,C=1,q=2,m=0;cG5n\x1b\\"
+        );
+        // Split into chunks, all but the last one saying more is coming
+        let sequence = kitty_image_sequence(&[0; 4000], 80, 20);
+        let chunks: Vec<&str> = sequence.split_terminator("\x1b\\").collect();
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].starts_with("\x1b_Ga=T,f=100,i=1,c=80,r=20,C=1,q=2,m=1;AAAA"));
+        assert_eq!(
+            chunks[0].len() - chunks[0].find(';').unwrap() - 1,
+            KITTY_CHUNK_SIZE
+        );
+        assert!(chunks[1].starts_with("\x1b_Gm=0;AAAA"));
+    }
+
+    #[test]
+    fn test_sixel_sequence() {
+        let mut canvas = Canvas::new(5, 2);
+        canvas.fill_rect(0, 0, 5, 1, [255, 0, 0]);
+        canvas.fill_rect(0, 1, 5, 1, [0, 0, 250]);
+        let sequence = sixel_sequence(&canvas);
+        assert!(sequence.starts_with("\x1bP0;1;0q\"1;1;5;2#0;2;0;0;0#1;2;0;0;20"));
+        assert!(sequence.contains("#180;2;100;0;0"));
+        // Blue in the second row and red in the first, each run of them repeated
+        assert!(sequence.ends_with("#5!5A$#180!5@-\x1b\\"));
+
+        // A band every six rows
+        let canvas = Canvas::new(3, 7);
+        assert!(sixel_sequence(&canvas).ends_with("#215~~~-#215@@@-\x1b\\"));
+    }
+}
@@ -1,9 +1,15 @@
-#[cfg(feature = "python")]
-use crate::py_spy::SamplerStatus;
 use crate::{
-    app::{App, FlameGraphInput},
-    flame::{SortColumn, StackIdentifier, StackInfo},
-    state::ViewKind,
+    anonymize::fnv1a_seeded,
+    app::{App, FlameGraphInput, HintsMode, InputKind},
+    archive::SnapshotBrowser,
+    config::{expand_frame_label, Palette},
+    editor::source_link,
+    export::Canvas,
+    flame::{is_kernel_frame, SortColumn, StackIdentifier, StackInfo, TableRow, ROOT_ID},
+    memory::{format_memory, resident_memory},
+    sampler::SamplerStatus,
+    state::{MinWidth, ProcessSelector, ViewKind, ZoomState},
+    summary::samples_by_level,
 };
 use ratatui::{
     buffer::Buffer,
@@ -11,21 +17,39 @@ use ratatui::{
     style::{Color, Modifier, Style, Stylize},
     text::{Line, Span, Text},
     widgets::{
-        block::Position, Block, Borders, Paragraph, Row, StatefulWidget, Table, TableState, Widget,
-        Wrap,
+        block::Position, Block, Borders, Clear, Paragraph, Row, Scrollbar, ScrollbarOrientation,
+        ScrollbarState, StatefulWidget, Table, TableState, Widget, Wrap,
     },
     Frame,
 };
+use std::sync::Arc;
 use std::time::Duration;
-use std::{
-    collections::hash_map::DefaultHasher,
-    hash::{Hash, Hasher},
-};
 
 const SEARCH_PREFIX: &str = "";
 const COLOR_SELECTED_STACK: Color = Color::Rgb(250, 250, 250);
 const COLOR_MATCHED_BACKGROUND: Color = Color::Rgb(10, 35, 150);
 const COLOR_TABLE_SELECTED_ROW: Color = Color::Rgb(65, 65, 65);
+const COLOR_SELECTED_STACK_LIGHT: Color = Color::Rgb(30, 30, 30);
+const COLOR_TABLE_SELECTED_ROW_LIGHT: Color = Color::Rgb(205, 205, 205);
+/// Frames that grew and shrunk when comparing, in the colorblind palette
+const COLOR_GROWN_COLORBLIND: [u8; 3] = [230, 120, 0];
+const COLOR_SHRUNK_COLORBLIND: [u8; 3] = [0, 110, 220];
+const SUMMARY_NUM_TOP_MODULES: usize = 10;
+const SUMMARY_BAR_WIDTH: usize = 40;
+const MAX_TOP_THREAD_WIDTH: u16 = 40;
+const DEPTH_HISTOGRAM_WIDTH: u16 = 24;
+const MESSAGE_LOG_MAX_WIDTH: u16 = 100;
+const COLOR_HISTOGRAM_BAR: Color = Color::Rgb(225, 120, 10);
+const COLOR_OWN_TIME: Color = Color::Rgb(140, 0, 0);
+const COLOR_CODE_KIND_BOUNDARY: Color = Color::Rgb(90, 90, 90);
+const COLOR_RULER: Color = Color::DarkGray;
+/// Shares of the flamegraph width marked by the ruler, in percent.
+const RULER_MARKS: [u16; 3] = [25, 50, 75];
+/// Blocks filling a cell from the left in eighths, for frames narrower than a cell
+const SUB_CELL_BLOCKS: [&str; 8] = ["▏", "▎", "▍", "▌", "▋", "▊", "▉", "█"];
+/// How long the "Selected" bar stays on each line when scrolling through a long text
+const SELECTED_SCROLL_INTERVAL: Duration = Duration::from_millis(1500);
+const COLOR_WARNING: Color = Color::Rgb(225, 10, 10);
 
 #[derive(Debug, Clone, Default)]
 pub struct FlamelensWidgetState {
@@ -33,11 +57,86 @@ pub struct FlamelensWidgetState {
     frame_width: u16,
     render_time: Duration,
     cursor_position: Option<(u16, u16)>,
+    summary_num_lines: usize,
+    /// Where the name of the selected frame starts in the "Selected" bar
+    selected_name_area: Option<Rect>,
+    rendered_flamegraph: Option<RenderedFlameGraph>,
+    /// Flamegraph drawn as an image, unless something is drawn over it
+    image: Option<FlameGraphImage>,
+    /// Whether the "Selected" bar only shows part of its text
+    selected_overflows: bool,
+}
+
+/// Everything the flamegraph body is drawn from, to tell when the last drawing is still valid.
+#[derive(Debug, Clone, PartialEq)]
+struct FlameGraphRenderKey {
+    area: Rect,
+    updated_at: std::time::Instant,
+    selected: StackIdentifier,
+    level_offset: usize,
+    zoom: Option<StackIdentifier>,
+    search: Option<(String, bool)>,
+    search_in_zoom: bool,
+    min_width: MinWidth,
+    show_own_time: bool,
+    show_baseline: bool,
+    show_gil: bool,
+    palette: Palette,
+    /// Frames with a note, which get a marker
+    notes: Vec<String>,
+    /// Pixels of each cell, if the flamegraph is also drawn as an image
+    cell_size: Option<(u16, u16)>,
+}
+
+/// The flamegraph body as last drawn, reused as long as nothing it is drawn from has changed, so
+/// that redrawing for e.g. an expiring message or a ticking sampler does not walk all the frames.
+#[derive(Debug, Clone)]
+pub struct RenderedFlameGraph {
+    key: FlameGraphRenderKey,
+    buffer: Buffer,
+    image: Option<Arc<Canvas>>,
+}
+
+/// The flamegraph drawn as an image, to show over the cells of `area` with a terminal graphics
+/// protocol.
+#[derive(Debug, Clone)]
+pub struct FlameGraphImage {
+    pub area: Rect,
+    pub canvas: Arc<Canvas>,
+}
+
+impl PartialEq for FlameGraphImage {
+    /// Images are only drawn again when the flamegraph is, so there is no need to compare pixels.
+    fn eq(&self, other: &Self) -> bool {
+        self.area == other.area && Arc::ptr_eq(&self.canvas, &other.canvas)
+    }
 }
 
-pub struct ZoomState {
-    pub zoom_stack: StackIdentifier,
-    pub ancestors: Vec<StackIdentifier>,
+/// What is drawn over the text once ratatui has drawn it, as ratatui cannot draw it.
+#[derive(Debug, Clone, Default)]
+pub struct Overlays {
+    /// Link to the source of the selected frame, over its name
+    pub hyperlink: Option<Hyperlink>,
+    pub image: Option<FlameGraphImage>,
+}
+
+/// Text already drawn on the screen to turn into a hyperlink, as ratatui cannot draw them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hyperlink {
+    pub x: u16,
+    pub y: u16,
+    pub text: String,
+    pub url: String,
+}
+
+/// Frames left out of the flamegraph for being narrower than the minimum width.
+#[derive(Debug)]
+struct CulledFrames {
+    /// Minimum width in cells, at least one
+    min_width: f64,
+    num_frames: usize,
+    /// Samples of the culled frames, including their descendants
+    count: u64,
 }
 
 pub struct FlamelensWidget<'a> {
@@ -75,8 +174,15 @@ impl<'a> FlamelensWidget<'a> {
         let header_line_count_with_borders = header.line_count(filename_width) as u16 + 2;
 
         // Context such as search, selected stack, etc.
-        let context_bars = self
-            .get_status_text(area.width)
+        let mut status_text = self.get_status_text(area.width);
+        if self.app.zen {
+            // Still shown, as they would otherwise go unnoticed
+            status_text.retain(|(title, _)| self.app.input_buffer.is_some() || *title == "Info");
+        }
+        let selected_bar = status_text
+            .iter()
+            .position(|(title, _)| *title == "Selected");
+        let context_bars = status_text
             .iter()
             .map(|(title, line)| {
                 Paragraph::new(line.clone())
@@ -93,7 +199,7 @@ impl<'a> FlamelensWidget<'a> {
 
         // Help tags to be displayed at the bottom
         let help_tags = self.get_help_tags();
-        let help_bar = Paragraph::new(help_tags.get_line())
+        let help_bar = Paragraph::new(help_tags.get_line(self.app.hints, area.width))
             .block(
                 Block::new()
                     .borders(Borders::TOP)
@@ -102,19 +208,35 @@ impl<'a> FlamelensWidget<'a> {
             .alignment(Alignment::Center);
 
         let mut constraints = vec![
-            Constraint::Length(header_line_count_with_borders),
+            Constraint::Length(if self.app.zen {
+                0
+            } else {
+                header_line_count_with_borders
+            }),
             Constraint::Fill(1),
         ];
 
         // Constraints for context bars
         let context_bar_index_start = constraints.len();
-        for bar in context_bars.iter() {
-            constraints.push(Constraint::Length(bar.line_count(area.width) as u16 + 1));
+        let max_selected_lines = self.app.config.selected_max_lines;
+        let mut selected_scroll = 0;
+        for (i, bar) in context_bars.iter().enumerate() {
+            let mut num_lines = bar.line_count(area.width) as u16;
+            if selected_bar == Some(i) && max_selected_lines > 0 && num_lines > max_selected_lines {
+                selected_scroll = self.get_selected_scroll(num_lines - max_selected_lines);
+                state.selected_overflows = true;
+                num_lines = max_selected_lines;
+            }
+            constraints.push(Constraint::Length(num_lines + 1));
         }
 
         // Constraint for help bar
-        constraints.push(Constraint::Length(2));
+        let show_help_bar = self.app.hints != HintsMode::Off && !self.app.zen;
+        if show_help_bar {
+            constraints.push(Constraint::Length(2));
+        }
         let help_bar_index = constraints.len() - 1;
+        let status_bar_index = constraints.len() - 1 - show_help_bar as usize;
 
         let layout = Layout::default()
             .direction(Direction::Vertical)
@@ -130,80 +252,351 @@ impl<'a> FlamelensWidget<'a> {
                 Constraint::Length(version_indicator.width() as u16),
             ])
             .split(layout[0]);
-        let header_block = Block::default().borders(Borders::BOTTOM | Borders::TOP);
-        let header_offset = Offset { x: 0, y: 1 };
-        header_block.render(layout[0], buf);
-        view_kind_indicator.render(header_layout[0].offset(header_offset), buf);
-        header.render(header_layout[1].offset(header_offset), buf);
-        version_indicator.render(header_layout[2].offset(header_offset), buf);
+        if !self.app.zen {
+            let header_block = Block::default().borders(Borders::BOTTOM | Borders::TOP);
+            let header_offset = Offset { x: 0, y: 1 };
+            header_block.render(layout[0], buf);
+            view_kind_indicator.render(header_layout[0].offset(header_offset), buf);
+            header.render(header_layout[1].offset(header_offset), buf);
+            version_indicator.render(header_layout[2].offset(header_offset), buf);
+        }
 
         // Main area for flamegraph / top view
         let tic = std::time::Instant::now();
-        let main_area = layout[1];
-        if self.is_flamegraph_view() {
-            self.render_flamegraph(main_area, buf)
-        } else {
-            self.render_table(main_area, buf);
-            false
-        };
+        let mut main_area = layout[1];
+        if self.app.show_notes {
+            let notes_layout = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(vec![
+                    Constraint::Fill(1),
+                    Constraint::Length(std::cmp::min(main_area.width / 3, 60)),
+                ])
+                .split(main_area);
+            main_area = notes_layout[0];
+            self.render_notes(notes_layout[1], buf);
+        }
+        if self.app.show_depth_histogram && self.is_flamegraph_view() {
+            let histogram_layout = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(vec![
+                    Constraint::Fill(1),
+                    Constraint::Length(DEPTH_HISTOGRAM_WIDTH),
+                ])
+                .split(main_area);
+            main_area = histogram_layout[0];
+            let num_levels = main_area.height / self.app.config.row_height();
+            self.render_depth_histogram(histogram_layout[1], num_levels, buf);
+        }
+        let ruler_area = (self.app.show_ruler && self.is_flamegraph_view() && main_area.height > 1)
+            .then(|| {
+                let ruler_area = Rect {
+                    height: 1,
+                    ..main_area
+                };
+                main_area.y += 1;
+                main_area.height -= 1;
+                ruler_area
+            });
+        match self.view_kind() {
+            ViewKind::FlameGraph => {
+                self.render_flamegraph_or_reuse(main_area, buf, state);
+                if let Some(ruler_area) = ruler_area {
+                    self.render_ruler(ruler_area, main_area, buf);
+                }
+            }
+            ViewKind::Table => {
+                self.render_table(main_area, buf);
+            }
+            ViewKind::Summary => {
+                state.summary_num_lines = self.render_summary(main_area, buf);
+            }
+        }
         let flamegraph_render_time = tic.elapsed();
 
         // Context bars
-        for (i, bar) in context_bars.iter().enumerate() {
+        for (i, bar) in context_bars.into_iter().enumerate() {
+            let bar = if selected_bar == Some(i) {
+                bar.scroll((selected_scroll, 0))
+            } else {
+                bar
+            };
             bar.render(layout[context_bar_index_start + i], buf);
         }
 
+        if self.app.debug && self.is_flamegraph_view() {
+            self.render_placement(main_area, buf);
+        }
+
+        if self.app.messages.show_log {
+            self.render_message_log(layout[1], buf);
+        }
+
+        if let Some(browser) = &self.app.snapshot_browser {
+            self.render_snapshot_browser(browser, layout[1], buf);
+        }
+
+        if let Some(selector) = &self.app.process_selector {
+            self.render_process_selector(selector, layout[1], buf);
+        }
+
+        // The image would hide what is drawn over the flamegraph
+        if self.app.debug
+            || self.app.messages.show_log
+            || self.app.snapshot_browser.is_some()
+            || self.app.process_selector.is_some()
+        {
+            state.image = None;
+        }
+
         // Help bar
-        help_bar.render(layout[help_bar_index], buf);
+        if show_help_bar {
+            help_bar.render(layout[help_bar_index], buf);
+        }
 
         // Update widget state
-        state.frame_height = main_area.height;
+        // The flamegraph view moves by levels, which may be several rows high
+        state.frame_height = if self.is_flamegraph_view() {
+            (main_area.height / self.app.config.row_height()).max(1)
+        } else {
+            main_area.height
+        };
         state.frame_width = main_area.width;
         state.render_time = flamegraph_render_time;
-        state.cursor_position = self.get_cursor_position(layout[help_bar_index - 1]);
+        state.cursor_position = self.get_cursor_position(layout[status_bar_index]);
+        state.selected_name_area = selected_bar.map(|i| {
+            let bar_area = layout[context_bar_index_start + i];
+            // Below the title border
+            Rect::new(bar_area.x, bar_area.y + 1, bar_area.width, 1)
+        });
     }
 
     fn get_help_tags(&self) -> HelpTags {
         let mut help_tags = HelpTags::new();
-        if self.is_flamegraph_view() {
-            help_tags.add("hjkl", "move cursor");
+        if let Some(input) = &self.app.input_buffer {
+            // Only the keys that leave the prompt apply while typing
+            help_tags.default.clear();
+            if input.kind == InputKind::TableFilter {
+                help_tags.add_essential("enter", "keep filter");
+                help_tags.add_essential("esc", "clear filter");
+            } else {
+                help_tags.add_essential("enter", "confirm");
+                help_tags.add_essential("esc", "cancel");
+            }
+        } else if self.app.messages.show_log {
+            help_tags.default.clear();
+            help_tags.add_essential("j/k", "scroll");
+            help_tags.add_essential("esc", "close");
+        } else if self.app.snapshot_browser.is_some() {
+            help_tags.default.clear();
+            help_tags.add_essential("j/k", "move cursor");
+            help_tags.add_essential("enter", "load");
+            help_tags.add_essential("esc", "close");
+        } else if self.app.process_selector.is_some() {
+            help_tags.default.clear();
+            help_tags.add_essential("j/k", "move cursor");
+            help_tags.add_essential("enter", "zoom");
+            help_tags.add_essential("esc", "close");
+        } else if self.app.pending_key == Some('z') {
+            help_tags.default.clear();
+            help_tags.add_essential("z", "center");
+            help_tags.add_essential("t", "top");
+            help_tags.add_essential("b", "bottom");
+        } else if self.is_flamegraph_view() {
+            help_tags.add_essential("hjkl", "move cursor");
             help_tags.add("f/b", "scroll");
-            help_tags.add("enter/esc", "zoom");
-            help_tags.add("/", "search");
+            help_tags.add_essential("enter/esc", "zoom");
+            help_tags.add_essential("/", "search");
             help_tags.add("#", "search like cursor");
+            help_tags.add("c", "callers");
             if let Some(p) = &self.app.flamegraph_state().search_pattern {
                 if p.is_manual {
-                    help_tags.add("n/N", "next/prev search");
+                    help_tags.add_essential("n/N", "next/prev search");
                 }
             }
-            #[cfg(feature = "python")]
-            if let FlameGraphInput::Pid(_, _) = self.app.flamegraph_input {
+            if self.app.is_live() {
                 if self.app.flamegraph_state().freeze {
-                    help_tags.add("z", "unfreeze");
+                    help_tags.add_essential("z", "unfreeze");
                 } else {
-                    help_tags.add("z", "freeze");
+                    help_tags.add_essential("z", "freeze");
                 }
+                help_tags.add("D", "latest interval");
+                help_tags.add("W", "GIL");
             }
-        } else {
-            help_tags.add("j/k", "move cursor");
+            if self.app.baseline.is_some() {
+                help_tags.add("B", "baseline");
+            }
+            if !self.app.flamegraph().processes().is_empty() {
+                help_tags.add("p", "processes");
+            }
+            if self.app.has_activity_frames() {
+                help_tags.add("I", "on-CPU/waiting");
+            }
+            if self.app.is_aggregating_lines() {
+                help_tags.add("+", "expand lines");
+                help_tags.add("F", "per line");
+            }
+            if !self.app.flamegraph().thread_frames().is_empty() {
+                help_tags.add("{/}", "threads");
+                help_tags.add("T", "focus thread");
+            }
+        } else if self.is_table_view() {
+            help_tags.add_essential("j/k", "move cursor");
             help_tags.add("f/b", "scroll");
-            help_tags.add("1", "sort by total");
+            help_tags.add("home/end", "first/last");
+            help_tags.add_essential("1", "sort by total");
             help_tags.add("2", "sort by own");
-            help_tags.add("/", "filter");
+            help_tags.add("3", "sort by name");
+            help_tags.add("4", "sort by call sites");
+            if self.app.flamegraph().ordered_stacks.baseline.is_some() {
+                help_tags.add("5", "sort by delta");
+            }
+            help_tags.add_essential("/", "search");
+            help_tags.add("F", "filter table");
+            help_tags.add("m", "group by module");
+            help_tags.add("Z", "zoom to heaviest");
+            help_tags.add("t", "per-thread");
+            help_tags.add("c", "callers");
+        } else {
+            help_tags.add_essential("j/k", "scroll");
+            help_tags.add("f/b", "page");
         }
         help_tags
     }
 
+    fn render_flamegraph_or_reuse(
+        &self,
+        area: Rect,
+        buf: &mut Buffer,
+        state: &mut FlamelensWidgetState,
+    ) {
+        let flamegraph_state = self.app.flamegraph_state();
+        let key = FlameGraphRenderKey {
+            area,
+            updated_at: self.app.flamegraph_view.updated_at,
+            selected: flamegraph_state.selected,
+            level_offset: flamegraph_state.level_offset,
+            zoom: flamegraph_state.zoom.as_ref().map(|zoom| zoom.stack_id),
+            search: flamegraph_state
+                .search_pattern
+                .as_ref()
+                .map(|p| (p.pattern.clone(), p.is_manual)),
+            search_in_zoom: flamegraph_state.search_in_zoom,
+            min_width: flamegraph_state.min_width,
+            show_own_time: self.app.show_own_time,
+            show_baseline: self.app.is_showing_baseline(),
+            show_gil: self.app.is_showing_gil(),
+            palette: self.app.config.palette,
+            notes: self.app.notes.notes.keys().cloned().collect(),
+            cell_size: self.app.cell_size,
+        };
+        let (buffer, image) = match state.rendered_flamegraph.take() {
+            Some(rendered) if rendered.key == key => (rendered.buffer, rendered.image),
+            _ => {
+                let mut buffer = Buffer::empty(area);
+                self.render_flamegraph(area, &mut buffer);
+                let image = self
+                    .app
+                    .cell_size
+                    .map(|cell_size| Arc::new(self.render_flamegraph_image(area, cell_size)));
+                (buffer, image)
+            }
+        };
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                *buf.get_mut(x, y) = buffer.get(x, y).clone();
+            }
+        }
+        state.image = image.clone().map(|canvas| FlameGraphImage { area, canvas });
+        state.rendered_flamegraph = Some(RenderedFlameGraph { key, buffer, image });
+    }
+
+    /// The flamegraph as [`Self::render_flamegraph`] draws it, as an image of `cell_size` pixels
+    /// per cell in which frames are as wide as their samples to the pixel rather than to the cell.
+    fn render_flamegraph_image(&self, area: Rect, cell_size: (u16, u16)) -> Canvas {
+        let (cell_width, cell_height) = (cell_size.0 as usize, cell_size.1 as usize);
+        let level_height = cell_height * self.app.config.row_height() as usize;
+        let mut canvas = Canvas::new(
+            area.width as usize * cell_width,
+            area.height as usize * cell_height,
+        );
+        let background = match self.app.config.palette {
+            Palette::Dark | Palette::Colorblind => [0, 0, 0],
+            Palette::Light => [255, 255, 255],
+        };
+        canvas.fill_rect(0, 0, canvas.width, canvas.height, background);
+        // Larger text where cells have more pixels
+        let scale = (cell_height / 16).max(1);
+        let flamegraph = self.app.flamegraph();
+        let level_offset = self.app.flamegraph_state().level_offset;
+        let zoom_state = self.app.flamegraph_state().zoom.as_ref();
+        let mut to_visit = vec![(ROOT_ID, 0.0_f64, canvas.width as f64)];
+        while let Some((stack_id, x, width)) = to_visit.pop() {
+            let stack = flamegraph.get_stack(&stack_id).unwrap();
+            // Leave a gap between frames so that neighbors with similar colors can be told apart
+            let (left, right) = (x.round() as usize, (x + width).round() as usize);
+            if right <= left {
+                continue;
+            }
+            if stack.level >= level_offset {
+                let top = (stack.level - level_offset) * level_height;
+                if top >= canvas.height {
+                    continue;
+                }
+                let color = self.get_stack_color(stack, zoom_state);
+                let (Color::Rgb(r, g, b), Color::Rgb(text_r, text_g, text_b)) =
+                    (color, self.get_text_color(color))
+                else {
+                    unreachable!();
+                };
+                let frame_width = (right - left).saturating_sub(1).max(1);
+                canvas.fill_rect(left, top, frame_width, level_height - 1, [r, g, b]);
+
+                let name = flamegraph.get_stack_short_name_from_info(stack);
+                let char_width = Canvas::CHAR_WIDTH * scale;
+                let max_chars = frame_width.saturating_sub(2 * scale) / char_width;
+                let label: String = if name.chars().count() <= max_chars {
+                    name.to_string()
+                } else if max_chars >= 3 {
+                    name.chars()
+                        .take(max_chars - 2)
+                        .chain("..".chars())
+                        .collect()
+                } else {
+                    String::new()
+                };
+                let text_top = top + cell_height.saturating_sub(Canvas::CHAR_HEIGHT * scale) / 2;
+                canvas.draw_scaled_text(
+                    left + 2 * scale,
+                    text_top,
+                    &label,
+                    [text_r, text_g, text_b],
+                    scale,
+                );
+            }
+
+            let children = flamegraph.get_children(stack);
+            let zoomed_child = children
+                .iter()
+                .find(|child_id| zoom_state.is_some_and(|zoom| zoom.ancestors.contains(child_id)));
+            let mut child_x = x;
+            for child_id in children {
+                let child = flamegraph.get_stack(child_id).unwrap();
+                let child_width = match zoomed_child {
+                    Some(zoomed_child_id) if zoomed_child_id == child_id => width,
+                    Some(_) => 0.0,
+                    None => width * child.total_count as f64 / stack.total_count as f64,
+                };
+                to_visit.push((*child_id, child_x, child_width));
+                child_x += child_width;
+            }
+        }
+        canvas
+    }
+
     fn render_flamegraph(&self, area: Rect, buf: &mut Buffer) -> bool {
-        let zoom_state = self
-            .app
-            .flamegraph_state()
-            .zoom
-            .as_ref()
-            .map(|zoom| ZoomState {
-                zoom_stack: zoom.stack_id,
-                ancestors: self.app.flamegraph().get_ancestors(&zoom.stack_id),
-            });
+        // Ancestors of the zoomed frame are kept up to date by the view as the zoom or the
+        // flamegraph changes, so there is no need to walk up from the zoomed frame on each draw
+        let zoom_state = self.app.flamegraph_state().zoom.as_ref();
         let re = self
             .app
             .flamegraph_state()
@@ -218,6 +611,11 @@ impl<'a> FlamelensWidget<'a> {
                     None
                 }
             });
+        let mut culled = CulledFrames {
+            min_width: self.app.flamegraph_state().min_width.cells(area.width),
+            num_frames: 0,
+            count: 0,
+        };
         let has_more_rows_to_render = self.render_stacks(
             self.app.flamegraph().root(),
             buf,
@@ -225,18 +623,394 @@ impl<'a> FlamelensWidget<'a> {
             area.y,
             area.width as f64,
             area.bottom(),
-            &zoom_state,
+            zoom_state,
             &re,
+            &mut culled,
         );
+        self.render_culled_indicator(&culled, area, buf);
         has_more_rows_to_render
     }
 
+    /// Tell how much of the flamegraph is left out for being too narrow to draw, at the bottom
+    /// right of the flamegraph, so that the view is not mistaken for the complete profile.
+    fn render_culled_indicator(&self, culled: &CulledFrames, area: Rect, buf: &mut Buffer) {
+        if culled.num_frames == 0 || area.height == 0 {
+            return;
+        }
+        let percent =
+            100.0 * culled.count as f64 / self.app.flamegraph().total_count().max(1) as f64;
+        let candidates = [
+            format!(
+                " {} narrow {} hidden ({}, {:.2}% of all); {} to see them ",
+                culled.num_frames,
+                if culled.num_frames == 1 {
+                    "frame"
+                } else {
+                    "frames"
+                },
+                self.app.flamegraph().format_count(culled.count),
+                percent,
+                if culled.min_width > 1.0 {
+                    "zoom in or press <"
+                } else {
+                    "zoom in"
+                }
+            ),
+            format!(" {:.2}% hidden in narrow frames ", percent),
+            format!(" {:.2}% hidden ", percent),
+        ];
+        let Some(text) = candidates
+            .into_iter()
+            .find(|text| text.chars().count() < area.width as usize)
+        else {
+            return;
+        };
+        let width = text.chars().count() as u16;
+        buf.set_string(
+            area.x + area.width - width,
+            area.bottom() - 1,
+            text,
+            Style::default()
+                .add_modifier(Modifier::BOLD)
+                .add_modifier(Modifier::REVERSED),
+        );
+    }
+
+    fn render_notes(&self, area: Rect, buf: &mut Buffer) {
+        let selected_full_name = self
+            .app
+            .flamegraph()
+            .get_stack_full_name(&self.app.flamegraph_state().selected);
+        let lines = if self.app.notes.is_empty() {
+            vec![Line::from("No notes. Press 'a' to annotate the selected frame.").italic()]
+        } else {
+            self.app
+                .notes
+                .notes
+                .iter()
+                .map(|(full_name, note)| {
+                    let short_name = full_name.rsplit(';').next().unwrap_or(full_name);
+                    let style = if Some(full_name.as_str()) == selected_full_name {
+                        Style::default().bold().yellow()
+                    } else {
+                        Style::default()
+                    };
+                    Line::from(vec![
+                        Span::styled(format!("{}: ", short_name), style.bold()),
+                        Span::styled(note.as_str(), style),
+                    ])
+                })
+                .collect()
+        };
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: true })
+            .block(
+                Block::new()
+                    .borders(Borders::LEFT)
+                    .title("Notes")
+                    .title_style(Style::default().bold().yellow()),
+            )
+            .render(area, buf);
+    }
+
+    /// Render the summary view and return the number of lines of the content.
+    fn render_message_log(&self, area: Rect, buf: &mut Buffer) {
+        let messages = &self.app.messages;
+        let width = std::cmp::min(area.width, MESSAGE_LOG_MAX_WIDTH);
+        let height = std::cmp::min(area.height, messages.log.len().max(1) as u16 + 2);
+        let area = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+        let num_visible = height.saturating_sub(2) as usize;
+        let offset = std::cmp::min(
+            messages.log_offset,
+            messages.log.len().saturating_sub(num_visible),
+        );
+        let lines = if messages.log.is_empty() {
+            vec![Line::from("No messages yet").italic()]
+        } else {
+            messages
+                .log
+                .iter()
+                .skip(offset)
+                .take(num_visible)
+                .map(|m| {
+                    let elapsed = m.time.duration_since(messages.start).as_secs();
+                    Line::from(vec![
+                        Span::from(format!(
+                            "[{:0>2}:{:0>2}:{:0>2}] ",
+                            elapsed / 3600,
+                            (elapsed / 60) % 60,
+                            elapsed % 60
+                        ))
+                        .dim(),
+                        Span::from(m.text.as_str()),
+                    ])
+                })
+                .collect()
+        };
+        Clear.render(area, buf);
+        Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title(" Messages "))
+            .render(area, buf);
+    }
+
+    fn render_snapshot_browser(&self, browser: &SnapshotBrowser, area: Rect, buf: &mut Buffer) {
+        let labels = browser
+            .snapshots
+            .iter()
+            .map(|snapshot| snapshot.label.clone())
+            .collect::<Vec<_>>();
+        let highlight = self.get_selected_row_color();
+        render_list_popup(
+            " Snapshots ",
+            &labels,
+            browser.selected,
+            highlight,
+            area,
+            buf,
+        );
+    }
+
+    fn render_process_selector(&self, selector: &ProcessSelector, area: Rect, buf: &mut Buffer) {
+        let total_count = self.app.flamegraph().total_count().max(1);
+        let labels = selector
+            .processes
+            .iter()
+            .map(|process| {
+                format!(
+                    "{}{} ({:.2}%)",
+                    "  ".repeat(process.depth),
+                    process.name,
+                    100.0 * process.total_count as f64 / total_count as f64
+                )
+            })
+            .collect::<Vec<_>>();
+        let highlight = self.get_selected_row_color();
+        render_list_popup(
+            " Processes ",
+            &labels,
+            selector.selected,
+            highlight,
+            area,
+            buf,
+        );
+    }
+
+    /// Debug panel in the top right corner on how the selected frame is laid out.
+    fn render_placement(&self, area: Rect, buf: &mut Buffer) {
+        let selected = &self.app.flamegraph_state().selected;
+        let Some(placement) = self.app.flamegraph_view.get_placement(selected) else {
+            return;
+        };
+        let format_cells = |cells: Option<f64>| {
+            cells
+                .map(|cells| format!("{:.2}", cells))
+                .unwrap_or("?".to_string())
+        };
+        let lines = vec![
+            Line::from(format!("Level: {}", placement.level)),
+            Line::from(format!("Width factor: {:.6}", placement.width_factor)),
+            Line::from(format!(
+                "Zoom factor: {}",
+                placement
+                    .zoom_factor
+                    .map(|factor| format!("{:.2}", factor))
+                    .unwrap_or("not zoomed".to_string())
+            )),
+            Line::from(format!(
+                "Cells: {} (min {})",
+                format_cells(placement.cells),
+                format_cells(placement.min_cells)
+            )),
+            match placement.hidden_because {
+                Some(reason) => Line::from(format!("Hidden: {}", reason)).bold(),
+                None => Line::from("Drawn"),
+            },
+        ];
+        let width = lines
+            .iter()
+            .map(|line| line.width() as u16 + 2)
+            .max()
+            .unwrap_or(0)
+            .max(" Placement ".len() as u16 + 2)
+            .min(area.width);
+        let height = (lines.len() as u16 + 2).min(area.height);
+        let area = Rect {
+            x: area.right() - width,
+            y: area.y,
+            width,
+            height,
+        };
+        Clear.render(area, buf);
+        Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title(" Placement "))
+            .render(area, buf);
+    }
+
+    fn render_summary(&self, area: Rect, buf: &mut Buffer) -> usize {
+        let summary = self.app.flamegraph_view.summary();
+        let total_count = summary.total_count;
+        let percent = |count: u64| 100.0 * count as f64 / total_count.max(1) as f64;
+        let heading = |text: &'static str| Line::from(text).bold().yellow();
+        let field = |label: &'static str, value: String| {
+            Line::from(vec![
+                Span::from(format!("  {:<20}", label)).bold(),
+                Span::from(value),
+            ])
+        };
+
+        let mut lines = vec![
+            heading("Overview"),
+            match self.app.flamegraph().metric() {
+                Some(metric) => field("Total", metric.format_count(total_count)),
+                None => field("Total samples", total_count.to_string()),
+            },
+            field("Unique frames", summary.num_unique_frames.to_string()),
+            field("Stacks", summary.num_stacks.to_string()),
+            field("Max depth", summary.max_depth.to_string()),
+            field("Root children", summary.num_root_children.to_string()),
+            Line::from(""),
+            heading("Top modules (own samples)"),
+        ];
+        for (name, count) in summary.top_modules.iter().take(SUMMARY_NUM_TOP_MODULES) {
+            lines.push(Line::from(format!(
+                "  {:>10} {:>7.2}%  {}",
+                count,
+                percent(*count),
+                name
+            )));
+        }
+        lines.push(Line::from(""));
+        lines.push(heading("Samples by stack depth"));
+        let max_depth_count = summary.samples_by_depth.iter().max().copied().unwrap_or(0);
+        for (i, count) in summary.samples_by_depth.iter().enumerate() {
+            let bar_width = if max_depth_count > 0 {
+                (SUMMARY_BAR_WIDTH as f64 * *count as f64 / max_depth_count as f64).ceil() as usize
+            } else {
+                0
+            };
+            lines.push(Line::from(vec![
+                Span::from(format!("  {:>5} ", i + 1)),
+                Span::from(format!(
+                    "{:<width$}",
+                    "█".repeat(bar_width),
+                    width = SUMMARY_BAR_WIDTH
+                ))
+                .fg(COLOR_HISTOGRAM_BAR),
+                Span::from(format!(" {:>10} {:>7.2}%", count, percent(*count))),
+            ]));
+        }
+
+        let num_lines = lines.len();
+        let offset = std::cmp::min(
+            self.app.flamegraph_state().summary_offset,
+            num_lines.saturating_sub(1),
+        );
+        Paragraph::new(lines)
+            .scroll((offset as u16, 0))
+            .render(area, buf);
+        num_lines
+    }
+
+    /// Render samples per stack depth, with consecutive levels bucketed into a row if there are
+    /// more levels than rows. Rows overlapping with the flamegraph viewport are highlighted.
+    fn render_depth_histogram(&self, area: Rect, frame_height: u16, buf: &mut Buffer) {
+        let block = Block::new()
+            .borders(Borders::LEFT)
+            .title("Depth")
+            .title_style(Style::default().bold().yellow());
+        let inner = block.inner(area);
+        block.render(area, buf);
+        // One row for the title
+        let num_rows = inner.height.saturating_sub(1) as usize;
+        let samples = samples_by_level(self.app.flamegraph());
+        if num_rows == 0 || samples.is_empty() {
+            return;
+        }
+        let bucket_size = samples.len().div_ceil(num_rows);
+        let buckets = samples
+            .chunks(bucket_size)
+            .map(|chunk| chunk.iter().sum::<u64>())
+            .collect::<Vec<_>>();
+        let max_bucket = buckets.iter().max().copied().unwrap_or(0).max(1);
+        let viewport_start = self.app.flamegraph_state().level_offset;
+        let viewport_end = viewport_start + frame_height as usize;
+        let label_width = 5;
+        let bar_max_width = inner.width.saturating_sub(label_width + 1) as usize;
+        for (i, count) in buckets.iter().enumerate() {
+            let level_start = i * bucket_size;
+            let level_end = level_start + bucket_size;
+            let in_viewport = level_start < viewport_end && viewport_start < level_end;
+            let style = if in_viewport {
+                Style::default().bg(self.get_selected_row_color())
+            } else {
+                Style::default()
+            };
+            let bar_width =
+                (bar_max_width as f64 * *count as f64 / max_bucket as f64).ceil() as usize;
+            let line = Line::from(vec![
+                Span::styled(format!("{:>4} ", level_start), style),
+                Span::styled(
+                    format!("{:<width$}", "▇".repeat(bar_width), width = bar_max_width),
+                    style.fg(COLOR_HISTOGRAM_BAR),
+                ),
+            ]);
+            buf.set_line(inner.x, inner.y + 1 + i as u16, &line, inner.width);
+        }
+    }
+
     fn render_table(&self, area: Rect, buf: &mut Buffer) {
+        let num_rows = self.app.flamegraph().ordered_stacks.num_rows;
+        let selected = self.app.flamegraph_state().table_state.selected;
+        // Leave room for the scrollbar only if not all rows fit (one line is used by the header)
+        let has_scrollbar = num_rows > area.height.saturating_sub(1) as usize;
+        let table_area = if has_scrollbar {
+            Rect {
+                width: area.width.saturating_sub(1),
+                ..area
+            }
+        } else {
+            area
+        };
         let ordered_stacks_table = self.get_ordered_stacks_table();
         let mut table_state = TableState::default()
-            .with_selected(self.app.flamegraph_state().table_state.selected)
+            .with_selected(selected)
             .with_offset(self.app.flamegraph_state().table_state.offset);
-        StatefulWidget::render(ordered_stacks_table, area, buf, &mut table_state);
+        StatefulWidget::render(ordered_stacks_table, table_area, buf, &mut table_state);
+
+        if has_scrollbar {
+            let scrollbar_area = Rect {
+                x: area.x + table_area.width,
+                y: area.y + 1,
+                width: 1,
+                height: area.height.saturating_sub(1),
+            };
+            let mut scrollbar_state = ScrollbarState::new(num_rows).position(selected);
+            Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                .begin_symbol(None)
+                .end_symbol(None)
+                .render(scrollbar_area, buf, &mut scrollbar_state);
+        }
+
+        // Position indicator at the right end of the header
+        if num_rows > 0 && area.height > 0 {
+            let position = format!(" row {}/{} ", selected + 1, num_rows);
+            let width = position.len() as u16;
+            if width < table_area.width {
+                buf.set_string(
+                    table_area.x + table_area.width - width,
+                    table_area.y,
+                    position,
+                    Style::default()
+                        .add_modifier(Modifier::BOLD)
+                        .add_modifier(Modifier::REVERSED),
+                );
+            }
+        }
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -248,43 +1022,61 @@ impl<'a> FlamelensWidget<'a> {
         y: u16,
         x_budget: f64,
         y_max: u16,
-        zoom_state: &Option<ZoomState>,
+        zoom_state: Option<&ZoomState>,
         re: &Option<&regex::Regex>,
+        culled: &mut CulledFrames,
     ) -> bool {
         let after_level_offset = stack.level >= self.app.flamegraph_state().level_offset;
 
         // Only render if the stack is visible
         let effective_x_budget = x_budget as u16;
-        if y < y_max && effective_x_budget > 0 {
+        let wide_enough = x_budget >= culled.min_width;
+        // Frames without any budget are the siblings of a zoomed frame rather than narrow ones
+        if y < y_max && !wide_enough && x_budget > 0.0 {
+            culled.num_frames += 1;
+            culled.count += stack.total_count;
+        }
+        let row_height = self.app.config.row_height();
+        if y < y_max && wide_enough {
             if after_level_offset {
                 let stack_color = self.get_stack_color(stack, zoom_state);
-                let text_color = FlamelensWidget::<'a>::get_text_color(stack_color);
+                let text_color = self.get_text_color(stack_color);
                 let style = Style::default().fg(text_color).bg(stack_color);
-                let line = self.get_line_for_stack(stack, effective_x_budget, style, re);
-                buf.set_line(x, y, &line, effective_x_budget);
+                let num_rows = row_height.min(y_max - y);
+                self.render_stack_rows(stack, buf, x, y, effective_x_budget, num_rows, style, re);
+                if self.app.show_own_time {
+                    self.render_own_time(stack, buf, x, y + num_rows - 1, x_budget);
+                }
+                // Separate the frame from its caller drawn above it, where native and interpreted
+                // code meet
+                let level_offset = self.app.flamegraph_state().level_offset;
+                if stack.level > level_offset && self.app.flamegraph().is_code_kind_boundary(stack)
+                {
+                    buf.set_style(
+                        Rect::new(x, y - 1, effective_x_budget, 1),
+                        Style::default()
+                            .add_modifier(Modifier::UNDERLINED)
+                            .underline_color(COLOR_CODE_KIND_BOUNDARY),
+                    );
+                }
             }
         } else {
             // Can skip rendering children if the stack is already not visible
-            let has_more_rows_to_render = (y >= y_max) && effective_x_budget > 0;
+            let has_more_rows_to_render = (y >= y_max) && wide_enough;
             return has_more_rows_to_render;
         }
 
         // Render children
         let mut x_offset = 0;
-        let zoomed_child = stack
-            .children
+        let children = self.app.flamegraph().get_children(stack);
+        let zoomed_child = children
             .iter()
-            .position(|child_id| {
-                if let Some(zoom_state) = zoom_state {
-                    *child_id == zoom_state.zoom_stack || zoom_state.ancestors.contains(child_id)
-                } else {
-                    false
-                }
-            })
-            .map(|idx| stack.children[idx]);
+            .find(|child_id| zoom_state.is_some_and(|zoom| zoom.ancestors.contains(child_id)))
+            .copied();
 
         let mut has_more_rows_to_render = false;
-        for child in &stack.children {
+        let mut thin_children = Vec::new();
+        for child in children {
             let child_stack = self.app.flamegraph().get_stack(child).unwrap();
             let child_x_budget = if let Some(zoomed_child_id) = zoomed_child {
                 // Zoomer takes all
@@ -296,46 +1088,251 @@ impl<'a> FlamelensWidget<'a> {
             } else {
                 x_budget * (child_stack.total_count as f64 / stack.total_count as f64)
             };
+            if child_x_budget > 0.0 && child_x_budget < 1.0 {
+                thin_children.push((child_stack, child_x_budget));
+            }
             has_more_rows_to_render |= self.render_stacks(
                 child_stack,
                 buf,
                 x + x_offset,
-                y + if after_level_offset { 1 } else { 0 },
+                y + if after_level_offset { row_height } else { 0 },
                 child_x_budget,
                 y_max,
                 zoom_state,
                 re,
+                culled,
             );
             x_offset += child_x_budget as u16;
         }
 
+        let child_y = y + if after_level_offset { row_height } else { 0 };
+        let level_offset = self.app.flamegraph_state().level_offset;
+        if !thin_children.is_empty() && child_y < y_max && stack.level + 1 >= level_offset {
+            self.render_thin_stacks(
+                &thin_children,
+                buf,
+                x + x_offset,
+                child_y,
+                effective_x_budget.saturating_sub(x_offset),
+                row_height.min(y_max - child_y),
+                zoom_state,
+            );
+        }
+
         has_more_rows_to_render
     }
 
-    fn get_ordered_stacks_table(&self) -> Table {
+    /// Draw the frames narrower than a cell one after another in the cells their parent has left
+    /// after its wider children. Each cell is filled from the left by how much of it the frames
+    /// cover, in the color of the frame covering most of it, so that the long tail of the profile
+    /// can still be seen.
+    #[allow(clippy::too_many_arguments)]
+    fn render_thin_stacks(
+        &self,
+        thin_stacks: &[(&StackInfo, f64)],
+        buf: &mut Buffer,
+        x: u16,
+        y: u16,
+        width: u16,
+        num_rows: u16,
+        zoom_state: Option<&ZoomState>,
+    ) {
+        let mut spans = Vec::with_capacity(thin_stacks.len());
+        let mut end = 0.0;
+        for (stack, budget) in thin_stacks {
+            spans.push((*stack, end, end + budget));
+            end += budget;
+        }
+        let mut first = 0;
+        for column in 0..width.min(end.ceil() as u16) {
+            let (left, right) = (column as f64, column as f64 + 1.0);
+            while first < spans.len() && spans[first].2 <= left {
+                first += 1;
+            }
+            let mut covered = 0.0;
+            let mut widest: Option<(&StackInfo, f64)> = None;
+            for (stack, start, end) in spans[first..].iter().take_while(|span| span.1 < right) {
+                let overlap = end.min(right) - start.max(left);
+                covered += overlap;
+                if widest.is_none_or(|(_, widest_overlap)| overlap > widest_overlap) {
+                    widest = Some((stack, overlap));
+                }
+            }
+            let Some((stack, _)) = widest else {
+                continue;
+            };
+            let eighths = ((covered * 8.0).round() as usize).clamp(1, 8);
+            let style = Style::default().fg(self.get_stack_color(stack, zoom_state));
+            for row in 0..num_rows {
+                buf.get_mut(x + column, y + row)
+                    .set_symbol(SUB_CELL_BLOCKS[eighths - 1])
+                    .set_style(style);
+            }
+        }
+    }
+
+    /// Draw a frame `num_rows` rows high, its label wrapping onto the rows below the first, each
+    /// row starting with a space to keep it apart from the frame to its left.
+    #[allow(clippy::too_many_arguments)]
+    fn render_stack_rows(
+        &self,
+        stack: &StackInfo,
+        buf: &mut Buffer,
+        x: u16,
+        y: u16,
+        width: u16,
+        num_rows: u16,
+        style: Style,
+        re: &Option<&regex::Regex>,
+    ) {
+        if num_rows <= 1 || width <= 1 {
+            let line = self.get_line_for_stack(stack, width, style, re);
+            buf.set_line(x, y, &line, width);
+            for row in 1..num_rows {
+                buf.set_style(Rect::new(x, y + row, width, 1), style);
+            }
+            return;
+        }
+        let label_width = width + (width - 1) * (num_rows - 1);
+        let line = self.get_line_for_stack(stack, label_width, style, re);
+        let mut label = Buffer::empty(Rect::new(0, 0, label_width, 1));
+        label.set_line(0, 0, &line, label_width);
+        for i in 0..label_width {
+            let (column, row) = match i.checked_sub(width) {
+                None => (i, 0),
+                Some(rest) => (1 + rest % (width - 1), 1 + rest / (width - 1)),
+            };
+            if column == 1 && row > 0 {
+                buf.get_mut(x, y + row).set_symbol(" ").set_style(style);
+            }
+            *buf.get_mut(x + column, y + row) = label.get(i, 0).clone();
+        }
+    }
+
+    /// Ruler above the flamegraph with a tick at each mark, and a guide down from each tick through
+    /// the cells no frame is drawn in.
+    fn render_ruler(&self, area: Rect, flamegraph_area: Rect, buf: &mut Buffer) {
+        let mut ruler = vec!['─'; area.width as usize];
+        for mark in RULER_MARKS {
+            // The same rounding as the widths of the frames
+            let column = (area.width as f64 * mark as f64 / 100.0) as usize;
+            let label = format!("┬{}%", mark);
+            for (i, c) in label.chars().enumerate() {
+                if let Some(cell) = ruler.get_mut(column + i) {
+                    *cell = c;
+                }
+            }
+            let x = area.x + column as u16;
+            for y in flamegraph_area.top()..flamegraph_area.bottom() {
+                let cell = buf.get_mut(x, y);
+                if cell.symbol() == " " && cell.bg == Color::Reset {
+                    cell.set_char('┊').set_fg(COLOR_RULER);
+                }
+            }
+        }
+        buf.set_string(
+            area.x,
+            area.y,
+            ruler.into_iter().collect::<String>(),
+            Style::default().fg(COLOR_RULER),
+        );
+    }
+
+    /// Underline the end of the frame in proportion to its own count, where no children are drawn
+    /// below it, to tell the frames doing the work from the ones only passing through.
+    fn render_own_time(&self, stack: &StackInfo, buf: &mut Buffer, x: u16, y: u16, x_budget: f64) {
+        let width = x_budget as u16;
+        let own_width = (x_budget * stack.self_count as f64 / stack.total_count.max(1) as f64)
+            .round()
+            .min(width as f64) as u16;
+        if own_width > 0 {
+            buf.set_style(
+                Rect::new(x + width - own_width, y, own_width, 1),
+                Style::default()
+                    .add_modifier(Modifier::UNDERLINED)
+                    .underline_color(COLOR_OWN_TIME),
+            );
+        }
+    }
+
+    fn get_ordered_stacks_table(&self) -> Table<'_> {
         let add_sorted_indicator = |label: &str, sort_column: SortColumn| {
-            let suffix = if sort_column == self.app.flamegraph().ordered_stacks.sorted_column {
-                " [▼]"
-            } else {
+            let suffix = if sort_column != self.app.flamegraph().ordered_stacks.sorted_column {
                 ""
+            } else if sort_column == SortColumn::Name {
+                " [▲]"
+            } else {
+                " [▼]"
             };
             format!("{}{}", label, suffix)
         };
-        let header = Row::new(vec![
-            add_sorted_indicator("Total", SortColumn::Total),
-            add_sorted_indicator("Own", SortColumn::Own),
-            "Name".to_string(),
-        ])
-        .style(
+        let thread_counts = self.app.flamegraph().ordered_stacks.thread_counts.as_ref();
+        let has_baseline = self.app.flamegraph().ordered_stacks.baseline.is_some();
+        let mut header_cells = vec![
+            Line::from(add_sorted_indicator("Total", SortColumn::Total)),
+            Line::from(add_sorted_indicator("Own", SortColumn::Own)),
+            Line::from(add_sorted_indicator("Call sites", SortColumn::CallSites)),
+        ];
+        if has_baseline {
+            header_cells.push(Line::from(add_sorted_indicator("Delta", SortColumn::Delta)));
+        }
+        if thread_counts.is_some() {
+            header_cells.push(Line::from("Threads"));
+            header_cells.push(Line::from("Top thread"));
+        }
+        header_cells.push({
+            let ordered_stacks = &self.app.flamegraph().ordered_stacks;
+            let mut name = add_sorted_indicator("Name", SortColumn::Name);
+            let mut warning = None;
+            if ordered_stacks.is_grouped() {
+                name.push_str(" [by module]");
+            }
+            match &self.app.flamegraph_state().search_pattern {
+                Some(p)
+                    if p.is_manual && ordered_stacks.search_pattern_ignored_because_of_no_match =>
+                {
+                    warning = Some(format!(
+                        " [no function matches /{}/; showing all]",
+                        p.re.as_str()
+                    ));
+                }
+                Some(p) if p.is_manual => {
+                    name.push_str(&format!(
+                        " [showing {} of {} functions matching /{}/]",
+                        ordered_stacks.shown_entries().count(),
+                        ordered_stacks.entries.len(),
+                        p.re.as_str()
+                    ));
+                }
+                _ => {}
+            }
+            if let Some(filter) = &ordered_stacks.filter {
+                name.push_str(&format!(
+                    " [filter: \"{}\", {} hidden]",
+                    filter,
+                    ordered_stacks.num_hidden_by_filter()
+                ));
+            }
+            let mut spans = vec![Span::from(name)];
+            if let Some(warning) = warning {
+                spans.push(Span::styled(warning, Style::default().fg(COLOR_WARNING)));
+            }
+            Line::from(spans)
+        });
+        let header = Row::new(header_cells).style(
             Style::default()
                 .add_modifier(Modifier::BOLD)
                 .add_modifier(Modifier::REVERSED),
         );
-        let counts = &self.app.flamegraph().ordered_stacks.entries;
+        let ordered_stacks = &self.app.flamegraph().ordered_stacks;
         let mut rows = vec![];
         let total_count = self.app.flamegraph().total_count();
         let mut total_max_width: u16 = 0;
         let mut own_max_width: u16 = 0;
+        let mut call_sites_max_width: u16 = 0;
+        let mut delta_max_width: u16 = 0;
+        let mut threads_max_width: u16 = 0;
+        let mut top_thread_max_width: u16 = 0;
 
         fn format_count(count: u64, total_count: u64) -> String {
             format!(
@@ -345,38 +1342,94 @@ impl<'a> FlamelensWidget<'a> {
             )
         }
 
-        for entry in counts.iter().filter(|entry| entry.visible) {
-            let total_formatted = Line::from(format_count(entry.count.total, total_count));
-            let own_formatted = Line::from(format_count(entry.count.own, total_count));
+        let is_grouped = ordered_stacks.is_grouped();
+        for row in ordered_stacks.rows() {
+            let (name, count, style) = match row {
+                TableRow::Group(group) => (
+                    group.name.as_str(),
+                    &group.count,
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                TableRow::Entry(entry) => (entry.name.as_str(), &entry.count, Style::default()),
+            };
+            let total_formatted = Line::from(format_count(count.total, total_count));
+            let own_formatted = Line::from(format_count(count.own, total_count));
             total_max_width = total_max_width.max(total_formatted.width() as u16);
             own_max_width = own_max_width.max(own_formatted.width() as u16);
-            let name_formatted = if let Some(p) = &self.app.flamegraph_state().search_pattern {
-                if p.is_manual {
-                    Line::from(self.get_highlighted_spans(
-                        entry.name.as_str(),
-                        &p.re,
-                        Style::default(),
-                    ))
-                } else {
-                    Line::from(entry.name.as_str())
-                }
-            } else {
-                Line::from(entry.name.as_str())
+            let call_sites_formatted = Line::from(format!("{}  ", count.call_sites));
+            call_sites_max_width = call_sites_max_width.max(call_sites_formatted.width() as u16);
+            let mut name_spans = match row {
+                TableRow::Group(group) => vec![Span::styled(
+                    if group.expanded { "▾ " } else { "▸ " },
+                    style,
+                )],
+                TableRow::Entry(_) if is_grouped => vec![Span::raw("    ")],
+                TableRow::Entry(_) => vec![],
             };
-            rows.push(Row::new(vec![
-                total_formatted,
-                own_formatted,
-                name_formatted,
-            ]));
+            match &self.app.flamegraph_state().search_pattern {
+                Some(p) if p.is_manual => {
+                    name_spans.extend(self.get_highlighted_spans(name, &p.re, style));
+                }
+                _ => name_spans.push(Span::styled(name, style)),
+            }
+            let mut cells = vec![total_formatted, own_formatted, call_sites_formatted];
+            if has_baseline {
+                let delta = count.delta.unwrap_or(0);
+                let delta_formatted = Line::from(format!(
+                    "{:+} ({:+.2}%)  ",
+                    delta,
+                    100.0 * delta as f64 / total_count.max(1) as f64
+                ));
+                delta_max_width = delta_max_width.max(delta_formatted.width() as u16);
+                cells.push(delta_formatted);
+            }
+            if let Some(thread_counts) = thread_counts {
+                let threads = match row {
+                    TableRow::Entry(entry) => thread_counts.get(&entry.name),
+                    TableRow::Group(_) => None,
+                };
+                let (threads_formatted, top_thread_formatted) = match threads {
+                    Some(threads) => (
+                        Line::from(format!("{}  ", threads.len())),
+                        Line::from(format!(
+                            "{:.0}% {}  ",
+                            100.0 * threads[0].1 as f64 / count.total as f64,
+                            threads[0].0
+                        )),
+                    ),
+                    None => (Line::default(), Line::default()),
+                };
+                threads_max_width = threads_max_width.max(threads_formatted.width() as u16);
+                top_thread_max_width =
+                    top_thread_max_width.max(top_thread_formatted.width() as u16);
+                cells.push(threads_formatted);
+                cells.push(top_thread_formatted);
+            }
+            cells.push(Line::from(name_spans));
+            rows.push(Row::new(cells));
         }
-        let widths = [
+        let mut widths = vec![
             Constraint::Max(total_max_width),
             Constraint::Max(own_max_width),
-            Constraint::Fill(1),
+            Constraint::Max(call_sites_max_width.max("Call sites [▼]  ".chars().count() as u16)),
         ];
+        if has_baseline {
+            widths.push(Constraint::Max(
+                delta_max_width.max("Delta [▼]  ".chars().count() as u16),
+            ));
+        }
+        if thread_counts.is_some() {
+            widths.push(Constraint::Max(
+                threads_max_width.max("Threads  ".len() as u16),
+            ));
+            widths.push(Constraint::Max(
+                top_thread_max_width.min(MAX_TOP_THREAD_WIDTH),
+            ));
+        }
+        widths.push(Constraint::Fill(1));
         Table::new(rows, widths)
             .header(header)
-            .highlight_style(Style::default().bg(COLOR_TABLE_SELECTED_ROW))
+            .highlight_style(Style::default().bg(self.get_selected_row_color()))
     }
 
     fn get_highlighted_spans<'b>(
@@ -409,24 +1462,55 @@ impl<'a> FlamelensWidget<'a> {
         width: u16,
         style: Style,
         re: &Option<&regex::Regex>,
-    ) -> Line {
+    ) -> Line<'_> {
         let short_name = self.app.flamegraph().get_stack_short_name_from_info(stack);
+        let has_note = self
+            .app
+            .notes
+            .get(self.app.flamegraph().get_stack_full_name_from_info(stack))
+            .is_some();
 
-        // Empty space separator at the beginning
-        let mut spans = vec![Span::styled(if width > 1 { " " } else { "." }, style)];
+        // Empty space separator at the beginning, or a marker if the frame has a note
+        let mut spans = vec![if has_note {
+            Span::styled("*", style.add_modifier(Modifier::BOLD))
+        } else {
+            Span::styled(if width > 1 { " " } else { "." }, style)
+        }];
 
-        // Stack name with highlighted search terms if needed
-        let short_name_spans = if let (true, &Some(re)) = (stack.hit, re) {
-            self.get_highlighted_spans(short_name, re, style)
+        // Label around the name from the template in the config, if it is not just the name
+        let flamegraph = self.app.flamegraph();
+        let template = self
+            .app
+            .config
+            .frame_label(flamegraph.metric().map(|metric| metric.name.as_str()));
+        let (before, after) = if template == "{name}" {
+            (String::new(), Some(String::new()))
         } else {
-            vec![Span::styled(short_name, style)]
+            expand_frame_label(
+                template,
+                short_name,
+                &flamegraph.format_count(stack.total_count),
+                &flamegraph.format_count(stack.self_count),
+                100.0 * stack.total_count as f64 / flamegraph.total_count().max(1) as f64,
+            )
         };
-        spans.extend(short_name_spans);
+        let mut label_length = before.chars().count();
+        spans.push(Span::styled(before, style));
+
+        // Stack name with highlighted search terms if needed
+        if let Some(after) = after {
+            let short_name_spans = if let (true, &Some(re)) = (stack.hit, re) {
+                self.get_highlighted_spans(short_name, re, style)
+            } else {
+                vec![Span::styled(short_name, style)]
+            };
+            spans.extend(short_name_spans);
+            label_length += short_name.len() + after.chars().count();
+            spans.push(Span::styled(after, style));
+        }
 
         // Padding to fill the rest of the width
-        let pad_length = width
-            .saturating_sub(short_name.len() as u16)
-            .saturating_sub(1) as usize;
+        let pad_length = width.saturating_sub(label_length as u16).saturating_sub(1) as usize;
         spans.push(Span::styled(
             format!("{:width$}", "", width = pad_length),
             style,
@@ -435,23 +1519,81 @@ impl<'a> FlamelensWidget<'a> {
         Line::from(spans)
     }
 
-    fn get_stack_color(&self, stack: &StackInfo, zoom_state: &Option<ZoomState>) -> Color {
+    fn get_stack_color(&self, stack: &StackInfo, zoom_state: Option<&ZoomState>) -> Color {
+        let palette = self.app.config.palette;
         if self.app.flamegraph_state().selected == stack.id {
-            return COLOR_SELECTED_STACK;
-        }
-        // Roughly based on flamegraph.pl
-        fn hash_name(name: &str) -> f64 {
-            let mut hasher = DefaultHasher::new();
-            name.hash(&mut hasher);
-            hasher.finish() as f64 / u64::MAX as f64
+            return match palette {
+                Palette::Dark | Palette::Colorblind => COLOR_SELECTED_STACK,
+                Palette::Light => COLOR_SELECTED_STACK_LIGHT,
+            };
         }
+        // Roughly based on flamegraph.pl, with a hash that is stable across builds so that frames
+        // keep their colors
+        let hash_name =
+            |name: &str| fnv1a_seeded(name, self.app.config.color_seed) as f64 / u64::MAX as f64;
         let full_name = self.app.flamegraph().get_stack_full_name_from_info(stack);
         let v1 = hash_name(full_name);
         let v2 = hash_name(full_name);
+        let short_name = self.app.flamegraph().get_stack_short_name_from_info(stack);
+        let rule_color = self
+            .app
+            .config
+            .color_rules
+            .iter()
+            .find(|rule| rule.pattern.is_match(short_name))
+            .map(|rule| rule.color);
         let mut r;
         let mut g;
         let mut b;
-        if !stack.hit {
+        let compared = self
+            .app
+            .live_delta_of(stack)
+            .or_else(|| self.app.baseline_of(stack));
+        if let (false, Some((held, total))) = (stack.hit, self.app.gil_held_of(stack)) {
+            // Green (blue without red and green) where the GIL was held, gray where it was waited
+            // for
+            let held = held as f64 / total.max(1) as f64;
+            (r, g, b) = match palette {
+                Palette::Dark | Palette::Light => (
+                    170 - (100.0 * held) as u8,
+                    170 + (50.0 * held) as u8,
+                    170 - (100.0 * held) as u8,
+                ),
+                Palette::Colorblind => (
+                    170 - (140.0 * held) as u8,
+                    170 - (60.0 * held) as u8,
+                    170 + (50.0 * held) as u8,
+                ),
+            };
+        } else if let (false, Some((previous, latest))) = (stack.hit, compared) {
+            // Red (orange without red and green) where hotter than in the previous window or the
+            // baseline and blue where cooler
+            let change = (latest as f64 - previous as f64) / previous.max(latest).max(1) as f64;
+            if palette == Palette::Colorblind {
+                let target = if change >= 0.0 {
+                    COLOR_GROWN_COLORBLIND
+                } else {
+                    COLOR_SHRUNK_COLORBLIND
+                };
+                let blend = |c: u8| (210.0 + (c as f64 - 210.0) * change.abs()) as u8;
+                (r, g, b) = (blend(target[0]), blend(target[1]), blend(target[2]));
+            } else {
+                let faded = (210.0 * (1.0 - change.abs())) as u8;
+                let strong = 210 + (45.0 * change.abs()) as u8;
+                (r, g, b) = if change >= 0.0 {
+                    (strong, faded, faded)
+                } else {
+                    (faded, faded, strong)
+                };
+            }
+        } else if let (false, Some([r_, g_, b_])) = (stack.hit, rule_color) {
+            (r, g, b) = (r_, g_, b_);
+        } else if !stack.hit && is_kernel_frame(short_name) {
+            // Dimmed, to tell where the kernel is entered
+            r = 120 + (40.0 * v1) as u8;
+            g = 120 + (40.0 * v1) as u8;
+            b = 145 + (40.0 * v1) as u8;
+        } else if !stack.hit {
             r = 205 + (50.0 * v2) as u8;
             g = (230.0 * v1) as u8;
             b = (55.0 * v2) as u8;
@@ -464,36 +1606,53 @@ impl<'a> FlamelensWidget<'a> {
         }
         if let Some(zoom_state) = zoom_state {
             if zoom_state.ancestors.contains(&stack.id) {
-                r = (r as f64 / 2.5) as u8;
-                g = (g as f64 / 2.5) as u8;
-                b = (b as f64 / 2.5) as u8;
+                // Faded towards the background
+                let fade = |c: u8| match palette {
+                    Palette::Dark | Palette::Colorblind => (c as f64 / 2.5) as u8,
+                    Palette::Light => (c as f64 + (255.0 - c as f64) * 0.6) as u8,
+                };
+                (r, g, b) = (fade(r), fade(g), fade(b));
             }
         }
         Color::Rgb(r, g, b)
     }
 
-    fn get_text_color(c: Color) -> Color {
+    fn get_text_color(&self, c: Color) -> Color {
+        // Softer than black and white on dark backgrounds, and as far apart as they go without red
+        // and green
+        let (dark, light) = match self.app.config.palette {
+            Palette::Dark => (Color::Rgb(10, 10, 10), Color::Rgb(225, 225, 225)),
+            Palette::Light => (Color::Rgb(0, 0, 0), Color::Rgb(245, 245, 245)),
+            Palette::Colorblind => (Color::Rgb(0, 0, 0), Color::Rgb(255, 255, 255)),
+        };
         match c {
             Color::Rgb(r, g, b) => {
                 let luma = 0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64;
                 if luma > 128.0 {
-                    Color::Rgb(10, 10, 10)
+                    dark
                 } else {
-                    Color::Rgb(225, 225, 225)
+                    light
                 }
             }
             _ => Color::Black,
         }
     }
 
-    fn get_view_kind_indicator(&self) -> Line {
+    fn get_selected_row_color(&self) -> Color {
+        match self.app.config.palette {
+            Palette::Dark | Palette::Colorblind => COLOR_TABLE_SELECTED_ROW,
+            Palette::Light => COLOR_TABLE_SELECTED_ROW_LIGHT,
+        }
+    }
+
+    fn get_view_kind_indicator(&self) -> Line<'_> {
         let mut header_bottom_title_spans = vec![Span::from(" ")];
 
         fn _get_view_kind_span(
             label: &str,
             view_kind: ViewKind,
             current_view_kind: ViewKind,
-        ) -> Span {
+        ) -> Span<'_> {
             let (content, style) = if view_kind == current_view_kind {
                 (format!("[{}]", label), Style::default().bold().yellow())
             } else {
@@ -513,16 +1672,22 @@ impl<'a> FlamelensWidget<'a> {
             ViewKind::Table,
             self.app.flamegraph_state().view_kind,
         ));
+        header_bottom_title_spans.push(Span::from(" | "));
+        header_bottom_title_spans.push(_get_view_kind_span(
+            "Summary",
+            ViewKind::Summary,
+            self.app.flamegraph_state().view_kind,
+        ));
         header_bottom_title_spans.push(Span::from(" "));
         Line::from(header_bottom_title_spans)
     }
 
-    fn get_version_indicator(&self) -> Line {
+    fn get_version_indicator(&self) -> Line<'_> {
         Line::from(format!("flamelens v{}", env!("CARGO_PKG_VERSION")))
             .style(Style::default().bold())
     }
 
-    fn get_header_text(&self, _width: u16) -> Line {
+    fn get_header_text(&self, _width: u16) -> Line<'_> {
         let header_text = match &self.app.flamegraph_input {
             FlameGraphInput::File(path) => path.to_string(),
             FlameGraphInput::Pid(pid, info) => {
@@ -530,10 +1695,10 @@ impl<'a> FlamelensWidget<'a> {
                 if let Some(info) = info {
                     out += format!(" [{}]", info).as_str();
                 }
-                #[cfg(feature = "python")]
                 if let Some(state) = &self.app.sampler_state() {
-                    out += match state.status {
-                        SamplerStatus::Running => " [Running]".to_string(),
+                    out += match (&state.status, &self.app.sampler_finished) {
+                        (SamplerStatus::Running, _) => " [Running]".to_string(),
+                        (_, Some(finished)) => format!(" [{}]", finished),
                         _ => " [Exited]".to_string(),
                     }
                     .as_str();
@@ -543,17 +1708,138 @@ impl<'a> FlamelensWidget<'a> {
                     let hours = (duration.as_secs() / 60) / 60;
                     out += format!(" [Duration: {:0>2}:{:0>2}:{:0>2}]", hours, minutes, seconds)
                         .as_str();
-                    if self.app.flamegraph_state().freeze {
+                    let num_processes = self.app.flamegraph().processes().len();
+                    if num_processes > 1 {
+                        out += &format!(" [Processes: {}; press 'p' to pick one]", num_processes);
+                    }
+                    if let Some((held, total)) = state.gil_held.filter(|(_, total)| *total > 0) {
+                        out += &format!(" [GIL held: {:.1}%]", 100.0 * held as f64 / total as f64);
+                    }
+                    if let Some(max_stacks) = state.max_stacks {
+                        out += &format!(" [Memory limit reached; keeping {} stacks]", max_stacks);
+                    }
+                    // Frozen for good once a capture is complete
+                    if self.app.flamegraph_state().freeze && self.app.sampler_finished.is_none() {
                         out += " [Frozen; press 'z' again to unfreeze]";
                     }
                 }
                 out
             }
+            FlameGraphInput::Listen(address, received) => {
+                let mut out = format!("Listening on {} [Profiles received: {}]", address, received);
+                if self.app.flamegraph_state().freeze {
+                    out += " [Frozen; press 'z' again to unfreeze]";
+                }
+                out
+            }
+        };
+        let metrics = self.app.flamegraph().metrics();
+        let header_text = if metrics.len() > 1 {
+            let index = self.app.flamegraph().metric_index();
+            format!(
+                "{} [Metric: {} ({}/{}); press 'v' to switch]",
+                header_text,
+                metrics[index].name,
+                index + 1,
+                metrics.len()
+            )
+        } else {
+            header_text
+        };
+        let header_text = match self.app.sample_rate {
+            Some(rate) => format!(
+                "{} [Sampled about {}% of stacks; counts are estimates]",
+                header_text,
+                (rate * 10000.0).round() / 100.0
+            ),
+            None => header_text,
+        };
+        let header_text = if self.app.is_hiding_kernel_frames() {
+            format!("{} [Kernel frames hidden; press 'K' to show]", header_text)
+        } else {
+            header_text
+        };
+        let header_text = match self.app.group_by() {
+            Some(group_by) => format!(
+                "{} [Grouped by {}; type ':groupby off' to undo]",
+                header_text,
+                clap::ValueEnum::to_possible_value(&group_by)
+                    .map_or(String::new(), |value| value.get_name().to_string())
+            ),
+            None => header_text,
+        };
+        let header_text = if self.app.is_aggregating_lines() {
+            format!(
+                "{} [Lines merged per function; press '+' to expand the selected one, 'F' to undo]",
+                header_text
+            )
+        } else {
+            header_text
+        };
+        let header_text = if self.app.is_splitting_activity() {
+            format!(
+                "{} [Split into on-CPU and waiting; press 'I' to merge]",
+                header_text
+            )
+        } else {
+            header_text
+        };
+        let header_text = if self.app.is_collapsing_common_prefix() {
+            format!(
+                "{} [Common frames collapsed; press 'P' to expand]",
+                header_text
+            )
+        } else {
+            header_text
+        };
+        let palette = self.app.config.palette;
+        let (grown, shrunk) = palette.change_colors();
+        let header_text = if self.app.is_live_delta() {
+            format!(
+                "{} [Latest interval; {} hotter, {} cooler; press 'D' to show all]",
+                header_text, grown, shrunk
+            )
+        } else {
+            header_text
+        };
+        let header_text = if self.app.is_showing_gil() {
+            format!(
+                "{} [GIL held in {} frames; press 'W' to hide]",
+                header_text,
+                palette.gil_color()
+            )
+        } else {
+            header_text
+        };
+        let header_text = match &self.app.baseline {
+            Some(baseline) if self.app.is_showing_baseline() => format!(
+                "{} [Compared with {}; {} grown, {} shrunk; press 'B' to hide]",
+                header_text, baseline.name, grown, shrunk
+            ),
+            _ => header_text,
+        };
+        let header_text = match &self.app.callers_of {
+            Some((short_name, _)) => format!(
+                "{} [Callers of {}; press Esc to go back]",
+                header_text, short_name
+            ),
+            None => header_text,
         };
         Line::from(header_text).style(Style::default().bold())
     }
 
-    fn get_status_text(&self, width: u16) -> Vec<(&'static str, Line)> {
+    /// Lines to scroll the "Selected" bar by, going through the `num_hidden` lines that do not fit
+    /// one at a time and pausing at both ends, from the start whenever the selection changes.
+    fn get_selected_scroll(&self, num_hidden: u16) -> u16 {
+        let shown_for = match self.app.selected_shown_since {
+            Some((id, since)) if id == self.app.flamegraph_state().selected => since.elapsed(),
+            _ => Duration::ZERO,
+        };
+        let step = (shown_for.as_millis() / SELECTED_SCROLL_INTERVAL.as_millis()) as u16;
+        (step % (num_hidden + 3)).saturating_sub(1).min(num_hidden)
+    }
+
+    fn get_status_text(&self, width: u16) -> Vec<(&'static str, Line<'_>)> {
         if self.app.input_buffer.is_some() {
             self.get_status_text_buffer()
         } else {
@@ -561,10 +1847,17 @@ impl<'a> FlamelensWidget<'a> {
         }
     }
 
-    fn get_status_text_buffer(&self) -> Vec<(&'static str, Line)> {
+    fn get_status_text_buffer(&self) -> Vec<(&'static str, Line<'_>)> {
         let input_buffer = self.app.input_buffer.as_ref().unwrap();
         let status_text = format!("{}{}", SEARCH_PREFIX, input_buffer.buffer);
-        vec![("Search", Line::from(status_text))]
+        let title = match input_buffer.kind {
+            InputKind::Search => "Search",
+            InputKind::Command => "Command",
+            InputKind::Note => "Note",
+            InputKind::Level => "Go to level",
+            InputKind::TableFilter => "Filter",
+        };
+        vec![(title, Line::from(status_text))]
     }
 
     fn get_cursor_position(&self, status_area: Rect) -> Option<(u16, u16)> {
@@ -576,7 +1869,7 @@ impl<'a> FlamelensWidget<'a> {
         })
     }
 
-    fn get_status_text_command(&self, width: u16) -> Vec<(&'static str, Line)> {
+    fn get_status_text_command(&self, width: u16) -> Vec<(&'static str, Line<'_>)> {
         let stack = self
             .app
             .flamegraph()
@@ -599,42 +1892,77 @@ impl<'a> FlamelensWidget<'a> {
                         let mut match_text = format!(
                             "\"{}\" {}",
                             p.re.as_str(),
-                            FlamelensWidget::get_count_stats_str(
+                            self.get_count_stats_str(
                                 None,
                                 hit_coverage_count,
                                 root_total_count,
                                 zoom_total_count,
                             )
                         );
-                        if self.is_table_view()
-                            && self
-                                .app
-                                .flamegraph()
-                                .ordered_stacks
-                                .search_pattern_ignored_because_of_no_match
+                        if self.app.flamegraph_state().search_in_zoom && zoom_total_count.is_some()
                         {
-                            match_text += " (no match; showing all)";
+                            match_text += " (in zoom)";
                         }
                         let match_text = format!("{:width$}", match_text, width = width as usize,);
                         lines.push(("Match", Line::from(match_text)));
                     }
                 }
-                let selected_text = format!(
-                    "{} {}",
-                    self.app.flamegraph().get_stack_short_name_from_info(stack),
-                    FlamelensWidget::get_count_stats_str(
-                        None,
+                let short_name = self.app.flamegraph().get_stack_short_name_from_info(stack);
+                let mut selected_text = format!(
+                    "{} {} {}",
+                    short_name,
+                    self.get_count_stats_str(
+                        Some("Total"),
                         stack.total_count,
                         root_total_count,
                         zoom_total_count
                     ),
+                    self.get_count_stats_str(
+                        Some("Own"),
+                        stack.self_count,
+                        root_total_count,
+                        zoom_total_count
+                    ),
                 );
+                // The same function may be called from other places too
+                if let Some(count) = self.app.flamegraph().get_function_count(short_name) {
+                    let percent = |count: u64| 100.0 * count as f64 / root_total_count as f64;
+                    selected_text += &format!(
+                        " [All calls: {} ({:.2}%) total, {} ({:.2}%) own]",
+                        self.app.flamegraph().format_count(count.total),
+                        percent(count.total),
+                        self.app.flamegraph().format_count(count.own),
+                        percent(count.own),
+                    );
+                }
+                if let Some((previous, latest)) = self.app.live_delta_of(stack) {
+                    selected_text += &format!(
+                        " [{:+} since the previous interval]",
+                        latest as i64 - previous as i64
+                    );
+                }
+                if let Some((held, total)) = self.app.gil_held_of(stack) {
+                    selected_text += &format!(
+                        " [GIL held in {:.2}%]",
+                        100.0 * held as f64 / total.max(1) as f64
+                    );
+                }
+                if let Some((baseline, latest)) = self.app.baseline_of(stack) {
+                    let percent = |count: u64| 100.0 * count as f64 / root_total_count as f64;
+                    selected_text += &format!(
+                        " [{:+.2}% of all since the baseline]",
+                        percent(latest) - percent(baseline)
+                    );
+                }
                 let status_text = format!("{:width$}", selected_text, width = width as usize,);
                 if self.is_flamegraph_view() {
                     lines.push(("Selected", Line::from(status_text)));
+                    if let Some(note) = self.app.get_selected_note() {
+                        lines.push(("Note", Line::from(note)));
+                    }
                 }
                 if self.app.debug {
-                    let elapsed_str = format!(
+                    let mut elapsed_str = format!(
                         "Debug: {}",
                         self.app
                             .elapsed
@@ -643,10 +1971,20 @@ impl<'a> FlamelensWidget<'a> {
                             .collect::<Vec<String>>()
                             .join(" ")
                     );
+                    if let Some(rss) = resident_memory() {
+                        elapsed_str += &format!(" rss:{}", format_memory(rss));
+                    }
                     lines.push(("Debug", Line::from(elapsed_str)));
                 }
-                if let Some(transient_message) = &self.app.transient_message {
-                    lines.push(("Info", Line::from(transient_message.as_str())));
+                if let Some(message) = self.app.messages.current() {
+                    let mut spans = vec![Span::from(message)];
+                    let num_queued = self.app.messages.num_queued();
+                    if num_queued > 0 {
+                        spans.push(
+                            Span::from(format!(" (+{} more, M for all)", num_queued)).italic(),
+                        );
+                    }
+                    lines.push(("Info", Line::from(spans)));
                 }
                 lines
             }
@@ -655,15 +1993,16 @@ impl<'a> FlamelensWidget<'a> {
     }
 
     fn get_count_stats_str(
+        &self,
         name: Option<&str>,
         count: u64,
         total_count: u64,
         zoomed_total_count: Option<u64>,
     ) -> String {
         format!(
-            "[{}{} samples, {:.2}% of all{}]",
+            "[{}{}, {:.2}% of all{}]",
             name.map(|n| format!("{}: ", n)).unwrap_or_default(),
-            count,
+            self.app.flamegraph().format_count(count),
             (count as f64 / total_count as f64) * 100.0,
             if let Some(zoomed_total_count) = zoomed_total_count {
                 format!(
@@ -689,26 +2028,84 @@ impl<'a> FlamelensWidget<'a> {
     }
 }
 
+struct HelpTag {
+    tag: &'static str,
+    description: &'static str,
+    essential: bool,
+}
+
 struct HelpTags {
-    tags: Vec<(&'static str, &'static str)>,
-    default: Vec<(&'static str, &'static str)>,
+    tags: Vec<HelpTag>,
+    default: Vec<HelpTag>,
 }
 
 impl HelpTags {
     fn new() -> Self {
         Self {
             tags: vec![],
-            default: vec![("r", "reset"), ("tab", "switch view"), ("q", "quit")],
+            default: vec![
+                HelpTag {
+                    tag: "r",
+                    description: "reset",
+                    essential: false,
+                },
+                HelpTag {
+                    tag: "tab",
+                    description: "switch view",
+                    essential: true,
+                },
+                HelpTag {
+                    tag: "q",
+                    description: "quit",
+                    essential: true,
+                },
+            ],
         }
     }
 
     fn add(&mut self, tag: &'static str, description: &'static str) {
-        self.tags.push((tag, description));
+        self.tags.push(HelpTag {
+            tag,
+            description,
+            essential: false,
+        });
+    }
+
+    /// Add a tag that is still shown when only the essential keys are shown.
+    fn add_essential(&mut self, tag: &'static str, description: &'static str) {
+        self.tags.push(HelpTag {
+            tag,
+            description,
+            essential: true,
+        });
+    }
+
+    /// Line showing the tags for the given mode. In full mode, non-essential tags are dropped
+    /// starting from the last one until the line fits in the width.
+    fn get_line(&self, mode: HintsMode, width: u16) -> Line<'static> {
+        let mut tags = self
+            .tags
+            .iter()
+            .chain(self.default.iter())
+            .filter(|tag| mode == HintsMode::Full || tag.essential)
+            .collect::<Vec<_>>();
+        while Self::get_line_for_tags(&tags).width() > width as usize {
+            match tags.iter().rposition(|tag| !tag.essential) {
+                Some(i) => {
+                    tags.remove(i);
+                }
+                None => break,
+            }
+        }
+        Self::get_line_for_tags(&tags)
     }
 
-    fn get_line(&self) -> Line<'static> {
+    fn get_line_for_tags(tags: &[&HelpTag]) -> Line<'static> {
         let mut spans = vec![Span::from(" ")];
-        for (tag, description) in self.tags.iter().chain(self.default.iter()) {
+        for HelpTag {
+            tag, description, ..
+        } in tags.iter()
+        {
             spans.push(Span::from("["));
             spans.push(Span::styled(
                 *tag,
@@ -721,21 +2118,103 @@ impl HelpTags {
     }
 }
 
-/// Renders the user interface widgets.
-pub fn render(app: &mut App, frame: &mut Frame) {
+/// Centered popup listing items to pick one, keeping the selected one in view.
+fn render_list_popup(
+    title: &str,
+    labels: &[String],
+    selected: usize,
+    highlight: Color,
+    area: Rect,
+    buf: &mut Buffer,
+) {
+    let width = std::cmp::min(area.width, MESSAGE_LOG_MAX_WIDTH);
+    let height = std::cmp::min(area.height, labels.len() as u16 + 2);
+    let area = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let num_visible = height.saturating_sub(2) as usize;
+    let offset = (selected + 1).saturating_sub(num_visible);
+    let lines = labels
+        .iter()
+        .enumerate()
+        .skip(offset)
+        .take(num_visible)
+        .map(|(i, label)| {
+            let line = Line::from(format!(" {}", label));
+            if i == selected {
+                line.bg(highlight).bold()
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>();
+    Clear.render(area, buf);
+    Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .render(area, buf);
+}
+
+/// Renders the user interface widgets. Returns what is left to draw over them: the hyperlink to the
+/// source of the selected frame over its name, and the flamegraph as an image.
+pub fn render(app: &mut App, frame: &mut Frame) -> Overlays {
     // This is where you add new widgets.
     // See the following resources:
     // - https://docs.rs/ratatui/latest/ratatui/widgets/index.html
     // - https://github.com/ratatui-org/ratatui/tree/master/examples
+    let mut flamelens_state = FlamelensWidgetState {
+        rendered_flamegraph: app.rendered_flamegraph.take(),
+        ..Default::default()
+    };
     let flamelens_widget = FlamelensWidget::new(app);
-    let mut flamelens_state = FlamelensWidgetState::default();
     frame.render_stateful_widget(flamelens_widget, frame.size(), &mut flamelens_state);
+    app.rendered_flamegraph = flamelens_state.rendered_flamegraph.take();
     app.flamegraph_view
         .set_frame_height(flamelens_state.frame_height);
     app.flamegraph_view
         .set_frame_width(flamelens_state.frame_width);
     app.add_elapsed("render", flamelens_state.render_time);
+    if app.flamegraph_state().view_kind == ViewKind::Summary {
+        app.flamegraph_view
+            .clamp_summary_offset(flamelens_state.summary_num_lines);
+    }
     if let Some(input_buffer) = &mut app.input_buffer {
         input_buffer.cursor = flamelens_state.cursor_position;
     }
+    let selected = app.flamegraph_state().selected;
+    if app.selected_shown_since.map(|(id, _)| id) != Some(selected) {
+        app.selected_shown_since = Some((selected, std::time::Instant::now()));
+    }
+    app.selected_overflows = flamelens_state.selected_overflows;
+    Overlays {
+        hyperlink: flamelens_state
+            .selected_name_area
+            .and_then(|area| selected_hyperlink(app, area)),
+        image: flamelens_state.image,
+    }
+}
+
+/// Link to the source of the selected frame, over its name drawn in `area`.
+fn selected_hyperlink(app: &App, area: Rect) -> Option<Hyperlink> {
+    let stack = app.flamegraph_view.get_selected_stack()?;
+    let text = app.flamegraph().get_stack_short_name_from_info(stack);
+    // Only when the name is drawn as is, without being wrapped
+    if text.chars().count() > area.width as usize || text.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let info = stack.frame_info.as_deref()?;
+    let url = source_link(
+        &app.config.link_template,
+        info.file.as_deref()?,
+        info.line,
+        app.input_path(),
+    )?;
+    Some(Hyperlink {
+        x: area.x,
+        y: area.y,
+        text: text.to_string(),
+        url,
+    })
 }
@@ -1,15 +1,37 @@
+use std::cell::OnceCell;
 use std::cmp::min;
 
 use crate::{
-    flame::{FlameGraph, SearchPattern, SortColumn, StackIdentifier, StackInfo, ROOT_ID},
-    state::{FlameGraphState, ZoomState},
+    flame::{FlameGraph, SearchPattern, SortColumn, StackIdentifier, StackInfo, TableRow, ROOT_ID},
+    state::{
+        FlameGraphState, History, MinWidth, SavedSearchPattern, SavedViewState, ViewKind, ZoomState,
+    },
+    summary::{module_name, Summary},
 };
 
+/// How a frame is laid out in the flamegraph, to tell why a frame is not drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    pub level: usize,
+    /// Share of the width of the whole flamegraph
+    pub width_factor: f64,
+    /// Factor the frames under the zoomed frame are widened by, if zoomed
+    pub zoom_factor: Option<f64>,
+    /// Width in cells and the minimum width to be drawn, once the size of the flamegraph is known
+    pub cells: Option<f64>,
+    pub min_cells: Option<f64>,
+    /// Why the frame is not drawn, if it is not
+    pub hidden_because: Option<String>,
+}
+
 #[derive(Debug)]
 pub struct FlameGraphView {
     pub flamegraph: FlameGraph,
     pub state: FlameGraphState,
     pub updated_at: std::time::Instant,
+    history: History,
+    /// Statistics shown in the summary view, computed on first use for each flamegraph
+    summary: OnceCell<Summary>,
 }
 
 impl FlameGraphView {
@@ -18,9 +40,55 @@ impl FlameGraphView {
             flamegraph,
             state: FlameGraphState::default(),
             updated_at: std::time::Instant::now(),
+            history: History::default(),
+            summary: OnceCell::new(),
+        }
+    }
+
+    /// Statistics of the flamegraph for the summary view.
+    pub fn summary(&self) -> &Summary {
+        self.summary.get_or_init(|| Summary::new(&self.flamegraph))
+    }
+
+    /// Apply a change to the view and record it in the undo history if the state changed.
+    pub fn with_history<F: FnOnce(&mut Self)>(&mut self, f: F) {
+        let before = self.save_state();
+        f(self);
+        if self.save_state() != before {
+            self.history.push(before);
+        }
+    }
+
+    /// Returns false if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        match self.history.undo(self.save_state()) {
+            Some(previous) => {
+                self.apply_saved_state(&previous);
+                true
+            }
+            None => false,
         }
     }
 
+    /// Returns false if there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        match self.history.redo(self.save_state()) {
+            Some(next) => {
+                self.apply_saved_state(&next);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn apply_saved_state(&mut self, saved: &SavedViewState) {
+        self.state.select_root();
+        self.state.level_offset = 0;
+        self.state.unset_zoom();
+        self.unset_search_pattern();
+        self.restore_state(saved);
+    }
+
     pub fn select_id(&mut self, stack_id: &StackIdentifier) {
         self.state.select_id(stack_id);
         if let Some(p) = self.state.search_pattern.as_ref() {
@@ -38,11 +106,30 @@ impl FlameGraphView {
     pub fn replace_flamegraph(&mut self, mut new_flamegraph: FlameGraph) {
         self.state
             .handle_flamegraph_replacement(&self.flamegraph, &mut new_flamegraph);
-        // Preserve the sort column
+        new_flamegraph.set_baseline(self.flamegraph.ordered_stacks.baseline.clone());
+        // Preserve the sort column and table filter
         new_flamegraph
             .ordered_stacks
             .set_sort_column(self.flamegraph.ordered_stacks.sorted_column);
+        if let Some(filter) = &self.flamegraph.ordered_stacks.filter {
+            new_flamegraph.ordered_stacks.set_filter(filter);
+        }
+        if let Some(groups) = &self.flamegraph.ordered_stacks.groups {
+            let expanded = groups
+                .iter()
+                .filter(|g| g.expanded)
+                .map(|g| g.name.clone())
+                .collect::<Vec<_>>();
+            new_flamegraph.set_grouped(true, &expanded);
+        }
+        if self.flamegraph.ordered_stacks.thread_counts.is_some() {
+            new_flamegraph.set_thread_breakdown(true);
+        }
+        if self.state.view_kind == ViewKind::Table {
+            new_flamegraph.build_ordered_stacks();
+        }
         self.flamegraph = new_flamegraph;
+        self.summary = OnceCell::new();
         // Now the id in ZoomState points to the one in new flamegraph, but the ancestors and
         // descendants are not. Set the zoom again to update them.
         if let Some(zoom) = &self.state.zoom {
@@ -70,8 +157,9 @@ impl FlameGraphView {
 
     pub fn to_child_stack(&mut self) {
         if let Some(stack) = self.flamegraph.get_stack(&self.state.selected) {
-            let mut children_stacks = stack
-                .children
+            let mut children_stacks = self
+                .flamegraph
+                .get_children(stack)
                 .iter()
                 .filter_map(|x| self.flamegraph.get_stack(x))
                 .collect::<Vec<_>>();
@@ -95,6 +183,172 @@ impl FlameGraphView {
         }
     }
 
+    /// Descend from the selected frame along the heaviest children down to a leaf.
+    pub fn to_heaviest_leaf(&mut self) {
+        let mut stack_id = self.state.selected;
+        while let Some(child) = self.flamegraph.get_stack(&stack_id).and_then(|stack| {
+            self.flamegraph
+                .get_children(stack)
+                .iter()
+                .filter_map(|x| self.flamegraph.get_stack(x))
+                .filter(|x| self.is_stack_visibly_wide(x, None))
+                .max_by_key(|x| x.total_count)
+        }) {
+            stack_id = child.id;
+        }
+        if stack_id == self.state.selected {
+            return;
+        }
+        self.select_id(&stack_id);
+        if let Some(stack) = self.get_selected_stack() {
+            if !self.is_stack_in_view_port(stack) {
+                self.scroll_selected_to_bottom();
+            }
+        }
+    }
+
+    /// Select where a random sample under the zoomed frame ends, so that stacks are picked in
+    /// proportion to their samples. Returns the frame the sample ends in, which may be too narrow
+    /// to be drawn and then has its nearest visible ancestor selected instead.
+    pub fn to_random_sample(&mut self) -> Option<StackIdentifier> {
+        let total_count = self
+            .flamegraph
+            .get_stack(&self.zoom_or_root_id())
+            .map(|stack| stack.total_count)
+            .unwrap_or(0);
+        if total_count == 0 {
+            return None;
+        }
+        self.to_sample(fastrand::u64(..total_count))
+    }
+
+    pub fn to_sample(&mut self, sample: u64) -> Option<StackIdentifier> {
+        let leaf = self
+            .flamegraph
+            .get_stack_of_sample(&self.zoom_or_root_id(), sample)?;
+        let stack_id = self.nearest_visible_ancestor(leaf);
+        self.select_id(&stack_id);
+        if let Some(stack) = self.get_selected_stack() {
+            if !self.is_stack_in_view_port(stack) {
+                self.scroll_selected_to_bottom();
+            }
+        }
+        Some(leaf)
+    }
+
+    /// Leave out frames narrower than `min_width`, selecting the nearest ancestor still drawn if
+    /// the selected frame is left out.
+    pub fn set_min_width(&mut self, min_width: MinWidth) {
+        self.state.min_width = min_width;
+        let stack_id = self.nearest_visible_ancestor(self.state.selected);
+        self.select_id(&stack_id);
+    }
+
+    /// The stack itself if wide enough to be drawn, or its nearest ancestor that is.
+    fn nearest_visible_ancestor(&self, mut stack_id: StackIdentifier) -> StackIdentifier {
+        while let Some(stack) = self.flamegraph.get_stack(&stack_id) {
+            match stack.parent {
+                Some(parent) if !self.is_stack_visibly_wide(stack, None) => stack_id = parent,
+                _ => break,
+            }
+        }
+        stack_id
+    }
+
+    fn zoom_or_root_id(&self) -> StackIdentifier {
+        self.state
+            .zoom
+            .as_ref()
+            .map(|zoom| zoom.stack_id)
+            .unwrap_or(ROOT_ID)
+    }
+
+    /// Select the nearest ancestor with the same name as the selected frame. Returns false if
+    /// there is none.
+    pub fn to_same_name_ancestor(&mut self) -> bool {
+        let Some(short_name) = self.flamegraph.get_stack_short_name(&self.state.selected) else {
+            return false;
+        };
+        let ancestor = self
+            .flamegraph
+            .get_ancestors(&self.state.selected)
+            .into_iter()
+            .skip(1)
+            .find(|id| {
+                *id != ROOT_ID && self.flamegraph.get_stack_short_name(id) == Some(short_name)
+            });
+        match ancestor {
+            Some(stack_id) => {
+                self.select_id(&stack_id);
+                self.scroll_to_selected();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Select the thread frame after or before the one the selected frame is in, wrapping around,
+    /// or the first or last one if the selected frame is in none. Returns false if the flamegraph
+    /// has no thread frames.
+    pub fn to_adjacent_thread(&mut self, forward: bool) -> bool {
+        let threads = self.flamegraph.thread_frames();
+        if threads.is_empty() {
+            return false;
+        }
+        let mut ancestors = self.flamegraph.get_ancestors(&self.state.selected);
+        ancestors.push(self.state.selected);
+        let current = threads.iter().position(|id| ancestors.contains(id));
+        let index = match (current, forward) {
+            (Some(i), true) => (i + 1) % threads.len(),
+            (Some(i), false) => (i + threads.len() - 1) % threads.len(),
+            (None, true) => 0,
+            (None, false) => threads.len() - 1,
+        };
+        self.select_id(&threads[index]);
+        self.scroll_to_selected();
+        true
+    }
+
+    /// Select the shallowest descendant with the same name as the selected frame, preferring the
+    /// heaviest one if there are several at the same level. Returns false if there is none.
+    pub fn to_same_name_descendant(&mut self) -> bool {
+        if self.is_root_selected() {
+            return false;
+        }
+        let Some(short_name) = self.flamegraph.get_stack_short_name(&self.state.selected) else {
+            return false;
+        };
+        let mut current_level = vec![self.state.selected];
+        let mut descendant = None;
+        while descendant.is_none() && !current_level.is_empty() {
+            let next_level = current_level
+                .iter()
+                .filter_map(|id| self.flamegraph.get_stack(id))
+                .flat_map(|stack| self.flamegraph.get_children(stack).iter())
+                .filter_map(|id| self.flamegraph.get_stack(id))
+                .filter(|stack| self.is_stack_visibly_wide(stack, None))
+                .collect::<Vec<_>>();
+            descendant = next_level
+                .iter()
+                .filter(|stack| self.flamegraph.get_stack_short_name_from_info(stack) == short_name)
+                .max_by_key(|stack| stack.total_count)
+                .map(|stack| stack.id);
+            current_level = next_level.iter().map(|stack| stack.id).collect();
+        }
+        match descendant {
+            Some(stack_id) => {
+                self.select_id(&stack_id);
+                if let Some(stack) = self.get_selected_stack() {
+                    if !self.is_stack_in_view_port(stack) {
+                        self.scroll_selected_to_bottom();
+                    }
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn to_parent_stack(&mut self) {
         // TODO: maybe also check parent visibility to handle resizing / edge cases
         if let Some(parent) = self
@@ -134,21 +388,68 @@ impl FlameGraphView {
                 expected_frame_width *= zoom_factor;
             } else if let Some(zoom) = &self.state.zoom {
                 let adjusted_frame_width = expected_frame_width * zoom.zoom_factor;
-                // Important: Must short circuit by checking the adjusted_frame_width >= 1.0
-                // condition first because the is_ancestor_or_descendant check is expensive for very
-                // deep call stacks.
-                if adjusted_frame_width >= 1.0 && zoom.is_ancestor_or_descendant(&stack.id) {
+                // Important: Must short circuit by checking the adjusted_frame_width condition
+                // first because the is_ancestor_or_descendant check is expensive for very deep call
+                // stacks.
+                if adjusted_frame_width >= self.state.min_width.cells(frame_width)
+                    && zoom.is_ancestor_or_descendant(&stack.id)
+                {
                     expected_frame_width = adjusted_frame_width;
                 } else {
                     return false;
                 }
             }
-            expected_frame_width >= 1.0
+            expected_frame_width >= self.state.min_width.cells(frame_width)
         } else {
             true
         }
     }
 
+    pub fn get_placement(&self, stack_id: &StackIdentifier) -> Option<Placement> {
+        let stack = self.flamegraph.get_stack(stack_id)?;
+        let zoom = self.state.zoom.as_ref();
+        // Ancestors of the zoomed frame, and the zoomed frame itself, take the full width
+        let share = match zoom {
+            Some(zoom) if zoom.ancestors.contains(stack_id) => 1.0,
+            Some(zoom) if zoom.descendants.contains(stack_id) => {
+                stack.width_factor * zoom.zoom_factor
+            }
+            Some(_) => 0.0,
+            None => stack.width_factor,
+        };
+        let cells = self.state.frame_width.map(|width| share * width as f64);
+        let min_cells = self
+            .state
+            .frame_width
+            .map(|width| self.state.min_width.cells(width));
+        let hidden_because = if share == 0.0 {
+            Some("outside the zoomed frame".to_string())
+        } else if stack.level < self.state.level_offset {
+            Some(format!(
+                "above the top row, scrolled to level {}",
+                self.state.level_offset
+            ))
+        } else if !self.is_stack_in_view_port(stack) {
+            Some("below the bottom row".to_string())
+        } else if matches!((cells, min_cells), (Some(cells), Some(min_cells)) if cells < min_cells)
+        {
+            Some(format!(
+                "narrower than the minimum width of {}",
+                self.state.min_width
+            ))
+        } else {
+            None
+        };
+        Some(Placement {
+            level: stack.level,
+            width_factor: stack.width_factor,
+            zoom_factor: zoom.map(|zoom| zoom.zoom_factor),
+            cells,
+            min_cells,
+            hidden_because,
+        })
+    }
+
     fn select_stack_in_view_port(&mut self) {
         if let Some(stacks) = self.flamegraph.get_stacks_at_level(self.state.level_offset) {
             for stack_id in stacks {
@@ -179,11 +480,11 @@ impl FlameGraphView {
         self.state.selected == ROOT_ID
     }
 
+    /// Next visible frame at the same level, which may belong to a different parent.
     pub fn get_next_sibling(&self, stack_id: &StackIdentifier) -> Option<StackIdentifier> {
         let stack = self.flamegraph.get_stack(stack_id)?;
         let level = self.flamegraph.get_stacks_at_level(stack.level)?;
-        let level_idx = level.iter().position(|x| x == stack_id)?;
-        for sibling_id in level[level_idx + 1..].iter() {
+        for sibling_id in level[stack.level_index + 1..].iter() {
             if let Some(stack) = self.flamegraph.get_stack(sibling_id) {
                 if self.is_stack_visibly_wide(stack, None) {
                     return Some(sibling_id).cloned();
@@ -193,11 +494,11 @@ impl FlameGraphView {
         None
     }
 
+    /// Previous visible frame at the same level, which may belong to a different parent.
     pub fn get_previous_sibling(&self, stack_id: &StackIdentifier) -> Option<StackIdentifier> {
         let stack = self.flamegraph.get_stack(stack_id)?;
         let level = self.flamegraph.get_stacks_at_level(stack.level)?;
-        let level_idx = level.iter().position(|x| x == stack_id)?;
-        for sibling_id in level[..level_idx].iter().rev() {
+        for sibling_id in level[..stack.level_index].iter().rev() {
             if let Some(stack) = self.flamegraph.get_stack(sibling_id) {
                 if self.is_stack_visibly_wide(stack, None) {
                     return Some(sibling_id).cloned();
@@ -254,6 +555,30 @@ impl FlameGraphView {
         }
     }
 
+    /// Move up to `count` siblings to the left, stopping at the first one.
+    pub fn to_nth_previous_sibling(&mut self, count: usize) {
+        let mut stack_id = self.state.selected;
+        for _ in 0..count {
+            match self.get_previous_sibling(&stack_id) {
+                Some(previous) => stack_id = previous,
+                None => break,
+            }
+        }
+        self.select_id(&stack_id);
+    }
+
+    /// Move up to `count` siblings to the right, stopping at the last one.
+    pub fn to_nth_next_sibling(&mut self, count: usize) {
+        let mut stack_id = self.state.selected;
+        for _ in 0..count {
+            match self.get_next_sibling(&stack_id) {
+                Some(next) => stack_id = next,
+                None => break,
+            }
+        }
+        self.select_id(&stack_id);
+    }
+
     pub fn to_previous_search_result(&mut self) {
         if let Some(previous_id) = self.get_previous_hit() {
             self.select_id(&previous_id);
@@ -272,59 +597,26 @@ impl FlameGraphView {
         // Nothing to do if not searching
         let _ = self.state.search_pattern.as_ref()?;
 
-        // Get from the current level
-        let selected_stack = self.flamegraph.get_stack(&self.state.selected)?;
-        let level_stacks = self.flamegraph.get_stacks_at_level(selected_stack.level)?;
-        let next_hit = self.get_next_hit_same_level(level_stacks.iter());
-        if next_hit.is_some() {
-            return next_hit;
-        }
-
-        // Get from the next level
-        self.flamegraph.hit_ids().and_then(|hit_ids| {
-            hit_ids
-                .iter()
-                .filter_map(|x| self.flamegraph.get_stack(x))
-                .filter(|x| x.level > selected_stack.level && self.is_stack_visibly_wide(x, None))
-                .map(|x| x.id)
-                .next()
-        })
+        // Get from the rest of the current level, then from the levels below
+        let (_, after) = self.flamegraph.split_hit_ids_at(&self.state.selected);
+        self.find_visible_hit(after.iter())
     }
 
     pub fn get_previous_hit(&self) -> Option<StackIdentifier> {
         // Nothing to do if not searching
         let _ = self.state.search_pattern.as_ref()?;
 
-        // Get from the current level
-        let selected_stack = self.flamegraph.get_stack(&self.state.selected)?;
-        let level_stacks = self.flamegraph.get_stacks_at_level(selected_stack.level)?;
-        let hit = self.get_next_hit_same_level(level_stacks.iter().rev());
-        if hit.is_some() {
-            return hit;
-        }
-
-        // Get from the previous level
-        self.flamegraph.hit_ids().and_then(|hit_ids| {
-            hit_ids
-                .iter()
-                .rev()
-                .filter_map(|x| self.flamegraph.get_stack(x))
-                .filter(|x| x.level < selected_stack.level && self.is_stack_visibly_wide(x, None))
-                .map(|x| x.id)
-                .next()
-        })
+        // Get from the start of the current level backwards, then from the levels above
+        let (before, _) = self.flamegraph.split_hit_ids_at(&self.state.selected);
+        self.find_visible_hit(before.iter().rev())
     }
 
-    fn get_next_hit_same_level<'a, I>(&self, level_stacks: I) -> Option<StackIdentifier>
+    fn find_visible_hit<'a, I>(&self, candidates: I) -> Option<StackIdentifier>
     where
         I: Iterator<Item = &'a StackIdentifier>,
     {
-        let same_level_candidates = level_stacks
+        candidates
             .filter_map(|x| self.flamegraph.get_stack(x))
-            .skip_while(|x| x.id != self.state.selected)
-            .skip(1); // skip the selected stack
-        same_level_candidates
-            .filter(|x| x.hit)
             .find(|x| self.is_stack_visibly_wide(x, None))
             .map(|x| x.id)
     }
@@ -341,37 +633,78 @@ impl FlameGraphView {
         self.keep_selected_stack_in_view_port();
     }
 
+    pub fn scroll_to_level(&mut self, level: usize) {
+        self.set_level_offset(level);
+        self.keep_selected_stack_in_view_port();
+    }
+
     pub fn scroll_to_selected(&mut self) {
         if let Some(stack) = self.get_selected_stack() {
-            if !self.is_stack_in_view_port(stack) {
+            // The view port is unknown before the first render, e.g. when selecting a frame on
+            // startup, so scroll to the selected frame to be safe
+            if self.state.frame_height.is_none() || !self.is_stack_in_view_port(stack) {
                 self.set_level_offset(stack.level);
             }
         }
     }
 
-    pub fn page_down(&mut self) {
-        if let (Some(frame_height), Some(bottom_offset)) =
-            (self.state.frame_height, self.get_bottom_level_offset())
-        {
-            self.set_level_offset(min(
-                self.state.level_offset + frame_height as usize,
-                bottom_offset,
-            ));
+    pub fn scroll_down(&mut self, num_levels: usize) {
+        if let Some(bottom_offset) = self.get_bottom_level_offset() {
+            self.set_level_offset(min(self.state.level_offset + num_levels, bottom_offset));
             self.keep_selected_stack_in_view_port();
         }
     }
 
+    pub fn scroll_up(&mut self, num_levels: usize) {
+        self.set_level_offset(self.state.level_offset.saturating_sub(num_levels));
+        self.keep_selected_stack_in_view_port();
+    }
+
+    pub fn page_down(&mut self) {
+        if let Some(frame_height) = self.state.frame_height {
+            self.scroll_down(frame_height as usize);
+        }
+    }
+
     pub fn page_up(&mut self) {
         if let Some(frame_height) = self.state.frame_height {
-            self.set_level_offset(
-                self.state
-                    .level_offset
-                    .saturating_sub(frame_height as usize),
-            );
-            self.keep_selected_stack_in_view_port();
+            self.scroll_up(frame_height as usize);
+        }
+    }
+
+    pub fn half_page_down(&mut self) {
+        if let Some(frame_height) = self.state.frame_height {
+            self.scroll_down((frame_height as usize / 2).max(1));
+        }
+    }
+
+    pub fn half_page_up(&mut self) {
+        if let Some(frame_height) = self.state.frame_height {
+            self.scroll_up((frame_height as usize / 2).max(1));
+        }
+    }
+
+    /// Scroll so that the selected frame is at the given row of the viewport, as far as possible.
+    fn scroll_selected_to_row(&mut self, row: usize) {
+        if let Some(level) = self.get_selected_stack().map(|stack| stack.level) {
+            self.set_level_offset(level.saturating_sub(row));
         }
     }
 
+    pub fn scroll_selected_to_center(&mut self) {
+        let frame_height = self.state.frame_height.unwrap_or(1) as usize;
+        self.scroll_selected_to_row(frame_height / 2);
+    }
+
+    pub fn scroll_selected_to_top(&mut self) {
+        self.scroll_selected_to_row(0);
+    }
+
+    pub fn scroll_selected_to_bottom(&mut self) {
+        let frame_height = self.state.frame_height.unwrap_or(1) as usize;
+        self.scroll_selected_to_row(frame_height.saturating_sub(1));
+    }
+
     pub fn set_zoom_for_id(&mut self, stack_id: StackIdentifier) {
         if let Some(selected_stack) = self.flamegraph.get_stack(&stack_id) {
             let zoom_factor =
@@ -388,46 +721,214 @@ impl FlameGraphView {
                     descendants,
                 };
                 self.state.set_zoom(zoom);
+                if self.state.search_in_zoom {
+                    self.refresh_search_scope();
+                }
             }
         }
     }
 
     pub fn set_zoom(&mut self) {
-        self.set_zoom_for_id(self.state.selected);
+        self.with_history(|view| view.set_zoom_for_id(view.state.selected));
     }
 
-    pub fn unset_zoom(&mut self) {
-        if let Some(zoom_stack_id) = self.state.zoom.as_ref().map(|z| z.stack_id) {
-            // Restore selected to previous zoom point
-            self.select_id(&zoom_stack_id);
+    /// Zoom in on the lowest common ancestor of all frames matching the manual search. Returns
+    /// false if there is no manual search or the matches only share the root.
+    pub fn zoom_to_search_coverage(&mut self) -> bool {
+        if !self
+            .state
+            .search_pattern
+            .as_ref()
+            .is_some_and(|p| p.is_manual)
+        {
+            return false;
+        }
+        let stack_id = self
+            .flamegraph
+            .hit_ids()
+            .and_then(|ids| self.flamegraph.get_lowest_common_ancestor(ids));
+        match stack_id {
+            Some(stack_id) if stack_id != ROOT_ID => {
+                self.with_history(|view| {
+                    view.select_id(&stack_id);
+                    view.set_zoom_for_id(stack_id);
+                    view.scroll_to_selected();
+                });
+                true
+            }
+            _ => false,
         }
-        self.state.unset_zoom();
+    }
+
+    /// Select and zoom in on the occurrence of a function with the most samples.
+    pub fn zoom_to_heaviest(&mut self, short_name: &str) -> bool {
+        self.zoom_to_heaviest_matching(|name| name == short_name)
+    }
+
+    /// Select and zoom in on the frame with the most samples among those whose name satisfies
+    /// `matches`.
+    pub fn zoom_to_heaviest_matching<F>(&mut self, matches: F) -> bool
+    where
+        F: Fn(&str) -> bool,
+    {
+        let Some(stack_id) = self.flamegraph.get_heaviest_stack_matching(matches) else {
+            return false;
+        };
+        self.with_history(|view| {
+            view.select_id(&stack_id);
+            view.set_zoom_for_id(stack_id);
+            view.scroll_to_selected();
+        });
+        self.state.set_view_kind(ViewKind::FlameGraph);
+        true
+    }
+
+    /// Zoom out by one level, onto the parent of the zoomed frame, keeping the selection. Returns
+    /// false if not zoomed in.
+    pub fn zoom_to_parent(&mut self) -> bool {
+        let Some(zoom_stack_id) = self.state.zoom.as_ref().map(|z| z.stack_id) else {
+            return false;
+        };
+        match self
+            .flamegraph
+            .get_stack(&zoom_stack_id)
+            .and_then(|stack| stack.parent)
+        {
+            Some(parent) if parent != ROOT_ID => {
+                self.with_history(|view| view.set_zoom_for_id(parent));
+            }
+            // Unlike `unset_zoom`, stay on the selected frame
+            _ => self.with_history(|view| {
+                view.state.unset_zoom();
+                if view.state.search_in_zoom {
+                    view.refresh_search_scope();
+                }
+            }),
+        }
+        true
+    }
+
+    pub fn unset_zoom(&mut self) {
+        self.with_history(|view| {
+            if let Some(zoom_stack_id) = view.state.zoom.as_ref().map(|z| z.stack_id) {
+                // Restore selected to previous zoom point
+                view.select_id(&zoom_stack_id);
+            }
+            view.state.unset_zoom();
+            if view.state.search_in_zoom {
+                view.refresh_search_scope();
+            }
+        });
     }
 
     pub fn set_search_pattern(&mut self, search_pattern: SearchPattern) {
-        self.flamegraph.set_hits(&search_pattern);
+        self.keep_selected_row(|view| {
+            view.flamegraph
+                .set_hits_under(&search_pattern, view.get_search_scope());
+        });
         self.state.set_search_pattern(search_pattern);
     }
 
+    /// Frame under which searches match, which is the zoomed frame if searching in zoom.
+    fn get_search_scope(&self) -> StackIdentifier {
+        match &self.state.zoom {
+            Some(zoom) if self.state.search_in_zoom => zoom.stack_id,
+            _ => ROOT_ID,
+        }
+    }
+
+    /// Match the current search pattern again after the search scope changes.
+    fn refresh_search_scope(&mut self) {
+        if let Some(p) = self.state.search_pattern.clone() {
+            self.flamegraph.set_hits_under(&p, self.get_search_scope());
+        }
+    }
+
+    pub fn toggle_search_in_zoom(&mut self) {
+        self.state.search_in_zoom = !self.state.search_in_zoom;
+        self.refresh_search_scope();
+    }
+
     pub fn unset_search_pattern(&mut self) {
-        self.flamegraph.clear_hits();
+        self.keep_selected_row(|view| view.flamegraph.clear_hits());
         self.state.unset_search_pattern();
     }
 
     pub fn unset_manual_search_pattern(&mut self) {
-        if let Some(p) = self.state.search_pattern.as_ref() {
-            if p.is_manual {
-                self.unset_search_pattern();
+        self.with_history(|view| {
+            if let Some(p) = view.state.search_pattern.as_ref() {
+                if p.is_manual {
+                    view.unset_search_pattern();
+                }
             }
-        }
+        });
     }
 
     pub fn reset(&mut self) {
-        self.state.select_root();
-        self.state.level_offset = 0;
-        self.state.unset_zoom();
-        self.state.table_state.reset();
-        self.unset_search_pattern();
+        self.with_history(|view| {
+            view.state.select_root();
+            view.state.level_offset = 0;
+            view.state.unset_zoom();
+            view.flamegraph.ordered_stacks.set_filter("");
+            view.state.table_state.reset();
+            view.state.summary_offset = 0;
+            view.unset_search_pattern();
+        });
+    }
+
+    pub fn save_state(&self) -> SavedViewState {
+        let full_name = |stack_id: &StackIdentifier| {
+            self.flamegraph
+                .get_stack_full_name(stack_id)
+                .map(|s| s.to_string())
+        };
+        SavedViewState {
+            selected: if self.is_root_selected() {
+                None
+            } else {
+                full_name(&self.state.selected)
+            },
+            zoom: self
+                .state
+                .zoom
+                .as_ref()
+                .and_then(|z| full_name(&z.stack_id)),
+            search: self
+                .state
+                .search_pattern
+                .as_ref()
+                .filter(|p| p.is_manual)
+                .map(|p| SavedSearchPattern {
+                    pattern: p.pattern.clone(),
+                    is_regex: p.is_regex,
+                }),
+            level_offset: self.state.level_offset,
+        }
+    }
+
+    /// Restore a previously saved state. Stacks that no longer exist in the current flamegraph
+    /// are silently skipped.
+    pub fn restore_state(&mut self, saved: &SavedViewState) {
+        if let Some(search) = &saved.search {
+            if let Ok(p) = SearchPattern::new(&search.pattern, search.is_regex, true) {
+                self.set_search_pattern(p);
+            }
+        }
+        if let Some(zoom_id) = saved
+            .zoom
+            .as_ref()
+            .and_then(|name| self.flamegraph.get_stack_id_by_full_name(name))
+        {
+            self.set_zoom_for_id(zoom_id);
+        }
+        if let Some(selected_id) = saved
+            .selected
+            .as_ref()
+            .and_then(|name| self.flamegraph.get_stack_id_by_full_name(name))
+        {
+            self.select_id(&selected_id);
+        }
+        self.set_level_offset(saved.level_offset);
     }
 
     pub fn to_next_row(&mut self) {
@@ -460,24 +961,231 @@ impl FlameGraphView {
         self.state.table_state.offset = new_value;
     }
 
+    pub fn to_first_row(&mut self) {
+        self.state.table_state.selected = 0;
+    }
+
+    pub fn to_last_row(&mut self) {
+        self.state.table_state.selected = self.flamegraph.ordered_stacks.num_rows.saturating_sub(1);
+    }
+
+    pub fn half_page_next_rows(&mut self) {
+        let delta = self.state.frame_height.unwrap_or(10) as usize / 2;
+        self.state.table_state.selected = min(
+            self.state.table_state.selected.saturating_add(delta.max(1)),
+            self.flamegraph.ordered_stacks.num_rows.saturating_sub(1),
+        );
+    }
+
+    pub fn half_page_previous_rows(&mut self) {
+        let delta = self.state.frame_height.unwrap_or(10) as usize / 2;
+        self.state.table_state.selected =
+            self.state.table_state.selected.saturating_sub(delta.max(1));
+    }
+
+    pub fn scroll_summary(&mut self, delta: isize) {
+        self.state.summary_offset = self.state.summary_offset.saturating_add_signed(delta);
+    }
+
+    pub fn clamp_summary_offset(&mut self, num_lines: usize) {
+        self.state.summary_offset = min(self.state.summary_offset, num_lines.saturating_sub(1));
+    }
+
     pub fn set_sort_by_own(&mut self) {
-        self.flamegraph
-            .ordered_stacks
-            .set_sort_column(SortColumn::Own);
+        self.set_sort_column(SortColumn::Own);
     }
 
     pub fn set_sort_by_total(&mut self) {
-        self.flamegraph
+        self.set_sort_column(SortColumn::Total);
+    }
+
+    pub fn set_sort_by_call_sites(&mut self) {
+        self.set_sort_column(SortColumn::CallSites);
+    }
+
+    pub fn set_sort_by_name(&mut self) {
+        self.set_sort_column(SortColumn::Name);
+    }
+
+    /// Sort by the change since the baseline. Returns false if there is no baseline.
+    pub fn set_sort_by_delta(&mut self) -> bool {
+        if self.flamegraph.ordered_stacks.baseline.is_none() {
+            return false;
+        }
+        self.set_sort_column(SortColumn::Delta);
+        true
+    }
+
+    fn set_sort_column(&mut self, column: SortColumn) {
+        self.keep_selected_row(|view| view.flamegraph.ordered_stacks.set_sort_column(column));
+    }
+
+    /// Apply a change to the table rows, such as sorting or searching, keeping the selected row
+    /// on the same function or module and in view. If it is no longer shown, the selection stays
+    /// at the same index within the remaining rows.
+    fn keep_selected_row<F>(&mut self, change: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        if !self.flamegraph.ordered_stacks.is_built() {
+            change(self);
+            return;
+        }
+        let selected_row = |view: &Self| {
+            let rows = view.flamegraph.ordered_stacks.rows();
+            match rows.get(view.state.table_state.selected)? {
+                TableRow::Entry(entry) => Some((false, entry.name.clone())),
+                TableRow::Group(group) => Some((true, group.name.clone())),
+            }
+        };
+        let selected = selected_row(self);
+        change(self);
+        let index = selected.and_then(|(is_group, name)| {
+            self.flamegraph
+                .ordered_stacks
+                .rows()
+                .iter()
+                .position(|row| match row {
+                    TableRow::Entry(entry) => !is_group && entry.name == name,
+                    TableRow::Group(group) => is_group && group.name == name,
+                })
+        });
+        let table_state = &mut self.state.table_state;
+        table_state.selected = index.unwrap_or(min(
+            table_state.selected,
+            self.flamegraph.ordered_stacks.num_rows.saturating_sub(1),
+        ));
+        let height = self
+            .state
+            .frame_height
+            .unwrap_or(10)
+            .saturating_sub(1)
+            .max(1) as usize;
+        if table_state.selected < table_state.offset
+            || table_state.selected >= table_state.offset + height
+        {
+            table_state.offset = table_state.selected.saturating_sub(height / 2);
+        }
+    }
+
+    pub fn set_table_filter(&mut self, filter: &str) {
+        self.flamegraph.ordered_stacks.set_filter(filter);
+        self.state.table_state.reset();
+    }
+
+    pub fn toggle_grouped(&mut self) {
+        let grouped = self.flamegraph.ordered_stacks.is_grouped();
+        self.flamegraph.set_grouped(!grouped, &[]);
+        self.state.table_state.reset();
+    }
+
+    /// Switch to the next view, carrying the selection over so that the selected frame and the
+    /// selected table row refer to the same function.
+    pub fn toggle_view_kind(&mut self) {
+        if self.state.view_kind == ViewKind::Table {
+            self.select_stack_of_selected_row();
+        }
+        self.state.toggle_view_kind();
+        if self.state.view_kind == ViewKind::Table {
+            self.flamegraph.build_ordered_stacks();
+            self.select_row_of_selected_stack();
+        }
+    }
+
+    fn select_row_of_selected_stack(&mut self) {
+        if self.is_root_selected() {
+            return;
+        }
+        let Some(short_name) = self.flamegraph.get_stack_short_name(&self.state.selected) else {
+            return;
+        };
+        let index = self
+            .flamegraph
             .ordered_stacks
-            .set_sort_column(SortColumn::Total);
+            .rows()
+            .iter()
+            .position(|row| match row {
+                TableRow::Entry(entry) => entry.name == short_name,
+                TableRow::Group(group) => !group.expanded && group.name == module_name(short_name),
+            });
+        if let Some(index) = index {
+            self.state.table_state.selected = index;
+        }
+    }
+
+    fn select_stack_of_selected_row(&mut self) {
+        let Some(short_name) = self.get_selected_row_name().map(|s| s.to_string()) else {
+            return;
+        };
+        if self.flamegraph.get_stack_short_name(&self.state.selected) == Some(&short_name) {
+            return;
+        }
+        if let Some(stack_id) = self.get_heaviest_stack_in_zoom(|name| name == short_name) {
+            self.select_id(&stack_id);
+            self.scroll_to_selected();
+        }
+    }
+
+    /// Heaviest frame whose name satisfies `matches`, only considering the zoomed frame and its
+    /// descendants if zoomed.
+    fn get_heaviest_stack_in_zoom<F>(&self, matches: F) -> Option<StackIdentifier>
+    where
+        F: Fn(&str) -> bool,
+    {
+        match &self.state.zoom {
+            Some(zoom) => zoom
+                .descendants
+                .iter()
+                .filter_map(|id| self.flamegraph.get_stack(id))
+                .filter(|stack| matches(self.flamegraph.get_stack_short_name_from_info(stack)))
+                .max_by_key(|stack| stack.total_count)
+                .map(|stack| stack.id),
+            None => self.flamegraph.get_heaviest_stack_matching(matches),
+        }
+    }
+
+    /// Select the heaviest frame matching the regex, within the current zoom if zoomed.
+    pub fn select_heaviest_matching(&mut self, re: &regex::Regex) -> bool {
+        match self.get_heaviest_stack_in_zoom(|name| re.is_match(name)) {
+            Some(stack_id) => {
+                self.select_id(&stack_id);
+                self.scroll_to_selected();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Show or hide the per-thread breakdown in the table. Returns false if it cannot be shown as
+    /// there are no thread frames.
+    pub fn toggle_thread_breakdown(&mut self) -> bool {
+        let enabled = self.flamegraph.ordered_stacks.thread_counts.is_some();
+        self.flamegraph.set_thread_breakdown(!enabled)
+    }
+
+    /// Expand or collapse the selected row if it is a group. Returns false if the selected row is
+    /// not a group.
+    pub fn toggle_selected_group(&mut self) -> bool {
+        let ordered_stacks = &mut self.flamegraph.ordered_stacks;
+        let group_name = match ordered_stacks.rows().get(self.state.table_state.selected) {
+            Some(TableRow::Group(group)) => group.name.clone(),
+            _ => return false,
+        };
+        ordered_stacks.toggle_group_expanded(&group_name);
+        true
     }
 
     pub fn get_selected_row_name(&mut self) -> Option<&str> {
-        self.flamegraph
+        match self
+            .flamegraph
             .ordered_stacks
-            .entries
-            .get(self.state.table_state.selected)
-            .map(|x| x.name.as_str())
+            .rows()
+            .into_iter()
+            .nth(self.state.table_state.selected)
+        {
+            Some(TableRow::Entry(entry)) => Some(entry.name.as_str()),
+            _ => None,
+        }
     }
 }
 
@@ -603,4 +1311,449 @@ mod tests {
         view.to_previous_search_result();
         assert_eq!(get_selected_short_name(&view), "level1-b");
     }
+
+    #[test]
+    fn test_save_and_restore_state() {
+        let content = std::fs::read_to_string("tests/data/py-spy-simple.txt").unwrap();
+        let fg = FlameGraph::from_string(content, true);
+        let mut view = FlameGraphView::new(fg.clone());
+
+        // Nothing to save in the default view
+        assert_eq!(view.save_state(), SavedViewState::default());
+
+        let zoom_name = "<module> (long_running.py:25)";
+        let selected_name = "<module> (long_running.py:25);work (long_running.py:7)";
+        view.set_search_pattern(SearchPattern::new("work", true, true).unwrap());
+        view.set_zoom_for_id(get_id(&view, zoom_name));
+        view.select_id(&get_id(&view, selected_name));
+        let saved = view.save_state();
+        assert_eq!(saved.selected.as_deref(), Some(selected_name));
+        assert_eq!(saved.zoom.as_deref(), Some(zoom_name));
+        assert_eq!(
+            saved.search,
+            Some(SavedSearchPattern {
+                pattern: "work".to_string(),
+                is_regex: true,
+            })
+        );
+
+        // Restore into a fresh view
+        let mut restored = FlameGraphView::new(fg);
+        restored.restore_state(&saved);
+        assert_eq!(restored.save_state(), saved);
+        assert_eq!(
+            get_selected_short_name(&restored),
+            "work (long_running.py:7)"
+        );
+        assert!(restored.flamegraph.hit_coverage_count().is_some());
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let content = std::fs::read_to_string("tests/data/py-spy-simple.txt").unwrap();
+        let fg = FlameGraph::from_string(content, true);
+        let mut view = FlameGraphView::new(fg);
+        let zoom_name = "<module> (long_running.py:25)";
+
+        // Nothing to undo or redo initially
+        assert!(!view.undo());
+        assert!(!view.redo());
+
+        // No-op changes are not recorded
+        view.unset_zoom();
+        assert!(!view.undo());
+
+        view.select_id(&get_id(&view, zoom_name));
+        view.set_zoom();
+        assert_eq!(view.save_state().zoom.as_deref(), Some(zoom_name));
+        view.reset();
+        assert_eq!(view.save_state(), SavedViewState::default());
+
+        // Undo the reset, then the zoom
+        assert!(view.undo());
+        assert_eq!(view.save_state().zoom.as_deref(), Some(zoom_name));
+        assert!(view.undo());
+        assert_eq!(view.save_state().zoom, None);
+        assert!(!view.undo());
+
+        // Redo the zoom
+        assert!(view.redo());
+        assert_eq!(view.save_state().zoom.as_deref(), Some(zoom_name));
+
+        // A new change discards the redo history
+        view.unset_zoom();
+        assert!(!view.redo());
+    }
+
+    #[test]
+    fn test_table_navigation() {
+        let content = (0..30)
+            .map(|i| format!("main;func_{} {}", i, i + 1))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut view = FlameGraphView::new(FlameGraph::from_string(content, false));
+        view.set_frame_height(10);
+        view.flamegraph.build_ordered_stacks();
+        let num_rows = view.flamegraph.ordered_stacks.num_rows;
+        assert_eq!(num_rows, 31);
+
+        view.half_page_next_rows();
+        assert_eq!(view.state.table_state.selected, 5);
+        view.half_page_previous_rows();
+        view.half_page_previous_rows();
+        assert_eq!(view.state.table_state.selected, 0);
+        view.to_last_row();
+        assert_eq!(view.state.table_state.selected, num_rows - 1);
+        view.half_page_next_rows();
+        assert_eq!(view.state.table_state.selected, num_rows - 1);
+        view.to_first_row();
+        assert_eq!(view.state.table_state.selected, 0);
+    }
+
+    #[test]
+    fn test_table_selection_kept_on_sort_and_search() {
+        let content = (0..30)
+            .map(|i| format!("main;func_{} {}", i, 30 - i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut view = FlameGraphView::new(FlameGraph::from_string(content, false));
+        view.set_frame_height(10);
+        view.flamegraph.build_ordered_stacks();
+        // Sorted by own: func_0, func_1, ..., main
+        view.state.table_state.selected = 2;
+        assert_eq!(view.get_selected_row_name(), Some("func_2"));
+
+        // By name: func_0, func_1, func_10, ..., func_19, func_2
+        view.set_sort_by_name();
+        assert_eq!(view.get_selected_row_name(), Some("func_2"));
+        assert_eq!(view.state.table_state.selected, 12);
+        assert_eq!(view.state.table_state.offset, 8);
+
+        view.set_search_pattern(SearchPattern::new("func_2", true, true).unwrap());
+        assert_eq!(view.get_selected_row_name(), Some("func_2"));
+        assert_eq!(view.state.table_state.selected, 0);
+        assert_eq!(view.state.table_state.offset, 0);
+
+        // The selected function is hidden by the search
+        view.to_last_row();
+        view.set_search_pattern(SearchPattern::new("func_1$", true, true).unwrap());
+        assert_eq!(view.get_selected_row_name(), Some("func_1"));
+
+        view.unset_search_pattern();
+        assert_eq!(view.get_selected_row_name(), Some("func_1"));
+    }
+
+    #[test]
+    fn test_selection_follows_view_switch() {
+        let content = "main;a;f 3\nmain;b;f 5\nmain;b;g 4\n".to_string();
+        let mut view = FlameGraphView::new(FlameGraph::from_string(content, false));
+        view.select_id(&get_id(&view, "main;a"));
+
+        // Flamegraph to table selects the row of the selected frame
+        view.toggle_view_kind();
+        assert_eq!(view.state.view_kind, ViewKind::Table);
+        assert_eq!(view.get_selected_row_name(), Some("a"));
+
+        // Table to the next view selects the heaviest occurrence of the selected row
+        view.to_first_row();
+        assert_eq!(view.get_selected_row_name(), Some("f"));
+        view.toggle_view_kind();
+        assert_eq!(view.state.view_kind, ViewKind::Summary);
+        assert_eq!(view.state.selected, get_id(&view, "main;b;f"));
+    }
+
+    #[test]
+    fn test_scroll() {
+        let content = (1..=20)
+            .map(|depth| {
+                let frames = (0..depth).map(|i| format!("f{}", i)).collect::<Vec<_>>();
+                format!("{} 1", frames.join(";"))
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut view = FlameGraphView::new(FlameGraph::from_string(content, false));
+        view.set_frame_height(6);
+
+        view.half_page_down();
+        assert_eq!(view.state.level_offset, 3);
+        view.scroll_down(1);
+        assert_eq!(view.state.level_offset, 4);
+        view.page_down();
+        assert_eq!(view.state.level_offset, 10);
+        // Cannot scroll past the bottom
+        view.page_down();
+        view.page_down();
+        assert_eq!(view.state.level_offset, 15);
+        view.half_page_up();
+        assert_eq!(view.state.level_offset, 12);
+        view.scroll_up(100);
+        assert_eq!(view.state.level_offset, 0);
+    }
+
+    #[test]
+    fn test_scroll_selected_to_center_top_and_bottom() {
+        let frames = (0..20).map(|i| format!("f{}", i)).collect::<Vec<_>>();
+        let content = format!("{} 1", frames.join(";"));
+        let mut view = FlameGraphView::new(FlameGraph::from_string(content, false));
+        view.set_frame_height(6);
+        view.select_id(&get_id(&view, &frames[..10].join(";")));
+
+        view.scroll_selected_to_center();
+        assert_eq!(view.state.level_offset, 7);
+        view.scroll_selected_to_top();
+        assert_eq!(view.state.level_offset, 10);
+        view.scroll_selected_to_bottom();
+        assert_eq!(view.state.level_offset, 5);
+
+        // Frames near the top cannot be scrolled further down
+        view.select_id(&get_id(&view, "f0"));
+        view.scroll_selected_to_bottom();
+        assert_eq!(view.state.level_offset, 0);
+    }
+
+    #[test]
+    fn test_to_heaviest_leaf() {
+        let content = "main;a;x 2\nmain;a;y;z 3\nmain;b 4\n".to_string();
+        let mut view = FlameGraphView::new(FlameGraph::from_string(content, false));
+        view.to_heaviest_leaf();
+        assert_eq!(view.state.selected, get_id(&view, "main;a;y;z"));
+
+        // Leaves stay selected
+        view.to_heaviest_leaf();
+        assert_eq!(view.state.selected, get_id(&view, "main;a;y;z"));
+    }
+
+    #[test]
+    fn test_to_same_name_ancestor_and_descendant() {
+        let content = "main;f;g;f;h;f 3\nmain;f;g;f 1\nmain;f;x;y;f 4\n".to_string();
+        let mut view = FlameGraphView::new(FlameGraph::from_string(content, false));
+        view.select_id(&get_id(&view, "main;f"));
+        assert!(!view.to_same_name_ancestor());
+
+        // The shallowest descendant is preferred over a heavier but deeper one
+        assert!(view.to_same_name_descendant());
+        assert_eq!(view.state.selected, get_id(&view, "main;f;g;f"));
+        assert!(view.to_same_name_descendant());
+        assert_eq!(view.state.selected, get_id(&view, "main;f;g;f;h;f"));
+        assert!(!view.to_same_name_descendant());
+
+        assert!(view.to_same_name_ancestor());
+        assert_eq!(view.state.selected, get_id(&view, "main;f;g;f"));
+        assert!(view.to_same_name_ancestor());
+        assert_eq!(view.state.selected, get_id(&view, "main;f"));
+    }
+
+    #[test]
+    fn test_to_adjacent_thread() {
+        let content = [
+            "thread (0x1): MainThread;main;run 3",
+            "process 7:\"worker\";thread (0x2): a;work 2",
+            "process 7:\"worker\";thread (0x3): b;work 1",
+            "idle 1",
+        ]
+        .join("\n");
+        let mut view = FlameGraphView::new(FlameGraph::from_string(content, false));
+        let threads = view.flamegraph.thread_frames();
+        assert_eq!(
+            threads,
+            vec![
+                get_id(&view, "thread (0x1): MainThread"),
+                get_id(&view, "process 7:\"worker\";thread (0x2): a"),
+                get_id(&view, "process 7:\"worker\";thread (0x3): b"),
+            ]
+        );
+
+        view.select_id(&get_id(&view, "thread (0x1): MainThread;main;run"));
+        assert!(view.to_adjacent_thread(true));
+        assert_eq!(view.state.selected, threads[1]);
+        assert!(view.to_adjacent_thread(false));
+        assert_eq!(view.state.selected, threads[0]);
+        assert!(view.to_adjacent_thread(false));
+        assert_eq!(view.state.selected, threads[2]);
+
+        view.select_id(&get_id(&view, "idle"));
+        assert!(view.to_adjacent_thread(false));
+        assert_eq!(view.state.selected, threads[2]);
+
+        let mut view = FlameGraphView::new(FlameGraph::from_string("main 1".to_string(), false));
+        assert!(!view.to_adjacent_thread(true));
+    }
+
+    #[test]
+    fn test_zoom_to_search_coverage() {
+        let content = "main;a;b;x 1\nmain;a;c;x 1\nmain;d;y 1\n".to_string();
+        let mut view = FlameGraphView::new(FlameGraph::from_string(content, false));
+        assert!(!view.zoom_to_search_coverage());
+
+        view.set_search_pattern(SearchPattern::new("x", false, true).unwrap());
+        assert!(view.zoom_to_search_coverage());
+        assert_eq!(view.save_state().zoom.as_deref(), Some("main;a"));
+
+        // Matches only sharing the root cannot be zoomed to
+        let mut view = FlameGraphView::new(FlameGraph::from_string("a 1\nb 1\n".into(), false));
+        view.set_search_pattern(SearchPattern::new("a|b", true, true).unwrap());
+        assert!(!view.zoom_to_search_coverage());
+    }
+
+    #[test]
+    fn test_to_nth_sibling() {
+        let content = "main;a 1\nmain;b 1\nmain;c 1\nmain;d 1\n".to_string();
+        let mut view = FlameGraphView::new(FlameGraph::from_string(content, false));
+        view.select_id(&get_id(&view, "main;a"));
+        view.to_nth_next_sibling(2);
+        assert_eq!(get_selected_short_name(&view), "c");
+        // Stops at the last sibling
+        view.to_nth_next_sibling(10);
+        assert_eq!(get_selected_short_name(&view), "d");
+        view.to_nth_previous_sibling(10);
+        assert_eq!(get_selected_short_name(&view), "a");
+    }
+
+    #[test]
+    fn test_to_sample() {
+        let content = "main;a;b 2\nmain 1\nmain;c 3\n".to_string();
+        let mut view = FlameGraphView::new(FlameGraph::from_string(content, false));
+        let leaves = (0..6)
+            .map(|sample| {
+                let leaf = view.to_sample(sample).unwrap();
+                assert_eq!(view.state.selected, leaf);
+                view.flamegraph
+                    .get_stack_short_name(&leaf)
+                    .unwrap()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(leaves, vec!["main", "b", "b", "c", "c", "c"]);
+        assert_eq!(view.to_sample(6), None);
+
+        // Only samples under the zoomed frame
+        view.select_id(&get_id(&view, "main;c"));
+        view.set_zoom();
+        assert_eq!(view.to_random_sample(), Some(get_id(&view, "main;c")));
+    }
+
+    #[test]
+    fn test_get_placement() {
+        let content = "main;big 90\nmain;small;leaf 10\n".to_string();
+        let mut view = FlameGraphView::new(FlameGraph::from_string(content, false));
+        view.set_frame_width(100);
+        view.set_min_width(MinWidth::Cells(20.0));
+        let placement = view.get_placement(&get_id(&view, "main;small")).unwrap();
+        assert_eq!(placement.level, 2);
+        assert_eq!(placement.width_factor, 0.1);
+        assert_eq!(placement.zoom_factor, None);
+        assert_eq!(placement.cells, Some(10.0));
+        assert_eq!(placement.min_cells, Some(20.0));
+        assert_eq!(
+            placement.hidden_because.as_deref(),
+            Some("narrower than the minimum width of 20 cells")
+        );
+
+        view.set_zoom_for_id(get_id(&view, "main;small"));
+        let placement = view
+            .get_placement(&get_id(&view, "main;small;leaf"))
+            .unwrap();
+        assert_eq!(placement.zoom_factor, Some(10.0));
+        assert_eq!(placement.cells, Some(100.0));
+        assert_eq!(placement.hidden_because, None);
+        let placement = view.get_placement(&get_id(&view, "main;big")).unwrap();
+        assert_eq!(
+            placement.hidden_because.as_deref(),
+            Some("outside the zoomed frame")
+        );
+        assert!(view
+            .get_placement(&ROOT_ID)
+            .unwrap()
+            .hidden_because
+            .is_none());
+    }
+
+    #[test]
+    fn test_set_min_width() {
+        let content = "main;big 90\nmain;small;leaf 10\n".to_string();
+        let mut view = FlameGraphView::new(FlameGraph::from_string(content, false));
+        view.set_frame_width(100);
+        view.select_id(&get_id(&view, "main;small;leaf"));
+        view.set_min_width(MinWidth::Cells(5.0));
+        assert_eq!(get_selected_short_name(&view), "leaf");
+        view.set_min_width(MinWidth::Percent(20.0));
+        assert_eq!(get_selected_short_name(&view), "main");
+        view.to_child_stack();
+        assert_eq!(get_selected_short_name(&view), "big");
+        view.to_next_sibling();
+        assert_eq!(get_selected_short_name(&view), "big");
+
+        assert_eq!("3".parse::<MinWidth>(), Ok(MinWidth::Cells(3.0)));
+        assert_eq!(" 0.5% ".parse::<MinWidth>(), Ok(MinWidth::Percent(0.5)));
+        assert!("-1".parse::<MinWidth>().is_err());
+        assert!("wide".parse::<MinWidth>().is_err());
+        assert_eq!(MinWidth::Percent(0.5).cells(100), 1.0);
+        assert_eq!(MinWidth::Percent(5.0).cells(100), 5.0);
+    }
+
+    #[test]
+    fn test_zoom_to_parent() {
+        let content = "main;a;b;c 1\nmain;d 1\n".to_string();
+        let mut view = FlameGraphView::new(FlameGraph::from_string(content, false));
+        assert!(!view.zoom_to_parent());
+
+        view.select_id(&get_id(&view, "main;a;b;c"));
+        view.set_zoom();
+        assert!(view.zoom_to_parent());
+        assert_eq!(view.save_state().zoom.as_deref(), Some("main;a;b"));
+        assert_eq!(get_selected_short_name(&view), "c");
+        assert!(view.zoom_to_parent());
+        assert!(view.zoom_to_parent());
+        assert_eq!(view.save_state().zoom.as_deref(), Some("main"));
+        // Zooming out of the top frame unzooms
+        assert!(view.zoom_to_parent());
+        assert_eq!(view.save_state().zoom, None);
+        assert_eq!(get_selected_short_name(&view), "c");
+        assert!(view.undo());
+        assert_eq!(view.save_state().zoom.as_deref(), Some("main"));
+    }
+
+    #[test]
+    fn test_search_in_zoom() {
+        let content = "main;a;x 1\nmain;b;x 2\nmain;b;y;x 4\n".to_string();
+        let mut view = FlameGraphView::new(FlameGraph::from_string(content, false));
+        view.toggle_search_in_zoom();
+        view.set_search_pattern(SearchPattern::new("x", false, true).unwrap());
+        assert_eq!(view.flamegraph.hit_coverage_count(), Some(7));
+
+        // Zooming in and out updates the matches
+        view.select_id(&get_id(&view, "main;b"));
+        view.set_zoom();
+        assert_eq!(view.flamegraph.hit_coverage_count(), Some(6));
+        assert!(
+            !view
+                .flamegraph
+                .get_stack_by_full_name("main;a;x")
+                .unwrap()
+                .hit
+        );
+        view.toggle_search_in_zoom();
+        assert_eq!(view.flamegraph.hit_coverage_count(), Some(7));
+        view.toggle_search_in_zoom();
+        view.unset_zoom();
+        assert_eq!(view.flamegraph.hit_coverage_count(), Some(7));
+    }
+
+    #[test]
+    fn test_select_and_zoom_to_heaviest_matching() {
+        let content = "main;a;work 3\nmain;b;work 5\nmain;b;other 1\n".to_string();
+        let mut view = FlameGraphView::new(FlameGraph::from_string(content, false));
+        let re = |pattern: &str| regex::Regex::new(pattern).unwrap();
+
+        assert!(view.zoom_to_heaviest_matching(|name| re("^a$").is_match(name)));
+        assert_eq!(view.save_state().zoom.as_deref(), Some("main;a"));
+        // Selection is restricted to the zoomed frame
+        assert!(view.select_heaviest_matching(&re("work")));
+        assert_eq!(view.state.selected, get_id(&view, "main;a;work"));
+        assert!(!view.select_heaviest_matching(&re("other")));
+
+        view.reset();
+        assert!(view.select_heaviest_matching(&re("work")));
+        assert_eq!(view.state.selected, get_id(&view, "main;b;work"));
+    }
 }
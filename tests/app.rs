@@ -0,0 +1,59 @@
+//! End-to-end tests driving flamelens through [`TestApp`], run with `--features testing`.
+
+use crossterm::event::KeyCode;
+use flamelens::testing::TestApp;
+
+const PROFILE: &str = "main;parse;lex 3\nmain;render;draw 5\nmain;render 2\n";
+
+#[test]
+fn test_zoom_and_back() {
+    let mut test_app = TestApp::new(PROFILE, 80, 24).unwrap();
+    assert!(test_app.shows(" all "));
+
+    // Select main;render, the heaviest child, and zoom into it
+    test_app.press_keys("jj").unwrap();
+    test_app.press_code(KeyCode::Enter).unwrap();
+    let zoom = test_app.app.flamegraph_state().zoom.as_ref().unwrap();
+    assert_eq!(
+        test_app
+            .app
+            .flamegraph()
+            .get_stack_full_name(&zoom.stack_id),
+        Some("main;render")
+    );
+    assert!(test_app.shows("render [Total: 7 samples, 70.00% of all, 100.00% of zoomed]"));
+
+    test_app.press_code(KeyCode::Esc).unwrap();
+    assert!(test_app.app.flamegraph_state().zoom.is_none());
+}
+
+#[test]
+fn test_table_sorting() {
+    let mut test_app = TestApp::new(PROFILE, 80, 24).unwrap();
+    test_app.press_code(KeyCode::Tab).unwrap();
+    assert!(test_app.shows("[Top]"));
+    assert!(test_app.shows("Own [▼]"));
+
+    // Heaviest own count first, then ascending names
+    let first_row = |test_app: &TestApp| {
+        test_app.lines()[4]
+            .split_whitespace()
+            .last()
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(first_row(&test_app), "draw");
+    test_app.press_keys("3").unwrap();
+    assert!(test_app.shows("Name [▲]"));
+    assert_eq!(first_row(&test_app), "draw");
+    test_app.press_keys("1").unwrap();
+    assert_eq!(first_row(&test_app), "main");
+}
+
+#[test]
+fn test_quit() {
+    let mut test_app = TestApp::new(PROFILE, 80, 24).unwrap();
+    assert!(test_app.app.running);
+    test_app.press_keys("q").unwrap();
+    assert!(!test_app.app.running);
+}